@@ -22,9 +22,11 @@ use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
 use merlin::Transcript;
 use zkvm::bulletproofs::PedersenGens;
 use zkvm::encoding::Encodable;
-use zkvm::{ClearValue, Commitment, Predicate, TranscriptProtocol, Value, VerificationKey};
+use zkvm::{
+    ClearValue, Commitment, Predicate, PredicateTree, TranscriptProtocol, Value, VerificationKey,
+};
 
-use super::Receiver;
+use super::{AddressError, Receiver};
 
 use bech32::{self, FromBase32, ToBase32};
 use std::{fmt, ops::Deref};
@@ -35,6 +37,43 @@ pub struct AddressLabel {
     inner: String,
 }
 
+/// Extension trait for converting between ZkVM predicates and addresses.
+pub trait PredicateAddress: Sized {
+    /// Creates an address with this predicate as a control key,
+    /// and a given key for encrypting the payment details.
+    /// Fails if the predicate is known to be a predicate tree:
+    /// funds sent to an address must be spendable with a signature.
+    fn to_address(
+        &self,
+        label: AddressLabel,
+        encryption_key: VerificationKey,
+    ) -> Result<Address, AddressError>;
+
+    /// Returns the opaque predicate controlling the funds sent to the address.
+    fn from_address(address: &Address) -> Self;
+}
+
+impl PredicateAddress for Predicate {
+    fn to_address(
+        &self,
+        label: AddressLabel,
+        encryption_key: VerificationKey,
+    ) -> Result<Address, AddressError> {
+        if self.verification_key_witness::<PredicateTree>().is_some() {
+            return Err(AddressError::PredicateTree);
+        }
+        let encryption_key = encryption_key
+            .into_point()
+            .decompress()
+            .ok_or(AddressError::InvalidEncryptionKey)?;
+        Ok(Address::new(label, self.to_point(), encryption_key))
+    }
+
+    fn from_address(address: &Address) -> Self {
+        address.predicate()
+    }
+}
+
 impl AddressLabel {
     /// Validates the address label
     pub fn new(label: String) -> Option<Self> {
@@ -160,6 +199,7 @@ impl Address {
             value,
             qty_blinding,
             flv_blinding,
+            exptime_ms: u64::max_value(),
         };
         (receiver, ciphertext)
     }
@@ -231,6 +271,7 @@ impl Address {
             value: ClearValue { qty, flv },
             qty_blinding,
             flv_blinding,
+            exptime_ms: u64::max_value(),
         })
     }
 
@@ -330,6 +371,54 @@ mod tests {
         assert_eq!(None, Address::from_string("test1uq90n36dnmdca0xpvr8we974x89adc54d71fzc4ca8k6yc8g9epca0ntey5jx9jk3q70cwzzjz6jgwx9zm6ezff4ss0f9a5p2junsnc480zqt"));
     }
 
+    #[test]
+    fn test_predicate_address() {
+        let label = AddressLabel::new("test".to_string()).expect("Valid label");
+        let ctrl_key = VerificationKey::from_secret(&Scalar::from(42u64));
+        let encr_key = VerificationKey::from_secret(&Scalar::from(24u64));
+
+        let predicate = Predicate::new(ctrl_key);
+        let addr = predicate.to_address(label.clone(), encr_key).unwrap();
+        assert_eq!(addr.control_key(), ctrl_key.as_point());
+        assert_eq!(Predicate::from_address(&addr), predicate);
+        assert_eq!(
+            addr,
+            Address::new(
+                label.clone(),
+                *ctrl_key.as_point(),
+                encr_key.as_point().decompress().unwrap(),
+            )
+        );
+
+        // Predicate with a signing key witness is representable.
+        let predicate = Predicate::with_witness(Scalar::from(42u64));
+        assert_eq!(predicate.to_address(label.clone(), encr_key).unwrap(), addr);
+
+        // Predicate tree is not representable.
+        let tree = PredicateTree::new(
+            Some(Predicate::new(ctrl_key)),
+            vec![zkvm::Program::new()],
+            [0u8; 32],
+        )
+        .unwrap();
+        let predicate = Predicate::tree(tree);
+        assert_eq!(
+            predicate.to_address(label.clone(), encr_key),
+            Err(AddressError::PredicateTree)
+        );
+        // ...unless it is opaque.
+        assert!(predicate
+            .as_opaque()
+            .to_address(label.clone(), encr_key)
+            .is_ok());
+
+        let invalid_key = VerificationKey::from_compressed(CompressedRistretto([0xff; 32]));
+        assert_eq!(
+            Predicate::new(ctrl_key).to_address(label, invalid_key),
+            Err(AddressError::InvalidEncryptionKey)
+        );
+    }
+
     #[test]
     fn test_encryption() {
         let label = AddressLabel::new("test".to_string()).expect("Valid label");
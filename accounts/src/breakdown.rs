@@ -0,0 +1,66 @@
+//! Contract breakdown: payment details sent to the recipient instead of the transaction.
+//!
+//! The breakdown contains only what the recipient needs to reconstruct and watch
+//! for the contract paying to its receiver:
+//! - the `ReceiverReply` with the contract's anchor,
+//! - the transaction header, to check the `maxtime` against the receiver's expiration time,
+//! - the transaction ID, to match the transaction once it is published.
+//!
+//! The program, the R1CS proof and the signature of the transaction are withheld,
+//! as well as the other inputs and outputs, so the recipient cannot publish the transaction.
+//! The sender publishes it only after the recipient has acknowledged the breakdown,
+//! which resolves the consistency issue without a receipt token
+//! (at the cost of trusting the sender to publish the transaction it has described).
+
+use serde::{Deserialize, Serialize};
+use zkvm::{ContractID, TxHeader, TxID, UnsignedTx};
+
+use crate::{Receiver, ReceiverError, ReceiverReply, ReceiverWitness};
+
+/// Payment details that allow the recipient to compute the contract ID without the transaction.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContractBreakdown {
+    /// Reply that allows the recipient to compute the contract ID.
+    pub reply: ReceiverReply,
+
+    /// Header of the payment transaction.
+    pub header: TxHeader,
+
+    /// ID of the payment transaction.
+    pub txid: TxID,
+}
+
+impl ContractBreakdown {
+    /// Creates the breakdown of the transaction for the receiver.
+    /// Returns `None` if the transaction does not create the contract paying to the receiver.
+    pub fn new(utx: &UnsignedTx, receiver: &Receiver) -> Option<Self> {
+        let anchor = utx
+            .txlog
+            .outputs()
+            .find(|c| c.id() == receiver.contract(c.anchor).id())?
+            .anchor;
+        Some(ContractBreakdown {
+            reply: ReceiverReply {
+                receiver_id: receiver.id(),
+                anchor,
+                note: None,
+            },
+            header: utx.header,
+            txid: utx.txid,
+        })
+    }
+}
+
+impl ReceiverWitness {
+    /// Verifies that the breakdown was made for this receiver and that the transaction
+    /// expires no later than the receiver. Returns the ID of the contract to watch for.
+    ///
+    /// Since the transaction itself is withheld, the recipient relies on the sender
+    /// to publish the transaction that creates this contract.
+    pub fn verify_breakdown(
+        &self,
+        breakdown: &ContractBreakdown,
+    ) -> Result<ContractID, ReceiverError> {
+        self.verify_reply_with_maxtime(&breakdown.reply, breakdown.header.maxtime_ms)
+    }
+}
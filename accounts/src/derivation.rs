@@ -27,6 +27,14 @@ pub trait XpubDerivation {
     /// Derives a key for a given sequence number.
     fn key_at_sequence(&self, sequence: Sequence) -> VerificationKey;
 
+    /// Finds the keys derived for sequence numbers up to `max_sequence` inclusive among the `outputs`.
+    /// Returns the pairs `(output index, sequence)` of the matches (see `Xpub::scan_candidates`).
+    fn scan_sequences(
+        &self,
+        outputs: &[VerificationKey],
+        max_sequence: Sequence,
+    ) -> Vec<(usize, Sequence)>;
+
     /// Derives an Address for a given sequence number.
     fn address_at_sequence(&self, label: AddressLabel, sequence: Sequence) -> (Address, Scalar);
 
@@ -46,6 +54,16 @@ impl XpubDerivation for Xpub {
         self.derive_key(|t| t.append_u64(b"sequence", sequence))
     }
 
+    fn scan_sequences(
+        &self,
+        outputs: &[VerificationKey],
+        max_sequence: Sequence,
+    ) -> Vec<(usize, Sequence)> {
+        self.scan_candidates(outputs, max_sequence, |t, sequence| {
+            t.append_u64(b"sequence", sequence)
+        })
+    }
+
     fn address_at_sequence(&self, label: AddressLabel, sequence: Sequence) -> (Address, Scalar) {
         let ctrl_key = self.key_at_sequence(sequence);
         // Note: we derive encryption privkey from the xpub public key, effectively binding it
@@ -77,6 +95,7 @@ impl XpubDerivation for Xpub {
             value,
             qty_blinding,
             flv_blinding,
+            exptime_ms: u64::max_value(),
         }
     }
 
@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+/// Represents an error in processing receivers and replies to them.
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+pub enum ReceiverError {
+    /// This error occurs when the reply refers to a different receiver.
+    #[error("Reply does not belong to this receiver")]
+    ReceiverMismatch,
+
+    /// This error occurs when the payment transaction may be published after the receiver expires.
+    #[error("Transaction maxtime is later than the receiver's expiration time")]
+    MaxtimeAfterExptime,
+
+    /// This error occurs when the receipt request is not signed by the receiver's key.
+    #[error("Receipt request is not signed by the receiver")]
+    InvalidReceiptRequest,
+
+    /// This error occurs when the offered transaction does not pay to the receiver,
+    /// or its adaptor signature cannot be completed with the receipt secret.
+    #[error("Payment offer is invalid")]
+    InvalidPaymentOffer,
+
+    /// This error occurs when the receipt secret does not match the requested receipt point.
+    #[error("Receipt is invalid")]
+    InvalidReceipt,
+
+    /// This error occurs when the payment URI has a scheme other than `slingshot:`.
+    #[error("Unknown payment URI scheme")]
+    UnknownURIScheme,
+
+    /// This error occurs when the payment URI does not specify a required parameter.
+    #[error("Payment URI parameter `{0}` is missing")]
+    MissingURIParameter(&'static str),
+
+    /// This error occurs when the payment URI parameter is malformed or specified more than once.
+    #[error("Payment URI parameter `{0}` is invalid")]
+    InvalidURIParameter(&'static str),
+}
+
+/// Represents an error in converting predicates to addresses.
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+pub enum AddressError {
+    /// This error occurs when the predicate is a tree of programs,
+    /// which cannot be spent with a single signature by the address owner.
+    #[error("Predicate tree cannot be represented as an address")]
+    PredicateTree,
+
+    /// This error occurs when the encryption key is not a valid Ristretto point.
+    #[error("Encryption key is not a valid point")]
+    InvalidEncryptionKey,
+}
@@ -20,12 +20,14 @@ and then be able to obtain a utxo proof in order to be able to spend the funds.
 
     Confirms payment details.
     Selects utxos to cover the payment amount
-    Forms a transaction with maxtime=min(sender's exptime, receiver exptime).
+    Forms a transaction with maxtime=min(sender's exptime, receiver exptime)
+    (see `Receiver::effective_maxtime`).
     Send back the ReceiverReply that allows constructing a contract ID.
 
                               ReceiverReply ------->
 
-                                   Reconstruct contract ID from ReceiverReply.
+                                   Reconstruct contract ID from ReceiverReply,
+                                     check that tx maxtime is within exptime.
                            Store the ReceiverReply together with the receiver.
 
                         <--------- ACK
@@ -53,22 +55,35 @@ Questions:
 2. Consistency issue: if we publish tx, but recipient never received contract contents - they cannot accept payment.
    If they pretend to fail, but have received contract - they can publish the tx and receive funds, while user does not.
    => resolvable by:
-   (a) receipt token - recipient and sender atomically swap money for the receipt token, which acts as a proof of completed
-       payment.
-       Problem: need to figure out how to mutually sign a proof
+   (a) receipt token - recipient gets the receipt token in exchange for publishing the payment,
+       which acts as a proof of the published payment.
+       Implemented in `receipt`: the sender signs the tx with an adaptor signature,
+       which the recipient can complete only by revealing the receipt secret.
+       Con: the receipt does not prove that the tx was confirmed, only that it was published.
    (b) tx must have exptime, even if recipient fails to accept tx,
        the sender still watches the chain for their tx confirmation until it expires.
        Con: sender cannot be sure whether to spend the same utxo or the new change utxo until tx expires.
    (c) do not send the entire tx to the recipient, instead send a contract breakdown.
        this way regardless of the recipient's reply, they won't be able to publish tx,
        so the sender can avoid publishing it unless recipient acknowledged the payment details.
+       Implemented in `breakdown`: the sender withholds the program, proof and signature of the tx.
 */
 mod address;
+mod breakdown;
 mod derivation;
+mod errors;
+mod note;
+mod receipt;
 mod receiver;
 #[cfg(test)]
 mod tests;
+mod uri;
 
-pub use address::{Address, AddressLabel};
+pub use address::{Address, AddressLabel, PredicateAddress};
+pub use breakdown::ContractBreakdown;
 pub use derivation::{Sequence, XprvDerivation, XpubDerivation};
+pub use errors::{AddressError, ReceiverError};
+pub use note::EncryptedNote;
+pub use receipt::{PaymentOffer, Receipt, ReceiptRequest};
 pub use receiver::{Receiver, ReceiverID, ReceiverReply, ReceiverWitness};
+pub use uri::PAYMENT_URI_SCHEME;
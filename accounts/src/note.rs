@@ -0,0 +1,137 @@
+//! Encrypted notes: secret payment details delivered together with the `ReceiverReply`.
+//!
+//! The sender encrypts the quantity and its blinding factor to the receiver's one-time key
+//! (the opaque predicate) using the Diffie-Hellman key exchange with an ephemeral key.
+//! The shared secret is fed to a transcript, like in the cybershake handshake,
+//! which produces the keystream and the authentication tag.
+//! Only the owner of the one-time private key can open the note,
+//! and any modification of the note is detected by the tag.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use keytree::Xprv;
+use merlin::Transcript;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use crate::{Receiver, ReceiverID, ReceiverReply, ReceiverWitness, XprvDerivation};
+
+/// Length of the plaintext: 8 bytes of the quantity (u64-LE) and 32 bytes of the blinding factor.
+const NOTE_LEN: usize = 40;
+
+/// Length of the authentication tag.
+const TAG_LEN: usize = 16;
+
+/// Quantity and blinding factor encrypted by the sender to the receiver's one-time key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncryptedNote {
+    /// Sender's ephemeral public key for the Diffie-Hellman key exchange.
+    pub nonce: CompressedRistretto,
+
+    /// Encrypted quantity and blinding factor followed by the authentication tag.
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedNote {
+    /// Encrypts the quantity and the blinding factor to the receiver's one-time key.
+    pub fn seal<R: RngCore + CryptoRng>(
+        receiver: &Receiver,
+        qty: u64,
+        blinding: Scalar,
+        mut rng: R,
+    ) -> Option<Self> {
+        let key = receiver.opaque_predicate.decompress()?;
+        let r = Scalar::random(&mut rng);
+        let nonce = (&r * &RISTRETTO_BASEPOINT_TABLE).compress();
+        let dh = (r * key).compress();
+
+        let mut t = note_transcript(&receiver.id(), &nonce, &dh);
+        let mut ciphertext = vec![0u8; NOTE_LEN];
+        t.challenge_bytes(b"pad", &mut ciphertext);
+        xor_slice(&mut ciphertext[..8], &qty.to_le_bytes());
+        xor_slice(&mut ciphertext[8..], blinding.as_bytes());
+
+        let mut tag = [0u8; TAG_LEN];
+        t.append_message(b"ct", &ciphertext);
+        t.challenge_bytes(b"tag", &mut tag);
+        ciphertext.extend_from_slice(&tag);
+
+        Some(EncryptedNote { nonce, ciphertext })
+    }
+}
+
+impl ReceiverReply {
+    /// Attaches the note with the receiver's quantity and its blinding factor,
+    /// so the recipient does not need to receive them out of band.
+    /// Returns `None` if the receiver's predicate is not a valid point.
+    pub fn with_note<R: RngCore + CryptoRng>(
+        mut self,
+        receiver: &Receiver,
+        rng: R,
+    ) -> Option<Self> {
+        self.note = Some(EncryptedNote::seal(
+            receiver,
+            receiver.value.qty,
+            receiver.qty_blinding,
+            rng,
+        )?);
+        Some(self)
+    }
+}
+
+impl ReceiverWitness {
+    /// Decrypts the note attached to the reply with the one-time key derived from `xprv`,
+    /// and returns the quantity and the blinding factor.
+    /// Returns `None` if there is no note, or it is malformed, tampered with,
+    /// or encrypted to another receiver.
+    pub fn open_note(&self, reply: &ReceiverReply, xprv: &Xprv) -> Option<(u64, Scalar)> {
+        let note = reply.note.as_ref()?;
+        if note.ciphertext.len() != NOTE_LEN + TAG_LEN {
+            return None;
+        }
+        let (ciphertext, tag) = note.ciphertext.split_at(NOTE_LEN);
+        let privkey = xprv.key_at_sequence(self.sequence);
+        let dh = (privkey * note.nonce.decompress()?).compress();
+
+        let mut t = note_transcript(&self.receiver.id(), &note.nonce, &dh);
+        let mut plaintext = [0u8; NOTE_LEN];
+        t.challenge_bytes(b"pad", &mut plaintext);
+
+        let mut expected_tag = [0u8; TAG_LEN];
+        t.append_message(b"ct", ciphertext);
+        t.challenge_bytes(b"tag", &mut expected_tag);
+        if !bool::from(expected_tag[..].ct_eq(tag)) {
+            return None;
+        }
+
+        xor_slice(&mut plaintext, ciphertext);
+        let mut qty = [0u8; 8];
+        qty.copy_from_slice(&plaintext[..8]);
+        let mut blinding = [0u8; 32];
+        blinding.copy_from_slice(&plaintext[8..]);
+        Some((
+            u64::from_le_bytes(qty),
+            Scalar::from_canonical_bytes(blinding)?,
+        ))
+    }
+}
+
+fn note_transcript(
+    receiver_id: &ReceiverID,
+    nonce: &CompressedRistretto,
+    dh: &CompressedRistretto,
+) -> Transcript {
+    let mut t = Transcript::new(b"ZkVM.accounts.note");
+    t.append_message(b"receiver_id", receiver_id.as_ref());
+    t.append_message(b"nonce", nonce.as_bytes());
+    t.append_message(b"dh", dh.as_bytes());
+    t
+}
+
+fn xor_slice(a: &mut [u8], b: &[u8]) {
+    for (x, y) in a.iter_mut().zip(b.iter()) {
+        *x ^= y;
+    }
+}
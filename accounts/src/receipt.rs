@@ -0,0 +1,217 @@
+//! Receipt tokens: proofs of published payments.
+//!
+//! The recipient and the sender exchange the publication of the payment for a receipt
+//! using an adaptor signature over the payment transaction:
+//!
+//! 1. Recipient derives a receipt secret `t` for the receiver and sends `ReceiptRequest`
+//!    with the receipt point `T = t·G`, signed by the receiver's key.
+//! 2. Sender builds the transaction paying to the receiver and sends `PaymentOffer`:
+//!    the `ReceiverReply`, the unsigned transaction and its signature encrypted with `T`.
+//!    The sender cannot publish the transaction, since the signature is incomplete without `t`.
+//! 3. Recipient checks that the transaction pays to the receiver, completes the signature with `t`
+//!    and publishes the transaction (`PaymentOffer::accept`).
+//! 4. Sender learns `t` from the published transaction's signature and assembles
+//!    the `Receipt` (`PaymentOffer::receipt`).
+//!
+//! The recipient can obtain the payment only by publishing the transaction,
+//! which reveals `t` to the sender, and the sender obtains the receipt only
+//! when the payment is published. The receipt is verified by anyone who knows the receiver,
+//! without access to the blockchain.
+//!
+//! This is not an atomic swap of the payment for the receipt: the sender learns `t`
+//! as soon as the signed transaction is published, not when it is confirmed.
+//! The published transaction may never be confirmed, e.g. if it expires
+//! or the sender double-spends its inputs. Therefore a receipt proves only that the recipient
+//! has published a valid transaction paying to the receiver. To prove that the payment was made,
+//! the receipt must be accompanied by the proof that the transaction `PaymentOffer::utx`
+//! (identified by its `txid`) was included in the blockchain.
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use keytree::Xprv;
+use merlin::Transcript;
+use musig::{AdaptorSignature, Signature, VerificationKey};
+use serde::{Deserialize, Serialize};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{TranscriptProtocol, Tx, UnsignedTx};
+
+use crate::{Receiver, ReceiverError, ReceiverID, ReceiverReply, ReceiverWitness, XprvDerivation};
+
+/// Request for a payment sent by the recipient to the sender in exchange for the receiver.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ReceiptRequest {
+    /// ID of the receiver to be paid.
+    pub receiver_id: ReceiverID,
+
+    /// Receipt point `T = t·G`: the secret `t` is revealed to the sender once the payment is published.
+    pub receipt_point: CompressedRistretto,
+
+    /// Signature over the receiver ID and the receipt point made with the receiver's key.
+    pub signature: Signature,
+}
+
+/// Payment offer sent by the sender in response to the `ReceiptRequest`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PaymentOffer {
+    /// Reply that allows the recipient to compute the contract ID.
+    pub reply: ReceiverReply,
+
+    /// Payment transaction without the signature.
+    pub utx: UnsignedTx,
+
+    /// Signature of the transaction encrypted with the receipt point.
+    pub presignature: AdaptorSignature,
+}
+
+/// Proof that the recipient has published the payment to the receiver.
+/// It does not prove that the payment was confirmed (see the module documentation).
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Request signed by the recipient.
+    pub request: ReceiptRequest,
+
+    /// Receipt secret revealed by the published payment transaction.
+    pub secret: Scalar,
+}
+
+impl ReceiptRequest {
+    /// Creates a receipt request for the receiver, signed with the receiver's key derived from `xprv`.
+    pub fn new(witness: &ReceiverWitness, xprv: &Xprv) -> Self {
+        let receiver_id = witness.receiver.id();
+        let privkey = xprv.key_at_sequence(witness.sequence);
+        let receipt_point =
+            VerificationKey::from_secret(&receipt_secret(&privkey, &receiver_id)).into_point();
+        let signature = Signature::sign(
+            &mut request_transcript(&receiver_id, &receipt_point),
+            privkey,
+        );
+        ReceiptRequest {
+            receiver_id,
+            receipt_point,
+            signature,
+        }
+    }
+
+    /// Verifies that the request was made for this receiver and signed by its key.
+    /// The sender must check the request before making a `PaymentOffer`.
+    pub fn verify(&self, receiver: &Receiver) -> Result<(), ReceiverError> {
+        if self.receiver_id != receiver.id() {
+            return Err(ReceiverError::ReceiverMismatch);
+        }
+        self.signature
+            .verify(
+                &mut request_transcript(&self.receiver_id, &self.receipt_point),
+                VerificationKey::from_compressed(receiver.opaque_predicate),
+            )
+            .map_err(|_| ReceiverError::InvalidReceiptRequest)
+    }
+}
+
+impl PaymentOffer {
+    /// Creates the offer for a verified request by signing the transaction with the adaptor signature.
+    /// The signing keys must be listed in the order of the transaction's `signing_instructions`.
+    pub fn new(
+        request: &ReceiptRequest,
+        reply: ReceiverReply,
+        utx: UnsignedTx,
+        signing_keys: &[Scalar],
+    ) -> Result<Self, ReceiverError> {
+        if reply.receiver_id != request.receiver_id {
+            return Err(ReceiverError::ReceiverMismatch);
+        }
+        let presignature = AdaptorSignature::sign_multi(
+            signing_keys,
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &request.receipt_point,
+            &mut utx.signing_transcript(),
+        )
+        .map_err(|_| ReceiverError::InvalidPaymentOffer)?;
+        Ok(PaymentOffer {
+            reply,
+            utx,
+            presignature,
+        })
+    }
+
+    /// Checks that the offered transaction pays to the receiver and completes its signature
+    /// with the receipt secret. Fails with `ReceiverError::InvalidPaymentOffer` if the transaction
+    /// is invalid, does not create the promised contract, or the signature cannot be completed.
+    ///
+    /// Publishing the returned transaction reveals the receipt secret to the sender.
+    pub fn accept(
+        &self,
+        witness: &ReceiverWitness,
+        xprv: &Xprv,
+        bp_gens: &BulletproofGens,
+    ) -> Result<Tx, ReceiverError> {
+        let contract_id =
+            witness.verify_reply_with_maxtime(&self.reply, self.utx.header.maxtime_ms)?;
+
+        let privkey = xprv.key_at_sequence(witness.sequence);
+        let secret = receipt_secret(&privkey, &witness.receiver.id());
+        let receipt_point = VerificationKey::from_secret(&secret).into_point();
+        self.presignature
+            .verify_multi(
+                &mut self.utx.signing_transcript(),
+                self.utx
+                    .signing_instructions
+                    .iter()
+                    .map(|(p, m)| (p.verification_key(), m))
+                    .collect(),
+                &receipt_point,
+            )
+            .map_err(|_| ReceiverError::InvalidPaymentOffer)?;
+
+        let tx = self.utx.clone().sign(self.presignature.complete(secret));
+        let vtx = tx
+            .verify(bp_gens)
+            .map_err(|_| ReceiverError::InvalidPaymentOffer)?;
+        if vtx.id != self.utx.txid || !vtx.log.outputs().any(|c| c.id() == contract_id) {
+            return Err(ReceiverError::InvalidPaymentOffer);
+        }
+        Ok(tx)
+    }
+
+    /// Extracts the receipt secret from the published transaction and creates the receipt.
+    /// Fails with `ReceiverError::InvalidReceipt` if the transaction's signature
+    /// was not completed from this offer.
+    pub fn receipt(&self, request: ReceiptRequest, tx: &Tx) -> Result<Receipt, ReceiverError> {
+        let secret = self
+            .presignature
+            .extract(&tx.signature, &request.receipt_point)
+            .ok_or(ReceiverError::InvalidReceipt)?;
+        Ok(Receipt { request, secret })
+    }
+}
+
+impl Receipt {
+    /// Verifies that the receipt was requested by the receiver
+    /// and contains the secret revealed by the published payment.
+    /// Does not check that the payment was confirmed.
+    pub fn verify(&self, receiver: &Receiver) -> Result<(), ReceiverError> {
+        self.request.verify(receiver)?;
+        if VerificationKey::from_secret(&self.secret).into_point() != self.request.receipt_point {
+            return Err(ReceiverError::InvalidReceipt);
+        }
+        Ok(())
+    }
+}
+
+/// Derives the receipt secret deterministically from the receiver's key,
+/// so the recipient does not need to store it.
+fn receipt_secret(privkey: &Scalar, receiver_id: &ReceiverID) -> Scalar {
+    let mut t = Transcript::new(b"ZkVM.accounts.receipt-secret");
+    t.append_message(b"key", privkey.as_bytes());
+    t.append_message(b"receiver_id", receiver_id.as_ref());
+    t.challenge_scalar(b"secret")
+}
+
+fn request_transcript(receiver_id: &ReceiverID, receipt_point: &CompressedRistretto) -> Transcript {
+    let mut t = Transcript::new(b"ZkVM.accounts.receipt-request");
+    t.append_message(b"receiver_id", receiver_id.as_ref());
+    t.append_message(b"receipt_point", receipt_point.as_bytes());
+    t
+}
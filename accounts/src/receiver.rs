@@ -4,9 +4,9 @@ use keytree::Xpub;
 use merlin::Transcript;
 use musig::VerificationKey;
 use serde::{Deserialize, Serialize};
-use zkvm::{Anchor, ClearValue, Commitment, Contract, PortableItem, Predicate, Value};
+use zkvm::{Anchor, ClearValue, Commitment, Contract, ContractID, PortableItem, Predicate, Value};
 
-use crate::{Sequence, XpubDerivation};
+use crate::{EncryptedNote, ReceiverError, Sequence, XpubDerivation};
 
 #[derive(Copy, Clone, Eq, Hash, Debug, PartialEq, Default, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -26,6 +26,13 @@ pub struct Receiver {
 
     /// Blinding factor for the flavor commitment.
     pub flv_blinding: Scalar,
+
+    /// Expiration time of the receiver in milliseconds since the Unix epoch:
+    /// the payment transaction must have `maxtime` not later than that.
+    /// `u64::max_value()` means that the receiver never expires.
+    /// Not committed to the receiver ID, so the ID does not depend on the expiration policy.
+    #[serde(default = "no_exptime")]
+    pub exptime_ms: u64,
 }
 
 /// Private annotation to the receiver that describes derivation path
@@ -47,6 +54,11 @@ pub struct ReceiverReply {
 
     /// Contract's anchor necessary to compute the contract ID
     pub anchor: Anchor,
+
+    /// Optional note with the secret payment details encrypted to the receiver
+    /// (see `ReceiverReply::with_note` and `ReceiverWitness::open_note`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<EncryptedNote>,
 }
 
 impl ReceiverWitness {
@@ -73,6 +85,30 @@ impl ReceiverWitness {
             anchor,
         }
     }
+
+    /// Verifies that the reply was made for this receiver and returns the ID of the contract
+    /// that the sender has promised to create.
+    ///
+    /// The contract ID is computed from the receiver's predicate and blinded value
+    /// (which are never taken from the reply) and the reply's `anchor`.
+    /// The reply's `receiver_id` is only used to check that the reply belongs to this receiver.
+    pub fn verify_reply(&self, reply: &ReceiverReply) -> Result<ContractID, ReceiverError> {
+        if reply.receiver_id != self.receiver.id() {
+            return Err(ReceiverError::ReceiverMismatch);
+        }
+        Ok(self.contract(reply.anchor).id())
+    }
+
+    /// Same as `verify_reply`, but also checks that the transaction paying to the receiver,
+    /// with the given `maxtime_ms`, expires no later than the receiver (see `Receiver::verify_maxtime`).
+    pub fn verify_reply_with_maxtime(
+        &self,
+        reply: &ReceiverReply,
+        maxtime_ms: u64,
+    ) -> Result<ContractID, ReceiverError> {
+        self.receiver.verify_maxtime(maxtime_ms)?;
+        self.verify_reply(reply)
+    }
 }
 
 impl Receiver {
@@ -94,6 +130,22 @@ impl Receiver {
         Predicate::new(VerificationKey::from_compressed(self.opaque_predicate))
     }
 
+    /// Returns the `maxtime` for the payment transaction: the earliest of the sender's
+    /// and the receiver's expiration times, so the transaction is valid only while both
+    /// are watching the chain for it.
+    pub fn effective_maxtime(&self, sender_exptime_ms: u64) -> u64 {
+        self.exptime_ms.min(sender_exptime_ms)
+    }
+
+    /// Checks that the payment transaction with the given `maxtime_ms` expires
+    /// no later than the receiver. Fails with `ReceiverError::MaxtimeAfterExptime` otherwise.
+    pub fn verify_maxtime(&self, maxtime_ms: u64) -> Result<(), ReceiverError> {
+        if maxtime_ms > self.exptime_ms {
+            return Err(ReceiverError::MaxtimeAfterExptime);
+        }
+        Ok(())
+    }
+
     /// Constructs a value object from the qty, flavor and blinding factors.
     pub fn blinded_value(&self) -> Value {
         Value {
@@ -117,3 +169,13 @@ impl Receiver {
         }
     }
 }
+
+impl AsRef<[u8]> for ReceiverID {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+fn no_exptime() -> u64 {
+    u64::max_value()
+}
@@ -6,11 +6,17 @@ use curve25519_dalek::scalar::Scalar;
 use keytree::Xprv;
 use musig::{Multisignature, Signature};
 
-use blockchain::{utreexo, BlockHeader, BlockTx, BlockchainState, Mempool};
+use blockchain::{utreexo, BlockHeader, BlockTx, BlockchainState, Mempool, TestChain};
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::{Anchor, ClearValue, Contract, ContractID, Program, Prover, TxEntry, TxHeader};
+use zkvm::{
+    Anchor, ClearValue, Contract, ContractID, Predicate, Program, Prover, TxEntry, TxHeader,
+    UnsignedTx, VerificationKey,
+};
 
-use crate::{ReceiverReply, ReceiverWitness, XprvDerivation, XpubDerivation};
+use crate::{
+    ContractBreakdown, PaymentOffer, Receipt, ReceiptRequest, Receiver, ReceiverError,
+    ReceiverReply, ReceiverWitness, XprvDerivation, XpubDerivation,
+};
 
 /// The complete state of the user node: their wallet and their blockchain state.
 #[derive(Clone)]
@@ -178,19 +184,30 @@ fn basic_accounts_test() {
     let reply = ReceiverReply {
         receiver_id: payment_receiver.id(),
         anchor: payment_anchor,
+        note: None,
     };
 
     // 6. Bob uses ReceiverReply to create PendingUtxo, replies with ACK.
     {
-        // In real implementation we'd check reply.receiver_id == payment_receiver.id()
+        let contract_id = payment_receiver_witness
+            .verify_reply(&reply)
+            .expect("Reply must match the receiver");
         let pending_utxo = PendingUtxo {
             receiver_witness: payment_receiver_witness,
             anchor: reply.anchor, // store anchor sent by Alice
         };
 
+        assert_eq!(pending_utxo.contract_id(), contract_id);
+
         bob.wallet.pending_utxos.push(pending_utxo);
     }
 
+    // Reply addressed to another receiver must be rejected.
+    assert_eq!(
+        change_receiver_witness.verify_reply(&reply),
+        Err(ReceiverError::ReceiverMismatch)
+    );
+
     // Alice receives ACK from Bob.
 
     // Alice signs the tx.
@@ -264,6 +281,68 @@ fn basic_accounts_test() {
     );
 }
 
+#[test]
+fn test_chain_payments() {
+    let flv = Scalar::from(0u64);
+    let alice_key = Scalar::from(1u64);
+    let bob_key = Scalar::from(2u64);
+    let alice = Predicate::new(VerificationKey::from_secret(&alice_key));
+    let bob = Predicate::new(VerificationKey::from_secret(&bob_key));
+
+    let mut chain = TestChain::new(vec![(alice.clone(), ClearValue { qty: 100, flv })]);
+    assert_eq!(chain.balance(&alice, flv), 100);
+    assert_eq!(chain.balance(&bob, flv), 0);
+
+    // Alice pays 30 to Bob and keeps 70 as change.
+    let pay =
+        |chain: &mut TestChain, from: &Predicate, key: Scalar, to: &Predicate, amount: u64| {
+            let input = chain
+                .utxos()
+                .find(|u| &u.contract.predicate == from)
+                .unwrap()
+                .contract
+                .clone();
+            let total = chain.balance(from, flv);
+            let program = Program::build(|p| {
+                p.push(input)
+                    .input()
+                    .signtx()
+                    .split_value(ClearValue { qty: total, flv }, &[total - amount, amount])
+                    .unwrap()
+                    .push(to.clone())
+                    .output(1)
+                    .push(from.clone())
+                    .output(1);
+            });
+            chain.push_tx(program, &[key]).unwrap()
+        };
+    pay(&mut chain, &alice, alice_key, &bob, 30);
+    assert_eq!(chain.mempool().len(), 1);
+    assert_eq!(chain.balance(&alice, flv), 70);
+    assert_eq!(chain.balance(&bob, flv), 30);
+    assert!(chain.utxos().all(|u| !u.confirmed));
+
+    let block = chain.mine_block().unwrap();
+    assert_eq!(block.verified_txs.len(), 1);
+    assert_eq!(chain.state().tip.height, 2);
+    assert_eq!(chain.mempool().len(), 0);
+    assert!(chain.utxos().all(|u| u.confirmed));
+
+    // Bob spends his confirmed output using the updated utreexo proof.
+    pay(&mut chain, &bob, bob_key, &alice, 10);
+    chain.mine_block().unwrap();
+    assert_eq!(chain.state().tip.height, 3);
+    assert_eq!(chain.balance(&alice, flv), 80);
+    assert_eq!(chain.balance(&bob, flv), 20);
+
+    // Spending without the signing key fails.
+    let input = chain.utxos().next().unwrap().contract.clone();
+    let program = Program::build(|p| {
+        p.push(input).input().signtx().push(bob.clone()).output(1);
+    });
+    assert!(chain.push_tx(program, &[]).is_err());
+}
+
 /// Processes a block
 fn process_block(
     node: &mut Node,
@@ -436,3 +515,317 @@ impl ConfirmedUtxo {
         self.contract().id()
     }
 }
+
+#[test]
+fn receiver_exptime() {
+    let xprv = Xprv::random(&mut ChaChaRng::from_seed([0u8; 32]));
+    let value = ClearValue {
+        qty: 10,
+        flv: Scalar::from(1u64),
+    };
+    let mut witness = ReceiverWitness::new(xprv.as_xpub(), 0, value);
+    assert_eq!(witness.receiver.effective_maxtime(1000), 1000);
+
+    let exptime_ms = 1000u64;
+    witness.receiver.exptime_ms = exptime_ms;
+    assert_eq!(witness.receiver.effective_maxtime(2000), exptime_ms);
+    assert_eq!(witness.receiver.effective_maxtime(500), 500);
+
+    // Sender and receiver expire at the same time: the tx may use exactly that maxtime.
+    let maxtime_ms = witness.receiver.effective_maxtime(exptime_ms);
+    assert_eq!(maxtime_ms, exptime_ms);
+    assert_eq!(witness.receiver.verify_maxtime(maxtime_ms), Ok(()));
+    assert_eq!(
+        witness.receiver.verify_maxtime(maxtime_ms + 1),
+        Err(ReceiverError::MaxtimeAfterExptime)
+    );
+
+    let reply = ReceiverReply {
+        receiver_id: witness.receiver.id(),
+        anchor: Anchor::from_raw_bytes([1u8; 32]),
+        note: None,
+    };
+    assert_eq!(
+        witness.verify_reply_with_maxtime(&reply, maxtime_ms),
+        witness.verify_reply(&reply)
+    );
+    assert_eq!(
+        witness.verify_reply_with_maxtime(&reply, maxtime_ms + 1),
+        Err(ReceiverError::MaxtimeAfterExptime)
+    );
+}
+
+#[test]
+fn receipt_swap() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let mut alice = Wallet::new([0; 32]);
+    let mut bob = Wallet::new([1; 32]);
+    alice.utxos = alice
+        .generate_pending_utxos([0; 32])
+        .into_iter()
+        .map(|utxo| utxo.to_confirmed(utreexo::Proof::Transient))
+        .collect();
+
+    // Bob creates a receiver and requests a receipt for the payment.
+    let payment = ClearValue {
+        qty: 14,
+        flv: Scalar::from(0u64),
+    };
+    let receiver_witness = bob.generate_receiver(payment);
+    let receiver = receiver_witness.receiver;
+    let request = ReceiptRequest::new(&receiver_witness, &bob.xprv);
+    assert_eq!(request.verify(&receiver), Ok(()));
+
+    // Alice makes the payment transaction and signs it with the adaptor signature.
+    let (utx, reply, signing_keys) = make_payment(&mut alice, &receiver, &bp_gens);
+    let offer = PaymentOffer::new(&request, reply, utx, &signing_keys).unwrap();
+
+    // Alice cannot publish the transaction without the receipt secret.
+    let incomplete = offer.utx.clone().sign(Signature {
+        s: offer.presignature.s,
+        R: offer.presignature.R,
+    });
+    assert!(incomplete.verify(&bp_gens).is_err());
+
+    // Bob completes the signature and publishes the transaction.
+    let tx = offer
+        .accept(&receiver_witness, &bob.xprv, &bp_gens)
+        .unwrap();
+
+    // Alice extracts the receipt secret from the published transaction.
+    let receipt = offer.receipt(request, &tx).unwrap();
+    assert_eq!(receipt.verify(&receiver), Ok(()));
+
+    // Receipt does not apply to another receiver.
+    let other_witness = bob.generate_receiver(payment);
+    assert_eq!(
+        receipt.verify(&other_witness.receiver),
+        Err(ReceiverError::ReceiverMismatch)
+    );
+}
+
+#[test]
+fn receipt_swap_cheating() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let mut alice = Wallet::new([0; 32]);
+    let mut bob = Wallet::new([1; 32]);
+    let mallory = Wallet::new([2; 32]);
+    alice.utxos = alice
+        .generate_pending_utxos([0; 32])
+        .into_iter()
+        .map(|utxo| utxo.to_confirmed(utreexo::Proof::Transient))
+        .collect();
+
+    let payment = ClearValue {
+        qty: 14,
+        flv: Scalar::from(0u64),
+    };
+    let receiver_witness = bob.generate_receiver(payment);
+    let receiver = receiver_witness.receiver;
+    let request = ReceiptRequest::new(&receiver_witness, &bob.xprv);
+
+    // Request signed with a key other than the receiver's is rejected by the sender.
+    let forged_request = ReceiptRequest::new(&receiver_witness, &mallory.xprv);
+    assert_eq!(
+        forged_request.verify(&receiver),
+        Err(ReceiverError::InvalidReceiptRequest)
+    );
+
+    let (utx, reply, signing_keys) = make_payment(&mut alice, &receiver, &bp_gens);
+    let offer = PaymentOffer::new(&request, reply, utx, &signing_keys).unwrap();
+
+    // Recipient cannot complete the signature without revealing the receipt secret:
+    // any other completion does not produce a valid transaction, nor a receipt.
+    let withheld = offer
+        .utx
+        .clone()
+        .sign(offer.presignature.complete(Scalar::from(7u64)));
+    assert!(withheld.verify(&bp_gens).is_err());
+    assert_eq!(
+        offer.receipt(request, &withheld).err(),
+        Some(ReceiverError::InvalidReceipt)
+    );
+
+    // Recipient cannot claim a receipt with a wrong secret.
+    let fake_receipt = Receipt {
+        request,
+        secret: Scalar::from(7u64),
+    };
+    assert_eq!(
+        fake_receipt.verify(&receiver),
+        Err(ReceiverError::InvalidReceipt)
+    );
+
+    // Recipient rejects the offer with a tampered adaptor signature.
+    let mut tampered = offer.clone();
+    tampered.presignature.s += Scalar::one();
+    assert_eq!(
+        tampered
+            .accept(&receiver_witness, &bob.xprv, &bp_gens)
+            .err(),
+        Some(ReceiverError::InvalidPaymentOffer)
+    );
+
+    // Recipient rejects the offer encrypted with a receipt point of another receiver.
+    let other_witness = bob.generate_receiver(payment);
+    let other_request = ReceiptRequest::new(&other_witness, &bob.xprv);
+    let mut misdirected = offer.clone();
+    misdirected.presignature = PaymentOffer::new(
+        &other_request,
+        ReceiverReply {
+            receiver_id: other_request.receiver_id,
+            anchor: offer.reply.anchor,
+            note: None,
+        },
+        offer.utx.clone(),
+        &signing_keys,
+    )
+    .unwrap()
+    .presignature;
+    assert_eq!(
+        misdirected
+            .accept(&receiver_witness, &bob.xprv, &bp_gens)
+            .err(),
+        Some(ReceiverError::InvalidPaymentOffer)
+    );
+
+    // Recipient rejects the offer that does not pay to the receiver.
+    assert_eq!(
+        offer.accept(&other_witness, &bob.xprv, &bp_gens).err(),
+        Some(ReceiverError::ReceiverMismatch)
+    );
+}
+
+#[test]
+fn contract_breakdown() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let mut alice = Wallet::new([0; 32]);
+    let mut bob = Wallet::new([1; 32]);
+    alice.utxos = alice
+        .generate_pending_utxos([0; 32])
+        .into_iter()
+        .map(|utxo| utxo.to_confirmed(utreexo::Proof::Transient))
+        .collect();
+
+    let payment = ClearValue {
+        qty: 14,
+        flv: Scalar::from(0u64),
+    };
+    let mut receiver_witness = bob.generate_receiver(payment);
+    let (utx, reply, _) = make_payment(&mut alice, &receiver_witness.receiver, &bp_gens);
+
+    // Alice sends the breakdown instead of the transaction.
+    let breakdown = ContractBreakdown::new(&utx, &receiver_witness.receiver).unwrap();
+    assert_eq!(breakdown.reply.anchor, reply.anchor);
+    assert!(breakdown.txid == utx.txid);
+
+    // Bob computes the same contract ID as the one created by the transaction.
+    let contract_id = receiver_witness.verify_breakdown(&breakdown).unwrap();
+    assert!(utx.txlog.outputs().any(|c| c.id() == contract_id));
+
+    // Transaction that does not pay to the receiver has no breakdown.
+    let other_witness = bob.generate_receiver(payment);
+    assert!(ContractBreakdown::new(&utx, &other_witness.receiver).is_none());
+    assert_eq!(
+        other_witness.verify_breakdown(&breakdown),
+        Err(ReceiverError::ReceiverMismatch)
+    );
+
+    // Breakdown of the transaction that outlives the receiver is rejected.
+    receiver_witness.receiver.exptime_ms = utx.header.maxtime_ms - 1;
+    assert_eq!(
+        receiver_witness.verify_breakdown(&breakdown),
+        Err(ReceiverError::MaxtimeAfterExptime)
+    );
+}
+
+#[test]
+fn encrypted_note() {
+    let mut rng = ChaChaRng::from_seed([0u8; 32]);
+    let mut bob = Wallet::new([1; 32]);
+    let payment = ClearValue {
+        qty: 14,
+        flv: Scalar::from(0u64),
+    };
+    let witness = bob.generate_receiver(payment);
+    let reply = ReceiverReply {
+        receiver_id: witness.receiver.id(),
+        anchor: Anchor::from_raw_bytes([1u8; 32]),
+        note: None,
+    };
+    assert_eq!(witness.open_note(&reply, &bob.xprv), None);
+
+    // Bob opens the note with the key of the receiver.
+    let reply = reply.with_note(&witness.receiver, &mut rng).unwrap();
+    assert_eq!(
+        witness.open_note(&reply, &bob.xprv),
+        Some((14, witness.receiver.qty_blinding))
+    );
+
+    // Note cannot be opened by another receiver or with another key.
+    let other_witness = bob.generate_receiver(payment);
+    assert_eq!(other_witness.open_note(&reply, &bob.xprv), None);
+    assert_eq!(witness.open_note(&reply, &Wallet::new([2; 32]).xprv), None);
+
+    // Tampered and malformed notes are rejected.
+    let mut tampered = reply.clone();
+    tampered.note.as_mut().unwrap().ciphertext[0] ^= 1;
+    assert_eq!(witness.open_note(&tampered, &bob.xprv), None);
+    let mut truncated = reply.clone();
+    truncated.note.as_mut().unwrap().ciphertext.pop();
+    assert_eq!(witness.open_note(&truncated, &bob.xprv), None);
+}
+
+/// Builds a transaction paying to the receiver with the change back to the wallet.
+/// Returns the unsigned transaction, the reply to the receiver and the signing keys.
+fn make_payment(
+    wallet: &mut Wallet,
+    receiver: &Receiver,
+    bp_gens: &BulletproofGens,
+) -> (UnsignedTx, ReceiverReply, Vec<Scalar>) {
+    let (spent_utxos, change_value) = receiver
+        .value
+        .select_coins(wallet.utxos.iter().cloned())
+        .unwrap();
+    let change_receiver = wallet.generate_receiver(change_value).receiver;
+
+    let program = Program::build(|p| {
+        for utxo in spent_utxos.iter() {
+            p.push(utxo.contract()).input().signtx();
+        }
+        let pmnt = receiver.blinded_value();
+        let change = change_receiver.blinded_value();
+        p.push(pmnt.qty)
+            .push(pmnt.flv)
+            .push(change.qty)
+            .push(change.flv)
+            .cloak(spent_utxos.len(), 2)
+            .push(receiver.predicate())
+            .output(1)
+            .push(change_receiver.predicate())
+            .output(1);
+    });
+    let header = TxHeader {
+        version: 1u64,
+        mintime_ms: 0u64,
+        maxtime_ms: u64::max_value(),
+    };
+    let utx = Prover::build_tx(program, header, bp_gens).unwrap();
+
+    let anchor = utx
+        .txlog
+        .outputs()
+        .find(|c| c.predicate == receiver.predicate())
+        .unwrap()
+        .anchor;
+    let reply = ReceiverReply {
+        receiver_id: receiver.id(),
+        anchor,
+        note: None,
+    };
+    let signing_keys = spent_utxos
+        .iter()
+        .map(|utxo| wallet.xprv.key_at_sequence(utxo.receiver_witness.sequence))
+        .collect();
+    (utx, reply, signing_keys)
+}
@@ -0,0 +1,257 @@
+//! Payment URI encoding of the Receiver.
+//!
+//! Payment URI allows the recipient to show the receiver to the sender (e.g. as a QR code)
+//! so the sender's wallet can scan it and form a payment:
+//!
+//! ```ascii
+//! slingshot:<predicate>?qty=<qty>&flv=<flv>&qty_blinding=<qty_blinding>&flv_blinding=<flv_blinding>[&exptime=<exptime_ms>]
+//! ```
+//!
+//! Quantity and expiration time are encoded as decimal numbers. All other fields are raw 32-byte strings
+//! (compressed point and scalars) that are percent-encoded.
+//! Expiration time is omitted for the receivers that never expire.
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use zkvm::ClearValue;
+
+use super::{Receiver, ReceiverError};
+
+/// URI scheme for the payment URIs.
+pub const PAYMENT_URI_SCHEME: &str = "slingshot";
+
+impl Receiver {
+    /// Encodes the receiver as a payment URI.
+    pub fn to_payment_uri(&self) -> String {
+        let mut uri = format!(
+            "{}:{}?qty={}&flv={}&qty_blinding={}&flv_blinding={}",
+            PAYMENT_URI_SCHEME,
+            percent_encode(self.opaque_predicate.as_bytes()),
+            self.value.qty,
+            percent_encode(self.value.flv.as_bytes()),
+            percent_encode(self.qty_blinding.as_bytes()),
+            percent_encode(self.flv_blinding.as_bytes()),
+        );
+        if self.exptime_ms != u64::max_value() {
+            uri.push_str(&format!("&exptime={}", self.exptime_ms));
+        }
+        uri
+    }
+
+    /// Decodes the receiver from a payment URI.
+    /// Fails if the scheme is not `slingshot:`, or any of the required parameters is missing or malformed.
+    /// Unknown parameters are ignored.
+    pub fn from_payment_uri(uri: &str) -> Result<Self, ReceiverError> {
+        let (scheme, rest) = split_once(uri, ':').ok_or(ReceiverError::UnknownURIScheme)?;
+        if !scheme.eq_ignore_ascii_case(PAYMENT_URI_SCHEME) {
+            return Err(ReceiverError::UnknownURIScheme);
+        }
+        let (path, query) = split_once(rest, '?').unwrap_or((rest, ""));
+
+        if path.is_empty() {
+            return Err(ReceiverError::MissingURIParameter("predicate"));
+        }
+        let predicate = percent_decode(path)
+            .filter(|bytes| bytes.len() == 32)
+            .map(|bytes| CompressedRistretto::from_slice(&bytes))
+            .ok_or(ReceiverError::InvalidURIParameter("predicate"))?;
+
+        let mut qty = None;
+        let mut flv = None;
+        let mut qty_blinding = None;
+        let mut flv_blinding = None;
+        let mut exptime_ms = None;
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = split_once(pair, '=').unwrap_or((pair, ""));
+            let (name, slot) = match key {
+                "qty" => {
+                    let q = value
+                        .parse::<u64>()
+                        .map_err(|_| ReceiverError::InvalidURIParameter("qty"))?;
+                    set_once(&mut qty, q, "qty")?;
+                    continue;
+                }
+                "exptime" => {
+                    let t = value
+                        .parse::<u64>()
+                        .map_err(|_| ReceiverError::InvalidURIParameter("exptime"))?;
+                    set_once(&mut exptime_ms, t, "exptime")?;
+                    continue;
+                }
+                "flv" => ("flv", &mut flv),
+                "qty_blinding" => ("qty_blinding", &mut qty_blinding),
+                "flv_blinding" => ("flv_blinding", &mut flv_blinding),
+                _ => continue,
+            };
+            let scalar = decode_scalar(value).ok_or(ReceiverError::InvalidURIParameter(name))?;
+            set_once(slot, scalar, name)?;
+        }
+
+        Ok(Receiver {
+            opaque_predicate: predicate,
+            value: ClearValue {
+                qty: qty.ok_or(ReceiverError::MissingURIParameter("qty"))?,
+                flv: flv.ok_or(ReceiverError::MissingURIParameter("flv"))?,
+            },
+            qty_blinding: qty_blinding.ok_or(ReceiverError::MissingURIParameter("qty_blinding"))?,
+            flv_blinding: flv_blinding.ok_or(ReceiverError::MissingURIParameter("flv_blinding"))?,
+            exptime_ms: exptime_ms.unwrap_or(u64::max_value()),
+        })
+    }
+}
+
+/// Stores the value in the slot, failing if the parameter was already specified.
+fn set_once<T>(slot: &mut Option<T>, value: T, name: &'static str) -> Result<(), ReceiverError> {
+    if slot.is_some() {
+        return Err(ReceiverError::InvalidURIParameter(name));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn split_once(s: &str, delimiter: char) -> Option<(&str, &str)> {
+    let i = s.find(delimiter)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+fn decode_scalar(s: &str) -> Option<Scalar> {
+    let bytes = percent_decode(s)?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    let mut buf = [0u8; 32];
+    buf.copy_from_slice(&bytes);
+    Scalar::from_canonical_bytes(buf)
+}
+
+/// Percent-encodes all bytes except the unreserved characters (RFC 3986).
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len() * 3);
+    for &b in bytes.iter() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(b as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    result
+}
+
+/// Decodes a percent-encoded string. Returns None if the string contains a malformed escape sequence.
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            result.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keytree::Xprv;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::XpubDerivation;
+
+    fn test_receiver() -> Receiver {
+        let xprv = Xprv::random(&mut ChaChaRng::from_seed([0u8; 32]));
+        let value = ClearValue {
+            qty: 1000,
+            flv: Scalar::from(7u64),
+        };
+        xprv.as_xpub().receiver_at_sequence(42, value)
+    }
+
+    #[test]
+    fn test_payment_uri_roundtrip() {
+        let receiver = test_receiver();
+        let uri = receiver.to_payment_uri();
+        assert!(uri.starts_with("slingshot:"));
+
+        let decoded = Receiver::from_payment_uri(&uri).unwrap();
+        assert_eq!(decoded.id(), receiver.id());
+
+        // unknown parameters are ignored
+        let decoded = Receiver::from_payment_uri(&format!("{}&label=coffee", uri)).unwrap();
+        assert_eq!(decoded.id(), receiver.id());
+
+        // expiration time is optional
+        assert!(!uri.contains("exptime"));
+        assert_eq!(decoded.exptime_ms, u64::max_value());
+        let expiring = Receiver {
+            exptime_ms: 1_600_000_000_000,
+            ..receiver
+        };
+        let uri = expiring.to_payment_uri();
+        assert!(uri.ends_with("&exptime=1600000000000"));
+        let decoded = Receiver::from_payment_uri(&uri).unwrap();
+        assert_eq!(decoded.exptime_ms, 1_600_000_000_000);
+        assert_eq!(
+            Receiver::from_payment_uri(&format!("{}&exptime=1", uri)).unwrap_err(),
+            ReceiverError::InvalidURIParameter("exptime")
+        );
+    }
+
+    #[test]
+    fn test_payment_uri_errors() {
+        let uri = test_receiver().to_payment_uri();
+
+        assert_eq!(
+            Receiver::from_payment_uri(&uri.replacen("slingshot:", "bitcoin:", 1)).unwrap_err(),
+            ReceiverError::UnknownURIScheme
+        );
+        assert_eq!(
+            Receiver::from_payment_uri("no scheme").unwrap_err(),
+            ReceiverError::UnknownURIScheme
+        );
+
+        let (_, query) = split_once(&uri, '?').unwrap();
+        assert_eq!(
+            Receiver::from_payment_uri(&format!("slingshot:?{}", query)).unwrap_err(),
+            ReceiverError::MissingURIParameter("predicate")
+        );
+        assert_eq!(
+            Receiver::from_payment_uri(&uri.replacen("qty=1000&", "", 1)).unwrap_err(),
+            ReceiverError::MissingURIParameter("qty")
+        );
+        assert_eq!(
+            Receiver::from_payment_uri(&uri.replacen("qty=1000", "qty=-1", 1)).unwrap_err(),
+            ReceiverError::InvalidURIParameter("qty")
+        );
+        assert_eq!(
+            Receiver::from_payment_uri(&format!("{}&qty=1000", uri)).unwrap_err(),
+            ReceiverError::InvalidURIParameter("qty")
+        );
+        assert_eq!(
+            Receiver::from_payment_uri(&format!("{}&flv=%0", uri)).unwrap_err(),
+            ReceiverError::InvalidURIParameter("flv")
+        );
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        let bytes: Vec<u8> = (0..=255u8).collect();
+        let encoded = percent_encode(&bytes);
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "%-._~".contains(c)));
+        assert_eq!(percent_decode(&encoded), Some(bytes));
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("abc%4"), None);
+    }
+}
@@ -2,9 +2,9 @@ use merlin::Transcript;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use zkvm::encoding::*;
-use zkvm::{merkle, Hash, MerkleItem, MerkleTree, Tx, VerifiedTx};
+use zkvm::{Hash, MerkleItem, MerkleTree, Tx, VerifiedTx};
 
-use super::state::BlockchainState;
+use super::state::{BlockchainState, SpentAnchors};
 use super::utreexo::{self, Proof};
 use readerwriter::Encodable;
 
@@ -58,6 +58,8 @@ pub struct VerifiedBlock {
     pub utreexo: utreexo::Forest,
     /// Utreexo catchup map for updating the proofs
     pub catchup: utreexo::Catchup,
+    /// Anchors of the transactions confirmed in the latest blocks, including this one
+    pub spent_anchors: SpentAnchors,
     /// List of verified transactions
     pub raw_txs: Vec<BlockTx>,
     /// List of verified transactions
@@ -148,13 +150,7 @@ impl Encodable for BlockTx {
         self.tx.encode(w)?;
         w.write_size(b"n", self.proofs.len())?;
         for proof in self.proofs.iter() {
-            match proof {
-                Proof::Transient => w.write_u8(b"type", 0)?,
-                Proof::Committed(path) => {
-                    w.write_u8(b"type", 1)?;
-                    path.encode(w)?;
-                }
-            }
+            proof.encode(w)?;
         }
         Ok(())
     }
@@ -168,10 +164,7 @@ impl ExactSizeEncodable for BlockTx {
             + self
                 .proofs
                 .iter()
-                .map(|proof| match proof {
-                    Proof::Transient => 1,
-                    Proof::Committed(path) => 1 + path.encoded_size(),
-                })
+                .map(|proof| proof.encoded_size())
                 .sum::<usize>()
     }
 }
@@ -180,13 +173,7 @@ impl Decodable for BlockTx {
     fn decode(r: &mut impl Reader) -> Result<Self, ReadError> {
         let tx = Tx::decode(r)?;
         let n = r.read_size()? as usize;
-        let proofs = r.read_vec(n, |r| match r.read_u8()? {
-            0 => Ok(Proof::Transient),
-            1 => merkle::Path::decode(r)
-                .map(Proof::Committed)
-                .map_err(|_| ReadError::InvalidFormat),
-            _ => Err(ReadError::InvalidFormat),
-        })?;
+        let proofs = r.read_vec(n, |r| Proof::decode(r))?;
         Ok(BlockTx { tx, proofs })
     }
 }
@@ -245,6 +232,7 @@ impl VerifiedBlock {
         BlockchainState {
             tip: self.header.clone(),
             utreexo: self.utreexo.clone(),
+            spent_anchors: self.spent_anchors.clone(),
         }
     }
 }
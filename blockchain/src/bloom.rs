@@ -0,0 +1,111 @@
+//! Bloom filter for remembering the transactions dropped from the mempool.
+//!
+//! Items are 32-byte hashes (e.g. transaction IDs) that are already uniformly distributed,
+//! so the bit indices are derived directly from the item using double hashing:
+//! `index_i = h1 + i·h2 mod m`, where `h1` and `h2` are the first two little-endian u64 words.
+//!
+//! For capacity `n` and a false positive rate `p`, the filter uses the optimal parameters:
+//! `m = -n·ln(p)/ln(2)^2` bits and `k = (m/n)·ln(2)` hash functions.
+//! The false positive rate `p` is achieved when the filter contains at most `n` items,
+//! and grows quickly once the filter is filled beyond its capacity.
+
+use core::f64::consts::LN_2;
+use serde::{Deserialize, Serialize};
+use zkvm::Hash;
+
+/// Bloom filter over 32-byte hashes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `capacity` items with the given false positive rate.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        // Clamp the rate to a meaningful range: the filter with `p >= 1` is useless,
+        // and `p <= 0` would require infinite memory.
+        let fp_rate = fp_rate.max(1e-9).min(0.5);
+
+        let num_bits = (-capacity * fp_rate.ln() / (LN_2 * LN_2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / capacity) * LN_2).round().max(1.0) as u32;
+        let words = ((num_bits + 63) / 64) as usize;
+        BloomFilter {
+            bits: vec![0u64; words],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Adds an item to the filter.
+    pub fn insert(&mut self, item: &Hash) {
+        for i in self.indices(item) {
+            self.bits[(i / 64) as usize] |= 1 << (i % 64);
+        }
+    }
+
+    /// Returns true if the item may be in the filter,
+    /// and false if it is definitely not in the filter.
+    pub fn contains(&self, item: &Hash) -> bool {
+        self.indices(item)
+            .all(|i| self.bits[(i / 64) as usize] & (1 << (i % 64)) != 0)
+    }
+
+    /// Removes all items from the filter.
+    pub fn clear(&mut self) {
+        for word in self.bits.iter_mut() {
+            *word = 0;
+        }
+    }
+
+    fn indices(&self, item: &Hash) -> impl Iterator<Item = u64> {
+        let mut h1 = [0u8; 8];
+        let mut h2 = [0u8; 8];
+        h1.copy_from_slice(&item.0[0..8]);
+        h2.copy_from_slice(&item.0[8..16]);
+        let h1 = u64::from_le_bytes(h1);
+        let h2 = u64::from_le_bytes(h2);
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    fn random_hash() -> Hash {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Hash(bytes)
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        let items: Vec<_> = (0..100).map(|_| random_hash()).collect();
+        for item in items.iter() {
+            filter.insert(item);
+        }
+        assert!(items.iter().all(|item| filter.contains(item)));
+
+        filter.clear();
+        assert!(items.iter().all(|item| !filter.contains(item)));
+    }
+
+    #[test]
+    fn false_positive_rate() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for _ in 0..1000 {
+            filter.insert(&random_hash());
+        }
+        let false_positives = (0..10000)
+            .filter(|_| filter.contains(&random_hash()))
+            .count();
+        // Expected ~100 false positives, allow for the statistical variance.
+        assert!(false_positives < 200);
+    }
+}
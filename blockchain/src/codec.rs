@@ -1,11 +1,13 @@
 use crate::shortid::ShortIDVec;
 use crate::{
-    Block, BlockHeader, BlockID, BlockTx, GetBlock, GetInventory, GetMempoolTxs, Inventory,
-    MempoolTxs, Message,
+    utreexo, Block, BlockHeader, BlockID, BlockTx, GetBlock, GetInventory, GetMempoolTxs,
+    Inventory, MempoolTxs, Message, Request, Response,
+};
+use readerwriter::{
+    Decodable, Encodable, ExactSizeEncodable, ReadError, Reader, WriteError, Writer,
 };
-use readerwriter::{Decodable, Encodable, ReadError, Reader, WriteError, Writer};
 use std::convert::TryFrom;
-use zkvm::{Hash, Signature};
+use zkvm::{ContractID, Hash, Signature};
 
 #[repr(u8)]
 enum MessageType {
@@ -15,6 +17,15 @@ enum MessageType {
     GetInventory = 3,
     MempoolTxs = 4,
     GetMempoolTxs = 5,
+    Request = 6,
+    Response = 7,
+}
+
+#[repr(u8)]
+enum RequestType {
+    GetHeaders = 0,
+    GetBlock = 1,
+    GetUtxoProof = 2,
 }
 
 impl TryFrom<u8> for MessageType {
@@ -28,6 +39,8 @@ impl TryFrom<u8> for MessageType {
             3 => Ok(MessageType::GetInventory),
             4 => Ok(MessageType::MempoolTxs),
             5 => Ok(MessageType::GetMempoolTxs),
+            6 => Ok(MessageType::Request),
+            7 => Ok(MessageType::Response),
             _ => Err(ReadError::Custom(
                 format!("unknown message type: {}", value).into(),
             )),
@@ -35,6 +48,96 @@ impl TryFrom<u8> for MessageType {
     }
 }
 
+impl TryFrom<u8> for RequestType {
+    type Error = ReadError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RequestType::GetHeaders),
+            1 => Ok(RequestType::GetBlock),
+            2 => Ok(RequestType::GetUtxoProof),
+            _ => Err(ReadError::Custom(
+                format!("unknown request type: {}", value).into(),
+            )),
+        }
+    }
+}
+
+impl Encodable for Request {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        match self {
+            Request::GetHeaders { from_height, count } => {
+                w.write_u8(b"request_type", RequestType::GetHeaders as u8)?;
+                w.write_u64(b"from_height", *from_height)?;
+                w.write_u64(b"count", *count)?;
+            }
+            Request::GetBlock(g) => {
+                w.write_u8(b"request_type", RequestType::GetBlock as u8)?;
+                w.write_u64(b"block_height", g.height)?;
+            }
+            Request::GetUtxoProof(contract_id) => {
+                w.write_u8(b"request_type", RequestType::GetUtxoProof as u8)?;
+                w.write(b"contract_id", contract_id.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Decodable for Request {
+    fn decode(buf: &mut impl Reader) -> Result<Self, ReadError> {
+        match RequestType::try_from(buf.read_u8()?)? {
+            RequestType::GetHeaders => Ok(Request::GetHeaders {
+                from_height: buf.read_u64()?,
+                count: buf.read_u64()?,
+            }),
+            RequestType::GetBlock => Ok(Request::GetBlock(GetBlock {
+                height: buf.read_u64()?,
+            })),
+            RequestType::GetUtxoProof => Ok(Request::GetUtxoProof(ContractID(buf.read_u8x32()?))),
+        }
+    }
+}
+
+impl Encodable for Response {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        // Responses use the same type bytes as the requests they answer.
+        match self {
+            Response::Headers(headers) => {
+                w.write_u8(b"response_type", RequestType::GetHeaders as u8)?;
+                w.write_u32(b"n", headers.len() as u32)?;
+                for header in headers.iter() {
+                    header.encode(w)?;
+                }
+            }
+            Response::Block(block) => {
+                w.write_u8(b"response_type", RequestType::GetBlock as u8)?;
+                w.write_option(block)?;
+            }
+            Response::UtxoProof(proof) => {
+                w.write_u8(b"response_type", RequestType::GetUtxoProof as u8)?;
+                w.write_option(proof)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Decodable for Response {
+    fn decode(buf: &mut impl Reader) -> Result<Self, ReadError> {
+        match RequestType::try_from(buf.read_u8()?)? {
+            RequestType::GetHeaders => {
+                let n = buf.read_u32()? as usize;
+                Ok(Response::Headers(buf.read_vec(n, BlockHeader::decode)?))
+            }
+            RequestType::GetBlock => Ok(Response::Block(buf.read_option(Block::decode)?)),
+            RequestType::GetUtxoProof => Ok(Response::UtxoProof(
+                buf.read_option(utreexo::Proof::decode)?,
+            )),
+        }
+    }
+}
+
 impl Encodable for Inventory {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
         w.write_u64(b"version", self.version)?;
@@ -58,6 +161,34 @@ impl Decodable for Inventory {
     }
 }
 
+impl Encodable for Block {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        self.header.encode(w)?;
+        w.write_signature(&self.signature)?;
+        write_block_txs(&self.txs, w)?;
+        Ok(())
+    }
+}
+
+impl ExactSizeEncodable for Block {
+    fn encoded_size(&self) -> usize {
+        self.header.encoded_size()
+            + 64
+            + 4
+            + self.txs.iter().map(|btx| btx.encoded_size()).sum::<usize>()
+    }
+}
+
+impl Decodable for Block {
+    fn decode(buf: &mut impl Reader) -> Result<Self, ReadError> {
+        Ok(Block {
+            header: BlockHeader::decode(buf)?,
+            signature: buf.read_signature()?,
+            txs: read_block_txs(buf)?,
+        })
+    }
+}
+
 fn read_block_txs(src: &mut impl Reader) -> Result<Vec<BlockTx>, ReadError> {
     let n = src.read_u32()? as usize;
     src.read_vec(n, BlockTx::decode)
@@ -90,6 +221,17 @@ trait ReaderExt: Reader + Sized {
     fn read_hash(&mut self) -> Result<Hash, ReadError> {
         self.read_u8x32().map(Hash)
     }
+
+    fn read_option<T>(
+        &mut self,
+        decode: impl FnOnce(&mut Self) -> Result<T, ReadError>,
+    ) -> Result<Option<T>, ReadError> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => decode(self).map(Some),
+            _ => Err(ReadError::InvalidFormat),
+        }
+    }
 }
 
 trait WriterExt: Writer + Sized {
@@ -122,6 +264,16 @@ trait WriterExt: Writer + Sized {
     fn write_hash(&mut self, label: &'static [u8], hash: &Hash) -> Result<(), WriteError> {
         self.write(label, hash.0.as_ref())
     }
+
+    fn write_option<T: Encodable>(&mut self, item: &Option<T>) -> Result<(), WriteError> {
+        match item {
+            None => self.write_u8(b"some", 0),
+            Some(item) => {
+                self.write_u8(b"some", 1)?;
+                item.encode(self)
+            }
+        }
+    }
 }
 
 impl<R: Reader> ReaderExt for R {}
@@ -130,20 +282,10 @@ impl<W: Writer> WriterExt for W {}
 
 impl Message {
     fn encode_block(b: &Block, dst: &mut impl Writer) -> Result<(), WriteError> {
-        BlockHeader::encode(&b.header, dst)?;
-        dst.write_signature(&b.signature)?;
-        write_block_txs(&b.txs, dst)?;
-        Ok(())
+        Block::encode(b, dst)
     }
     fn decode_block(src: &mut impl Reader) -> Result<Self, ReadError> {
-        let header = BlockHeader::decode(src)?;
-        let signature = src.read_signature()?;
-        let txs = read_block_txs(src)?;
-        Ok(Message::Block(Block {
-            header,
-            signature,
-            txs,
-        }))
+        Ok(Message::Block(Block::decode(src)?))
     }
 
     fn encode_get_block(g: &GetBlock, dst: &mut impl Writer) -> Result<(), WriteError> {
@@ -215,6 +357,8 @@ impl Decodable for Message {
             MessageType::GetInventory => Message::decode_get_inventory(src),
             MessageType::MempoolTxs => Message::decode_mempool_txs(src),
             MessageType::GetMempoolTxs => Message::decode_get_mempool_txs(src),
+            MessageType::Request => Ok(Message::Request(Request::decode(src)?)),
+            MessageType::Response => Ok(Message::Response(Response::decode(src)?)),
         }
     }
 }
@@ -251,6 +395,14 @@ impl Encodable for Message {
                 typ!(MessageType::GetMempoolTxs);
                 Self::encode_get_mempool_txs(g, dst)
             }
+            Message::Request(request) => {
+                typ!(MessageType::Request);
+                request.encode(dst)
+            }
+            Message::Response(response) => {
+                typ!(MessageType::Response);
+                response.encode(dst)
+            }
         }
     }
 }
@@ -318,6 +470,128 @@ mod tests {
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn block_roundtrip() {
+        let make_tx = |seed: u8, proofs: Vec<utreexo::Proof>| BlockTx {
+            tx: Tx {
+                header: TxHeader {
+                    version: 1,
+                    mintime_ms: seed as u64,
+                    maxtime_ms: u64::max_value(),
+                },
+                program: vec![seed; seed as usize],
+                signature: Signature {
+                    s: Scalar::from_bits([seed; 32]),
+                    R: CompressedRistretto([seed; 32]),
+                },
+                proof: R1CSProof::from_bytes(&[0; 1 + 15 * 32]).unwrap(),
+            },
+            proofs,
+        };
+        let block = Block {
+            header: BlockHeader {
+                version: 1,
+                height: 2,
+                prev: BlockID([3; 32]),
+                timestamp_ms: 4,
+                txroot: Hash([5; 32]),
+                utxoroot: Hash([6; 32]),
+                ext: vec![],
+            },
+            signature: Signature {
+                s: Scalar::from_bits([7; 32]),
+                R: CompressedRistretto([8; 32]),
+            },
+            txs: vec![
+                make_tx(10, vec![]),
+                make_tx(
+                    20,
+                    vec![
+                        utreexo::Proof::Committed(zkvm::merkle::Path {
+                            position: 21,
+                            neighbors: vec![Hash([22; 32]); 3],
+                        }),
+                        utreexo::Proof::Transient,
+                    ],
+                ),
+                make_tx(30, vec![utreexo::Proof::Transient]),
+            ],
+        };
+
+        let bytes = block.encode_to_vec();
+        assert_eq!(bytes.len(), block.encoded_size());
+        assert_eq!(bytes.len(), block.size());
+        for btx in block.txs() {
+            assert_eq!(btx.tx.encode_to_vec().len(), btx.tx.size());
+        }
+        let res = Block::decode(&mut bytes.as_slice()).unwrap();
+        assert_eq!(format!("{:?}", block), format!("{:?}", res));
+        assert_eq!(res.encode_to_vec(), bytes);
+
+        // Block message is encoded as the message type followed by the block.
+        let message_bytes = Message::Block(block).encode_to_vec();
+        assert_eq!(message_bytes[0], MessageType::Block as u8);
+        assert_eq!(&message_bytes[1..], &bytes[..]);
+
+        // Truncated block is rejected.
+        assert!(Block::decode(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn request_response_roundtrip() {
+        let header = BlockHeader {
+            version: 1,
+            height: 2,
+            prev: BlockID([3; 32]),
+            timestamp_ms: 4,
+            txroot: Hash([5; 32]),
+            utxoroot: Hash([6; 32]),
+            ext: vec![7; 3],
+        };
+        let messages = vec![
+            Message::Request(Request::GetHeaders {
+                from_height: 10,
+                count: 20,
+            }),
+            Message::Request(Request::GetBlock(GetBlock { height: 30 })),
+            Message::Request(Request::GetUtxoProof(ContractID([40; 32]))),
+            Message::Response(Response::Headers(vec![])),
+            Message::Response(Response::Headers(vec![header.clone(), header.clone()])),
+            Message::Response(Response::Block(None)),
+            Message::Response(Response::Block(Some(Block {
+                header,
+                signature: Signature {
+                    s: Scalar::from_bits([8; 32]),
+                    R: CompressedRistretto([9; 32]),
+                },
+                txs: vec![],
+            }))),
+            Message::Response(Response::UtxoProof(None)),
+            Message::Response(Response::UtxoProof(Some(utreexo::Proof::Transient))),
+            Message::Response(Response::UtxoProof(Some(utreexo::Proof::Committed(
+                zkvm::merkle::Path {
+                    position: 50,
+                    neighbors: vec![Hash([51; 32]), Hash([52; 32])],
+                },
+            )))),
+        ];
+        for message in messages {
+            let bytes = message.encode_to_vec();
+            let mut bytes_to_decode = bytes.as_slice();
+            let res = Message::decode(&mut bytes_to_decode).unwrap();
+            assert!(bytes_to_decode.is_empty());
+            assert_eq!(format!("{:?}", message), format!("{:?}", res));
+
+            // Truncated message is rejected.
+            assert!(Message::decode(&mut &bytes[..bytes.len() - 1]).is_err());
+        }
+
+        // Unknown request type is rejected.
+        assert!(Message::decode(&mut &[MessageType::Request as u8, 3][..]).is_err());
+        // Invalid option flag is rejected.
+        assert!(Message::decode(&mut &[MessageType::Response as u8, 1, 2][..]).is_err());
+    }
+
     #[test]
     fn message_get_block() {
         let message = Message::GetBlock(GetBlock { height: 30 });
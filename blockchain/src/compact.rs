@@ -0,0 +1,170 @@
+//! Compact block relay.
+//!
+//! Instead of sending all the transactions in a block, the node announces the block
+//! with short IDs of its transactions, and the receiver reconstructs the block
+//! from its mempool, requesting only the transactions it does not have.
+//!
+//! Short IDs are computed over the witness hashes of the transactions (`BlockTx::witness_hash`),
+//! so the transactions found in the mempool also match the utreexo proofs committed in the block.
+//! The short ID nonce is chosen by the sender, and the first 8 bytes of the block ID are used as a context.
+//! Since short IDs may collide, the receiver treats ambiguous IDs as missing
+//! and double-checks the reconstructed block against the `txroot` in its header.
+//!
+//! Based on [BIP-152](https://github.com/bitcoin/bips/blob/master/bip-0152.mediawiki).
+
+use serde::{Deserialize, Serialize};
+use starsig::Signature;
+use std::collections::{BTreeMap, HashMap};
+use zkvm::MerkleTree;
+
+use super::block::{BlockHeader, BlockTx};
+use super::mempool::Mempool;
+use super::protocol::Block;
+use super::shortid::{self, ShortID, ShortIDVec};
+
+/// Block announced with short IDs of its transactions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompactBlock {
+    pub(crate) header: BlockHeader,
+    pub(crate) signature: Signature,
+    pub(crate) shortid_nonce: u64,
+    pub(crate) shortid_list: ShortIDVec,
+    pub(crate) prefilled_txs: BTreeMap<usize, BlockTx>,
+}
+
+/// Transactions that could not be found while reconstructing the compact block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingTxs {
+    indices: Vec<usize>,
+    shortid_list: ShortIDVec,
+}
+
+impl CompactBlock {
+    /// Creates a compact block with short IDs of all the transactions in the block.
+    pub fn from_block(block: &Block, shortid_nonce: u64) -> Self {
+        let transform = shortid_transform(&block.header, shortid_nonce);
+        let mut shortid_list = ShortIDVec::with_capacity(block.txs.len());
+        for block_tx in block.txs.iter() {
+            shortid_list.push(transform.apply(block_tx.witness_hash()));
+        }
+        CompactBlock {
+            header: block.header.clone(),
+            signature: block.signature,
+            shortid_nonce,
+            shortid_list,
+            prefilled_txs: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the block header.
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// Number of transactions in the block.
+    pub fn len(&self) -> usize {
+        self.shortid_list.len()
+    }
+
+    /// Returns true if the block has no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds a full transaction at a given index in the block:
+    /// either by the sender for the transactions the receiver is unlikely to have,
+    /// or by the receiver once it has fetched the missing transactions.
+    /// Returns `false` if the index is out of bounds.
+    pub fn prefill(&mut self, index: usize, block_tx: BlockTx) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        self.prefilled_txs.insert(index, block_tx);
+        true
+    }
+
+    /// Reconstructs the block using the prefilled transactions and the transactions from the mempool.
+    /// Returns the list of transactions to be requested from the sender if some are missing,
+    /// their short IDs collide, or the reconstructed block does not match the header.
+    pub fn reconstruct(&self, mempool: &Mempool) -> Result<Block, MissingTxs> {
+        let transform = shortid_transform(&self.header, self.shortid_nonce);
+
+        // Short IDs matching more than one transaction are ambiguous and cannot be used.
+        let mut candidates: HashMap<ShortID, Option<&BlockTx>> = HashMap::new();
+        for entry in mempool.entries() {
+            candidates
+                .entry(transform.apply(entry.block_tx().witness_hash()))
+                .and_modify(|tx| *tx = None)
+                .or_insert(Some(entry.block_tx()));
+        }
+        let mut counts: HashMap<ShortID, usize> = HashMap::new();
+        for id in self.shortid_list.iter() {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+
+        let mut txs = Vec::with_capacity(self.len());
+        let mut from_mempool = Vec::new();
+        let mut missing = Vec::new();
+        for (i, id) in self.shortid_list.iter().enumerate() {
+            if let Some(block_tx) = self.prefilled_txs.get(&i) {
+                txs.push(block_tx.clone());
+                continue;
+            }
+            match candidates.get(&id) {
+                Some(Some(block_tx)) if counts[&id] == 1 => {
+                    txs.push((*block_tx).clone());
+                    from_mempool.push(i);
+                }
+                _ => missing.push(i),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(MissingTxs::new(missing, &self.shortid_list));
+        }
+
+        // Collision with a transaction that is not in the block cannot be detected by short IDs alone,
+        // so we check the txroot and request all the transactions taken from the mempool if it does not match.
+        let mut txroot_builder = MerkleTree::build_root(b"ZkVM.txroot");
+        for block_tx in txs.iter() {
+            txroot_builder.append(&block_tx.witness_hash());
+        }
+        if self.header.txroot != txroot_builder.root() {
+            return Err(MissingTxs::new(from_mempool, &self.shortid_list));
+        }
+
+        Ok(Block {
+            header: self.header.clone(),
+            signature: self.signature,
+            txs,
+        })
+    }
+}
+
+impl MissingTxs {
+    fn new(indices: Vec<usize>, shortid_list: &ShortIDVec) -> Self {
+        let mut missing_list = ShortIDVec::with_capacity(indices.len());
+        for i in indices.iter() {
+            if let Some(id) = shortid_list.get(*i) {
+                missing_list.push(id);
+            }
+        }
+        MissingTxs {
+            indices,
+            shortid_list: missing_list,
+        }
+    }
+
+    /// Positions of the missing transactions in the block.
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    /// Short IDs of the missing transactions.
+    pub fn shortid_list(&self) -> &ShortIDVec {
+        &self.shortid_list
+    }
+}
+
+fn shortid_transform(header: &BlockHeader, shortid_nonce: u64) -> shortid::Transform {
+    shortid::Transform::new(shortid_nonce, &header.id()[..8])
+}
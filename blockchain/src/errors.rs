@@ -34,6 +34,22 @@ pub enum BlockchainError {
     #[error("Utreexo operation failed.")]
     UtreexoError(UtreexoError),
 
+    /// Occurs when the transaction spends unconfirmed outputs that are not in the mempool yet.
+    #[error("Transaction is an orphan: some of its parents are not in the mempool.")]
+    OrphanTx,
+
+    /// Occurs when the transaction was recently dropped from the mempool.
+    #[error("Transaction was recently dropped from the mempool.")]
+    RecentlyEvicted,
+
+    /// Occurs when the transaction's anchor was already used by a recently confirmed transaction.
+    #[error("Transaction anchor was already used.")]
+    AnchorReplayed,
+
+    /// Occurs when the block is rejected by the custom validation policy.
+    #[error("Block is rejected by the validation policy: {0}")]
+    PolicyViolation(String),
+
     /// Block signature is invalid.
     #[error("Block signature is invalid.")]
     InvalidBlockSignature,
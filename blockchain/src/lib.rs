@@ -8,19 +8,28 @@ extern crate zkvm;
 extern crate starsig;
 
 mod block;
+mod bloom;
 mod codec;
+mod compact;
 mod errors;
 mod mempool;
+mod policy;
 mod protocol;
 mod shortid;
 mod state;
+#[cfg(feature = "testing")]
+mod testing;
 pub mod utreexo;
 
 #[cfg(test)]
 mod tests;
 
 pub use self::block::*;
+pub use self::compact::*;
 pub use self::errors::*;
 pub use self::mempool::*;
+pub use self::policy::*;
 pub use self::protocol::*;
 pub use self::state::*;
+#[cfg(feature = "testing")]
+pub use self::testing::*;
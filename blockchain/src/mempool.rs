@@ -1,15 +1,22 @@
 //! Super-simple mempool implementation.
+use core::cmp::Ordering;
 use core::mem;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::{ContractID, MerkleTree, Tx, TxEntry, TxID, TxLog, VerifiedTx};
+use zkvm::{ContractID, FeeRate, Hash, MerkleTree, Tx, TxEntry, TxID, TxLog, VerifiedTx};
 
 use super::block::{BlockHeader, BlockTx, VerifiedBlock};
+use super::bloom::BloomFilter;
 use super::errors::BlockchainError;
 use super::state::{check_tx_header, BlockchainState};
 use super::utreexo::{self, utreexo_hasher, Catchup};
 
+/// Maximum number of orphan transactions kept in the mempool.
+/// When the limit is reached, the least recently used orphans are evicted.
+pub const MAX_ORPHAN_TXS: usize = 100;
+
 /// Implements a pool of unconfirmed (not-in-the-block) transactions.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Mempool {
@@ -17,8 +24,65 @@ pub struct Mempool {
     timestamp_ms: u64,
     work_utreexo: utreexo::WorkForest,
     entries: Vec<MempoolEntry>,
+    txids: HashSet<Hash>,
+    orphans: HashMap<Hash, Orphan>,
+    orphans_by_input: HashMap<ContractID, Vec<Hash>>,
+    orphans_seq: u64,
+    config: MempoolConfig,
+    evicted: EvictedTxs,
+    #[serde(skip)]
+    on_evict: EvictionCallback,
+}
+
+/// Configuration of the mempool.
+///
+/// The mempool remembers the IDs of the transactions it has dropped
+/// (confirmed, conflicting or expired, as well as invalid or expired orphans) in a pair of bloom filters,
+/// so that such transactions are rejected without an expensive verification.
+/// Every `bloom_reset_interval_ms` the older filter is cleared and the filters are swapped,
+/// so a dropped transaction is remembered for at least one and at most two intervals.
+///
+/// Each filter achieves the `bloom_fp_rate` only while it contains at most `bloom_capacity`
+/// transactions, and a lookup checks both filters, so the combined false positive rate
+/// is at most `2·bloom_fp_rate`. With the default settings (0.5% per filter) it stays below 1%
+/// as long as fewer than `bloom_capacity` transactions are dropped per interval.
+/// Each filter takes approximately `-bloom_capacity·ln(bloom_fp_rate)/ln(2)^2` bits of memory,
+/// that is about 14 KB for the default settings.
+///
+/// Orphans received from a peer (see `Mempool::append_from_peer`) are limited
+/// to `max_orphans_per_peer` transactions per peer, so that a single peer cannot push out
+/// the orphans received from the others. When a peer exceeds its limit,
+/// its least recently used orphan is evicted.
+/// Orphans whose parents do not arrive within `max_orphan_age_ms` are evicted as well.
+/// Orphans evicted due to the limits are not remembered in the bloom filters,
+/// since they are still valid and may be relayed again when their parents arrive.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MempoolConfig {
+    /// Number of dropped transactions each bloom filter is sized for.
+    pub bloom_capacity: usize,
+    /// False positive rate of each bloom filter when filled up to its capacity.
+    pub bloom_fp_rate: f64,
+    /// Interval between the resets of the bloom filters, in milliseconds.
+    pub bloom_reset_interval_ms: u64,
+    /// Maximum number of orphan transactions kept for a single peer (at least 1).
+    pub max_orphans_per_peer: usize,
+    /// Time an orphan transaction waits for its parents before it is evicted, in milliseconds.
+    pub max_orphan_age_ms: u64,
 }
 
+/// Pair of bloom filters remembering the IDs of the transactions dropped from the mempool.
+#[derive(Clone, Serialize, Deserialize)]
+struct EvictedTxs {
+    current: BloomFilter,
+    previous: BloomFilter,
+    last_reset_ms: u64,
+}
+
+/// Callback invoked for each transaction dropped from the mempool.
+/// It is not serialized, and is not inherited by the clones of the mempool.
+#[derive(Default)]
+struct EvictionCallback(Option<Box<dyn FnMut(&MempoolEntry) + Send>>);
+
 /// Tx item stored in the mempool
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MempoolEntry {
@@ -26,6 +90,47 @@ pub struct MempoolEntry {
     verified_tx: VerifiedTx,
 }
 
+/// Verified transaction that spends outputs of unconfirmed transactions
+/// that are not in the mempool yet.
+#[derive(Clone, Serialize, Deserialize)]
+struct Orphan {
+    missing_inputs: Vec<ContractID>,
+    entry: MempoolEntry,
+    /// Identifier of the peer that sent the transaction, if any.
+    peer: Option<Vec<u8>>,
+    /// Timestamp of the mempool when the orphan was received.
+    received_ms: u64,
+    /// Sequence number of the last announcement of the orphan, to evict the least recently used ones.
+    last_seen: u64,
+}
+
+/// Read-only summary of a transaction in the mempool.
+#[derive(Copy, Clone, Debug)]
+pub struct MempoolTxView {
+    /// Transaction ID.
+    pub id: TxID,
+    /// Size of the transaction in bytes.
+    pub size: usize,
+    /// Fee rate of the transaction alone.
+    pub feerate: FeeRate,
+    /// Combined fee rate of the transaction and all its unconfirmed ancestors.
+    pub effective_feerate: FeeRate,
+    /// Number of mempool transactions whose outputs are spent by this transaction.
+    pub unconfirmed_parents: usize,
+}
+
+impl MempoolTxView {
+    /// Compares the transactions by priority: the one with a higher effective feerate comes first,
+    /// and the ties are broken by the transaction ID, so the order does not depend
+    /// on when the transactions were received.
+    pub fn cmp_priority(&self, other: &Self) -> Ordering {
+        other
+            .effective_feerate
+            .cmp(&self.effective_feerate)
+            .then_with(|| (self.id.0).0.cmp(&(other.id.0).0))
+    }
+}
+
 impl MempoolEntry {
     /// Returns transaction log.
     pub fn txlog(&self) -> &TxLog {
@@ -58,37 +163,223 @@ impl MempoolEntry {
     }
 }
 
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            bloom_capacity: 10_000,
+            bloom_fp_rate: 0.005,
+            bloom_reset_interval_ms: 24 * 3600 * 1000,
+            max_orphans_per_peer: 25,
+            max_orphan_age_ms: 20 * 60 * 1000,
+        }
+    }
+}
+
+impl EvictionCallback {
+    fn notify(&mut self, entry: &MempoolEntry) {
+        if let Some(callback) = self.0.as_mut() {
+            callback(entry);
+        }
+    }
+}
+
+impl Clone for EvictionCallback {
+    fn clone(&self) -> Self {
+        EvictionCallback(None)
+    }
+}
+
+impl EvictedTxs {
+    fn new(config: &MempoolConfig, timestamp_ms: u64) -> Self {
+        EvictedTxs {
+            current: BloomFilter::new(config.bloom_capacity, config.bloom_fp_rate),
+            previous: BloomFilter::new(config.bloom_capacity, config.bloom_fp_rate),
+            last_reset_ms: timestamp_ms,
+        }
+    }
+
+    fn insert(&mut self, txid: &TxID) {
+        self.current.insert(&txid.0);
+    }
+
+    fn contains(&self, txid: &TxID) -> bool {
+        self.current.contains(&txid.0) || self.previous.contains(&txid.0)
+    }
+
+    /// Clears the older filter when the reset interval has passed.
+    fn update_timestamp(&mut self, timestamp_ms: u64, interval_ms: u64) {
+        let elapsed_ms = timestamp_ms.saturating_sub(self.last_reset_ms);
+        if elapsed_ms < interval_ms {
+            return;
+        }
+        mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+        if elapsed_ms >= interval_ms.saturating_mul(2) {
+            // both filters are outdated
+            self.previous.clear();
+        }
+        self.last_reset_ms = timestamp_ms;
+    }
+}
+
 impl Mempool {
-    /// Creates an empty mempool at a given state.
+    /// Creates an empty mempool at a given state with the default configuration.
     pub fn new(state: BlockchainState, timestamp_ms: u64) -> Self {
+        Self::with_config(state, timestamp_ms, MempoolConfig::default())
+    }
+
+    /// Creates an empty mempool at a given state with a custom configuration.
+    pub fn with_config(state: BlockchainState, timestamp_ms: u64, config: MempoolConfig) -> Self {
         let work_utreexo = state.utreexo.work_forest();
         Mempool {
             state,
             timestamp_ms,
             work_utreexo,
             entries: Vec::new(),
+            txids: HashSet::new(),
+            orphans: HashMap::new(),
+            orphans_by_input: HashMap::new(),
+            orphans_seq: 0,
+            evicted: EvictedTxs::new(&config, timestamp_ms),
+            on_evict: EvictionCallback::default(),
+            config,
         }
     }
 
+    /// Sets the callback to be invoked for each transaction dropped from the mempool,
+    /// so that its creator can re-broadcast it or bump its fee.
+    ///
+    /// The callback is invoked for the transactions that became invalid after the state or timestamp update
+    /// (including the ones that were confirmed or double-spent in a block, and all their descendants),
+    /// as well as for the orphans that expired, could not be applied or were pushed out by the newer ones.
+    /// The callback replaces the previously set one, and is not copied into the clones of the mempool.
+    pub fn on_evict(&mut self, callback: Box<dyn FnMut(&MempoolEntry) + Send>) {
+        self.on_evict = EvictionCallback(Some(callback));
+    }
+
+    /// Returns the configuration of the mempool.
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+
     /// Returns a list of transactions.
     pub fn entries(&self) -> impl Iterator<Item = &MempoolEntry> {
         self.entries.iter()
     }
 
+    /// Iterates over the summaries of the transactions in the order they were added.
+    /// Does not modify the mempool.
+    pub fn iter(&self) -> impl Iterator<Item = MempoolTxView> {
+        // Entries are stored after their parents, so we compute the ancestors in one pass.
+        let mut producers: HashMap<ContractID, usize> = HashMap::new();
+        let mut ancestors: Vec<BTreeSet<usize>> = Vec::with_capacity(self.entries.len());
+        let mut views = Vec::with_capacity(self.entries.len());
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let parents: BTreeSet<usize> = entry
+                .txlog()
+                .inputs()
+                .filter_map(|cid| producers.get(cid).copied())
+                .collect();
+            let mut tx_ancestors = parents.clone();
+            for parent in parents.iter() {
+                tx_ancestors.extend(ancestors[*parent].iter().copied());
+            }
+
+            let feerate = entry.verified_tx.feerate;
+            let effective_feerate = Self::effective_feerate(
+                feerate,
+                tx_ancestors
+                    .iter()
+                    .map(|a| self.entries[*a].verified_tx.feerate),
+            );
+
+            views.push(MempoolTxView {
+                id: entry.txid(),
+                size: entry.tx().size(),
+                feerate,
+                effective_feerate,
+                unconfirmed_parents: parents.len(),
+            });
+
+            for output in entry.txlog().outputs() {
+                producers.insert(output.id(), i);
+            }
+            ancestors.push(tx_ancestors);
+        }
+        views.into_iter()
+    }
+
+    /// Returns true if the transaction with a given fee rate, combined with the fee rates
+    /// of its unconfirmed ancestors, has at least the minimum fee rate.
+    /// This is how the mempool evaluates the packages of a parent and a child paying for it (CPFP).
+    pub fn is_feerate_sufficient(
+        feerate: FeeRate,
+        ancestors: impl IntoIterator<Item = FeeRate>,
+        min_feerate: FeeRate,
+    ) -> bool {
+        Self::effective_feerate(feerate, ancestors) >= min_feerate
+    }
+
+    /// Combines the fee rate of a transaction with the fee rates of its unconfirmed ancestors.
+    fn effective_feerate(
+        feerate: FeeRate,
+        ancestors: impl IntoIterator<Item = FeeRate>,
+    ) -> FeeRate {
+        ancestors
+            .into_iter()
+            .fold(feerate, |rate, ancestor| rate.combine(ancestor))
+    }
+
+    /// Iterates over the summaries of the transactions from the highest to the lowest priority
+    /// (see `MempoolTxView::cmp_priority`). The order is the same on all nodes
+    /// with the same set of transactions, but children may come before their parents.
+    pub fn iter_by_priority(&self) -> impl Iterator<Item = MempoolTxView> {
+        let mut views: Vec<_> = self.iter().collect();
+        views.sort_by(|a, b| a.cmp_priority(b));
+        views.into_iter()
+    }
+
     /// Returns the size of the mempool in number of transactions.
     pub fn len(&self) -> usize {
         self.entries.len()
     }
 
+    /// Returns true if the transaction with a given ID is in the mempool.
+    /// Orphan transactions are not considered.
+    pub fn contains(&self, txid: &TxID) -> bool {
+        self.txids.contains(&txid.0)
+    }
+
+    /// Returns the number of orphan transactions waiting for their parents.
+    pub fn orphans_len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Returns the number of orphan transactions received from a given peer.
+    pub fn orphans_len_for_peer(&self, peer: &[u8]) -> usize {
+        self.orphans
+            .values()
+            .filter(|orphan| orphan.peer.as_ref().map(|p| &p[..]) == Some(peer))
+            .count()
+    }
+
+    /// Returns true if the transaction with a given ID was recently dropped from the mempool.
+    /// May return true for a transaction that was never dropped
+    /// with the probability configured in `MempoolConfig`.
+    pub fn recently_evicted(&self, txid: &TxID) -> bool {
+        self.evicted.contains(txid)
+    }
+
     /// Updates timestamp and re-applies txs to filter out the outdated ones.
     pub fn update_timestamp(&mut self, timestamp_ms: u64) {
-        self.timestamp_ms = timestamp_ms;
+        self.set_timestamp(timestamp_ms);
         self.update_mempool(None);
     }
 
     /// Updates the state of the blockchain and removes conflicting transactions.
     pub fn update_state(&mut self, state: BlockchainState, catchup: &Catchup) {
-        self.timestamp_ms = state.tip.timestamp_ms;
+        self.set_timestamp(state.tip.timestamp_ms);
         self.state = state;
         self.update_mempool(Some(catchup));
     }
@@ -97,11 +388,39 @@ impl Mempool {
     /// Returns the reference to the stored mempool entry.
     /// If a duplicate is detected (by TxID), no changes are made and the corresponding entry
     /// is returned to the caller.
+    ///
+    /// If the tx spends unconfirmed outputs that are not in the mempool,
+    /// it is kept as an orphan and `BlockchainError::OrphanTx` is returned.
+    /// Orphans are added to the mempool as soon as all the missing parents arrive.
+    ///
+    /// If the tx was recently dropped from the mempool, `BlockchainError::RecentlyEvicted`
+    /// is returned before the expensive verification.
     /// FIXME: If tx is double-spending, detect it before doing the expensive r1cs validation.
     pub fn append(
         &mut self,
         block_tx: BlockTx,
         bp_gens: &BulletproofGens,
+    ) -> Result<&MempoolEntry, BlockchainError> {
+        self.append_tx(block_tx, None, bp_gens)
+    }
+
+    /// Adds transaction received from a given peer to the mempool and verifies it.
+    /// Behaves like `append`, but if the tx is kept as an orphan, it counts towards
+    /// the peer's limit `MempoolConfig::max_orphans_per_peer`.
+    pub fn append_from_peer(
+        &mut self,
+        block_tx: BlockTx,
+        peer: &[u8],
+        bp_gens: &BulletproofGens,
+    ) -> Result<&MempoolEntry, BlockchainError> {
+        self.append_tx(block_tx, Some(peer), bp_gens)
+    }
+
+    fn append_tx(
+        &mut self,
+        block_tx: BlockTx,
+        peer: Option<&[u8]>,
+        bp_gens: &BulletproofGens,
     ) -> Result<&MempoolEntry, BlockchainError> {
         // 1. Check the header
         check_tx_header(
@@ -113,16 +432,25 @@ impl Mempool {
         // 2. Precompute the transaction
         let precomputed_tx = block_tx.tx.precompute()?;
 
-        // 3. Check if this transaction already exists in the mempool.
+        // 3. Check if this transaction already exists in the mempool before expensive r1cs verification.
         //    If it does, simply return the reference to its entry.
-        // TODO: use a faster way to index existing transactions and do this check before expensive r1cs verification.
-        if let Some(existing_entry_index) = self
-            .entries
-            .iter()
-            .position(|entry| entry.verified_tx.id == precomputed_tx.id)
-        {
+        if self.contains(&precomputed_tx.id) {
+            let existing_entry_index = self
+                .entries
+                .iter()
+                .position(|entry| entry.verified_tx.id == precomputed_tx.id)
+                .expect("Index of txids is in sync with the entries");
             return Ok(&self.entries[existing_entry_index]);
         }
+        let seq = self.next_orphan_seq();
+        if let Some(orphan) = self.orphans.get_mut(&precomputed_tx.id.0) {
+            // Mark the orphan as recently used.
+            orphan.last_seen = seq;
+            return Err(BlockchainError::OrphanTx);
+        }
+        if self.recently_evicted(&precomputed_tx.id) {
+            return Err(BlockchainError::RecentlyEvicted);
+        }
 
         // 4. TODO: before verifying the transaction, immutably check if it can be applied to the mempool
         // to prevent double spends before expensive verification happens.
@@ -130,17 +458,40 @@ impl Mempool {
         // 5. Verify the tx
         let verified_tx = precomputed_tx.verify(bp_gens)?;
 
-        // 6. Apply to the state
-        self.apply_tx(&verified_tx.log, &block_tx.proofs, None)?;
-
-        // 7. Save in the list
-        self.entries.push(MempoolEntry {
+        let entry = MempoolEntry {
             block_tx,
             verified_tx,
-        });
+        };
+
+        // 6. Stash the tx if some of its parents are not in the mempool yet.
+        let missing_inputs = self.missing_inputs(&entry);
+        if !missing_inputs.is_empty() {
+            let evicted = self.park_orphan(Orphan {
+                missing_inputs,
+                entry,
+                peer: peer.map(|p| p.to_vec()),
+                received_ms: self.timestamp_ms,
+                last_seen: seq,
+            });
+            for entry in evicted.iter() {
+                self.on_evict.notify(entry);
+            }
+            return Err(BlockchainError::OrphanTx);
+        }
+
+        // 7. Apply to the state
+        self.apply_tx(&entry.verified_tx.log, &entry.block_tx.proofs, None)?;
+
+        // 8. Save in the list
+        let index = self.entries.len();
+        self.txids.insert(entry.txid().0);
+        self.entries.push(entry);
 
-        // 8. Return the reference to the entry we've just added.
-        Ok(self.entries.last().unwrap())
+        // 9. Add the orphans that were waiting for this transaction.
+        self.promote_orphans(index);
+
+        // 10. Return the reference to the entry we've just added.
+        Ok(&self.entries[index])
     }
 
     /// Creates a new verified block using the current set of transactions.
@@ -164,21 +515,207 @@ impl Mempool {
             ext: Vec::new(),
         };
 
+        let mut spent_anchors = self.state.spent_anchors.clone();
+        spent_anchors
+            .add_block(
+                new_header.height,
+                self.entries().filter_map(|e| e.txlog().anchor()),
+            )
+            .expect("Mempool rejects the transactions with the spent anchors");
+
         VerifiedBlock {
             header: new_header,
             utreexo: new_forest,
             catchup: new_catchup,
+            spent_anchors,
             raw_txs: self.entries().map(|e| e.block_tx()).cloned().collect(),
             verified_txs: self.entries().map(|e| e.verified_tx()).cloned().collect(),
         }
     }
 
+    /// Returns the IDs of the unconfirmed inputs that are not produced by the mempool transactions.
+    fn missing_inputs(&self, entry: &MempoolEntry) -> Vec<ContractID> {
+        let transient_inputs = entry
+            .txlog()
+            .inputs()
+            .zip(entry.utxo_proofs().iter())
+            .filter(|(_, proof)| match proof {
+                utreexo::Proof::Transient => true,
+                utreexo::Proof::Committed(_) => false,
+            })
+            .map(|(cid, _)| *cid)
+            .collect::<Vec<_>>();
+        if transient_inputs.is_empty() {
+            return transient_inputs;
+        }
+        let unconfirmed_outputs = self
+            .entries
+            .iter()
+            .flat_map(|e| e.txlog().outputs().map(|c| c.id()))
+            .collect::<HashSet<_>>();
+        transient_inputs
+            .into_iter()
+            .filter(|cid| !unconfirmed_outputs.contains(cid))
+            .collect()
+    }
+
+    /// Stores the orphan, evicting the least recently used orphans of the same peer
+    /// and of the whole mempool if the limits are exceeded.
+    /// Returns the evicted transactions.
+    fn park_orphan(&mut self, orphan: Orphan) -> Vec<MempoolEntry> {
+        let mut evicted = Vec::new();
+        if let Some(peer) = orphan.peer.as_ref() {
+            let max_orphans = self.config.max_orphans_per_peer.max(1);
+            let mut peer_orphans = self.orphans_len_for_peer(peer);
+            while peer_orphans >= max_orphans {
+                let txid = self
+                    .least_recently_used_orphan(Some(peer))
+                    .expect("Peer has at least one orphan");
+                evicted.extend(self.remove_orphan(&txid).map(|o| o.entry));
+                peer_orphans -= 1;
+            }
+        }
+        if self.orphans.len() >= MAX_ORPHAN_TXS {
+            if let Some(txid) = self.least_recently_used_orphan(None) {
+                evicted.extend(self.remove_orphan(&txid).map(|o| o.entry));
+            }
+        }
+        let txid = orphan.entry.txid().0;
+        for cid in orphan.missing_inputs.iter() {
+            self.orphans_by_input.entry(*cid).or_default().push(txid);
+        }
+        self.orphans.insert(txid, orphan);
+        evicted
+    }
+
+    /// Returns the ID of the least recently used orphan of a given peer, or of all the orphans.
+    fn least_recently_used_orphan(&self, peer: Option<&Vec<u8>>) -> Option<Hash> {
+        self.orphans
+            .iter()
+            .filter(|(_, orphan)| peer.is_none() || orphan.peer.as_ref() == peer)
+            .min_by_key(|(_, orphan)| orphan.last_seen)
+            .map(|(txid, _)| *txid)
+    }
+
+    /// Removes the orphan and its entries in the index of the missing inputs.
+    fn remove_orphan(&mut self, txid: &Hash) -> Option<Orphan> {
+        let orphan = self.orphans.remove(txid)?;
+        for cid in orphan.missing_inputs.iter() {
+            if let Some(waiting) = self.orphans_by_input.get_mut(cid) {
+                waiting.retain(|id| id != txid);
+                if waiting.is_empty() {
+                    self.orphans_by_input.remove(cid);
+                }
+            }
+        }
+        Some(orphan)
+    }
+
+    fn next_orphan_seq(&mut self) -> u64 {
+        self.orphans_seq += 1;
+        self.orphans_seq
+    }
+
+    /// Moves into the mempool the orphans that were waiting for the outputs of the entry
+    /// at a given index, and then the orphans waiting for the outputs of the promoted ones.
+    /// Orphans that cannot be applied (e.g. double-spends) are dropped and remembered as evicted.
+    fn promote_orphans(&mut self, index: usize) {
+        let mut new_outputs = self.entries[index]
+            .txlog()
+            .outputs()
+            .map(|c| c.id())
+            .collect::<VecDeque<_>>();
+
+        while let Some(cid) = new_outputs.pop_front() {
+            for txid in self.orphans_by_input.remove(&cid).unwrap_or_default() {
+                let ready = match self.orphans.get_mut(&txid) {
+                    Some(orphan) => {
+                        orphan.missing_inputs.retain(|input| input != &cid);
+                        orphan.missing_inputs.is_empty()
+                    }
+                    None => false,
+                };
+                if !ready {
+                    continue;
+                }
+                let entry = self
+                    .orphans
+                    .remove(&txid)
+                    .expect("Orphan is in the index")
+                    .entry;
+                let result = check_tx_header(
+                    &entry.block_tx.tx.header,
+                    self.timestamp_ms,
+                    self.state.tip.version,
+                )
+                .and_then(|_| self.apply_tx(&entry.verified_tx.log, &entry.block_tx.proofs, None));
+                if result.is_err() {
+                    self.evict(&entry);
+                } else if !self.txids.contains(&entry.txid().0) {
+                    new_outputs.extend(entry.txlog().outputs().map(|c| c.id()));
+                    self.txids.insert(entry.txid().0);
+                    self.entries.push(entry);
+                }
+            }
+        }
+    }
+
+    /// Forgets the orphans that can no longer be included (remembering them as evicted),
+    /// and the ones that waited for their parents for too long.
+    fn expire_orphans(&mut self) {
+        let expired = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| {
+                check_tx_header(
+                    &orphan.entry.block_tx.tx.header,
+                    self.timestamp_ms,
+                    self.state.tip.version,
+                )
+                .is_err()
+            })
+            .map(|(txid, _)| *txid)
+            .collect::<Vec<_>>();
+        for txid in expired {
+            if let Some(orphan) = self.remove_orphan(&txid) {
+                self.evict(&orphan.entry);
+            }
+        }
+
+        let max_age_ms = self.config.max_orphan_age_ms;
+        let timestamp_ms = self.timestamp_ms;
+        let outdated = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| timestamp_ms.saturating_sub(orphan.received_ms) > max_age_ms)
+            .map(|(txid, _)| *txid)
+            .collect::<Vec<_>>();
+        for txid in outdated {
+            if let Some(orphan) = self.remove_orphan(&txid) {
+                self.on_evict.notify(&orphan.entry);
+            }
+        }
+    }
+
+    /// Remembers the dropped transaction and notifies the observer.
+    fn evict(&mut self, entry: &MempoolEntry) {
+        self.evicted.insert(&entry.txid());
+        self.on_evict.notify(entry);
+    }
+
+    fn set_timestamp(&mut self, timestamp_ms: u64) {
+        self.timestamp_ms = timestamp_ms;
+        self.evicted
+            .update_timestamp(timestamp_ms, self.config.bloom_reset_interval_ms);
+    }
+
     fn update_mempool(&mut self, catchup: Option<&Catchup>) {
         // reset the utreexo to the original state
         self.work_utreexo = self.state.utreexo.work_forest();
 
         // extract old
         let old_entries = mem::replace(&mut self.entries, Vec::new());
+        self.txids.clear();
 
         for entry in old_entries.into_iter() {
             let result = check_tx_header(
@@ -189,9 +726,14 @@ impl Mempool {
             .and_then(|_| self.apply_tx(&entry.verified_tx.log, &entry.block_tx.proofs, catchup));
             if result.is_ok() {
                 // put the entry back into the mempool if it's still valid
+                self.txids.insert(entry.txid().0);
                 self.entries.push(entry);
+            } else {
+                self.evict(&entry);
             }
         }
+
+        self.expire_orphans();
     }
 
     fn apply_tx(
@@ -200,6 +742,12 @@ impl Mempool {
         utxo_proofs: &[utreexo::Proof],
         catchup: Option<&Catchup>,
     ) -> Result<(), BlockchainError> {
+        if let Some(anchor) = txlog.anchor() {
+            if self.state.spent_anchors.contains(&anchor) {
+                return Err(BlockchainError::AnchorReplayed);
+            }
+        }
+
         // Update block makes sure the that if half of tx fails, all changes are undone.
         self.work_utreexo
             .batch(|wf| {
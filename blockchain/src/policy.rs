@@ -0,0 +1,31 @@
+//! Pluggable rules for accepting blocks.
+//! Policy checks are performed in addition to the consensus rules
+//! and allow networks to use different parameters without changing the state machine.
+
+use super::block::{BlockHeader, BlockTx};
+use super::errors::BlockchainError;
+
+/// Custom acceptance rules for the blocks received from the network.
+/// All checks are performed before the transactions are verified.
+/// Default implementations accept everything, preserving the consensus-only behavior.
+pub trait BlockValidationPolicy {
+    /// Checks the new block header against the header of the current tip.
+    fn check_header(
+        &self,
+        _header: &BlockHeader,
+        _prev: &BlockHeader,
+    ) -> Result<(), BlockchainError> {
+        Ok(())
+    }
+
+    /// Checks the transactions of the new block.
+    fn check_txs(&self, _header: &BlockHeader, _txs: &[BlockTx]) -> Result<(), BlockchainError> {
+        Ok(())
+    }
+}
+
+/// Policy that does not add any rules on top of the consensus rules.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DefaultPolicy;
+
+impl BlockValidationPolicy for DefaultPolicy {}
@@ -15,13 +15,15 @@ use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use starsig::{Signature, SigningKey, VerificationKey};
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::ContractID;
+use zkvm::encoding::ExactSizeEncodable;
+use zkvm::{ContractID, VerifiedTx};
 
 use super::block::{BlockHeader, BlockID, BlockTx, VerifiedBlock};
 use super::errors::BlockchainError;
-use super::mempool::Mempool;
+use super::mempool::{Mempool, MempoolConfig, MempoolEntry};
+use super::policy::{BlockValidationPolicy, DefaultPolicy};
 use super::shortid::{self, ShortIDVec};
-use super::state::BlockchainState;
+use super::state::{verify_block_txs, BlockchainState};
 use super::utreexo;
 
 /// Current version of the sync protocol.
@@ -30,6 +32,9 @@ const CURRENT_VERSION: u64 = 0;
 /// Number of sync cycles after which the ShortID nonce is rotated.
 const SHORTID_NONCE_TTL: usize = 50;
 
+/// Maximum number of headers returned in response to `Request::GetHeaders`.
+pub const MAX_HEADERS_PER_REQUEST: u64 = 2000;
+
 /// Enumeration of all protocol messages
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Message {
@@ -39,6 +44,32 @@ pub enum Message {
     Block(Block),
     GetMempoolTxs(GetMempoolTxs),
     MempoolTxs(MempoolTxs),
+    Request(Request),
+    Response(Response),
+}
+
+/// Request to a peer sent over the p2p RPC, which matches the `Response` by the request ID.
+/// See [`RequestHandler::respond`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Request {
+    /// Headers of the consecutive blocks starting at a given height.
+    /// At most `MAX_HEADERS_PER_REQUEST` headers are returned.
+    GetHeaders { from_height: u64, count: u64 },
+    /// Block at a given height.
+    GetBlock(GetBlock),
+    /// Utreexo proof of the unspent output.
+    GetUtxoProof(ContractID),
+}
+
+/// Response to the `Request`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// Headers of the blocks known to the peer: empty if the peer does not have the first block.
+    Headers(Vec<BlockHeader>),
+    /// Requested block, if the peer has it.
+    Block(Option<Block>),
+    /// Proof of the unspent output, if the peer has it.
+    UtxoProof(Option<utreexo::Proof>),
 }
 
 /// Request for the state of the node.
@@ -72,6 +103,59 @@ pub struct Block {
     pub(crate) txs: Vec<BlockTx>,
 }
 
+impl GetBlock {
+    /// Creates a request for a block at a given height.
+    pub fn new(height: u64) -> Self {
+        GetBlock { height }
+    }
+}
+
+impl Block {
+    /// Creates a block message from the header, its signature and the transactions.
+    pub fn new(header: BlockHeader, signature: Signature, txs: Vec<BlockTx>) -> Self {
+        Block {
+            header,
+            signature,
+            txs,
+        }
+    }
+
+    /// Returns true if the block header is signed with the network key.
+    pub fn verify_signature(&self, network_pubkey: VerificationKey) -> bool {
+        verify_block_signature(&self.header, &self.signature, network_pubkey)
+    }
+
+    /// Returns the block header.
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// Returns the signature of the block header.
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Returns the transactions of the block.
+    pub fn txs(&self) -> &[BlockTx] {
+        &self.txs
+    }
+
+    /// Returns the size of the block message in bytes:
+    /// the header, the signature and the transactions with their utreexo proofs.
+    pub fn size(&self) -> usize {
+        self.encoded_size()
+    }
+
+    /// Verifies the transactions against the block header without using the blockchain state.
+    /// See [`verify_block_txs`] for details.
+    pub fn verify_txs(
+        &self,
+        bp_gens: &BulletproofGens,
+    ) -> Result<Vec<VerifiedTx>, BlockchainError> {
+        verify_block_txs(&self.header, &self.txs, bp_gens)
+    }
+}
+
 /// Request for mempool txs
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GetMempoolTxs {
@@ -112,6 +196,12 @@ pub trait Delegate {
     /// Returns a block at a given height
     fn block_at_height(&self, height: u64) -> Option<Block>;
 
+    /// Returns the utreexo proof of the unspent output that verifies against the current tip.
+    /// Default implementation returns None, since the node is not required to keep the utreexo trees.
+    fn utxo_proof(&self, _contract_id: &ContractID) -> Option<utreexo::Proof> {
+        None
+    }
+
     /// Blockchain state
     fn blockchain_state(&self) -> &BlockchainState;
 
@@ -120,6 +210,45 @@ pub trait Delegate {
     fn store_block(&mut self, verified_block: VerifiedBlock, signature: Signature);
 }
 
+/// Answers the `Request`s received from the peers over the RPC.
+/// Implementors provide the blockchain data, and the default `respond` builds the `Response`.
+pub trait RequestHandler {
+    /// Returns current height of the chain.
+    fn tip_height(&self) -> u64;
+
+    /// Returns the header of the block at a given height.
+    fn header_at_height(&self, height: u64) -> Option<BlockHeader>;
+
+    /// Returns a block at a given height.
+    fn block_at_height(&self, height: u64) -> Option<Block>;
+
+    /// Returns the utreexo proof of the unspent output that verifies against the current tip.
+    fn utxo_proof(&self, contract_id: &ContractID) -> Option<utreexo::Proof>;
+
+    /// Answers the request received from a peer.
+    /// Headers are returned up to the first one that is not available.
+    fn respond(&self, request: Request) -> Response {
+        match request {
+            Request::GetHeaders { from_height, count } => {
+                let tip_height = self.tip_height();
+                Response::Headers(
+                    (from_height..)
+                        .take(count.min(MAX_HEADERS_PER_REQUEST) as usize)
+                        .take_while(|height| *height <= tip_height)
+                        .map(|height| self.header_at_height(height))
+                        .take_while(Option::is_some)
+                        .flatten()
+                        .collect(),
+                )
+            }
+            Request::GetBlock(request) => Response::Block(self.block_at_height(request.height)),
+            Request::GetUtxoProof(contract_id) => {
+                Response::UtxoProof(self.utxo_proof(&contract_id))
+            }
+        }
+    }
+}
+
 pub struct BlockchainProtocol<D: Delegate> {
     network_pubkey: VerificationKey,
     delegate: D,
@@ -130,6 +259,7 @@ pub struct BlockchainProtocol<D: Delegate> {
     mempool: Mempool,
     bp_gens: BulletproofGens,
     inventory_interval_secs: u64,
+    policy: Box<dyn BlockValidationPolicy>,
 }
 
 /// Status of the peer.
@@ -157,6 +287,7 @@ impl<D: Delegate> BlockchainProtocol<D> {
             shortid_nonce: thread_rng().gen::<u64>(),
             shortid_nonce_ttl: SHORTID_NONCE_TTL,
             inventory_interval_secs: 60,
+            policy: Box::new(DefaultPolicy),
         }
     }
 
@@ -167,6 +298,33 @@ impl<D: Delegate> BlockchainProtocol<D> {
         self
     }
 
+    /// Sets the custom policy for accepting blocks received from the peers.
+    /// By default, only the consensus rules are checked.
+    pub fn set_validation_policy(mut self, policy: impl BlockValidationPolicy + 'static) -> Self {
+        self.policy = Box::new(policy);
+        self
+    }
+
+    /// Sets the configuration of the mempool.
+    /// The mempool is reset, so this must be called before receiving any transactions
+    /// and before setting the eviction callback.
+    pub fn set_mempool_config(mut self, config: MempoolConfig) -> Self {
+        let state = self.delegate.blockchain_state().clone();
+        let timestamp_ms = state.tip.timestamp_ms;
+        self.mempool = Mempool::with_config(state, timestamp_ms, config);
+        self
+    }
+
+    /// Sets the callback invoked for each transaction dropped from the mempool.
+    /// See [`Mempool::on_evict`] for details.
+    pub fn set_eviction_callback(
+        mut self,
+        callback: impl FnMut(&MempoolEntry) + Send + 'static,
+    ) -> Self {
+        self.mempool.on_evict(Box::new(callback));
+        self
+    }
+
     /// Creates a new network.
     pub fn new_network<I>(
         network_signing_key: SigningKey,
@@ -194,7 +352,10 @@ impl<D: Delegate> BlockchainProtocol<D> {
             Message::GetBlock(request) => self.send_block(pid, request).await?,
             Message::Block(block_msg) => self.receive_block(block_msg)?,
             Message::GetMempoolTxs(request) => self.send_txs(pid, request).await,
-            Message::MempoolTxs(request) => self.receive_txs(request).await?,
+            Message::MempoolTxs(request) => self.receive_txs(pid, request).await?,
+            // RPC messages are matched by the request IDs in the transport layer:
+            // requests are answered with `respond`, and responses go to the requester.
+            Message::Request(_) | Message::Response(_) => {}
         }
         Ok(())
     }
@@ -305,6 +466,26 @@ impl<D: Delegate> BlockchainProtocol<D> {
     }
 }
 
+impl<D: Delegate> RequestHandler for BlockchainProtocol<D> {
+    fn tip_height(&self) -> u64 {
+        self.delegate.tip_height()
+    }
+
+    fn header_at_height(&self, height: u64) -> Option<BlockHeader> {
+        self.delegate
+            .block_at_height(height)
+            .map(|block| block.header)
+    }
+
+    fn block_at_height(&self, height: u64) -> Option<Block> {
+        self.delegate.block_at_height(height)
+    }
+
+    fn utxo_proof(&self, contract_id: &ContractID) -> Option<utreexo::Proof> {
+        self.delegate.utxo_proof(contract_id)
+    }
+}
+
 impl<D: Delegate> BlockchainProtocol<D> {
     async fn synchronize_chain(&mut self) {
         use rand::seq::IteratorRandom;
@@ -470,8 +651,12 @@ impl<D: Delegate> BlockchainProtocol<D> {
 
         // Now the block header is authenticated, so we can do a more expensive validation.
         let state = self.delegate.blockchain_state();
-        let verified_block =
-            state.apply_block(block_msg.header.clone(), &block_msg.txs, &self.bp_gens)?;
+        let verified_block = state.apply_block_with_policy(
+            block_msg.header.clone(),
+            &block_msg.txs,
+            &self.bp_gens,
+            self.policy.as_ref(),
+        )?;
 
         // Update the mempool.
         self.mempool
@@ -505,17 +690,27 @@ impl<D: Delegate> BlockchainProtocol<D> {
         self.delegate.send(pid, Message::MempoolTxs(response)).await;
     }
 
-    async fn receive_txs(&mut self, request: MempoolTxs) -> Result<(), BlockchainError> {
+    async fn receive_txs(
+        &mut self,
+        pid: D::PeerIdentifier,
+        request: MempoolTxs,
+    ) -> Result<(), BlockchainError> {
         if request.tip != self.delegate.tip_id() {
             return Err(BlockchainError::StaleMempoolState(request.tip));
         }
 
         for tx in request.txs.into_iter() {
-            let result = self.mempool.append(tx, &self.bp_gens);
+            let result = self
+                .mempool
+                .append_from_peer(tx, pid.as_ref(), &self.bp_gens);
             if let Err(err) = result {
                 if let BlockchainError::UtreexoError(_) = err {
                     // Two nodes may have sent us double-spends, w/o being aware of them.
                     // that's not their fault.
+                } else if let BlockchainError::OrphanTx = err {
+                    // Parents may arrive later from another peer.
+                } else if let BlockchainError::RecentlyEvicted = err {
+                    // Peers may not have learned yet that the tx was dropped.
                 } else {
                     // Stop processing all remaining txs - the node is sending us garbage.
                     return Err(err);
@@ -551,13 +746,14 @@ impl<D: Delegate> BlockchainProtocol<D> {
 }
 
 /// Signs a block.
-fn create_block_signature(header: &BlockHeader, privkey: SigningKey) -> Signature {
+pub fn create_block_signature(header: &BlockHeader, privkey: SigningKey) -> Signature {
     let mut t = Transcript::new(b"ZkVM.stubnet1");
     t.append_message(b"block_id", &header.id());
     Signature::sign(&mut t, privkey)
 }
 
-fn verify_block_signature(
+/// Verifies the block signature against the network key.
+pub fn verify_block_signature(
     header: &BlockHeader,
     signature: &Signature,
     pubkey: VerificationKey,
@@ -1,18 +1,57 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 
 use super::block::{BlockHeader, BlockTx, VerifiedBlock};
 use super::errors::BlockchainError;
+use super::policy::{BlockValidationPolicy, DefaultPolicy};
 use crate::utreexo::{self, utreexo_hasher, Forest};
 use zkvm::bulletproofs::BulletproofGens;
-use zkvm::{ContractID, MerkleTree, TxEntry, TxHeader};
+use zkvm::{Anchor, ContractID, MerkleTree, TxEntry, TxHeader, VerifiedTx};
+
+/// Number of the latest blocks whose transaction anchors are remembered in `SpentAnchors`.
+pub const SPENT_ANCHORS_WINDOW: u64 = 1000;
 
 /// State of the blockchain node.
+///
+/// In addition to the utreexo state, the node keeps the anchors (see `TxLog::anchor`)
+/// of the transactions confirmed in the last `SPENT_ANCHORS_WINDOW` blocks
+/// and rejects a transaction whose anchor is among them.
+/// See the "Spent anchors" section of the blockchain spec for how this interacts
+/// with the ratcheted anchors of the `input` instruction.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct BlockchainState {
     /// Latest block header in the chain.
     pub tip: BlockHeader,
     /// The utreexo state.
     pub utreexo: Forest,
+    /// Anchors of the recently confirmed transactions.
+    #[serde(default)]
+    pub spent_anchors: SpentAnchors,
+}
+
+/// Anchors of the transactions confirmed in the last `SPENT_ANCHORS_WINDOW` blocks.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpentAnchors {
+    /// Anchors confirmed by each of the remembered blocks, oldest block first.
+    blocks: VecDeque<(u64, Vec<Anchor>)>,
+    /// All the anchors in `blocks`.
+    anchors: HashSet<Anchor>,
+}
+
+/// Copy of the blockchain state that can be restored later,
+/// e.g. when switching to a competing chain.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    tip: BlockHeader,
+    utreexo: Forest,
+    spent_anchors: SpentAnchors,
+}
+
+impl StateSnapshot {
+    /// Returns the tip of the saved state.
+    pub fn tip(&self) -> &BlockHeader {
+        &self.tip
+    }
 }
 
 impl BlockchainState {
@@ -41,45 +80,77 @@ impl BlockchainState {
                 .collect::<Vec<_>>();
 
         let tip = BlockHeader::make_initial(timestamp_ms, utreexo.root(&hasher));
-        (BlockchainState { tip, utreexo }, proofs)
+        let state = BlockchainState {
+            tip,
+            utreexo,
+            spent_anchors: SpentAnchors::default(),
+        };
+        (state, proofs)
+    }
+
+    /// Saves the current state so it can be restored with `rollback_to`.
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            tip: self.tip.clone(),
+            utreexo: self.utreexo.clone(),
+            spent_anchors: self.spent_anchors.clone(),
+        }
+    }
+
+    /// Restores the state saved in the snapshot.
+    pub fn rollback_to(&mut self, snapshot: StateSnapshot) {
+        self.tip = snapshot.tip;
+        self.utreexo = snapshot.utreexo;
+        self.spent_anchors = snapshot.spent_anchors;
+    }
+
+    /// Switches to the state produced by the verified block
+    /// and returns the snapshot of the previous state that undoes the change.
+    /// The block must be obtained via `apply_block` on the current state.
+    pub fn advance(&mut self, verified_block: &VerifiedBlock) -> StateSnapshot {
+        let undo = self.snapshot();
+        self.tip = verified_block.header.clone();
+        self.utreexo = verified_block.utreexo.clone();
+        self.spent_anchors = verified_block.spent_anchors.clone();
+        undo
     }
 
     /// Applies the block to the current state and returns a new one.
+    /// The current state is not modified: use `advance` to switch to the new state.
     pub fn apply_block(
         &self,
         block_header: BlockHeader,
         block_txs: &[BlockTx],
         bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedBlock, BlockchainError> {
+        self.apply_block_with_policy(block_header, block_txs, bp_gens, &DefaultPolicy)
+    }
+
+    /// Applies the block to the current state and returns a new one,
+    /// checking the block against the custom policy in addition to the consensus rules.
+    pub fn apply_block_with_policy(
+        &self,
+        block_header: BlockHeader,
+        block_txs: &[BlockTx],
+        bp_gens: &BulletproofGens,
+        policy: &dyn BlockValidationPolicy,
     ) -> Result<VerifiedBlock, BlockchainError> {
         check_block_header(&block_header, &self.tip)?;
+        policy.check_header(&block_header, &self.tip)?;
+        policy.check_txs(&block_header, block_txs)?;
 
-        let mut txroot_builder = MerkleTree::build_root(b"ZkVM.txroot");
-        for block_tx in block_txs.iter() {
-            // Check that tx header is consistent with the version / timestamp.
-            check_tx_header(
-                &block_tx.tx.header,
-                block_header.timestamp_ms,
-                block_header.version,
-            )?;
-
-            // Compute the commitment to all txs in a block.
-            txroot_builder.append(&block_tx.witness_hash());
-        }
+        let verified_txs = verify_block_txs(&block_header, block_txs, bp_gens)?;
 
-        // Check the txroot commitment
-        if block_header.txroot != txroot_builder.root() {
-            return Err(BlockchainError::InconsistentHeader);
-        }
+        // Reject the replayed transactions
+        let mut spent_anchors = self.spent_anchors.clone();
+        spent_anchors.add_block(
+            block_header.height,
+            verified_txs.iter().filter_map(|tx| tx.log.anchor()),
+        )?;
 
-        // At this point we know that we have all tx data authenticated w.r.t. to the origin of the block,
-        // so we can perform more expensive verification steps.
         let mut work_forest = self.utreexo.work_forest();
         let utxo_hasher = utreexo_hasher::<ContractID>();
-        let mut verified_txs = Vec::with_capacity(block_txs.len());
-        for block_tx in block_txs.iter() {
-            // TODO: this is a great place to do batch verification of signatures and bulletproofs.
-            let verified_tx = block_tx.tx.verify(bp_gens)?;
-
+        for (block_tx, verified_tx) in block_txs.iter().zip(verified_txs.iter()) {
             let mut utreexo_proofs = block_tx.proofs.iter();
 
             // Apply tx to the state
@@ -101,8 +172,6 @@ impl BlockchainState {
                     _ => {}
                 }
             }
-
-            verified_txs.push(verified_tx);
         }
 
         let (new_forest, new_catchup) = work_forest.normalize(&utxo_hasher);
@@ -117,12 +186,89 @@ impl BlockchainState {
             header: block_header,
             utreexo: new_forest,
             catchup: new_catchup,
+            spent_anchors,
             raw_txs: block_txs.iter().cloned().collect(),
             verified_txs: verified_txs,
         })
     }
 }
 
+impl SpentAnchors {
+    /// Returns true if the anchor was used by a transaction confirmed in the remembered blocks.
+    pub fn contains(&self, anchor: &Anchor) -> bool {
+        self.anchors.contains(anchor)
+    }
+
+    /// Remembers the anchors of the transactions confirmed by the block at a given height
+    /// and forgets the blocks that fall out of the `SPENT_ANCHORS_WINDOW`.
+    /// Fails if any of the anchors is already remembered, leaving the set partially updated.
+    pub fn add_block(
+        &mut self,
+        height: u64,
+        anchors: impl IntoIterator<Item = Anchor>,
+    ) -> Result<(), BlockchainError> {
+        let mut block_anchors = Vec::new();
+        for anchor in anchors {
+            if !self.anchors.insert(anchor) {
+                return Err(BlockchainError::AnchorReplayed);
+            }
+            block_anchors.push(anchor);
+        }
+        self.blocks.push_back((height, block_anchors));
+
+        while let Some((oldest_height, _)) = self.blocks.front() {
+            if oldest_height + SPENT_ANCHORS_WINDOW > height {
+                break;
+            }
+            if let Some((_, expired)) = self.blocks.pop_front() {
+                for anchor in expired.iter() {
+                    self.anchors.remove(anchor);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the transactions in a block without using the blockchain state:
+/// checks the block header fields, the tx headers, the txroot commitment
+/// and the proofs and signatures of all transactions.
+/// Does not check that the inputs exist in the UTXO set,
+/// nor the utxoroot and the link to the previous block.
+pub fn verify_block_txs(
+    block_header: &BlockHeader,
+    block_txs: &[BlockTx],
+    bp_gens: &BulletproofGens,
+) -> Result<Vec<VerifiedTx>, BlockchainError> {
+    check_block_header_fields(block_header)?;
+
+    let mut txroot_builder = MerkleTree::build_root(b"ZkVM.txroot");
+    for block_tx in block_txs.iter() {
+        // Check that tx header is consistent with the version / timestamp.
+        check_tx_header(
+            &block_tx.tx.header,
+            block_header.timestamp_ms,
+            block_header.version,
+        )?;
+
+        // Compute the commitment to all txs in a block.
+        txroot_builder.append(&block_tx.witness_hash());
+    }
+
+    // Check the txroot commitment
+    if block_header.txroot != txroot_builder.root() {
+        return Err(BlockchainError::InconsistentHeader);
+    }
+
+    // At this point we know that we have all tx data authenticated w.r.t. to the origin of the block,
+    // so we can perform more expensive verification steps.
+    block_txs
+        .iter()
+        // TODO: this is a great place to do batch verification of signatures and bulletproofs.
+        .map(|block_tx| block_tx.tx.verify(bp_gens).map_err(BlockchainError::from))
+        .collect()
+}
+
 /// Checks the tx header for consistency with the block version and the timestamp.
 pub fn check_tx_header(
     tx_header: &TxHeader,
@@ -154,12 +300,7 @@ fn check_block_header(
         block_header.version >= prev_header.version,
         BlockchainError::InconsistentHeader,
     )?;
-    if block_header.version == 1 {
-        check(
-            block_header.ext.len() == 0,
-            BlockchainError::IllegalExtension,
-        )?;
-    }
+    check_block_header_fields(block_header)?;
     check(
         block_header.height == prev_header.height + 1,
         BlockchainError::InconsistentHeader,
@@ -175,6 +316,17 @@ fn check_block_header(
     Ok(())
 }
 
+/// Verifies block header fields that do not depend on the previous header.
+fn check_block_header_fields(block_header: &BlockHeader) -> Result<(), BlockchainError> {
+    if block_header.version == 1 {
+        check(
+            block_header.ext.len() == 0,
+            BlockchainError::IllegalExtension,
+        )?;
+    }
+    Ok(())
+}
+
 #[inline]
 fn check<E>(cond: bool, err: E) -> Result<(), E> {
     if !cond {
@@ -0,0 +1,222 @@
+//! In-memory blockchain for testing the transaction flows end-to-end.
+//!
+//! `TestChain` creates a genesis state with funded outputs, accepts transactions
+//! into its mempool, mines blocks and keeps track of the unspent outputs
+//! together with their utreexo proofs, so the tests can build new spends.
+//! Available with the `testing` feature.
+
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{
+    Anchor, ClearValue, Commitment, Contract, ContractID, Multisignature, PortableItem, Predicate,
+    Program, Prover, Signature, TxHeader, TxID, VMError, Value, VerificationKey,
+};
+
+use curve25519_dalek::scalar::Scalar;
+
+use super::block::{BlockTx, VerifiedBlock};
+use super::errors::BlockchainError;
+use super::mempool::Mempool;
+use super::state::BlockchainState;
+use super::utreexo::{self, utreexo_hasher};
+
+/// In-memory blockchain with a single node that mines blocks on demand.
+pub struct TestChain {
+    state: BlockchainState,
+    mempool: Mempool,
+    bp_gens: BulletproofGens,
+    utxos: Vec<TestUtxo>,
+}
+
+/// Unspent output tracked by the `TestChain`.
+#[derive(Clone, Debug)]
+pub struct TestUtxo {
+    /// Output contract. Contains the openings of the value commitments
+    /// if the transaction was built by the `TestChain`.
+    pub contract: Contract,
+    /// Utreexo proof of the output. Transient until the output is confirmed.
+    pub proof: utreexo::Proof,
+    /// True if the output was confirmed in a block.
+    pub confirmed: bool,
+}
+
+impl TestChain {
+    /// Creates a new chain with the initial outputs holding the given values.
+    pub fn new(values: impl IntoIterator<Item = (Predicate, ClearValue)>) -> Self {
+        let mut anchor = Anchor::from_raw_bytes([0; 32]);
+        let contracts: Vec<_> = values
+            .into_iter()
+            .map(|(predicate, value)| {
+                anchor = anchor.ratchet();
+                Contract {
+                    predicate,
+                    payload: vec![PortableItem::Value(Value {
+                        qty: Commitment::blinded(value.qty),
+                        flv: Commitment::blinded(value.flv),
+                    })],
+                    anchor,
+                }
+            })
+            .collect();
+        let (state, proofs) = BlockchainState::make_initial(0u64, contracts.iter().map(|c| c.id()));
+        let utxos = contracts
+            .into_iter()
+            .zip(proofs.into_iter())
+            .map(|(contract, proof)| TestUtxo {
+                contract,
+                proof,
+                confirmed: true,
+            })
+            .collect();
+
+        TestChain {
+            mempool: Mempool::new(state.clone(), state.tip.timestamp_ms),
+            state,
+            bp_gens: BulletproofGens::new(256, 1),
+            utxos,
+        }
+    }
+
+    /// Returns the current blockchain state.
+    pub fn state(&self) -> &BlockchainState {
+        &self.state
+    }
+
+    /// Returns the mempool with the transactions waiting to be mined.
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    /// Returns the generators used to prove and verify the transactions.
+    pub fn bp_gens(&self) -> &BulletproofGens {
+        &self.bp_gens
+    }
+
+    /// Iterates over all the unspent outputs, including the unconfirmed ones.
+    pub fn utxos(&self) -> impl Iterator<Item = &TestUtxo> {
+        self.utxos.iter()
+    }
+
+    /// Returns the unspent output with a given ID.
+    pub fn utxo(&self, contract_id: &ContractID) -> Option<&TestUtxo> {
+        self.utxos.iter().find(|u| &u.contract.id() == contract_id)
+    }
+
+    /// Returns the total quantity of a given flavor in the unspent outputs locked by the predicate,
+    /// including the unconfirmed ones. Outputs without the openings of the commitments are not counted.
+    pub fn balance(&self, predicate: &Predicate, flv: Scalar) -> u64 {
+        self.utxos
+            .iter()
+            .filter(|u| &u.contract.predicate == predicate)
+            .flat_map(|u| u.contract.values())
+            .filter_map(|v| v.assignment())
+            .filter(|(_, f)| *f == flv)
+            .filter_map(|(q, _)| q.to_u64())
+            .sum()
+    }
+
+    /// Builds the transaction with the given program, signs it with the matching keys
+    /// and adds it to the mempool. Contracts spent by the program must be tracked by the chain.
+    /// Fails with `VMError::WitnessMissing` if a signing key is not provided.
+    pub fn push_tx(&mut self, program: Program, keys: &[Scalar]) -> Result<TxID, BlockchainError> {
+        let header = TxHeader {
+            version: 1u64,
+            mintime_ms: 0u64,
+            maxtime_ms: u64::max_value(),
+        };
+        let utx = Prover::build_tx(program, header, &self.bp_gens)?;
+
+        let signing_keys = utx
+            .signing_instructions
+            .iter()
+            .map(|(predicate, _)| {
+                keys.iter()
+                    .find(|k| VerificationKey::from_secret(k) == predicate.verification_key())
+                    .copied()
+                    .ok_or(VMError::WitnessMissing)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut signtx_transcript = utx.signing_transcript();
+        let signature = Signature::sign_multi(
+            signing_keys,
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &mut signtx_transcript,
+        )
+        .expect("Each signing instruction has a key.");
+
+        let proofs = utx
+            .txlog
+            .inputs()
+            .map(|cid| {
+                self.utxo(cid)
+                    .map(|u| u.proof.clone())
+                    .ok_or(BlockchainError::UtreexoProofMissing)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Outputs in the prover's log contain the openings of the commitments.
+        let outputs: Vec<Contract> = utx.txlog.outputs().cloned().collect();
+        let block_tx = BlockTx {
+            tx: utx.sign(signature),
+            proofs,
+        };
+        self.append(block_tx, Some(outputs))
+    }
+
+    /// Adds the signed transaction to the mempool.
+    pub fn push_block_tx(&mut self, block_tx: BlockTx) -> Result<TxID, BlockchainError> {
+        self.append(block_tx, None)
+    }
+
+    /// Mines a block with all the transactions in the mempool,
+    /// and updates the utreexo proofs of the unspent outputs.
+    pub fn mine_block(&mut self) -> Result<VerifiedBlock, BlockchainError> {
+        self.mempool
+            .update_timestamp(self.state.tip.timestamp_ms + 1);
+        let header = self.mempool.make_block().header;
+        let txs: Vec<_> = self
+            .mempool
+            .entries()
+            .map(|e| e.block_tx().clone())
+            .collect();
+        let block = self.state.apply_block(header, &txs, &self.bp_gens)?;
+
+        let hasher = utreexo_hasher::<ContractID>();
+        for utxo in self.utxos.iter_mut() {
+            let proof = utxo.proof.clone();
+            utxo.proof = block
+                .catchup
+                .update_proof(&utxo.contract.id(), proof, &hasher)?;
+            utxo.confirmed = match utxo.proof {
+                utreexo::Proof::Committed(_) => true,
+                utreexo::Proof::Transient => false,
+            };
+        }
+
+        self.state = block.blockchain_state();
+        self.mempool
+            .update_state(self.state.clone(), &block.catchup);
+        Ok(block)
+    }
+
+    fn append(
+        &mut self,
+        block_tx: BlockTx,
+        outputs: Option<Vec<Contract>>,
+    ) -> Result<TxID, BlockchainError> {
+        let entry = self.mempool.append(block_tx, &self.bp_gens)?;
+        let txid = entry.txid();
+        let spent: Vec<ContractID> = entry.txlog().inputs().copied().collect();
+        let outputs = outputs.unwrap_or_else(|| entry.txlog().outputs().cloned().collect());
+
+        self.utxos.retain(|u| !spent.contains(&u.contract.id()));
+        self.utxos
+            .extend(outputs.into_iter().map(|contract| TestUtxo {
+                contract,
+                proof: utreexo::Proof::Transient,
+                confirmed: false,
+            }));
+        Ok(txid)
+    }
+}
@@ -2,11 +2,13 @@ use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use rand::RngCore;
 use zkvm::bulletproofs::BulletproofGens;
+use zkvm::encoding::ExactSizeEncodable;
 
 use super::*;
 use zkvm::{
-    Anchor, Commitment, Contract, ContractID, Multisignature, PortableItem, Predicate, Program,
-    Prover, Signature, String, TxHeader, Value, VerificationKey,
+    Anchor, ClearValue, Commitment, Contract, ContractID, Hash, Multisignature, PortableItem,
+    Predicate, Program, Prover, Signature, String, TxHeader, VMError, Value, VerificationKey,
+    DEFAULT_BUDGET,
 };
 
 fn make_predicate(privkey: impl Into<Scalar>) -> Predicate {
@@ -130,6 +132,862 @@ fn test_state_machine() {
     );
 }
 
+#[test]
+fn replayed_anchor() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (tx, _) = dummy_tx(utxo, &bp_gens);
+    let anchor = tx
+        .tx
+        .verify(&bp_gens)
+        .unwrap()
+        .log
+        .anchor()
+        .expect("Tx must have an input");
+
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool
+        .append(tx.clone(), &bp_gens)
+        .expect("Tx must be valid");
+    let made_block = mempool.make_block();
+    assert!(made_block.spent_anchors.contains(&anchor));
+    let header = made_block.header;
+    let block = state
+        .apply_block(header.clone(), &[tx.clone()], &bp_gens)
+        .expect("Block must be valid");
+    let confirmed_state = block.blockchain_state();
+    assert!(confirmed_state.spent_anchors.contains(&anchor));
+
+    // The same tx is replayed against a fresh utreexo state that contains its input.
+    let (mut fresh_state, _) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+    fresh_state.spent_anchors = confirmed_state.spent_anchors.clone();
+    match fresh_state.apply_block(header, &[tx.clone()], &bp_gens) {
+        Err(BlockchainError::AnchorReplayed) => {}
+        _ => panic!("Replayed tx must be rejected"),
+    }
+    match Mempool::new(fresh_state, 42).append(tx, &bp_gens) {
+        Err(BlockchainError::AnchorReplayed) => {}
+        _ => panic!("Replayed tx must be rejected by the mempool"),
+    }
+}
+
+#[test]
+fn spent_anchors_window() {
+    let anchor = Anchor::from_raw_bytes([1u8; 32]);
+    let mut spent_anchors = SpentAnchors::default();
+    spent_anchors.add_block(2, vec![anchor]).unwrap();
+    assert!(spent_anchors.contains(&anchor));
+
+    // Anchors cannot repeat within the window, including the same block.
+    match spent_anchors.clone().add_block(3, vec![anchor]) {
+        Err(BlockchainError::AnchorReplayed) => {}
+        _ => panic!("Repeated anchor must be rejected"),
+    }
+    let other = Anchor::from_raw_bytes([2u8; 32]);
+    match spent_anchors.clone().add_block(3, vec![other, other]) {
+        Err(BlockchainError::AnchorReplayed) => {}
+        _ => panic!("Repeated anchor must be rejected"),
+    }
+
+    // The anchor is forgotten once its block falls out of the window.
+    spent_anchors
+        .add_block(1 + SPENT_ANCHORS_WINDOW, vec![])
+        .unwrap();
+    assert!(spent_anchors.contains(&anchor));
+    spent_anchors
+        .add_block(2 + SPENT_ANCHORS_WINDOW, vec![])
+        .unwrap();
+    assert!(!spent_anchors.contains(&anchor));
+}
+
+#[test]
+fn stateless_block_verification() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (tx1, utxo) = dummy_tx(utxo, &bp_gens);
+    let (tx2, _) = dummy_tx(utxo, &bp_gens);
+
+    let mut mempool = Mempool::new(state, 42);
+    for tx in vec![tx1.clone(), tx2.clone()] {
+        mempool.append(tx, &bp_gens).expect("Tx must be valid");
+    }
+    let header = mempool.make_block().header;
+    let make_block = |header: BlockHeader, txs: Vec<BlockTx>| Block {
+        header,
+        signature: Signature {
+            s: Scalar::zero(),
+            R: Default::default(),
+        },
+        txs,
+    };
+
+    // Utreexo proofs and the utxoroot are not checked.
+    let mut unrelated_utxoroot = header.clone();
+    unrelated_utxoroot.utxoroot = Hash([0u8; 32]);
+    let verified_txs = make_block(unrelated_utxoroot, vec![tx1.clone(), tx2.clone()])
+        .verify_txs(&bp_gens)
+        .expect("Block txs must be valid");
+    assert_eq!(verified_txs.len(), 2);
+    assert_eq!(verified_txs[0].id, tx1.tx.precompute().unwrap().id);
+    assert_eq!(verified_txs[1].id, tx2.tx.precompute().unwrap().id);
+
+    // Txs must match the txroot.
+    for txs in vec![vec![tx1.clone()], vec![tx2.clone(), tx1.clone()]] {
+        match make_block(header.clone(), txs).verify_txs(&bp_gens) {
+            Err(BlockchainError::InconsistentHeader) => {}
+            _ => panic!("Block txs must not match the txroot"),
+        }
+    }
+
+    // Txs must be valid w.r.t. the header timestamp.
+    let mut expired = header.clone();
+    expired.timestamp_ms = u64::max_value();
+    let mut expired_tx = tx1.clone();
+    expired_tx.tx.header.maxtime_ms = 100;
+    match make_block(expired, vec![expired_tx]).verify_txs(&bp_gens) {
+        Err(BlockchainError::BadTxTimestamp) => {}
+        _ => panic!("Expired tx must be rejected"),
+    }
+}
+
+#[test]
+fn compact_block_relay() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (tx1, utxo) = dummy_tx(utxo, &bp_gens);
+    let (tx2, _) = dummy_tx(utxo, &bp_gens);
+
+    let mut full_mempool = Mempool::new(state.clone(), 42);
+    for tx in vec![tx1.clone(), tx2.clone()] {
+        full_mempool.append(tx, &bp_gens).expect("Tx must be valid");
+    }
+    let block = Block {
+        header: full_mempool.make_block().header,
+        signature: Signature {
+            s: Scalar::zero(),
+            R: Default::default(),
+        },
+        txs: vec![tx1.clone(), tx2.clone()],
+    };
+    let compact = CompactBlock::from_block(&block, 7);
+    assert_eq!(compact.len(), 2);
+
+    // All txs are in the mempool.
+    let reconstructed = compact.reconstruct(&full_mempool).unwrap();
+    assert_eq!(reconstructed.header(), block.header());
+    assert_eq!(reconstructed.txs()[1].witness_hash(), tx2.witness_hash());
+
+    // Second tx is missing and has to be requested.
+    let mut partial_mempool = Mempool::new(state.clone(), 42);
+    partial_mempool
+        .append(tx1.clone(), &bp_gens)
+        .expect("Tx must be valid");
+    let missing = compact.reconstruct(&partial_mempool).unwrap_err();
+    assert_eq!(missing.indices(), &[1]);
+    assert_eq!(missing.shortid_list().get(0), compact.shortid_list.get(1));
+
+    let mut filled = compact.clone();
+    assert!(filled.prefill(1, block.txs()[1].clone()));
+    assert!(!filled.prefill(2, block.txs()[1].clone()));
+    let reconstructed = filled.reconstruct(&partial_mempool).unwrap();
+    assert_eq!(reconstructed.txs().len(), 2);
+
+    // Colliding short IDs are requested in full.
+    let mut colliding = compact.clone();
+    let id = colliding.shortid_list.get(0).unwrap();
+    colliding.shortid_list.clear();
+    colliding.shortid_list.push(id);
+    colliding.shortid_list.push(id);
+    assert_eq!(
+        colliding.reconstruct(&full_mempool).unwrap_err().indices(),
+        &[0, 1]
+    );
+
+    // Txs that do not match the txroot are requested in full.
+    let mut reordered = compact.clone();
+    let ids: Vec<_> = reordered.shortid_list.iter().collect();
+    reordered.shortid_list.clear();
+    reordered.shortid_list.push(ids[1]);
+    reordered.shortid_list.push(ids[0]);
+    assert_eq!(
+        reordered.reconstruct(&full_mempool).unwrap_err().indices(),
+        &[0, 1]
+    );
+}
+
+#[test]
+fn state_rollback() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let contract_a = make_nonce_contract(1u64, 100);
+    let contract_b = make_nonce_contract(1u64, 200);
+    let (mut state, proofs) =
+        BlockchainState::make_initial(0u64, vec![contract_a.id(), contract_b.id()]);
+    let hasher = utreexo::utreexo_hasher::<ContractID>();
+
+    // Makes a block with a given tx on top of the state and applies it.
+    let make_block = |state: &BlockchainState, tx: BlockTx, timestamp_ms: u64| {
+        let mut mempool = Mempool::new(state.clone(), timestamp_ms);
+        mempool
+            .append(tx.clone(), &bp_gens)
+            .expect("Tx must be valid");
+        let header = mempool.make_block().header;
+        state
+            .apply_block(header, &[tx], &bp_gens)
+            .expect("Block application should succeed.")
+    };
+
+    let (tx_a, _) = dummy_tx(
+        UTXO {
+            contract: contract_a,
+            proof: proofs[0].clone(),
+            privkey,
+        },
+        &bp_gens,
+    );
+    let block1 = make_block(&state, tx_a, 1);
+    state.advance(&block1);
+    let state1_root = state.utreexo.root(&hasher);
+
+    let proof_b = block1
+        .catchup
+        .update_proof(&contract_b.id(), proofs[1].clone(), &hasher)
+        .unwrap();
+    let (tx_b, _) = dummy_tx(
+        UTXO {
+            contract: contract_b,
+            proof: proof_b,
+            privkey,
+        },
+        &bp_gens,
+    );
+    let block2 = make_block(&state, tx_b.clone(), 2);
+    let undo = state.advance(&block2);
+    assert_eq!(state.tip.height, 3);
+    assert_eq!(undo.tip(), &block1.header);
+
+    // Roll back the second block and apply a competing one.
+    state.rollback_to(undo);
+    assert_eq!(state.tip, block1.header);
+    assert_eq!(state.utreexo.root(&hasher), state1_root);
+
+    let competing_block2 = make_block(&state, tx_b, 3);
+    state.advance(&competing_block2);
+    assert_eq!(state.tip.height, 3);
+    assert_ne!(state.tip, block2.header);
+    assert_eq!(state.utreexo.root(&hasher), block2.utreexo.root(&hasher));
+}
+
+#[test]
+fn block_validation_policy() {
+    struct MaxTxs(usize);
+
+    impl BlockValidationPolicy for MaxTxs {
+        fn check_txs(&self, _header: &BlockHeader, txs: &[BlockTx]) -> Result<(), BlockchainError> {
+            if txs.len() > self.0 {
+                return Err(BlockchainError::PolicyViolation(format!(
+                    "block has more than {} txs",
+                    self.0
+                )));
+            }
+            Ok(())
+        }
+    }
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let block_tx = dummy_tx(utxo, &bp_gens).0;
+
+    let mut mempool = Mempool::new(state.clone(), 42);
+    mempool
+        .append(block_tx.clone(), &bp_gens)
+        .expect("Tx must be valid");
+    let header = mempool.make_block().header;
+    let txs = [block_tx];
+
+    match state.apply_block_with_policy(header.clone(), &txs, &bp_gens, &MaxTxs(0)) {
+        Err(BlockchainError::PolicyViolation(_)) => {}
+        _ => panic!("Block must be rejected by the policy"),
+    }
+    state
+        .apply_block_with_policy(header.clone(), &txs, &bp_gens, &MaxTxs(1))
+        .expect("Block must be accepted by the policy");
+    state
+        .apply_block_with_policy(header, &txs, &bp_gens, &DefaultPolicy)
+        .expect("Block must be accepted by the default policy");
+}
+
+#[test]
+fn mempool_views() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    // Second tx spends the output of the first one.
+    let (parent_tx, utxo) = dummy_tx(utxo, &bp_gens);
+    let (child_tx, utxo) = dummy_tx(utxo, &bp_gens);
+    let (grandchild_tx, _) = dummy_tx(utxo, &bp_gens);
+
+    let mut mempool = Mempool::new(state, 42);
+    for tx in vec![parent_tx, child_tx, grandchild_tx] {
+        mempool.append(tx, &bp_gens).expect("Tx must be valid");
+    }
+
+    let views: Vec<_> = mempool.iter().collect();
+    let entries: Vec<_> = mempool.entries().collect();
+    assert_eq!(views.len(), 3);
+    for (view, entry) in views.iter().zip(entries.iter()) {
+        assert_eq!(view.id, entry.txid());
+        assert_eq!(view.size, entry.tx().encoded_size());
+        assert_eq!(view.feerate.size(), view.size);
+    }
+    assert_eq!(
+        views
+            .iter()
+            .map(|v| v.unconfirmed_parents)
+            .collect::<Vec<_>>(),
+        vec![0, 1, 1]
+    );
+    assert_eq!(views[0].effective_feerate.size(), views[0].size);
+    assert_eq!(
+        views[2].effective_feerate.size(),
+        views.iter().map(|v| v.size).sum::<usize>()
+    );
+}
+
+#[test]
+fn mempool_priority_order() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let contracts: Vec<_> = (1..=4u64)
+        .map(|privkey| make_nonce_contract(privkey, 100))
+        .collect();
+    let (state, proofs) =
+        BlockchainState::make_initial(0u64, contracts.iter().map(|c| c.id()).collect::<Vec<_>>());
+
+    // Independent transactions without fees have equal feerates.
+    let txs: Vec<_> = contracts
+        .into_iter()
+        .zip(proofs.into_iter())
+        .enumerate()
+        .map(|(i, (contract, proof))| {
+            let utxo = UTXO {
+                contract,
+                proof,
+                privkey: Scalar::from(i as u64 + 1),
+            };
+            dummy_tx(utxo, &bp_gens).0
+        })
+        .collect();
+
+    let mut mempool = Mempool::new(state.clone(), 42);
+    let mut reversed = Mempool::new(state, 42);
+    for tx in txs.iter() {
+        mempool
+            .append(tx.clone(), &bp_gens)
+            .expect("Tx must be valid");
+    }
+    for tx in txs.iter().rev() {
+        reversed
+            .append(tx.clone(), &bp_gens)
+            .expect("Tx must be valid");
+    }
+
+    let ids: Vec<_> = mempool.iter_by_priority().map(|v| v.id.0).collect();
+    let reversed_ids: Vec<_> = reversed.iter_by_priority().map(|v| v.id.0).collect();
+    assert_eq!(ids, reversed_ids);
+
+    let mut sorted_ids = ids.clone();
+    sorted_ids.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(ids, sorted_ids);
+}
+
+/// Makes a tx that spends the given utxos (with nonce values of the given quantities)
+/// and splits their total into outputs with the given quantities.
+fn cloak_tx(
+    utxos: Vec<(UTXO, u64)>,
+    amounts: &[u64],
+    bp_gens: &BulletproofGens,
+) -> (BlockTx, Vec<UTXO>) {
+    let privkey = utxos[0].0.privkey;
+    let values: Vec<_> = utxos
+        .iter()
+        .map(|(_, qty)| ClearValue {
+            qty: *qty,
+            flv: nonce_flavor(),
+        })
+        .collect();
+    let total = values.iter().map(|v| v.qty).sum();
+    let program = Program::build(|p| {
+        for (utxo, _) in utxos.iter() {
+            p.push(utxo.contract.clone()).input().signtx();
+        }
+        p.merge_values(&values)
+            .unwrap()
+            .split_value(
+                ClearValue {
+                    qty: total,
+                    flv: nonce_flavor(),
+                },
+                amounts,
+            )
+            .unwrap();
+        for _ in amounts {
+            p.push(make_predicate(privkey)).output(1);
+        }
+    });
+    let header = TxHeader {
+        version: 1u64,
+        mintime_ms: 0u64,
+        maxtime_ms: u64::max_value(),
+    };
+    let utx = Prover::build_tx(program, header, &bp_gens).unwrap();
+
+    let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+    signtx_transcript.append_message(b"txid", &utx.txid.0);
+    let sig = Signature::sign_multi(
+        utxos
+            .iter()
+            .map(|(utxo, _)| utxo.privkey)
+            .collect::<Vec<_>>(),
+        utx.signing_instructions
+            .iter()
+            .map(|(p, m)| (p.verification_key(), m))
+            .collect(),
+        &mut signtx_transcript,
+    )
+    .unwrap();
+    let tx = utx.sign(sig);
+
+    let outputs = tx
+        .precompute()
+        .unwrap()
+        .log
+        .outputs()
+        .map(|contract| UTXO {
+            contract: contract.clone(),
+            proof: utreexo::Proof::Transient,
+            privkey,
+        })
+        .collect();
+    let block_tx = BlockTx {
+        tx,
+        proofs: utxos.into_iter().map(|(utxo, _)| utxo.proof).collect(),
+    };
+    (block_tx, outputs)
+}
+
+#[test]
+fn mempool_diamond_feerate() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    // Grandparent splits the value in two, each half is moved by its own parent,
+    // and the child merges both halves back.
+    let (grandparent_tx, mut utxos) = cloak_tx(vec![(utxo, 100)], &[50, 50], &bp_gens);
+    let (parent1_tx, utxo1) = dummy_tx(utxos.remove(0), &bp_gens);
+    let (parent2_tx, utxo2) = dummy_tx(utxos.remove(0), &bp_gens);
+    let (child_tx, _) = cloak_tx(vec![(utxo1, 50), (utxo2, 50)], &[100], &bp_gens);
+
+    let mut mempool = Mempool::new(state, 42);
+    for tx in vec![grandparent_tx, parent1_tx, parent2_tx, child_tx] {
+        mempool.append(tx, &bp_gens).expect("Tx must be valid");
+    }
+
+    let views: Vec<_> = mempool.iter().collect();
+    assert_eq!(views.len(), 4);
+    assert_eq!(views[3].unconfirmed_parents, 2);
+    // The grandparent is counted once in the child's effective feerate.
+    assert_eq!(
+        views[3].effective_feerate.size(),
+        views.iter().map(|v| v.size).sum::<usize>()
+    );
+    assert_eq!(
+        views[3].effective_feerate.fee(),
+        views.iter().map(|v| v.feerate.fee()).sum::<u64>()
+    );
+}
+
+#[test]
+fn mempool_duplicates() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (tx, _) = dummy_tx(utxo, &bp_gens);
+
+    let mut mempool = Mempool::new(state, 42);
+    let txid = mempool
+        .append(tx.clone(), &bp_gens)
+        .expect("Tx must be valid")
+        .txid();
+    assert!(mempool.contains(&txid));
+
+    // Appending the same tx again returns the existing entry and does not change the mempool.
+    let dup_txid = mempool
+        .append(tx, &bp_gens)
+        .expect("Duplicate must not be an error")
+        .txid();
+    assert_eq!(dup_txid, txid);
+    assert_eq!(mempool.len(), 1);
+
+    // The index survives the re-application of the transactions.
+    mempool.update_timestamp(43);
+    assert!(mempool.contains(&txid));
+    assert_eq!(mempool.len(), 1);
+}
+
+#[test]
+fn mempool_execution_budget() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey: Scalar::from(1u64),
+    };
+    let (mut tx, _) = dummy_tx(utxo, &bp_gens);
+
+    // Each `push` and `drop` costs one step.
+    tx.tx.program = Program::build(|p| {
+        for _ in 0..DEFAULT_BUDGET / 2 + 1 {
+            p.push(String::default()).drop();
+        }
+    })
+    .to_bytes();
+
+    let mut mempool = Mempool::new(state, 42);
+    match mempool.append(tx, &bp_gens) {
+        Err(BlockchainError::VMError(VMError::BudgetExceeded)) => {}
+        _ => panic!("Tx must exceed the default execution budget"),
+    }
+    assert_eq!(mempool.len(), 0);
+}
+
+#[test]
+fn mempool_orphans() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (parent_tx, utxo) = dummy_tx(utxo, &bp_gens);
+    let (child_tx, utxo) = dummy_tx(utxo, &bp_gens);
+    let (grandchild_tx, _) = dummy_tx(utxo, &bp_gens);
+
+    let mut mempool = Mempool::new(state, 42);
+
+    // Descendants arrive before their parent and are kept aside.
+    for tx in vec![grandchild_tx.clone(), child_tx] {
+        match mempool.append(tx, &bp_gens) {
+            Err(BlockchainError::OrphanTx) => {}
+            _ => panic!("Tx must be an orphan"),
+        }
+    }
+    match mempool.append(grandchild_tx, &bp_gens) {
+        Err(BlockchainError::OrphanTx) => {}
+        _ => panic!("Duplicate orphan must be reported as an orphan"),
+    }
+    assert_eq!(mempool.len(), 0);
+    assert_eq!(mempool.orphans_len(), 2);
+
+    // When the parent arrives, the whole chain is added in order.
+    let parent_txid = mempool
+        .append(parent_tx, &bp_gens)
+        .expect("Tx must be valid")
+        .txid();
+    assert_eq!(mempool.orphans_len(), 0);
+    assert_eq!(mempool.len(), 3);
+    let entries: Vec<_> = mempool.entries().collect();
+    assert_eq!(entries[0].txid(), parent_txid);
+    assert_eq!(
+        mempool
+            .iter()
+            .map(|v| v.unconfirmed_parents)
+            .collect::<Vec<_>>(),
+        vec![0, 1, 1]
+    );
+}
+
+#[test]
+fn mempool_orphans_per_peer() {
+    use std::sync::{Arc, Mutex};
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let contract_a = make_nonce_contract(1u64, 100);
+    let contract_b = make_nonce_contract(1u64, 100);
+    let (state, proofs) =
+        BlockchainState::make_initial(0u64, vec![contract_a.id(), contract_b.id()]);
+
+    let utxo = UTXO {
+        contract: contract_a,
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (a1, utxo) = dummy_tx(utxo, &bp_gens);
+    let (a2, utxo) = dummy_tx(utxo, &bp_gens);
+    let (a3, utxo) = dummy_tx(utxo, &bp_gens);
+    let (a4, _) = dummy_tx(utxo, &bp_gens);
+    let utxo = UTXO {
+        contract: contract_b,
+        proof: proofs[1].clone(),
+        privkey,
+    };
+    let (_, utxo) = dummy_tx(utxo, &bp_gens);
+    let (b2, _) = dummy_tx(utxo, &bp_gens);
+
+    let config = MempoolConfig {
+        max_orphans_per_peer: 2,
+        ..MempoolConfig::default()
+    };
+    let mut mempool = Mempool::with_config(state, 42, config);
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let evicted_ref = evicted.clone();
+    mempool.on_evict(Box::new(move |entry| {
+        evicted_ref.lock().unwrap().push(entry.txid())
+    }));
+
+    let mut append_orphan =
+        |tx: &BlockTx, peer: &[u8]| match mempool.append_from_peer(tx.clone(), peer, &bp_gens) {
+            Err(BlockchainError::OrphanTx) => {}
+            _ => panic!("Tx must be an orphan"),
+        };
+    append_orphan(&a2, b"A");
+    append_orphan(&a3, b"A");
+    append_orphan(&b2, b"B");
+    // Re-announcing the orphan marks it as recently used.
+    append_orphan(&a2, b"A");
+    assert!(evicted.lock().unwrap().is_empty());
+
+    // Peer A exceeds its limit: its least recently used orphan is evicted,
+    // while the orphan of peer B is kept.
+    append_orphan(&a4, b"A");
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![a3.tx.precompute().unwrap().id]
+    );
+    assert_eq!(mempool.orphans_len(), 3);
+    assert_eq!(mempool.orphans_len_for_peer(b"A"), 2);
+    assert_eq!(mempool.orphans_len_for_peer(b"B"), 1);
+
+    // The evicted orphan is still valid, so it is not remembered as dropped
+    // and can be relayed again by another peer.
+    assert!(!mempool.recently_evicted(&a3.tx.precompute().unwrap().id));
+    match mempool.append_from_peer(a3, b"B", &bp_gens) {
+        Err(BlockchainError::OrphanTx) => {}
+        _ => panic!("Evicted orphan must be accepted again"),
+    }
+    assert_eq!(mempool.orphans_len_for_peer(b"B"), 2);
+
+    // The parent promotes the whole chain, while the unrelated orphan of peer B still waits.
+    mempool.append(a1, &bp_gens).expect("Tx must be valid");
+    assert_eq!(mempool.len(), 4);
+    assert_eq!(mempool.orphans_len(), 1);
+    assert_eq!(mempool.orphans_len_for_peer(b"B"), 1);
+}
+
+#[test]
+fn mempool_orphans_expire() {
+    use std::sync::{Arc, Mutex};
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (parent_tx, utxo) = dummy_tx(utxo, &bp_gens);
+    let (child_tx, _) = dummy_tx(utxo, &bp_gens);
+    let child_id = child_tx.tx.precompute().unwrap().id;
+
+    let config = MempoolConfig {
+        max_orphan_age_ms: 1000,
+        ..MempoolConfig::default()
+    };
+    let mut mempool = Mempool::with_config(state, 42, config);
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let evicted_ref = evicted.clone();
+    mempool.on_evict(Box::new(move |entry| {
+        evicted_ref.lock().unwrap().push(entry.txid())
+    }));
+
+    match mempool.append(child_tx.clone(), &bp_gens) {
+        Err(BlockchainError::OrphanTx) => {}
+        _ => panic!("Tx must be an orphan"),
+    }
+    mempool.update_timestamp(42 + 1000);
+    assert_eq!(mempool.orphans_len(), 1);
+
+    // The orphan waited for its parent for too long.
+    mempool.update_timestamp(42 + 1001);
+    assert_eq!(mempool.orphans_len(), 0);
+    assert_eq!(*evicted.lock().unwrap(), vec![child_id]);
+    assert!(!mempool.recently_evicted(&child_id));
+
+    mempool
+        .append(parent_tx, &bp_gens)
+        .expect("Tx must be valid");
+    assert_eq!(mempool.len(), 1);
+
+    // The child can be relayed again after its parent.
+    mempool
+        .append(child_tx, &bp_gens)
+        .expect("Tx must be valid");
+    assert_eq!(mempool.len(), 2);
+}
+
+#[test]
+fn mempool_evicted_txs() {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (tx, _) = dummy_tx(utxo, &bp_gens);
+
+    let config = MempoolConfig {
+        bloom_capacity: 100,
+        bloom_fp_rate: 0.01,
+        bloom_reset_interval_ms: 1000,
+        ..MempoolConfig::default()
+    };
+    let mut mempool = Mempool::with_config(state.clone(), 42, config);
+    let txid = mempool
+        .append(tx.clone(), &bp_gens)
+        .expect("Tx must be valid")
+        .txid();
+    assert!(!mempool.recently_evicted(&txid));
+
+    // Confirm the tx in a block: it is dropped from the mempool and remembered.
+    let header = mempool.make_block().header;
+    let block = state
+        .apply_block(header, &[tx.clone()], &bp_gens)
+        .expect("Block application should succeed.");
+    mempool.update_state(block.blockchain_state(), &block.catchup);
+    assert_eq!(mempool.len(), 0);
+    assert!(mempool.recently_evicted(&txid));
+    match mempool.append(tx, &bp_gens) {
+        Err(BlockchainError::RecentlyEvicted) => {}
+        _ => panic!("Dropped tx must be rejected"),
+    }
+
+    // The tx is remembered for at least one reset interval, and forgotten after two.
+    mempool.update_timestamp(42 + 1000);
+    assert!(mempool.recently_evicted(&txid));
+    mempool.update_timestamp(42 + 2000);
+    assert!(!mempool.recently_evicted(&txid));
+}
+
+#[test]
+fn mempool_eviction_callback() {
+    use std::sync::{Arc, Mutex};
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let privkey = Scalar::from(1u64);
+    let initial_contract = make_nonce_contract(1u64, 100);
+    let (state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
+
+    let utxo = UTXO {
+        contract: initial_contract.clone(),
+        proof: proofs[0].clone(),
+        privkey,
+    };
+    let (parent_tx, child_utxo) = dummy_tx(utxo.clone(), &bp_gens);
+    let (child_tx, _) = dummy_tx(child_utxo, &bp_gens);
+    let (conflicting_tx, _) = cloak_tx(vec![(utxo, 100)], &[60, 40], &bp_gens);
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut mempool = Mempool::new(state.clone(), 42);
+    let evicted_ref = evicted.clone();
+    mempool.on_evict(Box::new(move |entry| {
+        evicted_ref.lock().unwrap().push(entry.txid())
+    }));
+    let parent_id = mempool.append(parent_tx, &bp_gens).unwrap().txid();
+    let child_id = mempool.append(child_tx, &bp_gens).unwrap().txid();
+    assert_eq!(mempool.len(), 2);
+    assert!(evicted.lock().unwrap().is_empty());
+
+    // Block with a double-spend of the parent evicts both the parent and its child.
+    let mut block_mempool = Mempool::new(state.clone(), 42);
+    block_mempool
+        .append(conflicting_tx.clone(), &bp_gens)
+        .expect("Tx must be valid");
+    let header = block_mempool.make_block().header;
+    let block = state
+        .apply_block(header, &[conflicting_tx], &bp_gens)
+        .expect("Block application should succeed.");
+    mempool.update_state(block.blockchain_state(), &block.catchup);
+    assert_eq!(mempool.len(), 0);
+    assert_eq!(*evicted.lock().unwrap(), vec![parent_id, child_id]);
+}
+
 #[test]
 fn test_p2p_protocol() {
     use super::block::*;
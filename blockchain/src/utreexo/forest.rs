@@ -1,15 +1,20 @@
 use core::borrow::Borrow;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
 use std::mem;
 
+use readerwriter::{
+    Decodable, Encodable, ExactSizeEncodable, ReadError, Reader, WriteError, Writer,
+};
 use thiserror::Error;
 
 use super::heap::{Heap, HeapIndex};
 use zkvm::merkle::{Directions, Hash, Hasher, MerkleItem, MerkleTree, Path, Position};
 
 /// Forest consists of a number of roots of merkle binary trees.
+/// The inner nodes are not stored: proofs are kept by the owners of the items
+/// and updated with the `Catchup` structure (which can be pruned with `Catchup::prune`).
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Forest {
     #[serde(with = "array64")]
@@ -21,6 +26,20 @@ pub struct Forest {
 pub struct WorkForest {
     roots: Vec<HeapIndex>, // roots of all the perfect binary trees, including the newly inserted nodes
     heap: Heap<Node>,
+    #[serde(default)]
+    metrics: WorkForestMetrics,
+}
+
+/// Counters of work performed during the update cycle of the forest:
+/// from `Forest::work_forest` through insertions and deletions to `WorkForest::normalize`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkForestMetrics {
+    /// Number of leaf and intermediate hashes computed.
+    pub nodes_hashed: usize,
+    /// Number of pairs of same-level subtrees merged into a higher-level subtree during normalization.
+    pub subtrees_merged: usize,
+    /// Number of items deleted from the forest.
+    pub deletions: usize,
 }
 
 /// Structure that helps auto-updating the proofs created for a previous state of a forest.
@@ -41,6 +60,16 @@ pub enum UtreexoError {
     /// to which it should.
     #[error("Merkle proof is invalid")]
     InvalidProof,
+
+    /// This error occurs when one of the proofs in a batch is invalid.
+    /// Contains the index of the first failed item in the batch.
+    #[error("Merkle proof for the item #{0} in the batch is invalid")]
+    InvalidBatchProof(usize),
+
+    /// This error occurs when some proofs in a batch cannot be auto-updated.
+    /// Contains the indices of the outdated items in the batch.
+    #[error("Merkle proofs for the items {0:?} in the batch are outdated")]
+    OutdatedProofs(Vec<usize>),
 }
 
 /// Node in the merkle tree
@@ -83,6 +112,41 @@ impl Proof {
     }
 }
 
+impl Encodable for Proof {
+    /// Encodes the proof as a 1-byte type (0 for transient, 1 for committed),
+    /// followed by the merkle path for the committed proof.
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        match self {
+            Proof::Transient => w.write_u8(b"type", 0),
+            Proof::Committed(path) => {
+                w.write_u8(b"type", 1)?;
+                path.encode(w)
+            }
+        }
+    }
+}
+
+impl ExactSizeEncodable for Proof {
+    fn encoded_size(&self) -> usize {
+        match self {
+            Proof::Transient => 1,
+            Proof::Committed(path) => 1 + path.encoded_size(),
+        }
+    }
+}
+
+impl Decodable for Proof {
+    fn decode(r: &mut impl Reader) -> Result<Self, ReadError> {
+        match r.read_u8()? {
+            0 => Ok(Proof::Transient),
+            1 => Path::decode(r)
+                .map(Proof::Committed)
+                .map_err(|_| ReadError::InvalidFormat),
+            _ => Err(ReadError::InvalidFormat),
+        }
+    }
+}
+
 impl Forest {
     /// Creates a new instance of Forest.
     pub fn new() -> Self {
@@ -114,6 +178,73 @@ impl Forest {
         Err(UtreexoError::InvalidProof)
     }
 
+    /// Verifies a batch of items and their proofs against the forest.
+    /// Items in the same tree share the upper parts of their paths, so the nodes
+    /// already verified for one item are not hashed again for another.
+    ///
+    /// `Proof::Transient` is rejected: the normalized forest contains only committed items,
+    /// so the transient item cannot be verified against it.
+    /// Returns `UtreexoError::InvalidBatchProof` with the index of the first failed item.
+    pub fn verify_batch<M: MerkleItem>(
+        &self,
+        items: &[(M, &Proof)],
+        hasher: &Hasher<M>,
+    ) -> Result<(), UtreexoError> {
+        // Verified nodes indexed by their level and offset at that level.
+        let mut verified = HashMap::<(usize, Position), Hash>::new();
+        for (index, (item, proof)) in items.iter().enumerate() {
+            let verified_path = proof
+                .as_path()
+                .map(|path| self.verify_with_cache(item, path, hasher, &mut verified))
+                .unwrap_or(false);
+            if !verified_path {
+                return Err(UtreexoError::InvalidBatchProof(index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies the item against the forest, stopping as soon as the path reaches a node
+    /// that was already verified. Upon success, remembers the nodes and the neighbors along the path.
+    fn verify_with_cache<M: MerkleItem>(
+        &self,
+        item: &M,
+        path: &Path,
+        hasher: &Hasher<M>,
+        verified: &mut HashMap<(usize, Position), Hash>,
+    ) -> bool {
+        let level = match find_root(self.roots_iter().map(|(level, _)| level), path.position) {
+            Some((_i, level)) => level,
+            None => return false,
+        };
+        if path.neighbors.len() != level {
+            return false;
+        }
+        let mut new_nodes = Vec::with_capacity(2 * level);
+        let mut node_hash = hasher.leaf(item);
+        for (node_level, (side, neighbor_hash)) in path.iter().enumerate() {
+            let offset = path.position >> node_level;
+            if let Some(is_same) = verified
+                .get(&(node_level, offset))
+                .map(|known_hash| known_hash == &node_hash)
+            {
+                if is_same {
+                    verified.extend(new_nodes);
+                }
+                return is_same;
+            }
+            new_nodes.push(((node_level, offset), node_hash));
+            new_nodes.push(((node_level, offset ^ 1), *neighbor_hash));
+            let (l, r) = side.order(&node_hash, neighbor_hash);
+            node_hash = hasher.intermediate(l, r);
+        }
+        if self.roots[level] != Some(node_hash) {
+            return false;
+        }
+        verified.extend(new_nodes);
+        true
+    }
+
     /// Lets use modify the utreexo and yields a new state of the utreexo,
     /// along with a catchup structure.
     pub fn work_forest(&self) -> WorkForest {
@@ -129,15 +260,51 @@ impl Forest {
                 })
             })
             .collect();
-        WorkForest { roots, heap }
+        WorkForest {
+            roots,
+            heap,
+            metrics: WorkForestMetrics::default(),
+        }
     }
 
     /// Since each root is balanced, the top root is composed of n-1 pairs:
     /// `hash(R3, hash(R2, hash(R1, R0)))`
+    ///
+    /// This is the `utxoroot` committed in the block header.
+    /// Proofs verify against it after being extended with `root_path`.
     pub fn root<M: MerkleItem>(&self, hasher: &Hasher<M>) -> Hash {
         MerkleTree::connect_perfect_roots(self.roots.iter().filter_map(|r| *r), &hasher)
     }
 
+    /// Extends the path of an item within its tree to the path to the forest's `root`,
+    /// so the item can be verified with `Path::verify_root` against the root alone,
+    /// e.g. by a light client that knows only the `utxoroot` of the block header.
+    ///
+    /// The path continues with the root of the lower trees (if any) as the right neighbor
+    /// and the roots of the higher trees as the left neighbors.
+    /// Returns None if the path does not fit the shape of the forest.
+    pub fn root_path<M: MerkleItem>(&self, path: &Path, hasher: &Hasher<M>) -> Option<Path> {
+        let (_i, level) = find_root(self.roots_iter().map(|(level, _)| level), path.position)?;
+        if path.neighbors.len() != level {
+            return None;
+        }
+        let mut root_path = Path {
+            position: path.position & ((1 << level) - 1),
+            neighbors: path.neighbors.clone(),
+        };
+        if self.roots[..level].iter().any(|r| r.is_some()) {
+            root_path.neighbors.push(MerkleTree::connect_perfect_roots(
+                self.roots[..level].iter().filter_map(|r| *r),
+                hasher,
+            ));
+        }
+        for root in self.roots[level + 1..].iter().filter_map(|r| *r) {
+            root_path.position |= (1 as Position) << root_path.neighbors.len();
+            root_path.neighbors.push(root);
+        }
+        Some(root_path)
+    }
+
     /// Returns an iterator over roots of the forest as (level, hash) pairs,
     /// from the highest to the lowest level.
     fn roots_iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = (usize, Hash)> + 'a {
@@ -152,6 +319,7 @@ impl Forest {
 impl WorkForest {
     /// Adds a new item to the tree, appending a node to the end.
     pub fn insert<M: MerkleItem>(&mut self, item: &M, hasher: &Hasher<M>) {
+        self.metrics.nodes_hashed += 1;
         self.roots.push(self.heap.allocate(Node {
             level: 0,
             hash: hasher.leaf(item),
@@ -160,13 +328,21 @@ impl WorkForest {
         }));
     }
 
+    /// Returns the metrics of work performed by this forest since it was created by `Forest::work_forest`.
+    /// Normalization work is reported separately by `Catchup::metrics`.
+    pub fn metrics(&self) -> WorkForestMetrics {
+        self.metrics
+    }
+
     /// Performs multiple updates in a transactional fashion.
     /// If any update fails, all of the changes are effectively undone.
+    /// Hashing performed by the failed updates is still counted in the metrics.
     pub fn batch<F, E>(&mut self, closure: F) -> Result<&mut Self, E>
     where
         F: FnOnce(&mut Self) -> Result<(), E>,
     {
         let prev_roots = self.roots.clone();
+        let prev_deletions = self.metrics.deletions;
         let checkpoint = self.heap.checkpoint();
 
         match closure(self) {
@@ -177,6 +353,7 @@ impl WorkForest {
             Err(e) => {
                 self.heap.rollback(checkpoint);
                 self.roots = prev_roots;
+                self.metrics.deletions = prev_deletions;
                 Err(e)
             }
         }
@@ -205,6 +382,7 @@ impl WorkForest {
         hasher: &Hasher<M>,
     ) -> Result<(), UtreexoError> {
         let item_hash = hasher.leaf(item);
+        self.metrics.nodes_hashed += 1;
         let (index, node) = self
             .roots_iter()
             .enumerate()
@@ -219,6 +397,7 @@ impl WorkForest {
             return Err(UtreexoError::InvalidProof);
         }
         self.roots.remove(index);
+        self.metrics.deletions += 1;
 
         Ok(())
     }
@@ -285,6 +464,7 @@ impl WorkForest {
 
         // 4. Now, walk the merkle proof starting with the leaf,
         //    creating the missing nodes until we hit the bottom node.
+        self.metrics.nodes_hashed += 1 + existing.level;
         let new_node = path.iter().take(existing.level).fold(
             Node {
                 level: 0,
@@ -350,6 +530,8 @@ impl WorkForest {
                 }
             });
 
+        self.metrics.deletions += 1;
+
         Ok(())
     }
 
@@ -389,6 +571,7 @@ impl WorkForest {
 
         // 2. Compute perfect roots for the new tree,
         //    joining together same-level nodes into higher-level nodes.
+        let mut metrics = self.metrics;
         let new_root_nodes = non_modified_nodes.into_iter().fold(
             [None as Option<HeapIndex>; 64], // "2^64 of anything should be enough for everyone"]
             |mut roots, mut curr_node_index| {
@@ -402,6 +585,8 @@ impl WorkForest {
                         modified: true,
                         children: Some((left_node_index, curr_node_index)),
                     });
+                    metrics.subtrees_merged += 1;
+                    metrics.nodes_hashed += 1;
                     curr_level += 1;
                 }
                 roots[curr_level] = Some(curr_node_index);
@@ -431,27 +616,14 @@ impl WorkForest {
                 .copied()
                 .collect(),
             heap: new_heap,
+            metrics,
         };
 
         // 5. Finally, traverse the new forest and collect new positions for all childless nodes
         //    these will be the points of update for the proofs made against the old tree.
         //    All the paths from leaf to these childless nodes remain valid, while the rest of
         //    the path must be computed from
-        let catchup_map =
-            ChildlessNodesIterator::new(&new_work_forest.heap, new_work_forest.roots.iter()).fold(
-                HashMap::<Hash, Position>::new(),
-                |mut map, (offset, node)| {
-                    if node.children.is_none() {
-                        map.insert(node.hash, offset);
-                    }
-                    map
-                },
-            );
-
-        let catchup = Catchup {
-            forest: new_work_forest,
-            map: catchup_map,
-        };
+        let catchup = Catchup::new(new_work_forest);
 
         (new_forest, catchup)
     }
@@ -462,6 +634,28 @@ impl WorkForest {
 }
 
 impl Catchup {
+    /// Creates a catchup structure from the normalized forest,
+    /// collecting the new positions for all childless nodes.
+    fn new(forest: WorkForest) -> Self {
+        let map = ChildlessNodesIterator::new(&forest.heap, forest.roots.iter()).fold(
+            HashMap::<Hash, Position>::new(),
+            |mut map, (offset, node)| {
+                if node.children.is_none() {
+                    map.insert(node.hash, offset);
+                }
+                map
+            },
+        );
+        Catchup { forest, map }
+    }
+
+    /// Returns the metrics of the update cycle that produced this catchup structure,
+    /// including the work performed during normalization.
+    /// Catchup decoded from its binary encoding reports zero metrics.
+    pub fn metrics(&self) -> WorkForestMetrics {
+        self.forest.metrics
+    }
+
     /// Updates the proof if it's slightly out of date
     /// (made against the previous state of the Utreexo).
     pub fn update_proof<M: MerkleItem>(
@@ -484,14 +678,140 @@ impl Catchup {
         })
     }
 
+    /// Updates a batch of proofs in place, sharing the work between the items in the same subtree:
+    /// the neighbors above the catch-up point are looked up once per subtree.
+    /// Transient proofs of the items committed in this update are upgraded to `Proof::Committed`.
+    ///
+    /// Proofs that cannot be updated are left unchanged and reported with
+    /// `UtreexoError::OutdatedProofs` listing their indices in the batch.
+    pub fn update_proofs<M: MerkleItem>(
+        &self,
+        proofs: &mut [(M, Proof)],
+        hasher: &Hasher<M>,
+    ) -> Result<(), UtreexoError> {
+        let mut upper_neighbors: HashMap<(Position, usize), Option<Vec<Hash>>> = HashMap::new();
+        let mut outdated = Vec::new();
+        for (i, (item, proof)) in proofs.iter_mut().enumerate() {
+            let path = match proof {
+                Proof::Transient => Path::default(),
+                Proof::Committed(path) => path.clone(),
+            };
+            let updated = self
+                .catchup_point(item, path, hasher)
+                .and_then(|(mut path, level)| {
+                    let upper = upper_neighbors
+                        .entry((path.position >> level, level))
+                        .or_insert_with(|| self.upper_neighbors(path.position, level));
+                    path.neighbors.extend_from_slice(upper.as_ref()?);
+                    Some(path)
+                });
+            match updated {
+                Some(path) => *proof = Proof::Committed(path),
+                // transient item may remain transient if it was not committed in this update
+                None if proof.as_path().is_none() => {}
+                None => outdated.push(i),
+            }
+        }
+        if outdated.is_empty() {
+            Ok(())
+        } else {
+            Err(UtreexoError::OutdatedProofs(outdated))
+        }
+    }
+
+    /// Returns a smaller catchup structure that can update only the proofs of the retained items,
+    /// dropping the inner nodes that are not needed for them.
+    ///
+    /// `Forest` keeps only the roots, so the inner nodes of the trees are stored only in the catchup
+    /// structure, which may be large after a big update. A node that tracks a few items
+    /// (e.g. a wallet) can keep the pruned catchup to save memory, but it loses the ability
+    /// to update the proofs of any other items: these fail with `UtreexoError::InvalidProof`.
+    /// Transient proofs can be retained too, if their items were committed in this update.
+    /// Proofs that cannot be updated are ignored.
+    pub fn prune<M: MerkleItem>(&self, retained: &[(M, Proof)], hasher: &Hasher<M>) -> Catchup {
+        // Offsets of the subtrees where the retained paths meet the stored nodes.
+        let offsets: BTreeSet<Position> = retained
+            .iter()
+            .filter_map(|(item, proof)| {
+                let path = proof.as_path().cloned().unwrap_or_default();
+                self.catchup_point(item, path, hasher)
+                    .map(|(path, level)| (path.position >> level) << level)
+            })
+            .collect();
+
+        let mut heap = Heap::new();
+        let mut root_offset: Position = 0;
+        let roots = self
+            .forest
+            .roots
+            .iter()
+            .map(|root| {
+                let index = self.copy_subtree(*root, root_offset, &offsets, &mut heap);
+                root_offset += 1 << self.forest.heap.get_ref(*root).level;
+                index
+            })
+            .collect();
+        Catchup {
+            forest: WorkForest {
+                roots,
+                heap,
+                metrics: self.forest.metrics,
+            },
+            map: self
+                .map
+                .iter()
+                .filter(|(_, offset)| offsets.contains(offset))
+                .map(|(hash, offset)| (*hash, *offset))
+                .collect(),
+        }
+    }
+
+    /// Copies the node to a new heap together with the children leading to the given offsets.
+    /// Other children are dropped: their hashes are already stored in the copied node.
+    fn copy_subtree(
+        &self,
+        index: HeapIndex,
+        offset: Position,
+        offsets: &BTreeSet<Position>,
+        heap: &mut Heap<Node>,
+    ) -> HeapIndex {
+        let node = self.forest.heap.get_ref(index);
+        let children = node.children.filter(|_| {
+            offsets
+                .range(offset..offset + (1 << node.level))
+                .next()
+                .is_some()
+        });
+        let children = children.map(|(l, r)| {
+            (
+                self.copy_subtree(l, offset, offsets, heap),
+                self.copy_subtree(r, offset + (1 << node.level) / 2, offsets, heap),
+            )
+        });
+        heap.allocate(Node {
+            children,
+            ..node.clone()
+        })
+    }
+
     /// Returns an updated path. Returns None if the item pointed to by a path was not committed.
-    fn update_path<M: MerkleItem>(
+    fn update_path<M: MerkleItem>(&self, item: &M, path: Path, hasher: &Hasher<M>) -> Option<Path> {
+        let (mut path, level) = self.catchup_point(item, path, hasher)?;
+        path.neighbors
+            .extend(self.upper_neighbors(path.position, level)?);
+        Some(path)
+    }
+
+    /// Climbs up the merkle path until it finds a node stored in the catchup structure.
+    /// Returns the path with the updated position and the neighbors below that node,
+    /// together with the level of the node.
+    /// Returns None if the item pointed to by a path was not committed.
+    fn catchup_point<M: MerkleItem>(
         &self,
         item: &M,
         mut path: Path,
         hasher: &Hasher<M>,
-    ) -> Option<Path> {
-        // 1. Climb up the merkle path until we find an existing node or nothing.
+    ) -> Option<(Path, usize)> {
         let leaf_hash = hasher.leaf(item);
         let (midlevel, maybe_offset, _midhash) = path.iter().fold(
             (0, self.map.get(&leaf_hash), leaf_hash),
@@ -519,31 +839,121 @@ impl Catchup {
         // Remove all outdated neighbors
         path.neighbors.truncate(midlevel);
 
+        Some((path, midlevel))
+    }
+
+    /// Returns the neighbors of the path at the given position above the given level,
+    /// from the lowest one to the one just below the root.
+    /// Returns None if the position does not belong to any tree in the forest.
+    fn upper_neighbors(&self, position: Position, level: usize) -> Option<Vec<Hash>> {
         // Find the root to which the updated position belongs
-        let root_index = self.forest.roots
-            [find_root(self.forest.roots_iter().map(|r| r.level), path.position)?.0];
+        let root_index =
+            self.forest.roots[find_root(self.forest.roots_iter().map(|r| r.level), position)?.0];
 
         // Construct a new directions object.
         // We cannot take it from path because it does not have all neighbors yet.
-        let directions = Directions::new(path.position, self.forest.heap.get_ref(root_index).level);
+        let directions = Directions::new(position, self.forest.heap.get_ref(root_index).level);
+
+        let mut neighbors = Vec::with_capacity(
+            self.forest
+                .heap
+                .get_ref(root_index)
+                .level
+                .saturating_sub(level),
+        );
+        let mut parent_index = root_index;
+        for side in directions.rev() {
+            if let Some((l, r)) = self.forest.heap.get_ref(parent_index).children {
+                let (trunk, neighbor) = side.order(l, r);
+                neighbors.push(self.forest.heap.get_ref(neighbor).hash);
+                parent_index = trunk;
+            }
+        }
+        // Neighbors were collected from the top, but the path lists them from the bottom.
+        neighbors.reverse();
+        Some(neighbors)
+    }
+}
 
-        path.neighbors = directions
-            .rev()
-            .fold(
-                (path.neighbors, root_index),
-                |(mut neighbors, mut parent_index), side| {
-                    if let Some((l, r)) = self.forest.heap.get_ref(parent_index).children {
-                        let (trunk, neighbor) = side.order(l, r);
-                        // TODO: this is not the fastest way to insert missing neighbors
-                        neighbors.insert(midlevel, self.forest.heap.get_ref(neighbor).hash);
-                        parent_index = trunk;
-                    }
-                    (neighbors, parent_index)
-                },
-            )
-            .0;
+/// Version of the binary encoding of the `Catchup` structure.
+const CATCHUP_ENCODING_VERSION: u8 = 1;
+
+/// Node flag indicating that the node is followed by its children.
+const NODE_HAS_CHILDREN: u8 = 0b01;
+
+/// Node flag indicating that the node is marked as modified.
+const NODE_MODIFIED: u8 = 0b10;
+
+/// Size of the encoded node: flags and a hash.
+const ENCODED_NODE_SIZE: u64 = 1 + 32;
+
+impl Encodable for Catchup {
+    /// Encodes the catchup structure in a compact format:
+    /// ```ascii
+    /// Catchup  =  0x01  ||  LE32(k)  ||  LE64(n)  ||  Tree[0]  || ... ||  Tree[k-1]
+    ///    Tree  =  Level  ||  Node
+    ///    Node  =  Flags  ||  Hash  ||  [Node(left)  ||  Node(right)]
+    /// ```
+    /// where `k` is the number of roots, `n` is the total number of nodes in all trees,
+    /// `Level` is a 1-byte level of the root, `Flags` is a byte with bit 0 indicating the
+    /// presence of children and bit 1 indicating the modification mark.
+    /// The nodes are stored in pre-order, so children of a node immediately follow it.
+    /// The map of positions is not stored since it is derived from the forest.
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        w.write_u8(b"version", CATCHUP_ENCODING_VERSION)?;
+        w.write_u32(b"k", self.forest.roots.len() as u32)?;
+        w.write_u64(
+            b"n",
+            self.forest
+                .roots_iter()
+                .map(|root| root.subtree_size(&self.forest.heap))
+                .sum(),
+        )?;
+        for root in self.forest.roots_iter() {
+            w.write_u8(b"level", root.level as u8)?;
+            root.encode_subtree(&self.forest.heap, w)?;
+        }
+        Ok(())
+    }
+}
 
-        Some(path)
+impl Decodable for Catchup {
+    /// Decodes the catchup structure encoded with `Catchup::encode`.
+    /// Fails with `ReadError::InsufficientBytes` if the data is truncated,
+    /// and with `ReadError::InvalidFormat` if the data is inconsistent.
+    fn decode(r: &mut impl Reader) -> Result<Self, ReadError> {
+        if r.read_u8()? != CATCHUP_ENCODING_VERSION {
+            return Err(ReadError::InvalidFormat);
+        }
+        let k = r.read_u32()? as usize;
+        let mut n = r.read_u64()?;
+        if k > 64 {
+            return Err(ReadError::InvalidFormat);
+        }
+        // Fail early on truncated data before allocating any nodes.
+        if n.saturating_mul(ENCODED_NODE_SIZE) > r.remaining_bytes() as u64 {
+            return Err(ReadError::InsufficientBytes);
+        }
+        let mut heap = Heap::new();
+        let mut roots = Vec::with_capacity(k);
+        let mut prev_level = None;
+        for _ in 0..k {
+            let level = r.read_u8()? as usize;
+            // Roots of the normalized forest are ordered from the highest level to the lowest.
+            if level >= 64 || prev_level.map(|prev| level >= prev).unwrap_or(false) {
+                return Err(ReadError::InvalidFormat);
+            }
+            prev_level = Some(level);
+            roots.push(Node::decode_subtree(level, r, &mut heap, &mut n)?);
+        }
+        if n != 0 {
+            return Err(ReadError::InvalidFormat);
+        }
+        Ok(Catchup::new(WorkForest {
+            roots,
+            heap,
+            metrics: WorkForestMetrics::default(),
+        }))
     }
 }
 
@@ -655,6 +1065,63 @@ impl fmt::Debug for WorkForest {
 }
 
 impl Node {
+    /// Returns the number of nodes in the subtree, including this node.
+    fn subtree_size(&self, heap: &Heap<Node>) -> u64 {
+        1 + self.children.map_or(0, |(l, r)| {
+            heap.get_ref(l).subtree_size(heap) + heap.get_ref(r).subtree_size(heap)
+        })
+    }
+
+    /// Encodes the node and all its children in pre-order.
+    fn encode_subtree(&self, heap: &Heap<Node>, w: &mut impl Writer) -> Result<(), WriteError> {
+        let mut flags = 0u8;
+        if self.children.is_some() {
+            flags |= NODE_HAS_CHILDREN;
+        }
+        if self.modified {
+            flags |= NODE_MODIFIED;
+        }
+        w.write_u8(b"flags", flags)?;
+        w.write(b"hash", &self.hash[..])?;
+        if let Some((l, r)) = self.children {
+            heap.get_ref(l).encode_subtree(heap, w)?;
+            heap.get_ref(r).encode_subtree(heap, w)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the node at a given level with all its children, allocating them on the heap.
+    /// Fails if the number of nodes exceeds the `remaining` count.
+    fn decode_subtree(
+        level: usize,
+        r: &mut impl Reader,
+        heap: &mut Heap<Node>,
+        remaining: &mut u64,
+    ) -> Result<HeapIndex, ReadError> {
+        *remaining = remaining.checked_sub(1).ok_or(ReadError::InvalidFormat)?;
+        let flags = r.read_u8()?;
+        if flags & !(NODE_HAS_CHILDREN | NODE_MODIFIED) != 0 {
+            return Err(ReadError::InvalidFormat);
+        }
+        let hash = Hash(r.read_u8x32()?);
+        let children = if flags & NODE_HAS_CHILDREN != 0 {
+            if level == 0 {
+                return Err(ReadError::InvalidFormat);
+            }
+            let left = Node::decode_subtree(level - 1, r, heap, remaining)?;
+            let right = Node::decode_subtree(level - 1, r, heap, remaining)?;
+            Some((left, right))
+        } else {
+            None
+        };
+        Ok(heap.allocate(Node {
+            level,
+            hash,
+            modified: flags & NODE_MODIFIED != 0,
+            children,
+        }))
+    }
+
     fn debug_fmt(
         &self,
         heap: &Heap<Node>,
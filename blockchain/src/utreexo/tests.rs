@@ -102,6 +102,57 @@ fn insert_to_utreexo() {
         .expect("all proofs must be valid");
 }
 
+#[test]
+fn verify_batch_utreexo() {
+    let hasher = utreexo_hasher();
+    let (forest1, catchup1) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..13 {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .expect("cannot fail")
+        .normalize(&hasher);
+
+    let proofs1 = (0..13)
+        .map(|i| {
+            catchup1
+                .update_proof(&Item(i), Proof::Transient, &hasher)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // all items, including duplicates, verify in any order.
+    let batch = (0..13)
+        .rev()
+        .chain(0..3)
+        .map(|i| (Item(i), &proofs1[i as usize]))
+        .collect::<Vec<_>>();
+    assert_eq!(forest1.verify_batch(&batch, &hasher), Ok(()));
+
+    // item that does not match its proof is reported by its index in the batch,
+    // even if its neighbors were already verified.
+    let batch = vec![
+        (Item(0), &proofs1[0]),
+        (Item(1), &proofs1[1]),
+        (Item(2), &proofs1[3]),
+    ];
+    assert_eq!(
+        forest1.verify_batch(&batch, &hasher),
+        Err(UtreexoError::InvalidBatchProof(2))
+    );
+
+    // transient proofs are rejected.
+    let transient = Proof::Transient;
+    let batch = vec![(Item(0), &proofs1[0]), (Item(12), &transient)];
+    assert_eq!(
+        forest1.verify_batch(&batch, &hasher),
+        Err(UtreexoError::InvalidBatchProof(1))
+    );
+}
+
 #[test]
 fn transaction_success() {
     let hasher = utreexo_hasher();
@@ -433,3 +484,361 @@ fn insert_and_delete_utreexo() {
         forest.delete(&Item(7), &proof7, &hasher).unwrap();
     });
 }
+
+#[test]
+fn catchup_encoding() {
+    use readerwriter::{Decodable, Encodable};
+
+    let n = 5000u64;
+    let hasher = utreexo_hasher();
+    let (forest1, catchup1) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..n {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .expect("cannot fail")
+        .normalize(&hasher);
+
+    let proofs1 = (0..n)
+        .map(|i| {
+            catchup1
+                .update_proof(&Item(i), Proof::Transient, &hasher)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // delete every 7th item so the catchup contains pruned subtrees
+    let (_forest2, catchup2) = forest1
+        .work_forest()
+        .batch::<_, UtreexoError>(|forest| {
+            for i in (0..n).step_by(7) {
+                forest.delete(&Item(i), &proofs1[i as usize], &hasher)?;
+            }
+            Ok(())
+        })
+        .expect("all proofs must be valid")
+        .normalize(&hasher);
+
+    let bytes = catchup2.encode_to_vec();
+    let decoded = Catchup::decode(&mut &bytes[..]).expect("must decode");
+    assert_eq!(decoded.encode_to_vec(), bytes);
+
+    // decoded catchup updates the proofs the same way as the original one
+    for i in (0..n).filter(|i| i % 7 != 0).step_by(11) {
+        let expected = catchup2
+            .update_proof(&Item(i), proofs1[i as usize].clone(), &hasher)
+            .unwrap();
+        let actual = decoded
+            .update_proof(&Item(i), proofs1[i as usize].clone(), &hasher)
+            .unwrap();
+        assert_eq!(actual.as_path(), expected.as_path());
+    }
+
+    // truncated data is rejected
+    for len in [0, 1, 13, 14, bytes.len() / 2, bytes.len() - 1].iter() {
+        assert!(Catchup::decode(&mut &bytes[..*len]).is_err());
+    }
+}
+
+#[test]
+fn work_forest_metrics() {
+    let hasher = utreexo_hasher();
+    let mut wf = Forest::new().work_forest();
+    assert_eq!(wf.metrics(), WorkForestMetrics::default());
+    for i in 0..6 {
+        wf.insert(&Item(i), &hasher);
+    }
+    assert_eq!(
+        wf.metrics(),
+        WorkForestMetrics {
+            nodes_hashed: 6,
+            subtrees_merged: 0,
+            deletions: 0,
+        }
+    );
+
+    //  d
+    //  |\
+    //  a   b   c
+    //  |\  |\  |\
+    //  0 1 2 3 4 5
+    let (forest1, catchup1) = wf.normalize(&hasher);
+    assert_eq!(
+        catchup1.metrics(),
+        WorkForestMetrics {
+            nodes_hashed: 10,
+            subtrees_merged: 4,
+            deletions: 0,
+        }
+    );
+
+    let proofs1 = (0..6)
+        .map(|i| {
+            catchup1
+                .update_proof(&Item(i), Proof::Transient, &hasher)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // metrics are reset for the new update cycle
+    let mut wf = forest1.work_forest();
+    assert_eq!(wf.metrics(), WorkForestMetrics::default());
+
+    // deletion of 0 requires hashing the leaf and two intermediate nodes up to the root.
+    wf.delete(&Item(0), &proofs1[0], &hasher).unwrap();
+    assert_eq!(
+        wf.metrics(),
+        WorkForestMetrics {
+            nodes_hashed: 3,
+            subtrees_merged: 0,
+            deletions: 1,
+        }
+    );
+
+    // failed batch does not count deletions, but counts the hashing work.
+    assert!(wf
+        .batch(|wf| {
+            wf.delete(&Item(5), &proofs1[5], &hasher)?;
+            Err(UtreexoError::InvalidProof)
+        })
+        .is_err());
+    assert_eq!(
+        wf.metrics(),
+        WorkForestMetrics {
+            nodes_hashed: 5,
+            subtrees_merged: 0,
+            deletions: 1,
+        }
+    );
+
+    //  d
+    //  |\
+    //  a   b   c      ->        b   c      ->  b   c
+    //  |\  |\  |\               |\  |\         |\  |\
+    //  0 1 2 3 4 5          x 1 2 3 4 5        2 3 4 5 1
+    let (_forest2, catchup2) = wf.normalize(&hasher);
+    assert_eq!(
+        catchup2.metrics(),
+        WorkForestMetrics {
+            nodes_hashed: 6,
+            subtrees_merged: 1,
+            deletions: 1,
+        }
+    );
+}
+
+#[test]
+fn proof_encoding() {
+    use readerwriter::{Decodable, Encodable, ExactSizeEncodable};
+
+    let hasher = utreexo_hasher();
+    let (forest, catchup) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..6 {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .expect("cannot fail")
+        .normalize(&hasher);
+    let proof = catchup
+        .update_proof(&Item(3), Proof::Transient, &hasher)
+        .unwrap();
+
+    let bytes = proof.encode_to_vec();
+    assert_eq!(bytes.len(), proof.encoded_size());
+    let decoded = Proof::decode(&mut &bytes[..]).expect("must decode");
+    assert_eq!(decoded.as_path(), proof.as_path());
+    assert!(forest
+        .verify(&Item(3), decoded.as_path().unwrap(), &hasher)
+        .is_ok());
+
+    let transient = Proof::Transient.encode_to_vec();
+    assert_eq!(transient, vec![0]);
+    assert!(Proof::decode(&mut &transient[..])
+        .unwrap()
+        .as_path()
+        .is_none());
+
+    // unknown type and truncated path are rejected
+    assert!(Proof::decode(&mut &[2u8][..]).is_err());
+    assert!(Proof::decode(&mut &bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn batch_proofs_update() {
+    let n = 300u64;
+    let hasher = utreexo_hasher();
+    let (forest1, catchup1) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..n {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .expect("cannot fail")
+        .normalize(&hasher);
+    let proofs1 = (0..n)
+        .map(|i| {
+            catchup1
+                .update_proof(&Item(i), Proof::Transient, &hasher)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // delete every 5th item and add a few new ones
+    let (forest2, catchup2) = forest1
+        .work_forest()
+        .batch::<_, UtreexoError>(|forest| {
+            for i in (0..n).step_by(5) {
+                forest.delete(&Item(i), &proofs1[i as usize], &hasher)?;
+            }
+            for i in n..n + 10 {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .expect("all proofs must be valid")
+        .normalize(&hasher);
+
+    let mut batch = (0..n)
+        .filter(|i| i % 5 != 0)
+        .map(|i| (Item(i), proofs1[i as usize].clone()))
+        .chain((n..n + 10).map(|i| (Item(i), Proof::Transient)))
+        .collect::<Vec<_>>();
+    let expected = batch
+        .iter()
+        .map(|(item, proof)| catchup2.update_proof(item, proof.clone(), &hasher).unwrap())
+        .collect::<Vec<_>>();
+    catchup2
+        .update_proofs(&mut batch, &hasher)
+        .expect("all proofs are up to date");
+    for ((item, proof), expected) in batch.iter().zip(expected.iter()) {
+        let path = proof.as_path().expect("all items are committed");
+        assert_eq!(Some(path), expected.as_path());
+        assert!(forest2.verify(item, path, &hasher).is_ok());
+    }
+
+    // deleted items are reported, and the uncommitted item remains transient
+    let mut batch = vec![
+        (Item(1), proofs1[1].clone()),
+        (Item(5), proofs1[5].clone()),
+        (Item(n + 100), Proof::Transient),
+        (Item(10), proofs1[10].clone()),
+    ];
+    assert_eq!(
+        catchup2.update_proofs(&mut batch, &hasher),
+        Err(UtreexoError::OutdatedProofs(vec![1, 3]))
+    );
+    assert!(forest2
+        .verify(&Item(1), batch[0].1.as_path().unwrap(), &hasher)
+        .is_ok());
+    assert_eq!(batch[1].1.as_path(), proofs1[5].as_path());
+    assert!(batch[2].1.as_path().is_none());
+}
+
+#[test]
+fn pruned_catchup() {
+    use readerwriter::Encodable;
+
+    let n = 1000u64;
+    let hasher = utreexo_hasher();
+    let (forest1, catchup1) = Forest::new()
+        .work_forest()
+        .batch::<_, ()>(|forest| {
+            for i in 0..n {
+                forest.insert(&Item(i), &hasher);
+            }
+            Ok(())
+        })
+        .expect("cannot fail")
+        .normalize(&hasher);
+    let proofs1 = (0..n)
+        .map(|i| {
+            catchup1
+                .update_proof(&Item(i), Proof::Transient, &hasher)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let (forest2, catchup2) = forest1
+        .work_forest()
+        .batch::<_, UtreexoError>(|forest| {
+            for i in (0..n).step_by(3) {
+                forest.delete(&Item(i), &proofs1[i as usize], &hasher)?;
+            }
+            forest.insert(&Item(n), &hasher);
+            Ok(())
+        })
+        .expect("all proofs must be valid")
+        .normalize(&hasher);
+
+    let retained = vec![
+        (Item(1), proofs1[1].clone()),
+        (Item(500), proofs1[500].clone()),
+        (Item(n), Proof::Transient),
+    ];
+    let pruned = catchup2.prune(&retained, &hasher);
+    assert!(pruned.encode_to_vec().len() < catchup2.encode_to_vec().len());
+
+    // retained proofs are updated the same way and verify against the new forest
+    for (item, proof) in retained.iter() {
+        let expected = catchup2.update_proof(item, proof.clone(), &hasher).unwrap();
+        let actual = pruned.update_proof(item, proof.clone(), &hasher).unwrap();
+        assert_eq!(actual.as_path(), expected.as_path());
+        assert!(forest2
+            .verify(item, actual.as_path().unwrap(), &hasher)
+            .is_ok());
+    }
+
+    // other proofs can no longer be updated
+    assert!(catchup2
+        .update_proof(&Item(800), proofs1[800].clone(), &hasher)
+        .is_ok());
+    assert_eq!(
+        pruned
+            .update_proof(&Item(800), proofs1[800].clone(), &hasher)
+            .unwrap_err(),
+        UtreexoError::InvalidProof
+    );
+}
+
+#[test]
+fn root_path() {
+    let hasher = utreexo_hasher();
+    for n in 1..14 {
+        let (forest, catchup) = Forest::new()
+            .work_forest()
+            .batch::<_, ()>(|forest| {
+                for i in 0..n {
+                    forest.insert(&Item(i), &hasher);
+                }
+                Ok(())
+            })
+            .expect("cannot fail")
+            .normalize(&hasher);
+        let root = forest.root(&hasher);
+
+        for i in 0..n {
+            let proof = catchup
+                .update_proof(&Item(i), Proof::Transient, &hasher)
+                .unwrap();
+            let path = forest.root_path(proof.as_path().unwrap(), &hasher).unwrap();
+            assert!(path.verify_root(&root, &Item(i), &hasher));
+            assert!(!path.verify_root(&root, &Item(n), &hasher));
+
+            // path within the tree alone does not verify against the root of the forest,
+            // unless the forest consists of a single tree.
+            let tree_path = proof.as_path().unwrap();
+            assert_eq!(
+                tree_path.verify_root(&root, &Item(i), &hasher),
+                n.is_power_of_two()
+            );
+        }
+    }
+}
@@ -411,6 +411,7 @@ impl Wallet {
         let reply = accounts::ReceiverReply {
             receiver_id: payment_receiver.id(),
             anchor: payment_anchor,
+            note: None,
         };
 
         let change_utxo = Utxo {
@@ -581,6 +582,7 @@ impl Wallet {
         let reply = accounts::ReceiverReply {
             receiver_id: payment_receiver.id(),
             anchor: payment_anchor,
+            note: None,
         };
 
         // Sign the tx.
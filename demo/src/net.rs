@@ -72,6 +72,8 @@ pub fn launch_p2p() -> P2PHandle {
             inbound_limit: 100,
             outbound_limit: 100,
             heartbeat_interval_sec: 3600,
+            ban_threshold: 100,
+            ban_duration_sec: 86400,
         };
 
         let mut rt =
@@ -99,12 +101,19 @@ pub fn launch_p2p() -> P2PHandle {
             while let Some(notif) = notifications_channel.recv().await {
                 match notif {
                     NodeNotification::PeerAdded(pid) => println!("p2p:    Peer connected: {}", pid),
-                    NodeNotification::PeerDisconnected(pid) => {
-                        println!("p2p: Peer disconnected: {}", pid)
+                    NodeNotification::PeerBanned(pid) => println!("p2p:    Peer banned: {}", pid),
+                    NodeNotification::PeerDisconnected(pid, reason) => {
+                        println!("p2p: Peer disconnected: {} ({:?})", pid, reason)
                     }
                     NodeNotification::MessageReceived(pid, msg) => {
                         println!("p2p: Received: `{:?}` from {}", msg, pid)
                     }
+                    NodeNotification::RequestReceived(pid, request_id, msg) => {
+                        println!(
+                            "p2p: Received request #{}: `{:?}` from {}",
+                            request_id, msg, pid
+                        )
+                    }
                     NodeNotification::InboundConnectionFailure(err) => {
                         println!("p2p: Inbound connection failure: {:?}", err)
                     }
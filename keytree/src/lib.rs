@@ -7,15 +7,22 @@ use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use rand::{CryptoRng, RngCore};
 use starsig::VerificationKey;
+use std::collections::HashMap;
 
 use crate::transcript::TranscriptProtocol;
 
 mod serialization;
 mod transcript;
 
+// Convenience re-export of the transcript helpers from `starsig` crate.
+pub use starsig::TranscriptProtocol as StarsigTranscriptProtocol;
+
 #[cfg(test)]
 mod tests;
 
+/// Transcript label used by `derive_child` to commit the child index.
+pub const CHILD_INDEX_LABEL: &[u8] = b"index";
+
 /// Xprv represents an extended private key.
 /// TBD: change serialization to encode a single 64-byte blob, with hex for human-readable formats
 #[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
@@ -94,6 +101,13 @@ impl Xprv {
         }
     }
 
+    /// Returns an intermediate Xprv for a given child index.
+    /// The index is committed as `prf.append_u64("index", index)`,
+    /// so the result matches `Xpub::derive_child` with the same index.
+    pub fn derive_child(&self, index: u64) -> Xprv {
+        self.derive_intermediate_key(|prf| prf.append_u64(CHILD_INDEX_LABEL, index))
+    }
+
     /// Returns a leaf secret scalar derived using a PRF customized with a user-provided closure.
     pub fn derive_key(&self, customize: impl FnOnce(&mut Transcript)) -> Scalar {
         let f = self
@@ -102,6 +116,24 @@ impl Xprv {
         self.scalar + f
     }
 
+    /// Returns a leaf secret scalar together with its `VerificationKey`,
+    /// both derived using a PRF customized with a user-provided closure.
+    /// The pair is guaranteed to be consistent: the verification key is the one
+    /// `Xpub::derive_key` returns for the same customization, and it matches the secret scalar.
+    pub fn derive_keypair(
+        &self,
+        customize: impl FnOnce(&mut Transcript),
+    ) -> (Scalar, VerificationKey) {
+        let f = self
+            .xpub
+            .derive_leaf_helper(self.xpub.prepare_prf(), customize);
+        let secret = self.scalar + f;
+        let pubkey: VerificationKey =
+            (self.xpub.pubkey_decompressed + (&f * &constants::RISTRETTO_BASEPOINT_TABLE)).into();
+        debug_assert_eq!(VerificationKey::from_secret(&secret), pubkey);
+        (secret, pubkey)
+    }
+
     /// Serializes this Xprv to a sequence of bytes.
     pub fn to_bytes(&self) -> [u8; 64] {
         let mut buf = [0u8; 64];
@@ -137,10 +169,52 @@ impl Xpub {
         xpub
     }
 
+    /// Returns an intermediate Xpub for a given child index.
+    /// The index is committed as `prf.append_u64("index", index)`,
+    /// so the result matches `Xprv::derive_child` with the same index.
+    pub fn derive_child(&self, index: u64) -> Xpub {
+        self.derive_intermediate_key(|prf| prf.append_u64(CHILD_INDEX_LABEL, index))
+    }
+
     /// Returns a leaf `VerificationKey` derived using a PRF customized with a user-provided closure.
     pub fn derive_key(&self, customize: impl FnOnce(&mut Transcript)) -> VerificationKey {
-        let f = self.derive_leaf_helper(self.prepare_prf(), customize);
-        (self.pubkey_decompressed + (&f * &constants::RISTRETTO_BASEPOINT_TABLE)).into()
+        self.derive_leaf_point(self.prepare_prf(), customize).into()
+    }
+
+    /// Derives the leaf keys for indices from 0 to `max_index` inclusive and finds them among the `outputs`.
+    /// Returns the pairs `(output index, derivation index)` of the matches, ordered by the output index.
+    ///
+    /// The key for an index is the same as `derive_key(|prf| customize(prf, index))`,
+    /// but the PRF is prepared once for all the indices, and the outputs are looked up in a hash map,
+    /// so the scan costs one scalar multiplication per index regardless of the number of outputs.
+    /// The scan stops early once all the outputs are matched.
+    /// Lookups are not constant-time, so the outputs are expected to be public data.
+    pub fn scan_candidates(
+        &self,
+        outputs: &[VerificationKey],
+        max_index: u64,
+        customize: impl Fn(&mut Transcript, u64),
+    ) -> Vec<(usize, u64)> {
+        let mut positions: HashMap<CompressedRistretto, Vec<usize>> = HashMap::new();
+        for (i, key) in outputs.iter().enumerate() {
+            positions.entry(key.into_point()).or_default().push(i);
+        }
+
+        let prf = self.prepare_prf();
+        let mut matches = Vec::new();
+        for index in 0..=max_index {
+            if positions.is_empty() {
+                break;
+            }
+            let key = self
+                .derive_leaf_point(prf.clone(), |prf| customize(prf, index))
+                .compress();
+            if let Some(found) = positions.remove(&key) {
+                matches.extend(found.into_iter().map(|i| (i, index)));
+            }
+        }
+        matches.sort();
+        matches
     }
 
     /// Serializes this Xpub to a sequence of bytes.
@@ -215,4 +289,13 @@ impl Xpub {
         customize(&mut prf);
         prf.challenge_scalar(b"f.leaf")
     }
+
+    fn derive_leaf_point(
+        &self,
+        prf: Transcript,
+        customize: impl FnOnce(&mut Transcript),
+    ) -> RistrettoPoint {
+        let f = self.derive_leaf_helper(prf, customize);
+        self.pubkey_decompressed + (&f * &constants::RISTRETTO_BASEPOINT_TABLE)
+    }
 }
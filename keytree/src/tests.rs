@@ -131,6 +131,23 @@ fn random_xprv_derivation_test() {
     );
 }
 
+#[test]
+fn derive_child_test() {
+    let seed = [0u8; 32];
+    let mut rng = ChaChaRng::from_seed(seed);
+    let xprv = Xprv::random(&mut rng);
+    let xpub = xprv.to_xpub();
+
+    let child_xprv = xprv.derive_child(7);
+    let child_xpub = xpub.derive_child(7);
+    assert_eq!(child_xprv.to_xpub(), child_xpub);
+    assert_eq!(
+        child_xprv,
+        xprv.derive_intermediate_key(|prf| prf.append_u64(b"index", 7))
+    );
+    assert_ne!(child_xpub, xpub.derive_child(8));
+}
+
 #[test]
 fn random_xprv_leaf_test() {
     let seed = [0u8; 32];
@@ -145,6 +162,19 @@ fn random_xprv_leaf_test() {
     );
 }
 
+#[test]
+fn derive_keypair_test() {
+    let seed = [0u8; 32];
+    let mut rng = ChaChaRng::from_seed(seed);
+    let xprv = Xprv::random(&mut rng);
+    let customize = |prf: &mut Transcript| prf.append_u64(b"invoice_id", 10034);
+
+    let (secret, pubkey) = xprv.derive_keypair(customize);
+    assert_eq!(secret, xprv.derive_key(customize));
+    assert_eq!(pubkey, xprv.as_xpub().derive_key(customize));
+    assert_eq!(pubkey, VerificationKey::from_secret(&secret));
+}
+
 #[test]
 fn serialize_xprv_test() {
     let seed = [0u8; 32];
@@ -251,6 +281,31 @@ fn random_xpub_leaf_test() {
     );
 }
 
+#[test]
+fn scan_candidates_test() {
+    let seed = [0u8; 32];
+    let mut rng = ChaChaRng::from_seed(seed);
+    let xpub = Xprv::random(&mut rng).to_xpub();
+    let key_at = |index: u64| xpub.derive_key(|prf| prf.append_u64(b"invoice_id", index));
+    let other = Xprv::random(&mut rng)
+        .to_xpub()
+        .derive_key(|prf| prf.append_u64(b"invoice_id", 3));
+
+    let outputs = vec![key_at(7), other, key_at(3), key_at(7), key_at(12)];
+    let scan = |max_index| {
+        xpub.scan_candidates(&outputs, max_index, |prf, index| {
+            prf.append_u64(b"invoice_id", index)
+        })
+    };
+
+    assert_eq!(scan(10), vec![(0, 7), (2, 3), (3, 7)]);
+    assert_eq!(scan(12), vec![(0, 7), (2, 3), (3, 7), (4, 12)]);
+    assert!(scan(2).is_empty());
+    assert!(xpub
+        .scan_candidates(&outputs, 20, |prf, index| prf.append_u64(b"other", index))
+        .is_empty());
+}
+
 fn to_hex_32(input: [u8; 32]) -> String {
     return hex::encode(&input[..]);
 }
@@ -1,11 +1,18 @@
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 //! API for operations on merkle binary trees.
+//! The crate can be used in `no_std` environments with `alloc` when the default `std` feature is disabled.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
 use core::marker::PhantomData;
 use merlin::Transcript;
 use readerwriter::*;
 use serde::{Deserialize, Serialize};
-use std::fmt;
 use subtle::ConstantTimeEq;
 
 /// Merkle hash of a node.
@@ -34,6 +41,7 @@ pub struct MerkleRootBuilder<M: MerkleItem> {
     roots: Vec<Option<Hash>>,
 }
 
+#[cfg(feature = "std")]
 impl fmt::Debug for Hash {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Hash({})", hex::encode(&self.0))
@@ -42,6 +50,18 @@ impl fmt::Debug for Hash {
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Same output as with the hex crate, without allocating a string.
+        write!(f, "Hash(")?;
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
 impl MerkleTree {
     /// Builds and returns the root hash of a Merkle tree constructed from
     /// the supplied list.
@@ -58,6 +78,23 @@ impl MerkleTree {
             .root()
     }
 
+    /// Builds the root hash of a Merkle tree constructed from the supplied list,
+    /// and returns it along with the number of items in the list.
+    /// For an empty list returns the empty root and 0.
+    pub fn root_with_count<M, I>(label: &'static [u8], list: I) -> (Hash, u64)
+    where
+        M: MerkleItem,
+        I: IntoIterator<Item = M>,
+    {
+        let builder = list
+            .into_iter()
+            .fold(Self::build_root(label), |mut builder, item| {
+                builder.append(&item);
+                builder
+            });
+        (builder.root(), builder.count())
+    }
+
     /// Prepares a root builder to compute the root iteratively.
     pub fn build_root<M: MerkleItem>(label: &'static [u8]) -> MerkleRootBuilder<M> {
         MerkleRootBuilder {
@@ -120,6 +157,17 @@ impl<M: MerkleItem> MerkleRootBuilder<M> {
         MerkleTree::connect_perfect_roots(self.roots.iter().filter_map(|r| *r), &self.hasher)
     }
 
+    /// Returns the number of items appended to the builder.
+    pub fn count(&self) -> u64 {
+        // Filled slot at level i holds the root of a perfect tree of 2^i items.
+        self.roots
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.is_some())
+            .map(|(level, _)| 1u64 << level)
+            .sum()
+    }
+
     /// Resets the builder to the clean state,
     /// keeping allocated memory.
     /// Use this to recycle allocated memoy when you need to compute multiple roots.
@@ -146,6 +194,42 @@ where
     }
 }
 
+impl Hash {
+    /// Parses the hash from a string of 64 hex digits (in lower or upper case).
+    /// Returns None if the string has a different length or contains non-hex characters.
+    pub fn from_hex(s: &str) -> Option<Hash> {
+        let digits = s.as_bytes();
+        if digits.len() != 64 {
+            return None;
+        }
+        let mut hash = Hash::default();
+        for (byte, pair) in hash.0.iter_mut().zip(digits.chunks(2)) {
+            *byte = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+        }
+        Some(hash)
+    }
+
+    /// Encodes the hash as a string of 64 lowercase hex digits.
+    pub fn to_hex(&self) -> String {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut s = String::with_capacity(64);
+        for byte in self.0.iter() {
+            s.push(DIGITS[(byte >> 4) as usize] as char);
+            s.push(DIGITS[(byte & 0x0f) as usize] as char);
+        }
+        s
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
 impl AsRef<[u8]> for Hash {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -247,6 +331,29 @@ pub struct Path {
     pub neighbors: Vec<Hash>,
 }
 
+/// Maximum depth of a path that can be described by a `Position`: one bit per neighbor.
+pub const MAX_PATH_DEPTH: usize = 64;
+
+/// Error returned by `Path::validate`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PathError {
+    /// The path has more neighbors than allowed.
+    TooDeep,
+    /// The position has bits set beyond the depth of the path.
+    InvalidPosition,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::TooDeep => write!(f, "path is too deep"),
+            PathError::InvalidPosition => write!(f, "position does not match the path depth"),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for PathError {}
+
 /// Side of the neighbour produced by the `Directions` iterator.
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Side {
@@ -290,10 +397,25 @@ impl Path {
     }
 
     fn directions(&self) -> Directions {
-        Directions {
-            position: self.position,
-            depth: self.neighbors.len(),
+        Directions::new(self.position, self.neighbors.len())
+    }
+
+    /// Checks that the path has at most `max_depth` neighbors (and no more than `MAX_PATH_DEPTH`),
+    /// and that the position has no bits set beyond the depth of the path.
+    /// Use this to reject malformed paths received from the network before hashing them.
+    ///
+    /// Note: this applies to paths within a single tree, such as the ones created by `Path::new`.
+    /// Paths in the utreexo forest use the absolute position of the item in the forest,
+    /// where the higher bits select the tree.
+    pub fn validate(&self, max_depth: usize) -> Result<(), PathError> {
+        let depth = self.neighbors.len();
+        if depth > max_depth || depth > MAX_PATH_DEPTH {
+            return Err(PathError::TooDeep);
+        }
+        if self.position.checked_shr(depth as u32).unwrap_or(0) != 0 {
+            return Err(PathError::InvalidPosition);
         }
+        Ok(())
     }
 
     /// Creates a new path by hashing the merkle tree on the fly
@@ -398,8 +520,16 @@ pub struct Directions {
 
 impl Directions {
     /// Creates a new directions object for a specified item’s position and depth.
+    /// Bits of the position beyond the depth are ignored (see `Path::validate`).
     pub fn new(position: Position, depth: usize) -> Self {
-        Self { position, depth }
+        let mask = (1 as Position)
+            .checked_shl(depth as u32)
+            .map(|m| m - 1)
+            .unwrap_or(Position::max_value());
+        Self {
+            position: position & mask,
+            depth,
+        }
     }
 }
 
@@ -434,7 +564,9 @@ impl DoubleEndedIterator for Directions {
         self.depth -= 1;
         // Note: we do not mask out the bit in `position` because we don't expose it.
         // The bit is ignored implicitly by having the depth decremented.
-        let side = Side::from_bit(((self.position >> self.depth) & 1) as u8);
+        // Positions have no bits beyond 64 levels, so deeper levels are always on the left.
+        let bit = self.position.checked_shr(self.depth as u32).unwrap_or(0) & 1;
+        let side = Side::from_bit(bit as u8);
         Some(side)
     }
 }
@@ -548,4 +680,70 @@ mod tests {
             assert_proof_err!(num, idx, wrong_idx);
         }
     }
+
+    #[test]
+    fn root_with_count() {
+        let (root, count) = MerkleTree::root_with_count(b"test", Vec::<TestItem>::new());
+        assert_eq!(root, MerkleTree::empty_root(b"test"));
+        assert_eq!(count, 0);
+
+        for num in [1, 2, 3, 7, 8, 25].iter() {
+            let items = test_items(*num);
+            let (root, count) = MerkleTree::root_with_count(b"test", items.iter());
+            assert_eq!(root, MerkleTree::root(b"test", items.iter()));
+            assert_eq!(count, *num as u64);
+        }
+    }
+
+    #[test]
+    fn validate_path() {
+        let hasher = Hasher::new(b"test");
+        let items = test_items(32);
+        for idx in [0, 9, 31].iter() {
+            let path = Path::new(&items, *idx, &hasher).unwrap();
+            assert_eq!(path.validate(5), Ok(()));
+            assert_eq!(path.validate(4), Err(PathError::TooDeep));
+        }
+
+        let mut path = Path::new(&items, 31, &hasher).unwrap();
+        path.position |= 1 << path.neighbors.len();
+        assert_eq!(path.validate(5), Err(PathError::InvalidPosition));
+        // Directions ignore the bits beyond the depth.
+        assert!(path.verify_root(
+            &MerkleTree::root(b"test", items.iter()),
+            &items[31],
+            &hasher
+        ));
+
+        let deep = Path {
+            position: u64::max_value(),
+            neighbors: vec![Hash::default(); MAX_PATH_DEPTH + 1],
+        };
+        assert_eq!(deep.validate(usize::max_value()), Err(PathError::TooDeep));
+        assert_eq!(deep.iter().rev().count(), MAX_PATH_DEPTH + 1);
+        let full = Path {
+            position: u64::max_value(),
+            neighbors: vec![Hash::default(); MAX_PATH_DEPTH],
+        };
+        assert_eq!(full.validate(MAX_PATH_DEPTH), Ok(()));
+    }
+
+    #[test]
+    fn hash_hex() {
+        let hash = MerkleTree::root(b"test", test_items(5).iter());
+        let hex = hash.to_hex();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(format!("{:?}", hash), format!("Hash({})", hex));
+        assert_eq!(Hash::from_hex(&hex), Some(hash));
+        assert_eq!(Hash::from_hex(&hex.to_uppercase()), Some(hash));
+        assert_eq!(Hash::default().to_hex(), "0".repeat(64));
+
+        assert_eq!(Hash::from_hex(""), None);
+        assert_eq!(Hash::from_hex(&hex[..62]), None);
+        assert_eq!(Hash::from_hex(&format!("{}00", hex)), None);
+        assert_eq!(Hash::from_hex(&format!("{}zz", &hex[..62])), None);
+        assert_eq!(Hash::from_hex(&format!("0x{}", &hex[..62])), None);
+        // multi-byte characters are rejected, not split
+        assert_eq!(Hash::from_hex(&format!("é{}", &hex[..62])), None);
+    }
 }
@@ -0,0 +1,132 @@
+use core::borrow::Borrow;
+use core::iter;
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+use starsig::{
+    BatchVerification, Signature, SingleVerifier, StarsigError, TranscriptProtocol, VerificationKey,
+};
+
+use super::{Multimessage, MusigContext, MusigError};
+
+/// Multi-message signature encrypted with an adaptor point `T = t·G`
+/// (also known as a pre-signature).
+///
+/// The nonce commitment `R` includes the adaptor point, so the signer cannot compute the actual signature
+/// without the adaptor secret `t`: `s'·G = R - T + sum{c_i·X_i}`.
+/// Whoever knows `t` can complete it into a valid signature `(s' + t, R)`,
+/// and whoever sees both the adaptor signature and the completed signature learns `t = s - s'`.
+/// This allows swapping a signature for a secret atomically.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AdaptorSignature {
+    /// Signature scalar `s'`, without the adaptor secret.
+    pub s: Scalar,
+    /// Nonce commitment `R = r·G + T`.
+    pub R: CompressedRistretto,
+}
+
+impl AdaptorSignature {
+    /// Creates a multi-message signature encrypted with the adaptor point.
+    /// The private keys must be listed in the same order as the messages' verification keys,
+    /// same as in [`Multisignature::sign_multi`](crate::Multisignature::sign_multi).
+    /// Returns `MusigError::BadArguments` if the number of keys does not match the number of messages,
+    /// or if there are no messages, and `MusigError::InvalidPoint` if the adaptor point is invalid.
+    pub fn sign_multi<P, M>(
+        privkeys: P,
+        messages: Vec<(VerificationKey, M)>,
+        adaptor: &CompressedRistretto,
+        transcript: &mut Transcript,
+    ) -> Result<Self, MusigError>
+    where
+        M: AsRef<[u8]>,
+        P: IntoIterator,
+        P::Item: Borrow<Scalar>,
+        P::IntoIter: ExactSizeIterator,
+    {
+        let mut privkeys = privkeys.into_iter().peekable();
+
+        if messages.len() != privkeys.len() {
+            return Err(MusigError::BadArguments);
+        }
+        if privkeys.len() == 0 {
+            return Err(MusigError::BadArguments);
+        }
+        let T = adaptor.decompress().ok_or(MusigError::InvalidPoint)?;
+
+        let context = Multimessage::new(messages);
+
+        let mut rng = transcript
+            .build_rng()
+            // We can call unwrap because we know that the privkeys length is > 0.
+            .rekey_with_witness_bytes(b"x_i", privkeys.peek().unwrap().borrow().as_bytes())
+            .finalize(&mut rand::thread_rng());
+
+        // R = generator * r + T
+        let r = Scalar::random(&mut rng);
+        let R = (RISTRETTO_BASEPOINT_POINT * r + T).compress();
+
+        context.commit(transcript);
+        transcript.append_point(b"R", &R);
+
+        // s' = r + sum{c_i * x_i}
+        let mut s = r;
+        for (i, x_i) in privkeys.enumerate() {
+            let mut t = transcript.clone();
+            let c_i = context.challenge(i, &mut t);
+            s = s + c_i * x_i.borrow();
+        }
+
+        Ok(AdaptorSignature { s, R })
+    }
+
+    /// Verifies that the adaptor signature can be completed with the secret of the adaptor point
+    /// into a valid multi-message signature.
+    pub fn verify_multi<M: AsRef<[u8]>>(
+        &self,
+        transcript: &mut Transcript,
+        messages: Vec<(VerificationKey, M)>,
+        adaptor: &CompressedRistretto,
+    ) -> Result<(), StarsigError> {
+        SingleVerifier::verify(|verifier| {
+            let context = Multimessage::new(messages);
+            context.commit(transcript);
+            transcript.append_point(b"R", &self.R);
+
+            // `s' * G = R - T + sum{c_i * X_i}`
+            let n = context.len();
+            verifier.append(
+                -self.s,
+                iter::once(Scalar::one())
+                    .chain(iter::once(-Scalar::one()))
+                    .chain((0..n).map(|i| context.challenge(i, &mut transcript.clone()))),
+                iter::once(self.R.decompress())
+                    .chain(iter::once(adaptor.decompress()))
+                    .chain((0..n).map(|i| context.key(i).into_point().decompress())),
+            );
+        })
+    }
+
+    /// Completes the adaptor signature with the secret of the adaptor point.
+    pub fn complete(&self, adaptor_secret: Scalar) -> Signature {
+        Signature {
+            s: self.s + adaptor_secret,
+            R: self.R,
+        }
+    }
+
+    /// Extracts the adaptor secret from the completed signature.
+    /// Returns None if the signature was not completed from this adaptor signature,
+    /// or the extracted secret does not match the adaptor point.
+    pub fn extract(&self, signature: &Signature, adaptor: &CompressedRistretto) -> Option<Scalar> {
+        if signature.R != self.R {
+            return None;
+        }
+        let secret = signature.s - self.s;
+        if (RISTRETTO_BASEPOINT_POINT * secret).compress() != *adaptor {
+            return None;
+        }
+        Some(secret)
+    }
+}
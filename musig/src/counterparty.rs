@@ -13,11 +13,25 @@ pub struct NoncePrecommitment([u8; 32]);
 #[derive(Copy, Clone, Debug)]
 pub struct NonceCommitment(RistrettoPoint);
 
+impl NoncePrecommitment {
+    pub(super) fn from_bytes(bytes: [u8; 32]) -> Self {
+        NoncePrecommitment(bytes)
+    }
+
+    pub(super) fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
 impl NonceCommitment {
     pub(super) fn new(commitment: RistrettoPoint) -> Self {
         NonceCommitment(commitment)
     }
 
+    pub(super) fn to_bytes(&self) -> [u8; 32] {
+        self.0.compress().to_bytes()
+    }
+
     pub(super) fn precommit(&self) -> NoncePrecommitment {
         let mut h = Transcript::new(b"Musig.nonce-precommit");
         h.append_point(b"R", &self.0.compress());
@@ -63,9 +77,23 @@ impl Counterparty {
             pubkey: self.pubkey,
         }
     }
+
+    /// Skips the precommitment round when resuming a session
+    /// in which the commitment was already checked against the precommitment.
+    pub(super) fn commit_nonce(self, commitment: NonceCommitment) -> CounterpartyCommitted {
+        CounterpartyCommitted {
+            commitment,
+            position: self.position,
+            pubkey: self.pubkey,
+        }
+    }
 }
 
 impl CounterpartyPrecommitted {
+    pub(super) fn precommitment(&self) -> NoncePrecommitment {
+        self.precommitment
+    }
+
     pub(super) fn verify_nonce(
         self,
         commitment: NonceCommitment,
@@ -74,7 +102,8 @@ impl CounterpartyPrecommitted {
         let received_precommitment = commitment.precommit();
         let equal = self.precommitment.0.ct_eq(&received_precommitment.0);
         if equal.unwrap_u8() == 0 {
-            return Err(MusigError::ShareError {
+            return Err(MusigError::NonceCommitmentError {
+                position: self.position,
                 pubkey: self.pubkey.to_bytes(),
             });
         }
@@ -88,6 +117,10 @@ impl CounterpartyPrecommitted {
 }
 
 impl CounterpartyCommitted {
+    pub(super) fn commitment(&self) -> NonceCommitment {
+        self.commitment
+    }
+
     pub(super) fn verify_share<C: MusigContext>(
         self,
         share: Scalar,
@@ -105,6 +138,7 @@ impl CounterpartyCommitted {
 
         if S_i != self.commitment.0 + c_i * X_i {
             return Err(MusigError::ShareError {
+                position: self.position,
                 pubkey: X_i_compressed.to_bytes(),
             });
         }
@@ -8,12 +8,23 @@ pub enum MusigError {
     InvalidPoint,
 
     /// This error occurs when a signature share fails to verify
-    #[error("Share #{pubkey:?} failed to verify correctly")]
+    #[error("Share #{position} failed to verify correctly")]
     ShareError {
+        /// The position of the party that sent the invalid share
+        position: usize,
         /// The pubkey corresponding to the share that failed fo verify correctly
         pubkey: [u8; 32],
     },
 
+    /// This error occurs when a nonce commitment does not match its precommitment
+    #[error("Nonce commitment #{position} does not match the precommitment")]
+    NonceCommitmentError {
+        /// The position of the party that sent the mismatching commitment
+        position: usize,
+        /// The pubkey of the party that sent the mismatching commitment
+        pubkey: [u8; 32],
+    },
+
     /// This error occurs when an individual point operation failed.
     #[error("Point operation failed")]
     PointOperationFailed,
@@ -21,4 +32,9 @@ pub enum MusigError {
     /// This error occurs when a function is called with bad arguments.
     #[error("Bad arguments")]
     BadArguments,
+
+    /// This error occurs when a serialized signer state is malformed
+    /// or belongs to a different signing session.
+    #[error("Invalid signer session")]
+    InvalidSession,
 }
@@ -2,6 +2,7 @@
 #![allow(non_snake_case)]
 //! Musig implementation
 
+mod adaptor;
 mod context;
 mod counterparty;
 mod multisignature;
@@ -19,6 +20,7 @@ pub use starsig::{
     BatchVerification, BatchVerifier, Signature, SingleVerifier, StarsigError, VerificationKey,
 };
 
+pub use self::adaptor::AdaptorSignature;
 pub use self::context::{Multikey, Multimessage, MusigContext};
 pub use self::errors::MusigError;
 pub use self::multisignature::Multisignature;
@@ -12,6 +12,14 @@ use super::{Multimessage, MusigContext, MusigError};
 /// Extension trait for `starsig::Signature`.
 pub trait Multisignature {
     /// Creates a multi-message signature.
+    ///
+    /// This is a one-shot alternative to the multi-party [`Signer`](crate::Signer) protocol
+    /// for the case when all the private keys are available locally.
+    /// The private keys must be listed in the same order as the messages' verification keys.
+    /// Returns `MusigError::BadArguments` if the number of keys does not match the number of messages,
+    /// or if there are no messages.
+    /// The signature is verified with [`verify_multi`](Multisignature::verify_multi),
+    /// same as the signature created with the `Signer` protocol.
     fn sign_multi<P, M>(
         privkeys: P,
         messages: Vec<(VerificationKey, M)>,
@@ -1,21 +1,27 @@
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use rand;
 
-use starsig::{Signature, TranscriptProtocol};
+use starsig::{Signature, TranscriptProtocol, VerificationKey};
 
 use super::counterparty::*;
 use super::{MusigContext, MusigError};
 
 /// Entry point to multi-party signing protocol.
+///
+/// Each signer state can be serialized with `to_bytes` and resumed with `from_bytes`
+/// to continue the session in another process between the rounds.
+/// The serialized state is bound to the transcript, the context and the signer's position,
+/// so it cannot be resumed for a different message.
 pub struct Signer {}
 
 /// State of the party when awaiting nonce precommitments from other parties.
 pub struct SignerAwaitingPrecommitments<'t, C: MusigContext> {
     transcript: &'t mut Transcript,
     context: C,
+    session_id: [u8; 32],
     position: usize,
     x_i: Scalar,
     r_i: Scalar,
@@ -27,6 +33,7 @@ pub struct SignerAwaitingPrecommitments<'t, C: MusigContext> {
 pub struct SignerAwaitingCommitments<'t, C: MusigContext> {
     transcript: &'t mut Transcript,
     context: C,
+    session_id: [u8; 32],
     position: usize,
     x_i: Scalar,
     r_i: Scalar,
@@ -37,6 +44,8 @@ pub struct SignerAwaitingCommitments<'t, C: MusigContext> {
 pub struct SignerAwaitingShares<C: MusigContext> {
     transcript: Transcript,
     context: C,
+    session_id: [u8; 32],
+    position: usize,
     R: RistrettoPoint,
     counterparties: Vec<CounterpartyCommitted>,
 }
@@ -65,11 +74,13 @@ impl Signer {
         let counterparties = (0..context.len())
             .map(|i| Counterparty::new(i, context.key(i)))
             .collect();
+        let session_id = session_id(transcript, &context, position);
 
         (
             SignerAwaitingPrecommitments {
                 transcript,
                 context,
+                session_id,
                 position,
                 x_i,
                 r_i,
@@ -98,6 +109,7 @@ impl<'t, C: MusigContext> SignerAwaitingPrecommitments<'t, C> {
             SignerAwaitingCommitments {
                 transcript: self.transcript,
                 context: self.context,
+                session_id: self.session_id,
                 position: self.position,
                 x_i: self.x_i,
                 r_i: self.r_i,
@@ -106,15 +118,65 @@ impl<'t, C: MusigContext> SignerAwaitingPrecommitments<'t, C> {
             self.R_i,
         )
     }
+
+    /// Serializes the signer state to resume the session with [`from_bytes`](Self::from_bytes).
+    ///
+    /// WARNING: the serialized state contains the secret key `x_i` and the secret nonce `r_i`.
+    /// It must be stored as securely as the secret key, and deleted once the session is resumed:
+    /// resuming the same state twice reuses the nonce and can reveal the secret key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(32 + 8 + 32 + 32);
+        write_header(&mut buf, &self.session_id, self.position);
+        buf.extend_from_slice(self.x_i.as_bytes());
+        buf.extend_from_slice(self.r_i.as_bytes());
+        buf
+    }
+
+    /// Resumes the signer state serialized with [`to_bytes`](Self::to_bytes).
+    /// The transcript and the context must be the same as the ones passed to [`Signer::new`].
+    /// Returns `MusigError::InvalidSession` if the state is malformed or belongs to a different session.
+    pub fn from_bytes(
+        bytes: &[u8],
+        transcript: &'t mut Transcript,
+        context: C,
+    ) -> Result<Self, MusigError> {
+        let mut reader = StateReader(bytes);
+        let (session_id, position) = reader.read_header(transcript, &context)?;
+        let x_i = reader.read_secret_key(&context, position)?;
+        let r_i = reader.read_scalar()?;
+        reader.finish()?;
+
+        let counterparties = (0..context.len())
+            .map(|i| Counterparty::new(i, context.key(i)))
+            .collect();
+
+        Ok(SignerAwaitingPrecommitments {
+            transcript,
+            context,
+            session_id,
+            position,
+            x_i,
+            r_i,
+            R_i: NonceCommitment::new(RISTRETTO_BASEPOINT_POINT * r_i),
+            counterparties,
+        })
+    }
 }
 
 impl<'t, C: MusigContext> SignerAwaitingCommitments<'t, C> {
     /// Provide nonce commitments to the party and transition to the next round
     /// if they match the precommitments.
+    /// Returns `MusigError::NonceCommitmentError` with the position of the first party
+    /// whose commitment does not match its precommitment,
+    /// and `MusigError::BadArguments` if the number of commitments does not match the number of parties.
     pub fn receive_commitments(
         mut self,
         nonce_commitments: Vec<NonceCommitment>,
     ) -> Result<(SignerAwaitingShares<C>, Scalar), MusigError> {
+        if nonce_commitments.len() != self.counterparties.len() {
+            return Err(MusigError::BadArguments);
+        }
+
         // Make R = sum_i(R_i). nonce_commitments = R_i from all the parties.
         let R = NonceCommitment::sum(&nonce_commitments);
 
@@ -146,15 +208,66 @@ impl<'t, C: MusigContext> SignerAwaitingCommitments<'t, C> {
             SignerAwaitingShares {
                 transcript,
                 context: self.context,
+                session_id: self.session_id,
+                position: self.position,
                 R,
                 counterparties,
             },
             s_i,
         ))
     }
+
+    /// Serializes the signer state to resume the session with [`from_bytes`](Self::from_bytes).
+    ///
+    /// WARNING: the serialized state contains the secret key `x_i` and the secret nonce `r_i`.
+    /// It must be stored as securely as the secret key, and deleted once the session is resumed:
+    /// resuming the same state twice reuses the nonce and can reveal the secret key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(32 + 8 + 32 + 32 + 8 + 32 * self.counterparties.len());
+        write_header(&mut buf, &self.session_id, self.position);
+        buf.extend_from_slice(self.x_i.as_bytes());
+        buf.extend_from_slice(self.r_i.as_bytes());
+        buf.extend_from_slice(&(self.counterparties.len() as u64).to_le_bytes());
+        for counterparty in self.counterparties.iter() {
+            buf.extend_from_slice(&counterparty.precommitment().to_bytes());
+        }
+        buf
+    }
+
+    /// Resumes the signer state serialized with [`to_bytes`](Self::to_bytes).
+    /// The transcript and the context must be the same as the ones passed to [`Signer::new`].
+    /// Returns `MusigError::InvalidSession` if the state is malformed or belongs to a different session.
+    pub fn from_bytes(
+        bytes: &[u8],
+        transcript: &'t mut Transcript,
+        context: C,
+    ) -> Result<Self, MusigError> {
+        let mut reader = StateReader(bytes);
+        let (session_id, position) = reader.read_header(transcript, &context)?;
+        let x_i = reader.read_secret_key(&context, position)?;
+        let r_i = reader.read_scalar()?;
+        reader.read_count(&context)?;
+        let counterparties = (0..context.len())
+            .map(|i| {
+                let precommitment = NoncePrecommitment::from_bytes(reader.read_bytes32()?);
+                Ok(Counterparty::new(i, context.key(i)).precommit_nonce(precommitment))
+            })
+            .collect::<Result<_, MusigError>>()?;
+        reader.finish()?;
+
+        Ok(SignerAwaitingCommitments {
+            transcript,
+            context,
+            session_id,
+            position,
+            x_i,
+            r_i,
+            counterparties,
+        })
+    }
 }
 
-impl<'t, C: MusigContext> SignerAwaitingShares<C> {
+impl<C: MusigContext> SignerAwaitingShares<C> {
     /// Assemble trusted signature shares (e.g. when all keys owned by one signer)
     pub fn receive_trusted_shares(self, shares: Vec<Scalar>) -> Signature {
         // s = sum(s_i), s_i = shares[i]
@@ -166,7 +279,13 @@ impl<'t, C: MusigContext> SignerAwaitingShares<C> {
     }
 
     /// Verify and assemble signature shares.
+    /// Returns `MusigError::ShareError` with the position of the first party whose share is invalid,
+    /// and `MusigError::BadArguments` if the number of shares does not match the number of parties.
     pub fn receive_shares(self, shares: Vec<Scalar>) -> Result<Signature, MusigError> {
+        if shares.len() != self.counterparties.len() {
+            return Err(MusigError::BadArguments);
+        }
+
         // Move out self's fields because `self.c` inside `map`'s closure would
         // lead to capturing `self` by reference, while we want
         // to move `self.counterparties` out of it.
@@ -188,3 +307,152 @@ impl<'t, C: MusigContext> SignerAwaitingShares<C> {
         })
     }
 }
+
+impl<C: MusigContext> SignerAwaitingShares<C> {
+    /// Serializes the signer state to resume the session with [`from_bytes`](Self::from_bytes).
+    /// The state contains only the nonce commitments of all the parties and no secrets.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(32 + 8 + 8 + 32 * self.counterparties.len());
+        write_header(&mut buf, &self.session_id, self.position);
+        buf.extend_from_slice(&(self.counterparties.len() as u64).to_le_bytes());
+        for counterparty in self.counterparties.iter() {
+            buf.extend_from_slice(&counterparty.commitment().to_bytes());
+        }
+        buf
+    }
+
+    /// Resumes the signer state serialized with [`to_bytes`](Self::to_bytes).
+    /// The transcript and the context must be the same as the ones passed to [`Signer::new`]:
+    /// the transcript is updated the same way as in [`SignerAwaitingCommitments::receive_commitments`].
+    /// Returns `MusigError::InvalidSession` if the state is malformed or belongs to a different session.
+    pub fn from_bytes(
+        bytes: &[u8],
+        transcript: &mut Transcript,
+        context: C,
+    ) -> Result<Self, MusigError> {
+        let mut reader = StateReader(bytes);
+        let (session_id, position) = reader.read_header(transcript, &context)?;
+        reader.read_count(&context)?;
+        let commitments = (0..context.len())
+            .map(|_| {
+                CompressedRistretto(reader.read_bytes32()?)
+                    .decompress()
+                    .map(NonceCommitment::new)
+                    .ok_or(MusigError::InvalidSession)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        reader.finish()?;
+
+        let R = NonceCommitment::sum(&commitments);
+        context.commit(transcript);
+        transcript.append_point(b"R", &R.compress());
+        let state_transcript = transcript.clone();
+        // Keep the caller's transcript in sync with the parties that did not resume the session.
+        let _ = context.challenge(position, transcript);
+
+        let counterparties = commitments
+            .into_iter()
+            .enumerate()
+            .map(|(i, commitment)| Counterparty::new(i, context.key(i)).commit_nonce(commitment))
+            .collect();
+
+        Ok(SignerAwaitingShares {
+            transcript: state_transcript,
+            context,
+            session_id,
+            position,
+            R,
+            counterparties,
+        })
+    }
+}
+
+/// Computes the identifier that binds the serialized signer state
+/// to the transcript, the context and the signer's position.
+fn session_id<C: MusigContext>(transcript: &Transcript, context: &C, position: usize) -> [u8; 32] {
+    let mut t = transcript.clone();
+    t.append_message(b"dom-sep", b"musig-session v1");
+    context.commit(&mut t);
+    t.append_u64(b"position", position as u64);
+    let mut id = [0u8; 32];
+    t.challenge_bytes(b"session_id", &mut id);
+    id
+}
+
+fn write_header(buf: &mut Vec<u8>, session_id: &[u8; 32], position: usize) {
+    buf.extend_from_slice(session_id);
+    buf.extend_from_slice(&(position as u64).to_le_bytes());
+}
+
+/// Reads the fields of the serialized signer state.
+struct StateReader<'a>(&'a [u8]);
+
+impl<'a> StateReader<'a> {
+    fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], MusigError> {
+        if self.0.len() < n {
+            return Err(MusigError::InvalidSession);
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn read_bytes32(&mut self) -> Result<[u8; 32], MusigError> {
+        let mut buf = [0u8; 32];
+        buf.copy_from_slice(self.read_bytes(32)?);
+        Ok(buf)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, MusigError> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_scalar(&mut self) -> Result<Scalar, MusigError> {
+        Scalar::from_canonical_bytes(self.read_bytes32()?).ok_or(MusigError::InvalidSession)
+    }
+
+    /// Reads the session ID and the position, and checks that they match
+    /// the transcript and the context of the resumed session.
+    fn read_header<C: MusigContext>(
+        &mut self,
+        transcript: &Transcript,
+        context: &C,
+    ) -> Result<([u8; 32], usize), MusigError> {
+        let id = self.read_bytes32()?;
+        let position = self.read_u64()? as usize;
+        if position >= context.len() || session_id(transcript, context, position) != id {
+            return Err(MusigError::InvalidSession);
+        }
+        Ok((id, position))
+    }
+
+    /// Reads the secret key and checks that it matches the signer's key in the context.
+    fn read_secret_key<C: MusigContext>(
+        &mut self,
+        context: &C,
+        position: usize,
+    ) -> Result<Scalar, MusigError> {
+        let x_i = self.read_scalar()?;
+        if VerificationKey::from_secret(&x_i) != context.key(position) {
+            return Err(MusigError::InvalidSession);
+        }
+        Ok(x_i)
+    }
+
+    /// Reads the number of parties and checks that it matches the context.
+    fn read_count<C: MusigContext>(&mut self, context: &C) -> Result<(), MusigError> {
+        if self.read_u64()? != context.len() as u64 {
+            return Err(MusigError::InvalidSession);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), MusigError> {
+        if !self.0.is_empty() {
+            return Err(MusigError::InvalidSession);
+        }
+        Ok(())
+    }
+}
@@ -4,7 +4,10 @@ use merlin::Transcript;
 
 use starsig::{Signature, TranscriptProtocol, VerificationKey};
 
-use crate::{Multikey, Multimessage, Multisignature, MusigContext, MusigError, Signer};
+use crate::{
+    AdaptorSignature, Multikey, Multimessage, Multisignature, MusigContext, MusigError, Signer,
+    SignerAwaitingCommitments, SignerAwaitingPrecommitments, SignerAwaitingShares,
+};
 
 #[test]
 fn sign_verify_single_multikey() {
@@ -156,6 +159,171 @@ fn verify_multikey() {
         .is_ok());
 }
 
+#[test]
+fn sign_multikey_with_serialized_sessions() {
+    // super secret, sshhh!
+    let priv_keys = vec![
+        Scalar::from(1u64),
+        Scalar::from(2u64),
+        Scalar::from(3u64),
+        Scalar::from(4u64),
+    ];
+    let multikey = multikey_helper(&priv_keys);
+    let transcript = Transcript::new(b"example transcript");
+    let mut transcripts: Vec<_> = priv_keys.iter().map(|_| transcript.clone()).collect();
+
+    // Every party saves its state after each round and resumes it for the next round.
+    let (states, precomms): (Vec<_>, Vec<_>) = priv_keys
+        .iter()
+        .zip(transcripts.iter_mut())
+        .enumerate()
+        .map(|(i, (x_i, t))| {
+            let (party, precomm) = Signer::new(t, i, *x_i, multikey.clone());
+            (party.to_bytes(), precomm)
+        })
+        .unzip();
+
+    let (states, comms): (Vec<_>, Vec<_>) = states
+        .iter()
+        .zip(transcripts.iter_mut())
+        .map(|(state, t)| {
+            let party =
+                SignerAwaitingPrecommitments::from_bytes(state, t, multikey.clone()).unwrap();
+            let (party, comm) = party.receive_precommitments(precomms.clone());
+            (party.to_bytes(), comm)
+        })
+        .unzip();
+
+    let (states, shares): (Vec<_>, Vec<_>) = states
+        .iter()
+        .zip(transcripts.iter_mut())
+        .map(|(state, t)| {
+            let party = SignerAwaitingCommitments::from_bytes(state, t, multikey.clone()).unwrap();
+            let (party, share) = party.receive_commitments(comms.clone()).unwrap();
+            (party.to_bytes(), share)
+        })
+        .unzip();
+
+    // The last round is resumed with a fresh transcript, as it would be in another process.
+    let signatures: Vec<Signature> = states
+        .iter()
+        .map(|state| {
+            let mut t = transcript.clone();
+            SignerAwaitingShares::from_bytes(state, &mut t, multikey.clone())
+                .unwrap()
+                .receive_shares(shares.clone())
+                .unwrap()
+        })
+        .collect();
+
+    for signature in signatures {
+        assert!(signature
+            .verify(
+                &mut Transcript::new(b"example transcript"),
+                multikey.aggregated_key()
+            )
+            .is_ok());
+    }
+}
+
+#[test]
+fn serialized_session_rejects_different_message() {
+    let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64)];
+    let multikey = multikey_helper(&priv_keys);
+    let mut transcript = Transcript::new(b"example transcript");
+    let (party, _) = Signer::new(&mut transcript, 0, priv_keys[0], multikey.clone());
+    let state = party.to_bytes();
+
+    let mut other_transcript = Transcript::new(b"other transcript");
+    assert_eq!(
+        SignerAwaitingPrecommitments::from_bytes(&state, &mut other_transcript, multikey.clone())
+            .err(),
+        Some(MusigError::InvalidSession)
+    );
+
+    // Position is bound to the session too.
+    let mut state_with_other_position = state.clone();
+    state_with_other_position[32] = 1;
+    let mut transcript = Transcript::new(b"example transcript");
+    assert_eq!(
+        SignerAwaitingPrecommitments::from_bytes(
+            &state_with_other_position,
+            &mut transcript,
+            multikey.clone()
+        )
+        .err(),
+        Some(MusigError::InvalidSession)
+    );
+
+    let mut transcript = Transcript::new(b"example transcript");
+    assert!(SignerAwaitingPrecommitments::from_bytes(&state, &mut transcript, multikey).is_ok());
+}
+
+#[test]
+fn misbehaving_party_is_identified() {
+    let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+    let multikey = multikey_helper(&priv_keys);
+    let mut transcripts: Vec<_> = priv_keys
+        .iter()
+        .map(|_| Transcript::new(b"example transcript"))
+        .collect();
+
+    let (parties, precomms): (Vec<_>, Vec<_>) = priv_keys
+        .iter()
+        .zip(transcripts.iter_mut())
+        .enumerate()
+        .map(|(i, (x_i, transcript))| Signer::new(transcript, i, *x_i, multikey.clone()))
+        .unzip();
+    let (parties, comms): (Vec<_>, Vec<_>) = parties
+        .into_iter()
+        .map(|p| p.receive_precommitments(precomms.clone()))
+        .unzip();
+
+    // Party 1 sends the commitment of party 2 instead of its own.
+    let mut bad_comms = comms.clone();
+    bad_comms[1] = comms[2];
+    let mut transcript = Transcript::new(b"example transcript");
+    let party =
+        SignerAwaitingCommitments::from_bytes(&parties[0].to_bytes(), &mut transcript, multikey)
+            .unwrap();
+    assert_eq!(
+        party.receive_commitments(bad_comms).err(),
+        Some(MusigError::NonceCommitmentError {
+            position: 1,
+            pubkey: VerificationKey::from_secret(&priv_keys[1]).to_bytes(),
+        })
+    );
+
+    let (parties, shares): (Vec<_>, Vec<_>) = parties
+        .into_iter()
+        .map(|p| p.receive_commitments(comms.clone()).unwrap())
+        .unzip();
+    let mut parties = parties.into_iter();
+
+    // Party 2 sends an invalid share.
+    let mut bad_shares = shares.clone();
+    bad_shares[2] += Scalar::one();
+    assert_eq!(
+        parties.next().unwrap().receive_shares(bad_shares).err(),
+        Some(MusigError::ShareError {
+            position: 2,
+            pubkey: VerificationKey::from_secret(&priv_keys[2]).to_bytes(),
+        })
+    );
+
+    // A missing share is not silently skipped.
+    assert_eq!(
+        parties
+            .next()
+            .unwrap()
+            .receive_shares(shares[..2].to_vec())
+            .err(),
+        Some(MusigError::BadArguments)
+    );
+
+    assert!(parties.next().unwrap().receive_shares(shares).is_ok());
+}
+
 #[test]
 fn check_transcripts_multikey() {
     // super secret, sshhh!
@@ -270,6 +438,98 @@ fn verify_multimessage_singleplayer() {
         .is_ok());
 }
 
+#[test]
+fn sign_multimessage_singleplayer_bad_arguments() {
+    let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64)];
+    let pairs = multimessage_helper(&priv_keys, vec![b"message1", b"message2"]);
+
+    assert_eq!(
+        Signature::sign_multi(
+            &priv_keys[..1],
+            pairs,
+            &mut Transcript::new(b"example transcript"),
+        )
+        .unwrap_err(),
+        MusigError::BadArguments
+    );
+    assert_eq!(
+        Signature::sign_multi(
+            Vec::<Scalar>::new(),
+            Vec::<(VerificationKey, &[u8])>::new(),
+            &mut Transcript::new(b"example transcript"),
+        )
+        .unwrap_err(),
+        MusigError::BadArguments
+    );
+}
+
+#[test]
+fn adaptor_signature_multimessage() {
+    let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+    let messages = vec![b"message1", b"message2", b"message3"];
+    let adaptor_secret = Scalar::from(42u64);
+    let adaptor = VerificationKey::from_secret(&adaptor_secret).into_point();
+
+    let presignature = AdaptorSignature::sign_multi(
+        priv_keys.clone(),
+        multimessage_helper(&priv_keys, messages.clone()),
+        &adaptor,
+        &mut Transcript::new(b"example transcript"),
+    )
+    .unwrap();
+
+    assert!(presignature
+        .verify_multi(
+            &mut Transcript::new(b"example transcript"),
+            multimessage_helper(&priv_keys, messages.clone()),
+            &adaptor,
+        )
+        .is_ok());
+
+    // Adaptor signature alone is not a valid signature.
+    let incomplete = Signature {
+        s: presignature.s,
+        R: presignature.R,
+    };
+    assert!(incomplete
+        .verify_multi(
+            &mut Transcript::new(b"example transcript"),
+            multimessage_helper(&priv_keys, messages.clone()),
+        )
+        .is_err());
+
+    // Completing with a wrong secret does not produce a valid signature.
+    let wrong = presignature.complete(Scalar::from(43u64));
+    assert!(wrong
+        .verify_multi(
+            &mut Transcript::new(b"example transcript"),
+            multimessage_helper(&priv_keys, messages.clone()),
+        )
+        .is_err());
+    assert_eq!(presignature.extract(&wrong, &adaptor), None);
+
+    let signature = presignature.complete(adaptor_secret);
+    assert!(signature
+        .verify_multi(
+            &mut Transcript::new(b"example transcript"),
+            multimessage_helper(&priv_keys, messages.clone()),
+        )
+        .is_ok());
+    assert_eq!(
+        presignature.extract(&signature, &adaptor),
+        Some(adaptor_secret)
+    );
+
+    // Adaptor signature does not verify against a different adaptor point.
+    assert!(presignature
+        .verify_multi(
+            &mut Transcript::new(b"example transcript"),
+            multimessage_helper(&priv_keys, messages),
+            &VerificationKey::from_secret(&Scalar::from(43u64)).into_point(),
+        )
+        .is_err());
+}
+
 #[test]
 fn check_transcripts_multimessage() {
     // super secret, sshhh!
@@ -1,11 +1,60 @@
-use std::net::SocketAddr;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use warp::http::StatusCode;
 use warp::Filter;
 
+use blockchain::{BlockTx, BlockchainError};
+use zkvm::encoding::{Decodable, Encodable, Reader};
+use zkvm::{ContractID, Hash, TxID};
+
 use crate::bc::BlockchainRef;
 use crate::config::Config;
+use crate::errors::Error;
 use crate::wallet_manager::WalletRef;
 
+/// Request body for `POST /v1/network/submit`.
+#[derive(Deserialize)]
+struct SubmitTxRequest {
+    /// Hex-encoded `BlockTx`: the transaction followed by the utreexo proofs of its inputs.
+    block_tx: String,
+}
+
+/// Response body for `POST /v1/network/submit`, tagged with the `status` field.
+/// Clients should not retry `invalid` transactions, while `low_fee` ones
+/// can be resubmitted with a higher fee.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum SubmitTxResponse {
+    /// Transaction is added to the mempool.
+    Accepted { tx_id: TxID },
+    /// Transaction is valid, but spends unconfirmed outputs that are not in the mempool yet.
+    /// It is kept aside until its parents arrive.
+    Orphan,
+    /// Transaction pays less than the minimum feerate of the node.
+    LowFee { feerate: f64, min_feerate: f64 },
+    /// Transaction is malformed, or its proof or signature is invalid.
+    Invalid { reason: String },
+    /// Transaction is well-formed, but cannot be applied to the current state:
+    /// it double-spends, its time bounds are not satisfied, or it was recently dropped.
+    Rejected { reason: String },
+    /// Node cannot accept transactions, e.g. the blockchain is not initialized.
+    Unavailable { reason: String },
+}
+
+/// Response body for `GET /v1/network/utxo/:contract_id/proof`.
+#[derive(Serialize)]
+struct UtxoProofResponse {
+    /// Hex-encoded ID of the utxo.
+    contract_id: String,
+    /// Hex-encoded `utreexo::Proof` that verifies against the utreexo of the current tip.
+    proof: String,
+}
+
 /// Launches the API server.
+///
+/// GET  /v1/echo/:text       -> echoes the text back
+/// POST /v1/network/submit   -> submits the transaction to the mempool (see `api.md`)
+/// GET  /v1/network/utxo/:contract_id/proof -> utreexo proof of the unspent output (see `api.md`)
 pub async fn launch(config: Config, bc: BlockchainRef, wallet: WalletRef) {
     let conf = &config.data.api;
     if conf.disabled {
@@ -14,11 +63,122 @@ pub async fn launch(config: Config, bc: BlockchainRef, wallet: WalletRef) {
     let echo =
         warp::path!("v1" / "echo" / String).map(|thingy| format!("API v1 echo: {}!", thingy));
 
+    let submit_tx = warp::post()
+        .and(warp::path!("v1" / "network" / "submit"))
+        .and(warp::body::json())
+        .and(warp::any().map({
+            let bc = bc.clone();
+            move || bc.clone()
+        }))
+        .and_then(submit_tx);
+
+    let utxo_proof = warp::get()
+        .and(warp::path!("v1" / "network" / "utxo" / String / "proof"))
+        .and(warp::any().map(move || bc.clone()))
+        .and(warp::any().map(move || wallet.clone()))
+        .and_then(utxo_proof);
+
     let not_found = warp::any()
         .map(|| warp::reply::with_status("Not found.", warp::http::StatusCode::NOT_FOUND));
 
-    let routes = echo.or(not_found);
+    let routes = echo.or(submit_tx).or(utxo_proof).or(not_found);
 
     eprintln!("API: http://{}", &conf.listen);
     warp::serve(routes).run(conf.listen).await;
 }
+
+async fn submit_tx(
+    request: SubmitTxRequest,
+    bc: BlockchainRef,
+) -> Result<impl warp::Reply, Infallible> {
+    let response = match decode_block_tx(&request.block_tx) {
+        Some(block_tx) => match bc.write().await.submit_tx(block_tx) {
+            Ok(tx_id) => SubmitTxResponse::Accepted { tx_id },
+            Err(err) => SubmitTxResponse::from(err),
+        },
+        None => SubmitTxResponse::Invalid {
+            reason: "Transaction cannot be decoded.".to_string(),
+        },
+    };
+    let status = match &response {
+        SubmitTxResponse::Accepted { .. } => StatusCode::OK,
+        SubmitTxResponse::Orphan => StatusCode::ACCEPTED,
+        SubmitTxResponse::LowFee { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        SubmitTxResponse::Invalid { .. } => StatusCode::BAD_REQUEST,
+        SubmitTxResponse::Rejected { .. } => StatusCode::CONFLICT,
+        SubmitTxResponse::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&crate::json::to_json_value(&response)),
+        status,
+    ))
+}
+
+/// Returns the proof only if it verifies against the current tip:
+/// the node does not keep the utreexo trees, so proofs are known only for the wallet's utxos,
+/// and a utxo that is spent, unconfirmed or not tracked by the wallet is reported as not found.
+async fn utxo_proof(
+    contract_id: String,
+    bc: BlockchainRef,
+    wm: WalletRef,
+) -> Result<impl warp::Reply, Infallible> {
+    let contract_id = match decode_contract_id(&contract_id) {
+        Some(cid) => cid,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&"Invalid contract ID."),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    let proof = match wm.read().await.wallet_ref() {
+        Ok(wallet) => wallet
+            .unspent_utxo(&contract_id)
+            .map(|utxo| utxo.proof().clone()),
+        Err(_) => None,
+    };
+    match proof {
+        Some(proof) if bc.read().await.verify_utxo_proof(&contract_id, &proof) => {
+            let response = UtxoProofResponse {
+                contract_id: Hash(contract_id.0).to_hex(),
+                proof: hex::encode(proof.encode_to_vec()),
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        _ => Ok(warp::reply::with_status(
+            warp::reply::json(&"Utxo not found."),
+            StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+fn decode_contract_id(hex_string: &str) -> Option<ContractID> {
+    Hash::from_hex(hex_string).map(|hash| ContractID(hash.0))
+}
+
+fn decode_block_tx(hex_string: &str) -> Option<BlockTx> {
+    let bytes = hex::decode(hex_string).ok()?;
+    let mut slice = &bytes[..];
+    slice.read_all(|r| BlockTx::decode(r)).ok()
+}
+
+impl From<Error> for SubmitTxResponse {
+    fn from(err: Error) -> Self {
+        let reason = err.to_string();
+        match err {
+            Error::LowFeerate(feerate, min_feerate) => SubmitTxResponse::LowFee {
+                feerate,
+                min_feerate,
+            },
+            Error::TxRejected(BlockchainError::OrphanTx) => SubmitTxResponse::Orphan,
+            Error::InvalidTx(_) | Error::TxRejected(BlockchainError::VMError(_)) => {
+                SubmitTxResponse::Invalid { reason }
+            }
+            Error::TxRejected(_) => SubmitTxResponse::Rejected { reason },
+            _ => SubmitTxResponse::Unavailable { reason },
+        }
+    }
+}
@@ -1,25 +1,41 @@
+use std::fmt;
 use std::fs::{self, File};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::io;
 use tokio::prelude::*;
 use tokio::sync::broadcast;
+use tokio::sync::mpsc;
 use tokio::sync::RwLock;
 use tokio::task;
 
 use curve25519_dalek::scalar::Scalar;
 use rand::thread_rng;
+use serde::Serialize;
 
-use blockchain::{self, BlockchainState};
-use p2p::{cybershake, PeerID};
+use blockchain::{
+    self, utreexo, BlockID, BlockTx, BlockchainError, BlockchainState, Mempool, RequestHandler,
+    VerifiedBlock,
+};
+use musig::VerificationKey;
+use p2p::{cybershake, NodeHandle, PeerID};
+use zkvm::bulletproofs::BulletproofGens;
+use zkvm::{ContractID, TxID};
 
 use crate::config::Config;
 use crate::errors::Error;
 
 const BC_STATE_FILENAME: &'static str = "blockchain_state";
 
+/// How long to wait for the peer to report its headers before giving up on syncing with it.
+const SYNC_REQUEST_TIMEOUT_SEC: u64 = 30;
+
+/// How long to wait for each block of the sync before considering the sync stalled.
+const BLOCK_REQUEST_TIMEOUT_SEC: u64 = 30;
+
 /// Interface for initializing and launching blockchain state machine.
 pub struct Blockchain;
 
@@ -29,13 +45,39 @@ pub struct BlockchainIdle {
     state: Option<BlockchainState>,
 }
 
-#[derive(Debug)]
 pub struct BlockchainRunning {
     /// Configuration
     config: Config,
 
     /// Sender end of the notification channel
     notifications_sender: broadcast::Sender<BlockchainEvent>,
+
+    /// Handle to the p2p node
+    node: NodeHandle<blockchain::Message>,
+
+    /// Blockchain state, if initialized
+    state: Option<BlockchainState>,
+
+    /// Unconfirmed transactions, if the blockchain is initialized
+    mempool: Option<Mempool>,
+
+    /// Block download in progress
+    sync: Option<BlockSync>,
+
+    /// Generators for verifying the blocks
+    bp_gens: BulletproofGens,
+
+    /// Key that signs the blocks, if configured
+    network_pubkey: Option<VerificationKey>,
+}
+
+/// Range of blocks being downloaded from a peer.
+struct BlockSync {
+    peer: PeerID,
+    next_height: u64,
+    to_height: u64,
+    /// The sync is stalled if the next block is not received by this time.
+    deadline: Instant,
 }
 
 /// Reference to the Blockchain instance
@@ -45,8 +87,29 @@ pub type BlockchainRef = Arc<RwLock<BlockchainRunning>>;
 pub type BlockchainEventReceiver = broadcast::Receiver<BlockchainEvent>;
 
 /// Type for all events about the BC state into the UI.
-#[derive(Clone, Debug)]
-pub enum BlockchainEvent {}
+///
+/// Events are serialized as JSON objects with the event name in the `type` field, e.g.
+/// `{"type": "TxConfirmed", "height": 12, "tx_id": "4a5b..."}`. IDs are hex-encoded.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum BlockchainEvent {
+    /// Block at a given height is verified and applied to the state.
+    BlockAccepted {
+        height: u64,
+        block_id: BlockID,
+        tx_count: usize,
+    },
+    /// Transaction is accepted into the mempool.
+    TxAccepted { tx_id: TxID },
+    /// Transaction is included in the block at a given height.
+    TxConfirmed { height: u64, tx_id: TxID },
+    /// Block at a given height is downloaded and verified.
+    SyncProgress { height: u64, to_height: u64 },
+    /// All the requested blocks are downloaded and verified.
+    SyncCompleted { height: u64 },
+    /// Block at a given height failed verification, the sync is stopped.
+    SyncFailed { height: u64, reason: String },
+}
 
 impl Blockchain {
     /// Sets up a blockchain instance, initialized or not.
@@ -89,21 +152,20 @@ impl BlockchainIdle {
 
     /// Launches the blockchain p2p stack and returns the communication reference to it.
     pub async fn launch(self) -> Result<BlockchainRef, Error> {
-        // TODO: make this channel capacity a config option
-        let (notifications_sender, _recv) = broadcast::channel(1000);
-
         // Launch p2p stack
 
         // TBD: load the peer privkey from disk instead of picking a random one.
         let host_privkey = cybershake::PrivateKey::from(Scalar::random(&mut thread_rng()));
 
-        let (node, mut p2p_channel) = p2p::Node::<blockchain::Message>::spawn(
+        let (node, p2p_channel) = p2p::Node::<blockchain::Message>::spawn(
             host_privkey,
             p2p::NodeConfig {
                 listen_addr: self.config.data.p2p.listen_addr,
                 inbound_limit: self.config.data.p2p.inbound_limit,
                 outbound_limit: self.config.data.p2p.outbound_limit,
                 heartbeat_interval_sec: self.config.data.p2p.heartbeat_interval_sec,
+                ban_threshold: self.config.data.p2p.ban_threshold,
+                ban_duration_sec: self.config.data.p2p.ban_duration_sec,
             },
         )
         .await?;
@@ -115,39 +177,14 @@ impl BlockchainIdle {
         );
 
         // Handle to a shared blockchain state machine instance.
-        let bc = Arc::new(RwLock::new(BlockchainRunning {
-            config: self.config,
-            notifications_sender,
-        }));
-
-        let notifications_loop = {
-            task::spawn_local(async move {
-                while let Some(notif) = p2p_channel.recv().await {
-                    match notif {
-                        p2p::NodeNotification::PeerAdded(pid) => {
-                            println!("\n=>    Peer connected: {}", pid);
-                        }
-                        p2p::NodeNotification::PeerDisconnected(pid) => {
-                            println!("\n=> Peer disconnected: {}", pid)
-                        }
-                        p2p::NodeNotification::MessageReceived(pid, msg) => {
-                            println!("\n=> Received: `{:?}` from {}", &msg, pid)
-                        }
-                        p2p::NodeNotification::InboundConnectionFailure(err) => {
-                            println!("\n=> Inbound connection failure: {:?}", err)
-                        }
-                        p2p::NodeNotification::OutboundConnectionFailure(err) => {
-                            println!("\n=> Outbound connection failure: {:?}", err)
-                        }
-                        p2p::NodeNotification::Shutdown => {
-                            println!("\n=> Node did shutdown.");
-                            break;
-                        }
-                    }
-                }
-                Result::<(), Error>::Ok(())
-            })
-        };
+        let bc = Arc::new(RwLock::new(BlockchainRunning::new(
+            self.config,
+            self.state,
+            node.clone(),
+        )));
+
+        let notifications_loop =
+            task::spawn_local(process_notifications(bc.clone(), node, p2p_channel));
 
         notifications_loop.await.expect("panic on JoinError")?;
 
@@ -156,6 +193,27 @@ impl BlockchainIdle {
 }
 
 impl BlockchainRunning {
+    fn new(
+        config: Config,
+        state: Option<BlockchainState>,
+        node: NodeHandle<blockchain::Message>,
+    ) -> Self {
+        // TODO: make this channel capacity a config option
+        let (notifications_sender, _recv) = broadcast::channel(1000);
+        BlockchainRunning {
+            network_pubkey: config.data.blockchain.network_pubkey(),
+            config,
+            notifications_sender,
+            node,
+            mempool: state
+                .as_ref()
+                .map(|state| Mempool::new(state.clone(), state.tip.timestamp_ms)),
+            state,
+            sync: None,
+            bp_gens: BulletproofGens::new(256, 1),
+        }
+    }
+
     /// Creates a subscription for notifications and returns a receiving end of a broadcast channel.
     pub async fn subscribe(&self) -> BlockchainEventReceiver {
         self.notifications_sender.subscribe()
@@ -163,6 +221,419 @@ impl BlockchainRunning {
 
     /// Stops the blockchain stack
     pub async fn stop(&self) {}
+
+    /// Verifies the transaction and adds it to the mempool.
+    /// Fails with `Error::LowFeerate` if the feerate is below `blockchain.mempool_min_feerate`
+    /// (checked before the expensive verification), and with `Error::TxRejected`
+    /// if the transaction is invalid or cannot be applied to the current state.
+    pub fn submit_tx(&mut self, block_tx: BlockTx) -> Result<TxID, Error> {
+        let min_feerate = self.config.data.blockchain.mempool_min_feerate as f64;
+        let mempool = self
+            .mempool
+            .as_mut()
+            .ok_or(Error::BlockchainNotInitialized)?;
+
+        let feerate = block_tx
+            .tx
+            .precompute()
+            .map_err(|e| Error::TxRejected(e.into()))?
+            .feerate
+            .to_f64();
+        if feerate < min_feerate {
+            return Err(Error::LowFeerate(feerate, min_feerate));
+        }
+
+        let tip_timestamp_ms = self.state.as_ref().map(|s| s.tip.timestamp_ms).unwrap_or(0);
+        mempool.update_timestamp(crate::current_timestamp_ms().max(tip_timestamp_ms));
+        let tx_id = mempool
+            .append(block_tx, &self.bp_gens)
+            .map_err(Error::TxRejected)?
+            .txid();
+        self.notify(BlockchainEvent::TxAccepted { tx_id });
+        Ok(tx_id)
+    }
+
+    /// Returns true if the utxo with the given proof is unspent in the current tip.
+    /// Transient proofs are never valid: unconfirmed outputs are not in the utreexo yet.
+    pub fn verify_utxo_proof(&self, contract_id: &ContractID, proof: &utreexo::Proof) -> bool {
+        match (&self.state, proof.as_path()) {
+            (Some(state), Some(path)) => state
+                .utreexo
+                .verify(contract_id, path, &utreexo::utreexo_hasher())
+                .is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Starts downloading the blocks at heights `from_height..=to_height` from a given peer.
+    /// The caller requests the blocks (see `download_blocks`).
+    /// Blocks are requested one at a time, and each block is verified before the next one is requested.
+    /// Blocks must be signed with the configured network key.
+    /// Progress is reported via `BlockchainEvent`s; verification failure stops the sync
+    /// and is reported with the height of the offending block.
+    ///
+    /// Fails with `Error::SyncInProgress` if another sync is running. The running sync is stopped
+    /// if the peer disconnects, and is replaced by the new one if its next block
+    /// did not arrive within `BLOCK_REQUEST_TIMEOUT_SEC`.
+    pub fn sync_from_peer(
+        &mut self,
+        peer: PeerID,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<(), Error> {
+        let tip_height = self
+            .state
+            .as_ref()
+            .ok_or(Error::BlockchainNotInitialized)?
+            .tip
+            .height;
+        if let Some(sync) = &self.sync {
+            if Instant::now() < sync.deadline {
+                return Err(Error::SyncInProgress);
+            }
+            let (stalled_peer, stalled_height) = (sync.peer, sync.next_height);
+            self.fail_sync(
+                stalled_peer,
+                stalled_height,
+                "block request timed out".to_string(),
+            );
+        }
+        if from_height != tip_height + 1 || to_height < from_height {
+            return Err(Error::BlockSyncFailed(
+                from_height,
+                BlockchainError::BlockNotRelevant(from_height),
+            ));
+        }
+        self.sync = Some(BlockSync {
+            peer,
+            next_height: from_height,
+            to_height,
+            deadline: block_deadline(),
+        });
+        Ok(())
+    }
+
+    /// Stops the sync with the disconnected peer.
+    pub fn peer_disconnected(&mut self, pid: PeerID) {
+        if let Some(sync) = &self.sync {
+            if sync.peer == pid {
+                let height = sync.next_height;
+                self.fail_sync(pid, height, "peer disconnected".to_string());
+            }
+        }
+    }
+
+    /// Stops the sync with a given peer that was waiting for the block at a given height,
+    /// and reports the failure. Does nothing if the sync has already moved on or was replaced.
+    fn fail_sync(&mut self, pid: PeerID, height: u64, reason: String) {
+        match &self.sync {
+            Some(sync) if sync.peer == pid && sync.next_height == height => {}
+            _ => return,
+        }
+        self.sync = None;
+        self.save_state();
+        self.notify(BlockchainEvent::SyncFailed { height, reason });
+    }
+
+    /// Verifies the block received during the sync and applies it to the state.
+    /// Returns the height of the next block to request from the peer, if the sync continues.
+    fn receive_block(&mut self, pid: PeerID, block: blockchain::Block) -> Option<u64> {
+        let (height, to_height) = match &self.sync {
+            Some(sync) if sync.peer == pid => (sync.next_height, sync.to_height),
+            // Ignore the blocks we did not ask for.
+            _ => return None,
+        };
+        if block.header().height != height {
+            self.fail_sync(
+                pid,
+                height,
+                "peer sent a block at a wrong height".to_string(),
+            );
+            return None;
+        }
+        match self.accept_block(&block) {
+            Ok(()) => {
+                self.notify(BlockchainEvent::SyncProgress { height, to_height });
+                if height < to_height {
+                    if let Some(sync) = self.sync.as_mut() {
+                        sync.next_height = height + 1;
+                        sync.deadline = block_deadline();
+                    }
+                    Some(height + 1)
+                } else {
+                    self.sync = None;
+                    self.save_state();
+                    self.notify(BlockchainEvent::SyncCompleted { height });
+                    None
+                }
+            }
+            Err(err) => {
+                self.fail_sync(pid, height, err.to_string());
+                None
+            }
+        }
+    }
+
+    /// Verifies the block and applies it to the state.
+    /// The block is stored, so it can be served to the peers.
+    fn accept_block(&mut self, block: &blockchain::Block) -> Result<(), BlockchainError> {
+        let height = block.header().height;
+        let verified_block = match &self.state {
+            Some(state) => verify_block(state, self.network_pubkey, block, &self.bp_gens)?,
+            None => return Err(BlockchainError::BlockNotRelevant(height)),
+        };
+        if let Some(state) = self.state.as_mut() {
+            state.advance(&verified_block);
+            if let Some(mempool) = self.mempool.as_mut() {
+                mempool.update_state(state.clone(), &verified_block.catchup);
+            }
+        }
+        self.store_block(block);
+        self.notify(BlockchainEvent::BlockAccepted {
+            height,
+            block_id: verified_block.header.id(),
+            tx_count: verified_block.verified_txs.len(),
+        });
+        for verified_tx in verified_block.verified_txs.iter() {
+            self.notify(BlockchainEvent::TxConfirmed {
+                height,
+                tx_id: verified_tx.id,
+            });
+        }
+        Ok(())
+    }
+
+    fn notify(&self, event: BlockchainEvent) {
+        // Sending fails only when there are no subscribers, which is fine.
+        let _ = self.notifications_sender.send(event);
+    }
+
+    fn save_state(&self) {
+        if let Some(state) = &self.state {
+            let result = File::create(self.config.blockchain_state_filepath())
+                .map_err(Error::from)
+                .and_then(|file| bincode::serialize_into(file, state).map_err(Error::from));
+            if let Err(err) = result {
+                eprintln!("Failed to save the blockchain state: {}", err);
+            }
+        }
+    }
+
+    fn store_block(&self, block: &blockchain::Block) {
+        let path = self.config.blocks_path();
+        let result = fs::create_dir_all(&path)
+            .and_then(|_| File::create(path.join(block.header().height.to_string())))
+            .map_err(Error::from)
+            .and_then(|file| bincode::serialize_into(file, block).map_err(Error::from));
+        if let Err(err) = result {
+            eprintln!("Failed to save the block: {}", err);
+        }
+    }
+}
+
+/// The node serves the blocks it has verified (stored in `Config::blocks_path`) and their headers.
+/// The blocks preceding the state the node was initialized with are not available,
+/// and neither are the utxo proofs, since the node keeps only the utreexo roots.
+impl RequestHandler for BlockchainRunning {
+    fn tip_height(&self) -> u64 {
+        self.state
+            .as_ref()
+            .map(|state| state.tip.height)
+            .unwrap_or(0)
+    }
+
+    fn header_at_height(&self, height: u64) -> Option<blockchain::BlockHeader> {
+        match &self.state {
+            Some(state) if state.tip.height == height => Some(state.tip.clone()),
+            _ => self
+                .block_at_height(height)
+                .map(|block| block.header().clone()),
+        }
+    }
+
+    fn block_at_height(&self, height: u64) -> Option<blockchain::Block> {
+        // Ignore the blocks left from the states that were not saved.
+        if height > self.tip_height() {
+            return None;
+        }
+        let file = File::open(self.config.blocks_path().join(height.to_string())).ok()?;
+        bincode::deserialize_from(file).ok()
+    }
+
+    fn utxo_proof(&self, _contract_id: &ContractID) -> Option<utreexo::Proof> {
+        None
+    }
+}
+
+/// Verifies the block signature against the network key and applies the block to the state.
+/// Blocks are rejected if the network key is not configured.
+fn verify_block(
+    state: &BlockchainState,
+    network_pubkey: Option<VerificationKey>,
+    block: &blockchain::Block,
+    bp_gens: &BulletproofGens,
+) -> Result<VerifiedBlock, BlockchainError> {
+    match network_pubkey {
+        Some(pubkey) if block.verify_signature(pubkey) => {
+            state.apply_block(block.header().clone(), block.txs(), bp_gens)
+        }
+        _ => Err(BlockchainError::InvalidBlockSignature),
+    }
+}
+
+/// Handles the notifications from the p2p node until it shuts down:
+/// answers the requests from the peers and syncs with the newly connected ones.
+async fn process_notifications(
+    bc: BlockchainRef,
+    mut node: NodeHandle<blockchain::Message>,
+    mut p2p_channel: mpsc::Receiver<p2p::NodeNotification<blockchain::Message>>,
+) -> Result<(), Error> {
+    while let Some(notif) = p2p_channel.recv().await {
+        match notif {
+            p2p::NodeNotification::RequestReceived(
+                pid,
+                request_id,
+                blockchain::Message::Request(request),
+            ) => {
+                // Release the lock before sending the response.
+                let response = bc.read().await.respond(request);
+                node.respond(pid, request_id, blockchain::Message::Response(response))
+                    .await
+            }
+            p2p::NodeNotification::PeerAdded(pid) => {
+                println!("\n=>    Peer connected: {}", pid);
+                // Sync in the background: the peer's response is delivered
+                // by the p2p node while this loop keeps processing notifications.
+                task::spawn_local(sync_with_peer(bc.clone(), node.clone(), pid));
+            }
+            p2p::NodeNotification::PeerBanned(pid) => {
+                println!("\n=>       Peer banned: {}", pid)
+            }
+            p2p::NodeNotification::PeerDisconnected(pid, reason) => {
+                println!("\n=> Peer disconnected: {} ({:?})", pid, reason);
+                bc.write().await.peer_disconnected(pid);
+            }
+            p2p::NodeNotification::MessageReceived(pid, msg) => {
+                println!("\n=> Received: `{:?}` from {}", &msg, pid)
+            }
+            p2p::NodeNotification::RequestReceived(pid, request_id, msg) => {
+                println!(
+                    "\n=> Received request #{}: `{:?}` from {}",
+                    request_id, &msg, pid
+                )
+            }
+            p2p::NodeNotification::InboundConnectionFailure(err) => {
+                println!("\n=> Inbound connection failure: {:?}", err)
+            }
+            p2p::NodeNotification::OutboundConnectionFailure(err) => {
+                println!("\n=> Outbound connection failure: {:?}", err)
+            }
+            p2p::NodeNotification::Shutdown => {
+                println!("\n=> Node did shutdown.");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asks the newly connected peer for the headers after our tip,
+/// and downloads the blocks if the peer is ahead of us.
+async fn sync_with_peer(
+    bc: BlockchainRef,
+    mut node: NodeHandle<blockchain::Message>,
+    peer: PeerID,
+) {
+    let from_height = match &bc.read().await.state {
+        Some(state) => state.tip.height + 1,
+        None => return,
+    };
+    let request = blockchain::Message::Request(blockchain::Request::GetHeaders {
+        from_height,
+        count: blockchain::MAX_HEADERS_PER_REQUEST,
+    });
+    let to_height = match node
+        .request(peer, request, Duration::from_secs(SYNC_REQUEST_TIMEOUT_SEC))
+        .await
+    {
+        Ok(blockchain::Message::Response(blockchain::Response::Headers(headers))) => {
+            match headers.last() {
+                Some(header) => header.height,
+                // The peer is not ahead of us.
+                None => return,
+            }
+        }
+        Ok(_) => return,
+        Err(err) => {
+            eprintln!("Failed to get the headers from {}: {}", peer, err);
+            return;
+        }
+    };
+    let result = bc
+        .write()
+        .await
+        .sync_from_peer(peer, from_height, to_height);
+    match result {
+        Ok(()) => download_blocks(bc, node, peer, from_height).await,
+        Err(err) => eprintln!("Cannot sync from {}: {}", peer, err),
+    }
+}
+
+/// Requests the blocks from the peer one at a time, starting at a given height,
+/// until the sync started with `sync_from_peer` completes or fails.
+/// The sync fails at the requested height if the peer does not have the block
+/// or does not respond within `BLOCK_REQUEST_TIMEOUT_SEC`.
+async fn download_blocks(
+    bc: BlockchainRef,
+    mut node: NodeHandle<blockchain::Message>,
+    peer: PeerID,
+    from_height: u64,
+) {
+    let mut next_height = Some(from_height);
+    while let Some(height) = next_height {
+        let request = blockchain::Message::Request(blockchain::Request::GetBlock(
+            blockchain::GetBlock::new(height),
+        ));
+        // Do not hold the lock while waiting for the response.
+        let response = node
+            .request(
+                peer,
+                request,
+                Duration::from_secs(BLOCK_REQUEST_TIMEOUT_SEC),
+            )
+            .await;
+        let mut running = bc.write().await;
+        next_height = match response {
+            Ok(blockchain::Message::Response(blockchain::Response::Block(Some(block)))) => {
+                running.receive_block(peer, block)
+            }
+            Ok(blockchain::Message::Response(blockchain::Response::Block(None))) => {
+                running.fail_sync(peer, height, "peer does not have the block".to_string());
+                None
+            }
+            Ok(_) => {
+                running.fail_sync(peer, height, "unexpected response".to_string());
+                None
+            }
+            Err(err) => {
+                running.fail_sync(peer, height, err.to_string());
+                None
+            }
+        };
+    }
+}
+
+fn block_deadline() -> Instant {
+    Instant::now() + Duration::from_secs(BLOCK_REQUEST_TIMEOUT_SEC)
+}
+
+impl fmt::Debug for BlockchainRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockchainRunning")
+            .field("config", &self.config)
+            .field("tip", &self.state.as_ref().map(|s| &s.tip))
+            .finish()
+    }
 }
 
 /*
@@ -204,3 +675,243 @@ impl protocol::Delegate for BlockchainRunning {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigData;
+    use curve25519_dalek::ristretto::CompressedRistretto;
+    use tokio::time;
+    use zkvm::Signature;
+
+    /// Config with the storage in a new temporary directory.
+    fn make_config() -> Config {
+        let mut path = std::env::temp_dir();
+        path.push(format!("slingshot-node-{}", rand::random::<u64>()));
+        path.push("config.toml");
+        let config = Config {
+            data: ConfigData::default(),
+            path,
+        };
+        fs::create_dir_all(config.blockchain_path()).unwrap();
+        config
+    }
+
+    fn make_peer_id(secret: u64) -> PeerID {
+        PeerID::from(cybershake::PrivateKey::from(Scalar::from(secret)).to_public_key())
+    }
+
+    /// Config trusting the blocks signed with a given key.
+    fn make_config_with_network_key(network_key: Scalar) -> Config {
+        let mut config = make_config();
+        let pubkey = VerificationKey::from_secret(&network_key).into_point();
+        config.data.blockchain.network_pubkey = Some(hex::encode(pubkey.as_bytes()));
+        config
+    }
+
+    async fn spawn_node() -> (
+        NodeHandle<blockchain::Message>,
+        mpsc::Receiver<p2p::NodeNotification<blockchain::Message>>,
+    ) {
+        p2p::Node::spawn(
+            cybershake::PrivateKey::from(Scalar::random(&mut thread_rng())),
+            p2p::NodeConfig {
+                listen_addr: "127.0.0.1:0".parse().unwrap(),
+                inbound_limit: 100,
+                outbound_limit: 100,
+                heartbeat_interval_sec: 3600,
+                ban_threshold: 100,
+                ban_duration_sec: 3600,
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Creates a blockchain instance with the p2p node that has no peers.
+    async fn make_running(state: BlockchainState) -> BlockchainRunning {
+        let (node, _notifications) = spawn_node().await;
+        BlockchainRunning::new(make_config(), Some(state), node)
+    }
+
+    fn make_state_and_header() -> (BlockchainState, blockchain::BlockHeader) {
+        let (state, _) = BlockchainState::make_initial(0, Vec::<ContractID>::new());
+        let mut mempool = Mempool::new(state.clone(), 0);
+        mempool.update_timestamp(1);
+        let header = mempool.make_block().header;
+        (state, header)
+    }
+
+    #[tokio::test]
+    async fn sync_stops_when_peer_disconnects_or_stalls() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (state, _) = make_state_and_header();
+                let mut bc = make_running(state).await;
+                let mut events = bc.subscribe().await;
+                let (peer, other) = (make_peer_id(7), make_peer_id(8));
+
+                bc.sync_from_peer(peer, 2, 5).unwrap();
+                assert!(matches!(
+                    bc.sync_from_peer(other, 2, 5),
+                    Err(Error::SyncInProgress)
+                ));
+
+                // Disconnect of another peer does not affect the sync.
+                bc.peer_disconnected(other);
+                assert!(matches!(
+                    bc.sync_from_peer(other, 2, 5),
+                    Err(Error::SyncInProgress)
+                ));
+
+                bc.peer_disconnected(peer);
+                assert!(matches!(
+                    events.try_recv(),
+                    Ok(BlockchainEvent::SyncFailed { height: 2, .. })
+                ));
+                bc.sync_from_peer(other, 2, 5).unwrap();
+
+                // The sync that did not receive the next block in time is replaced.
+                if let Some(sync) = bc.sync.as_mut() {
+                    sync.deadline = Instant::now() - Duration::from_secs(1);
+                }
+                bc.sync_from_peer(peer, 2, 5).unwrap();
+                assert!(matches!(
+                    events.try_recv(),
+                    Ok(BlockchainEvent::SyncFailed { height: 2, .. })
+                ));
+                assert_eq!(bc.sync.as_ref().map(|sync| sync.peer), Some(peer));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn node_syncs_blocks_from_peer() {
+        task::LocalSet::new()
+            .run_until(async {
+                let network_key = Scalar::from(1u64);
+                let (initial_state, _) = BlockchainState::make_initial(0, Vec::<ContractID>::new());
+
+                // Alice has the blocks at heights 2, 3 and 4.
+                let (alice_node, alice_notifs) = spawn_node().await;
+                let mut alice = BlockchainRunning::new(
+                    make_config_with_network_key(network_key),
+                    Some(initial_state.clone()),
+                    alice_node.clone(),
+                );
+                for timestamp_ms in 1..=3 {
+                    let state = alice.state.clone().unwrap();
+                    let header = Mempool::new(state, timestamp_ms).make_block().header;
+                    let signature = blockchain::create_block_signature(&header, network_key);
+                    alice
+                        .accept_block(&blockchain::Block::new(header, signature, Vec::new()))
+                        .unwrap();
+                }
+                let alice_tip = alice.state.as_ref().unwrap().tip.clone();
+                assert_eq!(alice_tip.height, 4);
+                let alice = Arc::new(RwLock::new(alice));
+                task::spawn_local(process_notifications(
+                    alice.clone(),
+                    alice_node.clone(),
+                    alice_notifs,
+                ));
+
+                let (mut bob_node, bob_notifs) = spawn_node().await;
+                let bob = Arc::new(RwLock::new(BlockchainRunning::new(
+                    make_config_with_network_key(network_key),
+                    Some(initial_state),
+                    bob_node.clone(),
+                )));
+                let mut events = bob.read().await.subscribe().await;
+                task::spawn_local(process_notifications(
+                    bob.clone(),
+                    bob_node.clone(),
+                    bob_notifs,
+                ));
+
+                // Bob syncs with Alice as soon as they connect.
+                bob_node
+                    .connect_to_peer(alice_node.socket_address(), Some(alice_node.id()))
+                    .await
+                    .unwrap();
+                let completed_height = time::timeout(Duration::from_secs(10), async {
+                    loop {
+                        match events.recv().await.unwrap() {
+                            BlockchainEvent::SyncCompleted { height } => return height,
+                            BlockchainEvent::SyncFailed { height, reason } => {
+                                panic!("Sync failed at height {}: {}", height, reason)
+                            }
+                            _ => {}
+                        }
+                    }
+                })
+                .await
+                .expect("Sync must complete in time");
+                assert_eq!(completed_height, 4);
+
+                let bob = bob.read().await;
+                assert_eq!(bob.state.as_ref().unwrap().tip.id(), alice_tip.id());
+                // Bob serves the downloaded blocks in turn.
+                let headers = match bob.respond(blockchain::Request::GetHeaders {
+                    from_height: 2,
+                    count: 10,
+                }) {
+                    blockchain::Response::Headers(headers) => headers,
+                    _ => panic!("Headers must be returned"),
+                };
+                assert_eq!(
+                    headers.iter().map(|h| h.height).collect::<Vec<_>>(),
+                    vec![2, 3, 4]
+                );
+                assert!(bob.block_at_height(3).is_some());
+                assert!(bob.block_at_height(5).is_none());
+            })
+            .await
+    }
+
+    #[test]
+    fn signed_block_is_accepted() {
+        let network_key = Scalar::from(1u64);
+        let (state, header) = make_state_and_header();
+        let signature = blockchain::create_block_signature(&header, network_key);
+        let block = blockchain::Block::new(header, signature, Vec::new());
+        let pubkey = VerificationKey::from_secret(&network_key);
+        let bp_gens = BulletproofGens::new(64, 1);
+
+        assert!(verify_block(&state, Some(pubkey), &block, &bp_gens).is_ok());
+        // Blocks are not accepted until the network key is configured.
+        assert!(matches!(
+            verify_block(&state, None, &block, &bp_gens),
+            Err(BlockchainError::InvalidBlockSignature)
+        ));
+    }
+
+    #[test]
+    fn unsigned_block_is_rejected() {
+        let (state, header) = make_state_and_header();
+        let signature = Signature {
+            s: Scalar::zero(),
+            R: CompressedRistretto::default(),
+        };
+        let block = blockchain::Block::new(header, signature, Vec::new());
+        let pubkey = VerificationKey::from_secret(&Scalar::from(1u64));
+
+        assert!(matches!(
+            verify_block(&state, Some(pubkey), &block, &BulletproofGens::new(64, 1)),
+            Err(BlockchainError::InvalidBlockSignature)
+        ));
+    }
+
+    #[test]
+    fn wrongly_signed_block_is_rejected() {
+        let (state, header) = make_state_and_header();
+        let signature = blockchain::create_block_signature(&header, Scalar::from(2u64));
+        let block = blockchain::Block::new(header, signature, Vec::new());
+        let pubkey = VerificationKey::from_secret(&Scalar::from(1u64));
+
+        assert!(matches!(
+            verify_block(&state, Some(pubkey), &block, &BulletproofGens::new(64, 1)),
+            Err(BlockchainError::InvalidBlockSignature)
+        ));
+    }
+}
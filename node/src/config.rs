@@ -1,3 +1,5 @@
+use curve25519_dalek::ristretto::CompressedRistretto;
+use musig::VerificationKey;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::net::SocketAddr;
@@ -8,6 +10,7 @@ use crate::errors::Error;
 /// Default config location
 pub const DEFAULT_CONFIG_LOCATION: &'static str = "~/.slingshot/config.toml";
 const BC_STATE_FILENAME: &'static str = "blockchain_state";
+const BC_BLOCKS_DIRNAME: &'static str = "blocks";
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -91,6 +94,14 @@ pub struct P2P {
     /// Ping frequency of the other nodes.
     #[serde(default = "P2P::default_heartbeat_interval_sec")]
     pub heartbeat_interval_sec: u64,
+
+    /// Misbehavior score at which the peer is disconnected and banned.
+    #[serde(default = "P2P::default_ban_threshold")]
+    pub ban_threshold: u64,
+
+    /// Duration of the ban for misbehaving peers.
+    #[serde(default = "P2P::default_ban_duration_sec")]
+    pub ban_duration_sec: u64,
 }
 
 /// P2P configuration options
@@ -107,6 +118,11 @@ pub struct Blockchain {
     /// Minimum feerate in units/byte.
     #[serde(default)]
     pub mempool_min_feerate: f32,
+
+    /// Hex-encoded public key that signs the blocks.
+    /// Blocks received from peers are rejected if it is not set.
+    #[serde(default)]
+    pub network_pubkey: Option<String>,
 }
 
 /// P2P configuration options
@@ -132,6 +148,8 @@ impl Config {
     [p2p]
     listen = "0.0.0.0:0"           # socket address to listen in the peer-to-peer network
     peers = ["127.0.0.0:4000"]     # list of initial peers to connect to
+    ban_threshold = 100            # misbehavior score at which the peer is banned
+    ban_duration_sec = 86400       # for how long the banned peer cannot reconnect
     
     [blockchain]
     storage_path = "./storage"     # location of the stored data 
@@ -139,6 +157,8 @@ impl Config {
                                    #  which is ~/.slingshot/config.toml by default)
     mempool_max_size = 10_000_000  # maximum size in bytes for the mempool transactions
     mempool_min_feerate = 0        # minimum feerate for the transactions to be included in mempool
+    network_pubkey = "6a7b..."     # hex-encoded public key that signs the blocks
+                                   # (blocks from peers are rejected if not set)
 
     [wallet]
     storage_path = "./wallet"      # location of the wallet keys and account data
@@ -191,6 +211,13 @@ impl Config {
         path.push(BC_STATE_FILENAME);
         path
     }
+
+    /// Path to the directory with the verified blocks, stored one file per height
+    pub fn blocks_path(&self) -> PathBuf {
+        let mut path = self.blockchain_path();
+        path.push(BC_BLOCKS_DIRNAME);
+        path
+    }
 }
 
 impl UI {
@@ -245,6 +272,12 @@ impl P2P {
     pub fn default_heartbeat_interval_sec() -> u64 {
         3600
     }
+    pub fn default_ban_threshold() -> u64 {
+        100
+    }
+    pub fn default_ban_duration_sec() -> u64 {
+        86400
+    }
 }
 
 impl Default for P2P {
@@ -256,6 +289,8 @@ impl Default for P2P {
             inbound_limit: Self::default_inbound_limit(),
             outbound_limit: Self::default_outbound_limit(),
             heartbeat_interval_sec: Self::default_heartbeat_interval_sec(),
+            ban_threshold: Self::default_ban_threshold(),
+            ban_duration_sec: Self::default_ban_duration_sec(),
         }
     }
 }
@@ -269,6 +304,18 @@ impl Blockchain {
     pub fn default_mempool_max_size() -> usize {
         1_000_000
     }
+
+    /// Network public key decoded from hex.
+    /// Returns None if the key is not set or is not a valid 32-byte hex string.
+    pub fn network_pubkey(&self) -> Option<VerificationKey> {
+        let bytes = hex::decode(self.network_pubkey.as_ref()?).ok()?;
+        if bytes.len() != 32 {
+            return None;
+        }
+        Some(VerificationKey::from_compressed(
+            CompressedRistretto::from_slice(&bytes),
+        ))
+    }
 }
 
 impl Default for Blockchain {
@@ -277,6 +324,7 @@ impl Default for Blockchain {
             storage_path: Self::default_storage_path(),
             mempool_max_size: Self::default_mempool_max_size(),
             mempool_min_feerate: 0.0,
+            network_pubkey: None,
         }
     }
 }
@@ -2,6 +2,8 @@ use std;
 use std::path::PathBuf;
 use thiserror::Error as ThisError;
 
+use crate::wallet::WalletError;
+
 /// All error types in the node implementation
 #[derive(ThisError, Debug)]
 pub enum Error {
@@ -17,9 +19,36 @@ pub enum Error {
     #[error("Wallet is already initialized")]
     WalletAlreadyExists,
 
+    #[error("Wallet key file is invalid")]
+    InvalidKeyFile,
+
+    #[error("Wallet error: {0}")]
+    WalletError(WalletError),
+
+    #[error("Transaction is invalid: {0}")]
+    InvalidTx(zkvm::VMError),
+
+    #[error("Transaction is rejected: {0}")]
+    TxRejected(blockchain::BlockchainError),
+
+    #[error("Transaction feerate {0} is below the minimum {1}")]
+    LowFeerate(f64, f64),
+
     #[error("Blockchain is already initialized")]
     BlockchainAlreadyExists,
 
+    #[error("Blockchain is not initialized")]
+    BlockchainNotInitialized,
+
+    #[error("Block sync failed at height {0}: {1}")]
+    BlockSyncFailed(u64, blockchain::BlockchainError),
+
+    #[error("Block sync is already in progress")]
+    SyncInProgress,
+
+    #[error("Transaction is not accepted by the node: {0}")]
+    SubmitFailed(String),
+
     #[error("Configuration file does not exist")]
     ConfigNotFound(PathBuf),
 
@@ -33,6 +62,18 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<WalletError> for Error {
+    fn from(err: WalletError) -> Self {
+        Error::WalletError(err)
+    }
+}
+
+impl From<zkvm::VMError> for Error {
+    fn from(err: zkvm::VMError) -> Self {
+        Error::InvalidTx(err)
+    }
+}
+
 impl From<bincode::Error> for Error {
     fn from(err: bincode::Error) -> Self {
         Error::BincodeError(err)
@@ -14,15 +14,21 @@ use bc::{Blockchain, BlockchainIdle};
 use config::Config;
 use errors::Error;
 use ui::UI;
-use wallet::Wallet;
+use wallet::{Utxo, Wallet};
 use wallet_manager::WalletManager;
 
-use accounts::AddressLabel;
+use accounts::{Address, AddressLabel, Receiver, ReceiverReply};
+use blockchain::BlockTx;
 use keytree::Xprv;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
+use zkvm::bulletproofs::BulletproofGens;
 use zkvm::curve25519_dalek::scalar::Scalar;
-use zkvm::ClearValue;
+use zkvm::encoding::Encodable;
+use zkvm::{ClearValue, FeeRate, TxID};
+
+/// Default time after which the payment can no longer be published.
+const DEFAULT_PAYMENT_EXPIRATION_SEC: u64 = 3600;
 
 #[tokio::main]
 async fn main() {
@@ -67,7 +73,105 @@ async fn launch() -> Result<(), String> {
         .subcommand(
             SubCommand::with_name("wallet")
                 .about("Performs wallet operations")
-                .subcommand(SubCommand::with_name("new").about("Creates a new wallet")),
+                .subcommand(
+                    SubCommand::with_name("new")
+                        .about("Creates a new wallet")
+                        .arg(
+                            Arg::with_name("prefix")
+                                .long("prefix")
+                                .value_name("ADDRESS_PREFIX")
+                                .takes_value(true)
+                                .required(true)
+                                .help(
+                                    "Prefix for addresses (1-83 alphanumeric lowercase characters)",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Replaces the existing wallet"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("pay")
+                        .about("Pays to an address or a receiver and submits the payment to the node")
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required_unless("receiver")
+                                .help("Address of the recipient"),
+                        )
+                        .arg(
+                            Arg::with_name("receiver")
+                                .long("receiver")
+                                .value_name("PAYMENT_URI")
+                                .takes_value(true)
+                                .conflicts_with_all(&["to", "qty", "flavor"])
+                                .help("Payment URI of the receiver made by the recipient"),
+                        )
+                        .arg(
+                            Arg::with_name("qty")
+                                .long("qty")
+                                .value_name("QTY")
+                                .takes_value(true)
+                                .required_unless("receiver")
+                                .help("Quantity to pay"),
+                        )
+                        .arg(
+                            Arg::with_name("flavor")
+                                .long("flavor")
+                                .value_name("FLAVOR")
+                                .takes_value(true)
+                                .required_unless("receiver")
+                                .help("Hex-encoded flavor of the asset"),
+                        )
+                        .arg(
+                            Arg::with_name("expires-in")
+                                .long("expires-in")
+                                .value_name("SECONDS")
+                                .takes_value(true)
+                                .help("Time after which the payment can no longer be published (default: 1 hour)"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("receive")
+                        .about("Creates a receiver and prints its payment URI for the sender")
+                        .arg(
+                            Arg::with_name("qty")
+                                .long("qty")
+                                .value_name("QTY")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Quantity to receive"),
+                        )
+                        .arg(
+                            Arg::with_name("flavor")
+                                .long("flavor")
+                                .value_name("FLAVOR")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Hex-encoded flavor of the asset"),
+                        )
+                        .arg(
+                            Arg::with_name("expires-in")
+                                .long("expires-in")
+                                .value_name("SECONDS")
+                                .takes_value(true)
+                                .help("Time after which the payment is no longer accepted (default: never)"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("balance")
+                        .about("Shows confirmed and pending balances per asset")
+                        .arg(Arg::with_name("json").long("json").help("Prints JSON")),
+                )
+                .subcommand(
+                    SubCommand::with_name("utxos")
+                        .about("Lists unspent outputs")
+                        .arg(Arg::with_name("json").long("json").help("Prints JSON")),
+                ),
         )
         .get_matches();
     let config_path = cli_matches.value_of("config").map(|s| PathBuf::from(s));
@@ -92,7 +196,77 @@ async fn launch() -> Result<(), String> {
                 .await
                 .map_err(|e| format!("Failed to create a new blockchain {:?}", e))?;
         }
-        ("wallet", Some(wallet)) => {}
+        ("wallet", Some(wallet)) => match wallet.subcommand() {
+            ("new", Some(sm)) => {
+                let prefix = sm
+                    .value_of("prefix")
+                    .expect("This is a required argument")
+                    .to_string();
+                let addr_label = AddressLabel::new(prefix).ok_or(
+                    "Address prefix must be 1-83 alphanumeric characters long, US-ASCII lowercase."
+                        .to_string(),
+                )?;
+                let address = create_new_wallet(config, addr_label, sm.is_present("force"))
+                    .await
+                    .map_err(|e| match e {
+                        Error::WalletAlreadyExists => {
+                            "Wallet already exists. Use --force to replace it.".to_string()
+                        }
+                        e => format!("Failed to create a new wallet: {}", e),
+                    })?;
+                println!("Created a new wallet. Receive address: {}", address);
+            }
+            ("pay", Some(sm)) => {
+                let payee = match sm.value_of("receiver") {
+                    Some(uri) => Payee::Receiver(
+                        Receiver::from_payment_uri(uri)
+                            .map_err(|e| format!("Payment URI is not valid: {}", e))?,
+                    ),
+                    None => Payee::Address(
+                        Address::from_string(
+                            sm.value_of("to").expect("This is a required argument"),
+                        )
+                        .ok_or("Address is not valid.".to_string())?,
+                        parse_value(sm)?,
+                    ),
+                };
+                let expires_in_sec = parse_seconds(sm.value_of("expires-in"))?
+                    .unwrap_or(DEFAULT_PAYMENT_EXPIRATION_SEC);
+                let exptime_ms =
+                    current_timestamp_ms().saturating_add(expires_in_sec.saturating_mul(1000));
+                let (txid, reply) = make_payment(config, payee, exptime_ms)
+                    .await
+                    .map_err(|e| format!("Failed to make a payment: {}", e))?;
+                println!("Submitted transaction {}", hex::encode(txid.0));
+                if let Some(reply) = reply {
+                    println!("Send this reply to the recipient:");
+                    println!("{}", json::to_json(&reply));
+                }
+            }
+            ("receive", Some(sm)) => {
+                let value = parse_value(sm)?;
+                let exptime_ms = parse_seconds(sm.value_of("expires-in"))?
+                    .map(|sec| current_timestamp_ms().saturating_add(sec.saturating_mul(1000)))
+                    .unwrap_or(u64::max_value());
+                let receiver = create_receiver(config, value, exptime_ms)
+                    .await
+                    .map_err(|e| format!("Failed to create a receiver: {}", e))?;
+                println!("{}", receiver.to_payment_uri());
+            }
+            ("balance", Some(sm)) => {
+                show_balances(config, sm.is_present("json"))
+                    .await
+                    .map_err(|e| format!("Failed to read the wallet: {}", e))?;
+            }
+            ("utxos", Some(sm)) => {
+                show_utxos(config, sm.is_present("json"))
+                    .await
+                    .map_err(|e| format!("Failed to read the wallet: {}", e))?;
+            }
+            _ => {
+                eprintln!("Please specify a wallet subcommand. Use `wallet --help` to see a list of available options.");
+            }
+        },
         ("run", Some(sm)) => {
             run(config)
                 .await
@@ -136,6 +310,254 @@ async fn create_new_blockchain(
     Ok(bc)
 }
 
+async fn create_new_wallet(
+    config: Config,
+    addr_label: AddressLabel,
+    force: bool,
+) -> Result<Address, Error> {
+    let wallet_manager = WalletManager::new(config)?;
+    let mut wm = wallet_manager.write().await;
+    if wm.wallet_exists() {
+        if !force {
+            return Err(Error::WalletAlreadyExists);
+        }
+        wm.clear_wallet()?;
+    }
+    let xprv = Xprv::random(rand::thread_rng());
+    let wallet = Wallet::new(addr_label, xprv.to_xpub());
+    wm.save_xprv(xprv)?;
+    wm.initialize_wallet(wallet)?;
+    wm.update_wallet(|wallet| Ok(wallet.create_address()))
+}
+
+/// Recipient of the payment.
+enum Payee {
+    /// Address with the value to pay: the receiver is encrypted to the address.
+    Address(Address, ClearValue),
+    /// Receiver made by the recipient, who needs the reply to watch for the payment.
+    Receiver(Receiver),
+}
+
+/// Builds and signs a payment, submits it to the node and indexes the change output in the wallet.
+/// Returns the reply for the recipient if paying to a receiver.
+///
+/// The inputs are marked as spent only after the node accepts the transaction,
+/// so a failed submission does not lock the funds.
+async fn make_payment(
+    config: Config,
+    payee: Payee,
+    exptime_ms: u64,
+) -> Result<(TxID, Option<ReceiverReply>), Error> {
+    let wallet_manager = WalletManager::new(config.clone())?;
+    let mut wm = wallet_manager.write().await;
+    let xprv = wm.read_xprv()?;
+    let bp_gens = BulletproofGens::new(256, 1);
+    let feerate = payment_feerate(&config);
+    let (block_tx, receiver) = wm.update_wallet(|wallet| match payee {
+        Payee::Address(address, value) => {
+            let block_tx =
+                wallet.pay_to_address(value, address, exptime_ms, feerate, &xprv, &bp_gens)?;
+            Ok((block_tx, None))
+        }
+        Payee::Receiver(receiver) => {
+            let block_tx =
+                wallet.pay_to_receiver(receiver, exptime_ms, feerate, &xprv, &bp_gens)?;
+            Ok((block_tx, Some(receiver)))
+        }
+    })?;
+    let verified_tx = block_tx.tx.verify(&bp_gens)?;
+
+    submit_tx(&config, &block_tx).await?;
+
+    // Index the change output, so it can be spent in the next payment.
+    wm.update_wallet(|wallet| {
+        wallet.add_unconfirmed_tx(&verified_tx);
+        Ok(())
+    })?;
+    let reply = receiver.and_then(|r| wallet::receiver_reply(&verified_tx.log, &r));
+    Ok((verified_tx.id, reply))
+}
+
+/// Fee rate of the payments: the minimum fee rate accepted by the node's mempool
+/// (`blockchain.mempool_min_feerate`, in units per byte), rounded up to 1/1000 of a unit.
+fn payment_feerate(config: &Config) -> FeeRate {
+    let min_feerate = config.data.blockchain.mempool_min_feerate.max(0.0) as f64;
+    FeeRate::from_fee_and_size((min_feerate * 1000.0).ceil() as u64, 1000)
+}
+
+/// Submits the transaction to the running node via `POST /v1/network/submit`.
+/// Orphan transactions are also accepted: the node keeps them until their parents arrive.
+async fn submit_tx(config: &Config, block_tx: &BlockTx) -> Result<(), Error> {
+    let body = json!({ "block_tx": hex::encode(block_tx.encode_to_vec()) }).to_string();
+    let request = hyper::Request::post(format!(
+        "http://{}/v1/network/submit",
+        config.data.api.listen
+    ))
+    .header("content-type", "application/json")
+    .body(hyper::Body::from(body))
+    .map_err(|e| Error::SubmitFailed(e.to_string()))?;
+    let response = hyper::Client::new()
+        .request(request)
+        .await
+        .map_err(|e| Error::SubmitFailed(e.to_string()))?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| Error::SubmitFailed(e.to_string()))?;
+    if status == hyper::StatusCode::OK || status == hyper::StatusCode::ACCEPTED {
+        Ok(())
+    } else {
+        Err(Error::SubmitFailed(
+            String::from_utf8_lossy(&body).into_owned(),
+        ))
+    }
+}
+
+/// Creates a receiver for the value and records it in the wallet,
+/// so the payment is recognized when it is confirmed.
+async fn create_receiver(
+    config: Config,
+    value: ClearValue,
+    exptime_ms: u64,
+) -> Result<Receiver, Error> {
+    let wallet_manager = WalletManager::new(config)?;
+    let mut wm = wallet_manager.write().await;
+    wm.update_wallet(|wallet| {
+        let (_seq, mut receiver) = wallet.create_receiver(value);
+        receiver.exptime_ms = exptime_ms;
+        Ok(receiver)
+    })
+}
+
+/// Parses the `--qty` and `--flavor` arguments.
+fn parse_value(sm: &clap::ArgMatches) -> Result<ClearValue, String> {
+    let qty = sm
+        .value_of("qty")
+        .expect("This is a required argument")
+        .parse::<u64>()
+        .map_err(|_| "Quantity must be a non-negative integer.".to_string())?;
+    let flv = hex::decode(sm.value_of("flavor").expect("This is a required argument"))
+        .ok()
+        .and_then(|bytes| {
+            if bytes.len() == 32 {
+                let mut buf = [0u8; 32];
+                buf.copy_from_slice(&bytes);
+                Scalar::from_canonical_bytes(buf)
+            } else {
+                None
+            }
+        })
+        .ok_or("Flavor must be a hex-encoded 32-byte scalar.".to_string())?;
+    Ok(ClearValue { qty, flv })
+}
+
+/// Parses the optional duration in seconds.
+fn parse_seconds(arg: Option<&str>) -> Result<Option<u64>, String> {
+    arg.map(|s| {
+        s.parse::<u64>()
+            .map_err(|_| "Duration must be a non-negative number of seconds.".to_string())
+    })
+    .transpose()
+}
+
+async fn show_balances(config: Config, json_output: bool) -> Result<(), Error> {
+    let wallet_manager = WalletManager::new(config)?;
+    let wm = wallet_manager.read().await;
+    let wallet = wm.wallet_ref()?;
+    let balances: Vec<_> = wallet.balance_summaries().collect();
+
+    if json_output {
+        let list: Vec<_> = balances
+            .iter()
+            .map(|b| {
+                json!({
+                    "flavor": hex::encode(b.flavor.as_bytes()),
+                    "asset": wallet.find_asset(b.flavor).map(|(alias, _)| alias),
+                    "confirmed": b.confirmed,
+                    "pending": b.pending,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::Array(list));
+    } else if balances.is_empty() {
+        println!("No funds.");
+    } else {
+        for b in balances.iter() {
+            println!(
+                "{}: {} confirmed, {} pending",
+                asset_name(wallet, b.flavor),
+                b.confirmed,
+                b.pending
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn show_utxos(config: Config, json_output: bool) -> Result<(), Error> {
+    let wallet_manager = WalletManager::new(config)?;
+    let wm = wallet_manager.read().await;
+    let wallet = wm.wallet_ref()?;
+    let utxos: Vec<_> = wallet.unspent_utxos().collect();
+
+    if json_output {
+        let list: Vec<_> = utxos
+            .iter()
+            .map(|utxo| {
+                let value = utxo.value();
+                let (status, proof) = utxo_status(utxo);
+                json!({
+                    "contract_id": hex::encode(utxo.contract_id()),
+                    "flavor": hex::encode(value.flv.as_bytes()),
+                    "qty": value.qty,
+                    "status": status,
+                    "proof": proof,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::Array(list));
+    } else if utxos.is_empty() {
+        println!("No unspent outputs.");
+    } else {
+        for utxo in utxos.iter() {
+            let value = utxo.value();
+            let (status, proof) = utxo_status(utxo);
+            println!(
+                "{} {} {} ({}, {} proof)",
+                hex::encode(utxo.contract_id()),
+                value.qty,
+                asset_name(wallet, value.flv),
+                status,
+                proof
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the confirmation status and the kind of utreexo proof of the utxo.
+fn utxo_status(utxo: &Utxo) -> (&'static str, &'static str) {
+    let status = if utxo.is_confirmed() {
+        "confirmed"
+    } else {
+        "pending"
+    };
+    let proof = if utxo.has_committed_proof() {
+        "committed"
+    } else {
+        "transient"
+    };
+    (status, proof)
+}
+
+/// Returns the alias of a known asset or the hex-encoded flavor.
+fn asset_name(wallet: &Wallet, flavor: Scalar) -> String {
+    wallet
+        .find_asset(flavor)
+        .map(|(alias, _)| alias.to_string())
+        .unwrap_or_else(|| hex::encode(flavor.as_bytes()))
+}
+
 async fn run(config: Config) -> Result<(), Error> {
     // 1. Run the blockchain state machine with p2p interface
     let bc_ref = Blockchain::new(config.clone())?.launch().await?;
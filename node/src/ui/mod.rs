@@ -3,6 +3,7 @@ mod templates;
 mod ws;
 
 use super::config::Config;
+use super::json;
 use super::wallet;
 use super::wallet_manager::WalletRef;
 
@@ -14,6 +15,7 @@ use std::time::SystemTime;
 use std::collections::HashMap;
 
 use tera::Tera;
+use tokio::sync::broadcast;
 use warp::Filter;
 use warp::{filters::BoxedFilter, reply::Reply};
 
@@ -61,6 +63,20 @@ impl UI {
             .and_then(|ui: UI| async move { ui.render("index.html") });
 
         let ws_pool = Arc::new(ws::WebsocketPool::default());
+
+        // Forward the blockchain events to all websockets.
+        let events_pool = ws_pool.clone();
+        let bc = self.bc.clone();
+        tokio::spawn(async move {
+            let mut events = bc.read().await.subscribe().await;
+            loop {
+                match events.recv().await {
+                    Ok(event) => events_pool.broadcast(json::to_json(&event)).await,
+                    Err(broadcast::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::RecvError::Closed) => break,
+                }
+            }
+        });
         let ws_route = warp::path("ws")
             .and(warp::any().map(move || ws_pool.clone()))
             .and(warp::ws())
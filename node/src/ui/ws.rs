@@ -66,4 +66,22 @@ impl WebsocketPool {
         // Connect is closed, so we remove it from the map.
         self.conn_map.write().await.remove(&id);
     }
+
+    /// Sends a text message to all connected websockets.
+    /// Websockets that are no longer connected are removed from the pool.
+    pub async fn broadcast(&self, text: String) {
+        let disconnected: Vec<usize> = self
+            .conn_map
+            .read()
+            .await
+            .iter()
+            .filter_map(|(&id, tx)| tx.send(Ok(Message::text(text.clone()))).err().map(|_| id))
+            .collect();
+        if !disconnected.is_empty() {
+            let mut conn_map = self.conn_map.write().await;
+            for id in disconnected {
+                conn_map.remove(&id);
+            }
+        }
+    }
 }
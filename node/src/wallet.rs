@@ -8,16 +8,18 @@ use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
 use zkvm::bulletproofs::BulletproofGens;
 
-use accounts::{Address, AddressLabel, Receiver, Sequence, XprvDerivation, XpubDerivation};
+use accounts::{
+    Address, AddressLabel, Receiver, ReceiverReply, Sequence, XprvDerivation, XpubDerivation,
+};
 use keytree::{Xprv, Xpub};
 use musig::{Multisignature, VerificationKey};
 use token::{Token, XprvDerivation as TKXprvDeriv, XpubDerivation as TKXpubDeriv};
 
 use blockchain::utreexo;
-use blockchain::{BlockTx, BlockchainState};
+use blockchain::{BlockTx, BlockchainState, Mempool};
 use zkvm::{
-    self, Anchor, ClearValue, Contract, ContractID, PortableItem, Predicate, Program, TxLog,
-    UnsignedTx, VerifiedTx,
+    self, fee_flavor, Anchor, ClearValue, Contract, ContractID, FeeRate, PortableItem, Predicate,
+    Program, TxLog, UnsignedTx, VerifiedTx, MAX_FEE,
 };
 
 use rand::{thread_rng, RngCore};
@@ -60,6 +62,19 @@ pub struct Balance {
     pub utxos: Vec<Utxo>,
 }
 
+/// Confirmed and pending amounts of a certain asset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BalanceSummary {
+    /// Flavor of the asset
+    pub flavor: Scalar,
+
+    /// Total qty of the asset in confirmed utxos
+    pub confirmed: u64,
+
+    /// Total qty of the asset in unconfirmed utxos
+    pub pending: u64,
+}
+
 /// Contract details of the utxo
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Utxo {
@@ -96,6 +111,120 @@ pub enum WalletError {
     /// to receive funds from another ledger.
     #[error("Address label is not expected by this wallet.")]
     AddressLabelMismatch,
+    /// Unspent output in the fee flavor is not found in the wallet.
+    #[error("Unspent output in the fee flavor is not found in the wallet.")]
+    UtxoNotFound,
+    /// The required fee exceeds the maximum fee allowed in a transaction.
+    #[error("The required fee exceeds the maximum fee allowed in a transaction.")]
+    FeeTooHigh,
+    /// The fee rate of the package is below the target fee rate.
+    #[error("The fee rate of the package is below the target fee rate.")]
+    InsufficientFee,
+}
+
+/// Errors that may occur during coin selection.
+#[derive(Clone, Error, Debug, PartialEq)]
+pub enum SelectionError {
+    /// Available coins do not cover the target value and the estimated fee.
+    #[error("Insufficient funds: {shortfall} more units are needed.")]
+    InsufficientFunds {
+        /// Quantity missing to cover the target and the fee.
+        shortfall: u64,
+    },
+}
+
+/// Estimated size of a payment transaction without inputs, in bytes:
+/// - the header, the length prefixes and the signature take 96 bytes,
+/// - the fee and two outputs (payment and change), each pushing two value commitments
+///   and a predicate, take about 250 bytes,
+/// - the R1CS proof takes about 1100 bytes for up to 256 multipliers,
+///   128 of which are used by the range proofs of the two outputs.
+///
+/// The estimate is approximate: the actual fee rate may differ from the requested one by a few percent.
+const ESTIMATED_TX_OVERHEAD: usize = 1500;
+
+/// Estimated size added by each input, in bytes:
+/// the contract witness with the predicate, two value commitments and the anchor (about 140 bytes),
+/// plus the share of the inner-product proof, which grows by 64 bytes
+/// every time the number of multipliers doubles.
+const ESTIMATED_INPUT_SIZE: usize = 200;
+
+/// Returns the fee for a payment transaction with a given number of inputs
+/// to have at least the given fee rate, based on the estimated transaction size.
+pub fn estimate_fee(feerate: FeeRate, inputs: usize) -> u64 {
+    feerate.fee_for_size(ESTIMATED_TX_OVERHEAD + inputs * ESTIMATED_INPUT_SIZE)
+}
+
+/// Selects coins covering the target value and the fee for the transaction spending them.
+/// Returns the indices of the selected coins in `available`.
+///
+/// The fee is estimated for a payment with a given number of inputs (see `estimate_fee`)
+/// and is added to the target only if the target is in the fee flavor.
+/// Coins that do not cover the fee for their own input ("dust") are never selected.
+/// Coins are picked from the largest to minimize the number of inputs,
+/// and then the last picked coin is replaced with the smallest one that still covers the target
+/// to minimize the change.
+pub fn select_coins(
+    available: &[ClearValue],
+    target: ClearValue,
+    feerate: FeeRate,
+) -> Result<Vec<usize>, SelectionError> {
+    let pays_fee = target.flv == fee_flavor();
+    let input_fee = if pays_fee {
+        feerate.fee_for_size(ESTIMATED_INPUT_SIZE)
+    } else {
+        0
+    };
+    let required = |inputs: usize| -> u64 {
+        let fee = if pays_fee {
+            estimate_fee(feerate, inputs)
+        } else {
+            0
+        };
+        target.qty.saturating_add(fee)
+    };
+
+    let mut candidates = available
+        .iter()
+        .enumerate()
+        .filter(|(_, coin)| coin.flv == target.flv && coin.qty > input_fee)
+        .map(|(i, coin)| (i, coin.qty))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut count = 0;
+    let mut total = 0u64;
+    for (_, qty) in candidates.iter() {
+        if total >= required(count) {
+            break;
+        }
+        total = total.saturating_add(*qty);
+        count += 1;
+    }
+    if total < required(count) {
+        return Err(SelectionError::InsufficientFunds {
+            shortfall: required(count) - total,
+        });
+    }
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Replace the last coin with the smallest one that still covers the target.
+    let rest = total - candidates[count - 1].1;
+    let needed = required(count) - rest;
+    let last = candidates[count - 1..]
+        .iter()
+        .filter(|(_, qty)| *qty >= needed)
+        .min_by_key(|(_, qty)| *qty)
+        .map(|(i, _)| *i)
+        .expect("The last picked coin always covers the target");
+
+    Ok(candidates[..count - 1]
+        .iter()
+        .map(|(i, _)| *i)
+        .chain(Some(last))
+        .collect())
 }
 
 /// Single-account tx builder API.
@@ -103,6 +232,8 @@ pub enum WalletError {
 pub struct TxBuilder {
     xpub: Xpub,
     actions: Vec<TxAction>,
+    maxtime_ms: u64,
+    feerate: FeeRate,
 }
 
 /// Built, but not signed transaction.
@@ -375,6 +506,42 @@ impl Wallet {
         })
     }
 
+    /// Returns all unspent utxos, including unconfirmed incoming payments that are not spendable yet.
+    pub fn unspent_utxos(&self) -> impl Iterator<Item = &Utxo> {
+        self.utxos.values().filter(|utxo| utxo.spent == None)
+    }
+
+    /// Returns the unspent utxo with a given contract ID.
+    pub fn unspent_utxo(&self, contract_id: &ContractID) -> Option<&Utxo> {
+        self.utxos
+            .get(contract_id)
+            .filter(|utxo| utxo.spent == None)
+    }
+
+    /// Returns confirmed and pending amounts of each asset in the unspent utxos, one per asset flavor.
+    pub fn balance_summaries(&self) -> impl Iterator<Item = BalanceSummary> {
+        self.unspent_utxos()
+            .fold(
+                HashMap::new(),
+                |mut hm: HashMap<Scalar, BalanceSummary>, utxo| {
+                    let value = utxo.value();
+                    let summary = hm.entry(value.flv).or_insert_with(|| BalanceSummary {
+                        flavor: value.flv,
+                        confirmed: 0,
+                        pending: 0,
+                    });
+                    if utxo.confirmed {
+                        summary.confirmed += value.qty;
+                    } else {
+                        summary.pending += value.qty;
+                    }
+                    hm
+                },
+            )
+            .into_iter()
+            .map(|(_, summary)| summary)
+    }
+
     /// Returns a list of asset balances, one per asset flavor.
     pub fn balances(&self) -> impl Iterator<Item = Balance> {
         self.spendable_utxos()
@@ -401,6 +568,8 @@ impl Wallet {
         let mut rng = thread_rng();
         let mut builder = TxBuilder::new(self.xpub);
         closure(&mut builder);
+        let sender_maxtime_ms = builder.maxtime_ms;
+        let feerate = builder.feerate;
 
         // Collect issuances of each asset
         let grouped_issuances = builder
@@ -427,7 +596,7 @@ impl Wallet {
             )?;
 
         // Collect transfers of each asset
-        let grouped_transfers = builder
+        let transfers: Vec<ClearValue> = builder
             .actions
             .iter()
             .filter_map(|action| match action {
@@ -435,29 +604,44 @@ impl Wallet {
                 TxAction::TransferToReceiver(r) => Some(r.value),
                 _ => None,
             })
-            .fold(HashMap::new(), |mut hm: HashMap<Scalar, u64>, value| {
-                *(hm.entry(value.flv).or_default()) += value.qty;
-                hm
-            });
+            .collect();
+        let grouped_transfers = zkvm::group_by_flavor(&transfers);
 
         let mut outputs = Vec::<Receiver>::new();
+        let mut fee = 0u64;
 
-        // Collect utxos and change outputs for each asset transferred
-        let (inputs, _) = grouped_transfers.into_iter().try_fold(
-            (Vec::<Utxo>::new(), &mut outputs),
-            |(mut inputs, outputs), (flv, qty)| {
-                let (utxos_to_spend, change_clear_value) = ClearValue { qty, flv }
-                    .select_coins(self.spendable_utxos())
-                    .ok_or(WalletError::InsufficientFunds)?;
-
-                let (_seq, change_receiver) = self.create_receiver(change_clear_value);
+        // Collect utxos and change outputs for each asset transferred.
+        // The fee is paid from the inputs in the fee flavor.
+        let spendable = self.spendable_utxos().collect::<Vec<_>>();
+        let spendable_values = spendable
+            .iter()
+            .map(|utxo| utxo.value())
+            .collect::<Vec<_>>();
+        let mut inputs = Vec::<Utxo>::new();
+        for (flv, qty) in grouped_transfers.into_iter() {
+            let selected = select_coins(&spendable_values, ClearValue { qty, flv }, feerate)
+                .map_err(|_| WalletError::InsufficientFunds)?;
+            let total = selected.iter().fold(0u64, |total, i| {
+                total.saturating_add(spendable_values[*i].qty)
+            });
+            let flavor_fee = if flv == fee_flavor() {
+                estimate_fee(feerate, selected.len())
+            } else {
+                0
+            };
+            if flavor_fee > MAX_FEE {
+                return Err(WalletError::FeeTooHigh);
+            }
+            fee += flavor_fee;
 
-                inputs.extend(utxos_to_spend.into_iter());
-                outputs.push(change_receiver);
+            let (_seq, change_receiver) = self.create_receiver(ClearValue {
+                qty: total - qty - flavor_fee,
+                flv,
+            });
 
-                Ok((inputs, outputs))
-            },
-        )?;
+            inputs.extend(selected.into_iter().map(|i| spendable[i].clone()));
+            outputs.push(change_receiver);
+        }
 
         let mut memos = Vec::<Vec<u8>>::new();
 
@@ -511,6 +695,12 @@ impl Wallet {
                 p.signtx();
             }
 
+            // pay the fee from the inputs
+            if fee > 0 {
+                p.push(zkvm::String::U32(fee as u32));
+                p.fee();
+            }
+
             // prepare outputs for cloak mixer
             for recvr in outputs.iter() {
                 let v = recvr.blinded_value();
@@ -519,7 +709,7 @@ impl Wallet {
             }
 
             // merge/split assets
-            p.cloak(inputs.len(), outputs.len());
+            p.cloak(inputs.len() + (fee > 0) as usize, outputs.len());
 
             // lock outputs under new predicates
             for recvr in outputs.iter() {
@@ -534,10 +724,13 @@ impl Wallet {
             }
         });
 
+        // The transaction must expire no later than the sender and all the receivers.
         let header = zkvm::TxHeader {
             version: 1u64,
             mintime_ms: 0u64,
-            maxtime_ms: u64::max_value(),
+            maxtime_ms: outputs.iter().fold(sender_maxtime_ms, |maxtime, recvr| {
+                recvr.effective_maxtime(maxtime)
+            }),
         };
 
         // Build the UnverifiedTx
@@ -568,15 +761,24 @@ impl Wallet {
     /// index it in the wallet via `add_unconfirmed_tx`.
     /// After that you can sign another transaction and use the full balance
     /// that includes the change value from the previously signed transaction.
+    ///
+    /// The transaction expires at `exptime_ms` (see `TxBuilder::expire_at`)
+    /// and pays the fee at the given fee rate (see `TxBuilder::pay_feerate`).
     pub fn pay_to_address(
         &mut self,
         value: ClearValue,
         address: Address,
+        exptime_ms: u64,
+        feerate: FeeRate,
         xprv: &Xprv,
         bp_gens: &BulletproofGens,
     ) -> Result<BlockTx, WalletError> {
-        self.build_tx(bp_gens, |b| b.transfer_to_address(value, address))?
-            .sign(&xprv)
+        self.build_tx(bp_gens, |b| {
+            b.transfer_to_address(value, address);
+            b.expire_at(exptime_ms);
+            b.pay_feerate(feerate);
+        })?
+        .sign(&xprv)
     }
 
     /// Attempts to build and sign a transaction paying a value to a given receiver.
@@ -586,14 +788,132 @@ impl Wallet {
     /// index it in the wallet via `add_unconfirmed_tx`.
     /// After that you can sign another transaction and use the full balance
     /// that includes the change value from the previously signed transaction.
+    ///
+    /// The transaction expires at the earliest of `exptime_ms` and the receiver's expiration time,
+    /// and pays the fee at the given fee rate (see `TxBuilder::pay_feerate`).
+    /// Use `receiver_reply` to make the reply for the recipient from the verified transaction.
     pub fn pay_to_receiver(
         &mut self,
         receiver: Receiver,
+        exptime_ms: u64,
+        feerate: FeeRate,
         xprv: &Xprv,
         bp_gens: &BulletproofGens,
     ) -> Result<BlockTx, WalletError> {
-        self.build_tx(bp_gens, |b| b.transfer_to_receiver(receiver))?
-            .sign(xprv)
+        self.build_tx(bp_gens, |b| {
+            b.transfer_to_receiver(receiver);
+            b.expire_at(exptime_ms);
+            b.pay_feerate(feerate);
+        })?
+        .sign(xprv)
+    }
+
+    /// Builds and signs a child transaction that pays for a stuck parent transaction (CPFP).
+    ///
+    /// The child spends the wallet's unspent output of the parent in the fee flavor
+    /// (typically the change), pays the fee and sends the rest to a new receiver.
+    /// The fee is computed so that the package of the parent and the child
+    /// has at least the given fee rate, plus an `extra_fee` on top of that.
+    /// The package fee rate is checked with `Mempool::is_feerate_sufficient`,
+    /// taking into account only the parent: its own unconfirmed ancestors can only lower it.
+    ///
+    /// The resulting transaction is verified and its package fee rate is checked
+    /// against the target before it is returned. The parent's output is marked as spent
+    /// and the child's output is added as unconfirmed: use `remove_unconfirmed_tx`
+    /// to undo this if the child is not published.
+    pub fn build_cpfp_child(
+        &mut self,
+        parent: &VerifiedTx,
+        output: ContractID,
+        extra_fee: u64,
+        feerate: FeeRate,
+        xprv: &Xprv,
+        bp_gens: &BulletproofGens,
+    ) -> Result<BlockTx, WalletError> {
+        if !parent.log.outputs().any(|c| c.id() == output) {
+            return Err(WalletError::UtxoNotFound);
+        }
+        let utxo = self
+            .utxos
+            .get(&output)
+            .filter(|utxo| utxo.spent == None && utxo.value().flv == fee_flavor())
+            .cloned()
+            .ok_or(WalletError::UtxoNotFound)?;
+        let value = utxo.value();
+
+        // The change receiver is created once, and its value is updated with the fee on each attempt.
+        let (change_seq, _) = self.create_receiver(value);
+
+        // The exact size of the child is not known until it is built, so we start with an estimate
+        // and rebuild the child with the actual size if the estimate was too low.
+        // The size does not depend on the fee amount, so the second attempt always uses the exact size.
+        let mut child_size = ESTIMATED_TX_OVERHEAD + ESTIMATED_INPUT_SIZE;
+        for _ in 0..2 {
+            let fee = feerate
+                .fee_for_size(parent.feerate.size() + child_size)
+                .saturating_sub(parent.feerate.fee())
+                .saturating_add(extra_fee);
+            if fee > MAX_FEE {
+                return Err(WalletError::FeeTooHigh);
+            }
+            if fee > value.qty {
+                return Err(WalletError::InsufficientFunds);
+            }
+            let change = self.xpub.receiver_at_sequence(
+                change_seq,
+                ClearValue {
+                    qty: value.qty - fee,
+                    flv: value.flv,
+                },
+            );
+
+            let program = zkvm::Program::build(|p| {
+                p.push(utxo.contract_witness());
+                p.input();
+                p.signtx();
+
+                p.push(zkvm::String::U32(fee as u32));
+                p.fee();
+
+                let v = change.blinded_value();
+                p.push(v.qty);
+                p.push(v.flv);
+                p.cloak(2, 1);
+
+                p.push(change.predicate());
+                p.output(1);
+            });
+
+            let header = zkvm::TxHeader {
+                version: 1u64,
+                mintime_ms: 0u64,
+                maxtime_ms: u64::max_value(),
+            };
+            let unsigned_tx = zkvm::Prover::build_tx(program, header, &bp_gens)
+                .expect("We are supposed to compose the program correctly.");
+
+            let block_tx = BuiltTx {
+                unsigned_tx,
+                proofs: vec![utxo.proof.clone()],
+                signtx_items: vec![SigntxInstruction::Input(self.xpub, utxo.sequence)],
+            }
+            .sign(xprv)?;
+
+            let child = block_tx
+                .tx
+                .verify(bp_gens)
+                .expect("We are supposed to compose the program correctly.");
+            if Mempool::is_feerate_sufficient(child.feerate, Some(parent.feerate), feerate) {
+                self.receivers.insert(
+                    change.opaque_predicate.clone(),
+                    (change_seq, change, OutputKind::Incoming),
+                );
+                self.add_unconfirmed_tx(&child);
+                return Ok(block_tx);
+            }
+            child_size = child.feerate.size();
+        }
+        Err(WalletError::InsufficientFee)
     }
 
     /// Returns a pair of a sequence number and a receiver
@@ -633,8 +953,20 @@ impl TxBuilder {
         TxBuilder {
             xpub,
             actions: Vec::new(),
+            maxtime_ms: u64::max_value(),
+            feerate: FeeRate::zero(),
         }
     }
+    /// Sets the sender's expiration time: the transaction's `maxtime` is the earliest
+    /// of this time and the expiration times of the receivers.
+    pub fn expire_at(&mut self, exptime_ms: u64) {
+        self.maxtime_ms = exptime_ms;
+    }
+    /// Sets the fee rate of the transaction. The fee is estimated from the number of inputs
+    /// (see `select_coins`) and is paid only when transferring the fee flavor.
+    pub fn pay_feerate(&mut self, feerate: FeeRate) {
+        self.feerate = feerate;
+    }
     /// Issues the requested amount to the address.
     pub fn issue_to_address(&mut self, value: ClearValue, address: Address) {
         self.actions.push(TxAction::IssueToAddress(value, address));
@@ -663,8 +995,7 @@ impl BuiltTx {
     /// Xprv must match the wallet's xprv.
     pub fn sign(self, xprv: &Xprv) -> Result<BlockTx, WalletError> {
         let txid = self.unsigned_tx.txid;
-        let mut signtx_transcript = merlin::Transcript::new(b"ZkVM.signtx");
-        signtx_transcript.append_message(b"txid", &self.unsigned_tx.txid);
+        let mut signtx_transcript = self.unsigned_tx.signing_transcript();
 
         let signing_keys = self
             .signtx_items
@@ -704,6 +1035,20 @@ impl BuiltTx {
     }
 }
 
+/// Makes the reply for the recipient with the anchor of the output paying to the receiver.
+/// Returns None if the transaction does not pay to the receiver.
+pub fn receiver_reply(txlog: &TxLog, receiver: &Receiver) -> Option<ReceiverReply> {
+    let anchor = txlog
+        .outputs()
+        .find(|c| c.id() == receiver.contract(c.anchor).id())?
+        .anchor;
+    Some(ReceiverReply {
+        receiver_id: receiver.id(),
+        anchor,
+        note: None,
+    })
+}
+
 impl AsRef<ClearValue> for Utxo {
     fn as_ref(&self) -> &ClearValue {
         &self.receiver.value
@@ -742,4 +1087,174 @@ impl Utxo {
     pub fn value(&self) -> ClearValue {
         self.receiver.value
     }
+
+    /// Returns true if the utxo is confirmed in a block.
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed
+    }
+
+    /// Returns the utreexo proof of the utxo as of the last processed block.
+    pub fn proof(&self) -> &utreexo::Proof {
+        &self.proof
+    }
+
+    /// Returns true if the utxo has a utreexo proof with a merkle path.
+    /// Unconfirmed utxos have transient proofs.
+    pub fn has_committed_proof(&self) -> bool {
+        match self.proof {
+            utreexo::Proof::Committed(_) => true,
+            utreexo::Proof::Transient => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coins(quantities: &[u64]) -> Vec<ClearValue> {
+        quantities
+            .iter()
+            .map(|&qty| ClearValue {
+                qty,
+                flv: fee_flavor(),
+            })
+            .collect()
+    }
+
+    fn target(qty: u64) -> ClearValue {
+        ClearValue {
+            qty,
+            flv: fee_flavor(),
+        }
+    }
+
+    #[test]
+    fn selects_smallest_sufficient_coin() {
+        let available = coins(&[5000, 1200, 3000]);
+        assert_eq!(
+            select_coins(&available, target(1000), FeeRate::zero()),
+            Ok(vec![1])
+        );
+        assert_eq!(
+            select_coins(&available, target(8000), FeeRate::zero()),
+            Ok(vec![0, 2])
+        );
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let available = coins(&[300, 400]);
+        assert_eq!(
+            select_coins(&available, target(1000), FeeRate::zero()),
+            Err(SelectionError::InsufficientFunds { shortfall: 300 })
+        );
+
+        let feerate = FeeRate::from_fee_and_size(1, 1);
+        assert_eq!(
+            select_coins(&available, target(1000), feerate),
+            Err(SelectionError::InsufficientFunds {
+                shortfall: 1000 + estimate_fee(feerate, 2) - 700
+            })
+        );
+    }
+
+    #[test]
+    fn dust_is_not_selected() {
+        let feerate = FeeRate::from_fee_and_size(1, 1);
+        let dust = feerate.fee_for_size(ESTIMATED_INPUT_SIZE);
+
+        let available = coins(&[dust, 5000]);
+        assert_eq!(select_coins(&available, target(100), feerate), Ok(vec![1]));
+
+        // Dust alone cannot pay for anything, not even for its own input.
+        let available = coins(&[dust, dust, dust]);
+        assert_eq!(
+            select_coins(&available, target(1), feerate),
+            Err(SelectionError::InsufficientFunds {
+                shortfall: 1 + estimate_fee(feerate, 0)
+            })
+        );
+
+        // Without the fee, there is no dust.
+        assert_eq!(
+            select_coins(&available, target(1), FeeRate::zero()).map(|s| s.len()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn feerate_determines_number_of_inputs() {
+        let available = coins(&[2000, 2000, 2000]);
+        assert_eq!(
+            select_coins(&available, target(1000), FeeRate::zero()).map(|s| s.len()),
+            Ok(1)
+        );
+        let feerate = FeeRate::from_fee_and_size(1, 1);
+        assert!(1000 + estimate_fee(feerate, 1) > 2000);
+        assert_eq!(
+            select_coins(&available, target(1000), feerate).map(|s| s.len()),
+            Ok(2)
+        );
+
+        // Other flavors do not pay the fee.
+        let flv = Scalar::from(1u64);
+        let available = available
+            .into_iter()
+            .map(|coin| ClearValue { flv, ..coin })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            select_coins(&available, ClearValue { qty: 1000, flv }, feerate).map(|s| s.len()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn cpfp_package_passes_mempool_threshold() {
+        let bp_gens = BulletproofGens::new(256, 1);
+        let xprv = Xprv::random(thread_rng());
+        let label = AddressLabel::new("test".to_string()).unwrap();
+        let mut wallet = Wallet::new(label, xprv.to_xpub());
+        let state = wallet.seed_blockchain(0, vec![target(100_000)]);
+
+        // The parent pays no fee, and its change pays for it.
+        let address = wallet.create_address();
+        let parent_tx = wallet
+            .pay_to_address(
+                target(1000),
+                address,
+                u64::max_value(),
+                FeeRate::zero(),
+                &xprv,
+                &bp_gens,
+            )
+            .unwrap();
+        let parent = parent_tx.tx.verify(&bp_gens).unwrap();
+        wallet.add_unconfirmed_tx(&parent);
+        let change = parent
+            .log
+            .outputs()
+            .map(|c| c.id())
+            .find(|cid| wallet.unspent_utxo(cid).map(|u| u.value()) == Some(target(99_000)))
+            .expect("The change must be indexed by the wallet");
+
+        let min_feerate = FeeRate::from_fee_and_size(1, 1);
+        let child_tx = wallet
+            .build_cpfp_child(&parent, change, 0, min_feerate, &xprv, &bp_gens)
+            .unwrap();
+        let child = child_tx.tx.verify(&bp_gens).unwrap();
+        assert!(wallet.unspent_utxo(&change).is_none());
+        assert!(Mempool::is_feerate_sufficient(
+            child.feerate,
+            Some(parent.feerate),
+            min_feerate
+        ));
+
+        let mut mempool = Mempool::new(state, 0);
+        mempool.append(parent_tx, &bp_gens).unwrap();
+        mempool.append(child_tx, &bp_gens).unwrap();
+        let views = mempool.iter().collect::<Vec<_>>();
+        assert!(views[0].effective_feerate < min_feerate);
+        assert!(views[1].effective_feerate >= min_feerate);
+    }
 }
@@ -72,6 +72,12 @@ impl WalletManager {
         Ok(())
     }
 
+    /// Reads the signing key from the keyfile
+    pub fn read_xprv(&self) -> Result<Xprv, Error> {
+        let bytes = fs::read(self.wallet_keypath())?;
+        Xprv::from_bytes(&bytes).ok_or(Error::InvalidKeyFile)
+    }
+
     /// Removes the wallet
     pub fn clear_wallet(&mut self) -> Result<(), Error> {
         fs::remove_file(self.wallet_filepath())?;
@@ -7,7 +7,7 @@ use tokio::prelude::*;
 use tokio::task;
 
 use p2p::cybershake;
-use p2p::{Node, NodeConfig, NodeHandle, NodeNotification, PeerID};
+use p2p::{BroadcastPolicy, Node, NodeConfig, NodeHandle, NodeNotification, PeerID};
 
 fn main() {
     // Create the runtime.
@@ -23,6 +23,8 @@ fn main() {
                 inbound_limit: 100,
                 outbound_limit: 100,
                 heartbeat_interval_sec: 3600,
+                ban_threshold: 100,
+                ban_duration_sec: 86400,
             };
 
             let (node, mut notifications_channel) = Node::<Message>::spawn(host_privkey, config)
@@ -46,14 +48,23 @@ fn main() {
                             NodeNotification::PeerAdded(pid) => {
                                 println!("\n=>    Peer connected: {}", pid)
                             }
-                            NodeNotification::PeerDisconnected(pid) => {
-                                println!("\n=> Peer disconnected: {}", pid)
+                            NodeNotification::PeerBanned(pid) => {
+                                println!("\n=>       Peer banned: {}", pid)
+                            }
+                            NodeNotification::PeerDisconnected(pid, reason) => {
+                                println!("\n=> Peer disconnected: {} ({:?})", pid, reason)
                             }
                             NodeNotification::MessageReceived(pid, msg) => println!(
                                 "\n=> Received: `{}` from {}",
                                 String::from_utf8_lossy(&msg).into_owned(),
                                 pid
                             ),
+                            NodeNotification::RequestReceived(pid, id, msg) => println!(
+                                "\n=> Received request #{}: `{}` from {}",
+                                id,
+                                String::from_utf8_lossy(&msg).into_owned(),
+                                pid
+                            ),
                             NodeNotification::InboundConnectionFailure(err) => {
                                 println!("\n=> Inbound connection failure: {:?}", err)
                             }
@@ -147,7 +158,9 @@ impl Console {
             }
             UserCommand::Broadcast(msg) => {
                 println!("=> Broadcasting: {:?}", &msg);
-                self.node.broadcast(Message(msg.as_bytes().to_vec())).await;
+                self.node
+                    .broadcast(Message(msg.as_bytes().to_vec()), BroadcastPolicy::Block)
+                    .await;
             }
             UserCommand::ListPeers => {
                 let peer_infos = self.node.list_peers().await;
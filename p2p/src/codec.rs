@@ -1,5 +1,5 @@
 use crate::cybershake::PublicKey;
-use crate::peer::PeerAddr;
+use crate::peer::{DisconnectReason, PeerAddr};
 use crate::{PeerID, PeerMessage};
 use bytes::{Buf, BufMut, BytesMut};
 use curve25519_dalek::ristretto::CompressedRistretto;
@@ -36,12 +36,29 @@ impl<T: Codable> Encoder<PeerMessage<T>> for MessageEncoder<T> {
             PeerMessage::Data(data) => {
                 dst.put_u8(2); // Message type
                 dst.put_u32_le(0); // We put here length after
-                data.encode(dst).map_err(|e| {
-                    io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("An error occured when encode body: {}", e),
-                    )
-                })?;
+                encode_data(data, dst)?;
+                let body_len = (dst.len() - 5) as u32;
+                dst[1..5].copy_from_slice(&body_len.to_le_bytes()[..]);
+            }
+            PeerMessage::Goodbye(reason) => {
+                dst.put_u8(3); // Message type
+                const GOODBYE_MESSAGE_LEN: u32 = 1;
+                dst.put_u32_le(GOODBYE_MESSAGE_LEN);
+                dst.put_u8(reason.to_u8());
+            }
+            PeerMessage::Request(id, data) => {
+                dst.put_u8(4); // Message type
+                dst.put_u32_le(0); // We put here length after
+                dst.put_u64_le(id);
+                encode_data(data, dst)?;
+                let body_len = (dst.len() - 5) as u32;
+                dst[1..5].copy_from_slice(&body_len.to_le_bytes()[..]);
+            }
+            PeerMessage::Response(id, data) => {
+                dst.put_u8(5); // Message type
+                dst.put_u32_le(0); // We put here length after
+                dst.put_u64_le(id);
+                encode_data(data, dst)?;
                 let body_len = (dst.len() - 5) as u32;
                 dst[1..5].copy_from_slice(&body_len.to_le_bytes()[..]);
             }
@@ -50,6 +67,15 @@ impl<T: Codable> Encoder<PeerMessage<T>> for MessageEncoder<T> {
     }
 }
 
+fn encode_data<T: Codable>(data: T, dst: &mut BytesMut) -> Result<(), io::Error> {
+    data.encode(dst).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("An error occured when encode body: {}", e),
+        )
+    })
+}
+
 impl<T: Codable> MessageEncoder<T> {
     pub fn new() -> Self {
         Self {
@@ -91,7 +117,7 @@ impl<T: Codable> Decoder for MessageDecoder<T> {
                 }
                 let command_type = src.get_u8();
                 match command_type {
-                    0..=2 => {}
+                    0..=5 => {}
                     _ => {
                         return Err(io::Error::new(
                             io::ErrorKind::InvalidData,
@@ -146,14 +172,38 @@ fn read_message_body<T: Codable>(
             }
             Ok(PeerMessage::Peers(peers))
         }
-        2 => {
-            let body = src.split_to(len);
-            match T::decode(&mut body.freeze()) {
-                Ok(data) => Ok(PeerMessage::Data(data)),
-                Err(e) => Err(io::Error::new(
+        2 => decode_data(src.split_to(len)).map(PeerMessage::Data),
+        3 => {
+            if len != 1 {
+                return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
-                    format!("An error occurred when decode body: {}", e),
-                )),
+                    format!("Invalid length for goodbye message: {}", len),
+                ));
+            }
+            let code = src.get_u8();
+            DisconnectReason::from_u8(code)
+                .map(PeerMessage::Goodbye)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unknown disconnect reason: {}", code),
+                    )
+                })
+        }
+        4 | 5 => {
+            if len < 8 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid length for request or response: {}", len),
+                ));
+            }
+            let mut body = src.split_to(len);
+            let id = body.get_u64_le();
+            let data = decode_data(body)?;
+            if message_type == 4 {
+                Ok(PeerMessage::Request(id, data))
+            } else {
+                Ok(PeerMessage::Response(id, data))
             }
         }
         m => Err(io::Error::new(
@@ -163,6 +213,15 @@ fn read_message_body<T: Codable>(
     }
 }
 
+fn decode_data<T: Codable>(body: BytesMut) -> Result<T, io::Error> {
+    T::decode(&mut body.freeze()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("An error occurred when decode body: {}", e),
+        )
+    })
+}
+
 fn encode_peer_addr(peer: PeerAddr, buf: &mut BytesMut) {
     match peer.addr {
         SocketAddr::V4(d) => {
@@ -315,6 +374,56 @@ mod tests {
         assert_eq!(msg, res);
     }
 
+    #[test]
+    fn code_goodbye() {
+        let msg = PeerMessage::<Message>::Goodbye(DisconnectReason::Duplicate);
+        let mut bytes = BytesMut::new();
+        MessageEncoder::new()
+            .encode(msg.clone(), &mut bytes)
+            .expect("Must be encoded");
+        assert_eq!(&bytes[..], &[3, 1, 0, 0, 0, 2]);
+        let res = MessageDecoder::new()
+            .decode(&mut bytes)
+            .expect("Message must be decoded without errors")
+            .expect("message must be encoded to end");
+
+        assert_eq!(msg, res);
+
+        let mut bytes = BytesMut::from(&[3u8, 1, 0, 0, 0, 4][..]);
+        assert_eq!(
+            MessageDecoder::<Message>::new().decode(&mut bytes).unwrap(),
+            Some(PeerMessage::Goodbye(DisconnectReason::SlowPeer))
+        );
+
+        let mut bytes = BytesMut::from(&[3u8, 1, 0, 0, 0, 200][..]);
+        assert!(MessageDecoder::<Message>::new().decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn code_request_response() {
+        let mut encoder = MessageEncoder::new();
+        let mut decoder = MessageDecoder::new();
+        for msg in vec![
+            PeerMessage::Request(7, Message(vec![1, 2, 3])),
+            PeerMessage::Response(u64::max_value(), Message(vec![])),
+        ] {
+            let mut bytes = BytesMut::new();
+            encoder
+                .encode(msg.clone(), &mut bytes)
+                .expect("Must be encoded");
+            let res = decoder
+                .decode(&mut bytes)
+                .expect("Message must be decoded without errors")
+                .expect("message must be encoded to end");
+            assert_eq!(msg, res);
+            assert!(bytes.is_empty());
+        }
+
+        // Request ID is missing.
+        let mut bytes = BytesMut::from(&[4u8, 4, 0, 0, 0, 1, 2, 3, 4][..]);
+        assert!(MessageDecoder::<Message>::new().decode(&mut bytes).is_err());
+    }
+
     #[test]
     fn code_custom() {
         let msg = PeerMessage::Data(Message(vec![1, 2, 3, 4, 5, 6]));
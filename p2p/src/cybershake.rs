@@ -25,6 +25,8 @@
 //! * **Key blinding.** Long-term identity keys are never transmitted in the clear.
 //! * **Foward secrecy.** Keys are rotated on each sent message.
 //! * **Robust encryption.** cipher AES-SIV-PMAC-128 provides high speed and resistance to nonce-misuse.
+//! * **Streaming.** Large payloads can be sent as a stream of chunks, each in its own authenticated frame.
+//! * **Identity rotation.** Long-term identity keys can be replaced mid-session with `rekey`.
 //!
 //! ## TODO
 //!
@@ -45,6 +47,8 @@ use merlin::Transcript; // TODO: change for raw Strobe.
 use tokio::io;
 use tokio::prelude::*;
 
+use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt};
 use futures::task::{Context, Poll};
 use std::pin::Pin;
 
@@ -56,6 +60,9 @@ const CT_LEN_SIZE: usize = 2; // 16-bit length prefix for ciphertext chunks
 const CT_TAG_SIZE: usize = 16; // 128-bit auth tag
 const CT_SIZE: usize = CT_TAG_SIZE + PT_BUF_SIZE;
 const PT_OFFSET: usize = CT_LEN_SIZE + CT_TAG_SIZE; // offset of the plaintext in the outgoing buffer
+const STREAM_CHUNK_HEADER_SIZE: usize = 2; // 16-bit length prefix for stream chunks
+const MAX_STREAM_CHUNK_SIZE: usize = PT_BUF_SIZE - CT_TAG_SIZE - STREAM_CHUNK_HEADER_SIZE; // chunk fills exactly one frame
+const SALT_LEN: usize = 16; // salt for blinding the identity keys
 
 /// Private key for encrypting and authenticating connection.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -116,16 +123,7 @@ where
 {
     // We are going to need an additional ephemeral D-H key,
     // and a salt for blinding the reusable identity key.
-
-    let mut keygen_rng = Transcript::new(b"Cybershake.randomness")
-        .build_rng()
-        .rekey_with_witness_bytes(b"local_privkey", local_identity.as_secret_bytes())
-        .finalize(&mut rng);
-
-    const SALT_LEN: usize = 16;
-    let mut local_salt = [0u8; SALT_LEN];
-    keygen_rng.fill_bytes(&mut local_salt[..]);
-    let local_blinded_identity = local_identity.blind(&local_salt[..]);
+    let (local_salt, local_blinded_identity) = blind_identity(local_identity, &mut rng);
 
     // Now we send our first, unencrypted, message:
     //
@@ -185,7 +183,131 @@ where
     // in which we show the salt and the root pubkey.
     // If the transmission was successful (authenticated decryption succeeded),
     // we check the blinded key and then let user continue using the session.
+    let remote_identity = authenticate(
+        local_identity,
+        &local_salt,
+        &remote_blinded_identity,
+        &mut outgoing,
+        &mut incoming,
+    )
+    .await?;
+
+    Ok((remote_identity, outgoing, incoming))
+}
+
+/// Replaces the identity keys of an established session without interrupting it.
+///
+/// Both parties must call `rekey` at the same point in the session,
+/// after all previously sent messages have been received.
+/// The parties exchange the new identity keys blinded with fresh salts over the encrypted channel,
+/// mix the shared secret between them into the existing key derivations
+/// and authenticate each other just like in the initial handshake.
+/// Sequence numbers and key ratcheting continue uninterrupted.
+///
+/// Returns the new identity key of the remote peer.
+/// Fails with `InvalidInput` if the incoming endpoint has a partially read message.
+pub async fn rekey<R, W, RNG>(
+    new_local_identity: &PrivateKey,
+    outgoing: &mut Outgoing<W>,
+    incoming: &mut Incoming<R>,
+    mut rng: RNG,
+) -> Result<PublicKey, io::Error>
+where
+    R: io::AsyncRead + Unpin,
+    W: io::AsyncWrite + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    match incoming.state {
+        ReadState::Len(0) => {}
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot rekey with a partially read message",
+            ))
+        }
+    }
+    // Send out everything that was written before the rekey
+    // so the rekey message is sent in its own frame.
+    outgoing.flush().await?;
+
+    let (local_salt, local_blinded_identity) = blind_identity(new_local_identity, &mut rng);
+
+    // The blinded keys are sent with the current keys, in a dedicated frame:
+    //
+    // [version] [blinded local identity pubkey]
+    // u64-le     32 bytes
+    outgoing
+        .write_all(&encode_u64le(ONLY_SUPPORTED_VERSION)[..])
+        .await?;
+    outgoing
+        .write_all(local_blinded_identity.pubkey.as_bytes())
+        .await?;
+    outgoing.flush().await?;
 
+    let mut remote_version_and_id = [0u8; 8 + 32];
+    incoming.read_exact(&mut remote_version_and_id).await?;
+    let remote_version = LittleEndian::read_u64(&remote_version_and_id[..8]);
+    if remote_version != ONLY_SUPPORTED_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Incompatible cybershake version",
+        ));
+    }
+    let remote_blinded_identity = PublicKey::read_from(&mut &remote_version_and_id[8..]).await?;
+
+    // Both parties have sent and received exactly one frame since the rekey started,
+    // so the new shared secret is mixed in at the same sequence number on both ends.
+    let mut t = cybershake_dh(&local_blinded_identity, &remote_blinded_identity)?;
+    let mut shared_secret = [0u8; 32];
+    t.challenge_bytes(b"rekey", &mut shared_secret);
+    outgoing.kdf.append_message(b"rekey", &shared_secret);
+    outgoing
+        .kdf
+        .append_message(b"src", local_blinded_identity.pubkey.as_bytes());
+    incoming.kdf.append_message(b"rekey", &shared_secret);
+    incoming
+        .kdf
+        .append_message(b"src", remote_blinded_identity.as_bytes());
+
+    authenticate(
+        new_local_identity,
+        &local_salt,
+        &remote_blinded_identity,
+        outgoing,
+        incoming,
+    )
+    .await
+}
+
+/// Generates a fresh salt and blinds the identity key with it.
+fn blind_identity<RNG: RngCore + CryptoRng>(
+    identity: &PrivateKey,
+    rng: &mut RNG,
+) -> ([u8; SALT_LEN], PrivateKey) {
+    let mut keygen_rng = Transcript::new(b"Cybershake.randomness")
+        .build_rng()
+        .rekey_with_witness_bytes(b"local_privkey", identity.as_secret_bytes())
+        .finalize(rng);
+
+    let mut salt = [0u8; SALT_LEN];
+    keygen_rng.fill_bytes(&mut salt[..]);
+    let blinded_identity = identity.blind(&salt[..]);
+    (salt, blinded_identity)
+}
+
+/// Sends the salt and the local identity key, and receives the same from the remote party.
+/// Returns the remote identity key if it matches the blinded key that was used in the key exchange.
+async fn authenticate<R, W>(
+    local_identity: &PrivateKey,
+    local_salt: &[u8; SALT_LEN],
+    remote_blinded_identity: &PublicKey,
+    outgoing: &mut Outgoing<W>,
+    incoming: &mut Incoming<R>,
+) -> Result<PublicKey, io::Error>
+where
+    R: io::AsyncRead + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
     // Prepare and send the message: salt and local identity pubkey.
     outgoing.write_all(&local_salt[..]).await?;
     outgoing.write_all(local_identity.pubkey.as_bytes()).await?;
@@ -209,14 +331,14 @@ where
             )
         })?;
 
-    if received_remote_id_blinded != remote_blinded_identity {
+    if received_remote_id_blinded != *remote_blinded_identity {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             "Remote identity key mismatch",
         ));
     }
 
-    Ok((received_remote_identity, outgoing, incoming))
+    Ok(received_remote_identity)
 }
 
 macro_rules! ready {
@@ -295,7 +417,8 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Outgoing<W> {
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
         let me = self.get_mut();
         if !me.flushing {
-            if me.buf.is_empty() {
+            // Do not send empty frames: the recipient would see them as the end of the stream.
+            if me.buf.len() == PT_OFFSET {
                 return Poll::Ready(Ok(()));
             }
             me.cipher_buf();
@@ -321,6 +444,29 @@ impl<W: AsyncWrite + Unpin> Outgoing<W> {
         self.flush().await?;
         Ok(())
     }
+
+    /// Sends a payload of any length as a stream of chunks, without buffering it entirely.
+    /// Each chunk is sent in its own authenticated frame, prefixed with its length,
+    /// and the end of the stream is marked with a zero-length chunk,
+    /// so the recipient can detect if the stream was terminated prematurely.
+    ///
+    /// Use `Incoming::receive_stream` to receive the payload on the other end.
+    pub async fn send_stream<S>(&mut self, mut payload: S) -> Result<(), io::Error>
+    where
+        S: Stream<Item = Bytes> + Unpin,
+    {
+        while let Some(bytes) = payload.next().await {
+            // Empty pieces are skipped since they would be treated as the end of the stream.
+            for chunk in bytes.chunks(MAX_STREAM_CHUNK_SIZE) {
+                self.write_all(&encode_u16le(chunk.len() as u16)[..])
+                    .await?;
+                self.write_all(chunk).await?;
+                self.flush().await?;
+            }
+        }
+        self.write_all(&encode_u16le(0)[..]).await?;
+        self.flush().await
+    }
 }
 
 impl<R: AsyncRead + Unpin> Incoming<R> {
@@ -350,6 +496,42 @@ impl<R: AsyncRead + Unpin> Incoming<R> {
     }
 }
 
+impl<R: AsyncRead + Unpin> Incoming<R> {
+    /// Receives a payload sent with `Outgoing::send_stream` as a stream of chunks.
+    /// The stream ends when the sender marks the end of the payload.
+    /// If the connection is closed before that, the stream yields an `UnexpectedEof` error.
+    /// No chunks are yielded after an error.
+    pub fn receive_stream(&mut self) -> impl Stream<Item = Result<Bytes, io::Error>> + '_ {
+        stream::unfold(Some(self), |incoming| async move {
+            let incoming = incoming?;
+            match incoming.receive_stream_chunk().await {
+                Ok(Some(chunk)) => Some((Ok(chunk), Some(incoming))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Reads the next chunk of the stream. Returns None at the end of the stream.
+    async fn receive_stream_chunk(&mut self) -> Result<Option<Bytes>, io::Error> {
+        let mut header = [0u8; STREAM_CHUNK_HEADER_SIZE];
+        self.read_exact(&mut header[..]).await?;
+        let len = LittleEndian::read_u16(&header) as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+        if len > MAX_STREAM_CHUNK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("stream chunk length: {} > {}", len, MAX_STREAM_CHUNK_SIZE),
+            ));
+        }
+        let mut chunk = vec![0u8; len];
+        self.read_exact(&mut chunk[..]).await?;
+        Ok(Some(Bytes::from(chunk)))
+    }
+}
+
 impl<R: AsyncRead + Unpin> AsyncRead for Incoming<R> {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -528,6 +710,12 @@ fn challenge_scalar(label: &'static [u8], transcript: &mut Transcript) -> Scalar
     Scalar::from_bytes_mod_order_wide(&buf)
 }
 
+fn encode_u16le(i: u16) -> [u8; 2] {
+    let mut buf = [0u8; 2];
+    LittleEndian::write_u16(&mut buf, i);
+    buf
+}
+
 fn encode_u64le(i: u64) -> [u8; 8] {
     let mut buf = [0u8; 8];
     LittleEndian::write_u64(&mut buf, i);
@@ -672,4 +860,148 @@ mod tests {
         assert!(alice.await.is_ok());
         assert!(bob.await.is_ok());
     }
+
+    async fn connected_pair() -> (
+        (
+            Outgoing<io::WriteHalf<TcpStream>>,
+            Incoming<io::ReadHalf<TcpStream>>,
+        ),
+        (
+            Outgoing<io::WriteHalf<TcpStream>>,
+            Incoming<io::ReadHalf<TcpStream>>,
+        ),
+    ) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (alice_stream, (bob_stream, _)) =
+            futures::future::try_join(TcpStream::connect(addr), listener.accept())
+                .await
+                .unwrap();
+        let (alice_reader, alice_writer) = io::split(alice_stream);
+        let (bob_reader, bob_writer) = io::split(bob_stream);
+
+        let (alice, bob) = futures::future::join(
+            cybershake(
+                &PrivateKey::from(Scalar::from(1u64)),
+                Box::pin(alice_reader),
+                Box::pin(alice_writer),
+                StdRng::from_entropy(),
+            ),
+            cybershake(
+                &PrivateKey::from(Scalar::from(2u64)),
+                Box::pin(bob_reader),
+                Box::pin(bob_writer),
+                StdRng::from_entropy(),
+            ),
+        )
+        .await;
+        let (_, alice_out, alice_inc) = alice.expect("alice: should handshake correctly");
+        let (_, bob_out, bob_inc) = bob.expect("bob: should handshake correctly");
+        ((alice_out, alice_inc), (bob_out, bob_inc))
+    }
+
+    #[tokio::test]
+    async fn stream_message() {
+        let ((mut alice_out, _alice_inc), (_bob_out, mut bob_inc)) = connected_pair().await;
+
+        let payload: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let pieces = vec![
+            Bytes::from(payload[..100].to_vec()),
+            Bytes::new(),
+            Bytes::from(payload[100..].to_vec()),
+        ];
+
+        let send = alice_out.send_stream(stream::iter(pieces));
+        let receive = bob_inc.receive_stream().collect::<Vec<_>>();
+        let (sent, received) = futures::future::join(send, receive).await;
+        sent.expect("alice: should send the stream");
+
+        // 100 bytes + 9900 bytes split in 3 chunks.
+        assert_eq!(received.len(), 4);
+        let received = received
+            .into_iter()
+            .map(|chunk| chunk.expect("bob: should receive the chunk"))
+            .fold(Vec::new(), |mut acc, chunk| {
+                acc.extend_from_slice(&chunk);
+                acc
+            });
+        assert_eq!(received, payload);
+
+        // Single-message API keeps working after the stream.
+        alice_out.send_message(b"Hello, Bob").await.unwrap();
+        let mut buf = [0u8; 10];
+        bob_inc.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"Hello, Bob");
+    }
+
+    #[tokio::test]
+    async fn rekey_mid_session() {
+        let ((mut alice_out, mut alice_inc), (mut bob_out, mut bob_inc)) = connected_pair().await;
+
+        alice_out.send_message(b"Hello, Bob").await.unwrap();
+        let mut buf = [0u8; 10];
+        bob_inc.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"Hello, Bob");
+
+        let alice_new_key = PrivateKey::from(Scalar::from(3u64));
+        let bob_new_key = PrivateKey::from(Scalar::from(4u64));
+        let (alice_received_key, bob_received_key) = futures::future::join(
+            rekey(
+                &alice_new_key,
+                &mut alice_out,
+                &mut alice_inc,
+                StdRng::from_entropy(),
+            ),
+            rekey(
+                &bob_new_key,
+                &mut bob_out,
+                &mut bob_inc,
+                StdRng::from_entropy(),
+            ),
+        )
+        .await;
+        assert_eq!(alice_received_key.unwrap(), bob_new_key.to_public_key());
+        assert_eq!(bob_received_key.unwrap(), alice_new_key.to_public_key());
+
+        // Session continues in both directions with the new keys.
+        alice_out.send_message(b"Hello, Bob").await.unwrap();
+        bob_out.send_message(b"Hello, Alice").await.unwrap();
+        let mut buf = [0u8; 10];
+        bob_inc.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"Hello, Bob");
+        let mut buf = [0u8; 12];
+        alice_inc.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"Hello, Alice");
+
+        // Cannot rekey in the middle of a message.
+        alice_out.send_message(b"Hello, Bob").await.unwrap();
+        let mut buf = [0u8; 5];
+        bob_inc.read_exact(&mut buf).await.unwrap();
+        let err = rekey(
+            &bob_new_key,
+            &mut bob_out,
+            &mut bob_inc,
+            StdRng::from_entropy(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn stream_premature_termination() {
+        let ((mut alice_out, _alice_inc), (_bob_out, mut bob_inc)) = connected_pair().await;
+
+        // Send a single chunk without the end marker and close the connection.
+        alice_out.write_all(&[3, 0, 1, 2, 3]).await.unwrap();
+        alice_out.shutdown().await.unwrap();
+
+        let received = bob_inc.receive_stream().collect::<Vec<_>>().await;
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].as_ref().unwrap(), &Bytes::from(vec![1, 2, 3]));
+        assert_eq!(
+            received[1].as_ref().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
 }
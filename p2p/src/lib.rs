@@ -9,7 +9,13 @@ pub mod cybershake;
 mod node;
 mod peer;
 mod priority;
+mod rpc;
+mod score;
 
-pub use self::node::{Direction, Node, NodeConfig, NodeHandle, NodeNotification, PeerInfo};
-pub use self::peer::{PeerID, PeerLink, PeerMessage, PeerNotification};
-pub use self::priority::Priority;
+pub use self::node::{
+    BroadcastPolicy, Direction, Node, NodeConfig, NodeHandle, NodeNotification, PeerInfo,
+};
+pub use self::peer::{DisconnectReason, PeerID, PeerLink, PeerMessage, PeerNotification};
+pub use self::priority::{Priority, HIGH_PRIORITY, LOW_PRIORITY};
+pub use self::rpc::{RequestID, RpcError};
+pub use self::score::{PeerScore, MALFORMED_MESSAGE_PENALTY};
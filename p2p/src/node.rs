@@ -3,6 +3,7 @@ use core::time::Duration;
 use std::collections::HashMap;
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Instant;
 
 use futures::future::FutureExt;
 use futures::select;
@@ -18,8 +19,10 @@ use rand::thread_rng;
 
 use crate::codec::{MessageDecoder, MessageEncoder};
 use crate::cybershake;
-use crate::peer::{PeerAddr, PeerID, PeerLink, PeerMessage, PeerNotification};
+use crate::peer::{DisconnectReason, PeerAddr, PeerID, PeerLink, PeerMessage, PeerNotification};
 use crate::priority::{Priority, PriorityTable, HIGH_PRIORITY, LOW_PRIORITY};
+use crate::rpc::{PendingRequest, RequestID, RpcError};
+use crate::score::{PeerScore, ScoreTable, MALFORMED_MESSAGE_PENALTY};
 use readerwriter::Codable;
 
 type Reply<T> = sync::oneshot::Sender<T>;
@@ -40,6 +43,10 @@ pub struct NodeConfig {
     pub inbound_limit: usize,
     pub outbound_limit: usize,
     pub heartbeat_interval_sec: u64,
+    /// Peers whose misbehavior score reaches this threshold are disconnected and banned.
+    pub ban_threshold: PeerScore,
+    /// For how long the banned peers are not allowed to reconnect.
+    pub ban_duration_sec: u64,
 }
 
 pub struct Node<Custom: Codable> {
@@ -50,7 +57,10 @@ pub struct Node<Custom: Codable> {
     config: NodeConfig,
     inbound_semaphore: sync::Semaphore,
     peer_priorities: PriorityTable<PeerID>, // priorities of peers
+    peer_scores: ScoreTable<PeerID>,        // misbehavior scores and bans of peers
     notifications_channel: sync::mpsc::Sender<NodeNotification<Custom>>,
+    pending_requests: HashMap<(PeerID, RequestID), PendingRequest<Custom>>,
+    next_request_id: RequestID,
 }
 
 /// Direction of connection
@@ -73,8 +83,14 @@ struct PeerState<T: Codable> {
 #[derive(Debug)]
 pub enum NodeNotification<Custom: Codable> {
     PeerAdded(PeerID),
-    PeerDisconnected(PeerID),
+    /// Peer got disconnected. The reason is present if it was sent by either side.
+    PeerDisconnected(PeerID, Option<DisconnectReason>),
+    /// Peer's score has reached the ban threshold: it was disconnected
+    /// and won't be allowed to reconnect until the ban expires.
+    PeerBanned(PeerID),
     MessageReceived(PeerID, Custom),
+    /// Peer has sent a request: reply to it with `NodeHandle::respond` using the same request ID.
+    RequestReceived(PeerID, RequestID, Custom),
     InboundConnectionFailure(io::Error),
     OutboundConnectionFailure(io::Error),
     /// Node has finished running.
@@ -87,14 +103,34 @@ pub struct PeerInfo {
     pub address: SocketAddr,
     pub public: bool,
     pub priority: Priority,
+    pub score: PeerScore,
     pub direction: Direction,
+    /// Number of messages sent to the peer, but not yet written to the socket.
+    pub queue_depth: usize,
+}
+
+/// Policy for broadcasting messages to the peers that do not read them fast enough.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BroadcastPolicy {
+    /// Waits until each peer has room for the message: a single slow peer stalls the broadcast.
+    Block,
+    /// Skips the peers whose queue is full.
+    DropForSlowPeers,
+    /// Queues the message for each peer that has less than a given number of queued messages.
+    /// Peers exceeding the bound, or whose queue is full, are disconnected with `DisconnectReason::SlowPeer`.
+    Buffer(usize),
 }
 
 /// Internal representation of messages sent by `NodeHandle` to `Node`.
 enum NodeMessage<Custom: Codable> {
     ConnectPeer(net::TcpStream, Option<PeerID>),
     RemovePeer(PeerID),
-    Broadcast(Custom),
+    Disconnect(PeerID, DisconnectReason),
+    Penalize(PeerID, PeerScore),
+    SendToPeer(PeerID, Custom, Priority),
+    Broadcast(Custom, BroadcastPolicy),
+    Request(PeerID, Custom, Instant, Reply<Result<Custom, RpcError>>),
+    Respond(PeerID, RequestID, Custom),
     CountPeers(Reply<usize>),
     ListPeers(Reply<Vec<PeerInfo>>),
 }
@@ -136,7 +172,10 @@ where
             config,
             inbound_semaphore,
             peer_priorities: PriorityTable::new(1000),
+            peer_scores: ScoreTable::new(),
             notifications_channel: notif_sender,
+            pending_requests: HashMap::new(),
+            next_request_id: 0,
         };
 
         let node_handle = NodeHandle {
@@ -172,6 +211,7 @@ where
                     _ = node.try_accept().fuse() => {}
                 }
             }
+            node.disconnect_all(DisconnectReason::Shutdown).await;
             node.notify(NodeNotification::Shutdown).await
         });
 
@@ -202,6 +242,65 @@ impl<Custom: Codable> NodeHandle<Custom> {
         self.send_internal(NodeMessage::RemovePeer(peer_id)).await
     }
 
+    /// Tells the peer the reason for disconnect and closes all connections to it.
+    pub async fn disconnect(&mut self, peer_id: PeerID, reason: DisconnectReason) {
+        self.send_internal(NodeMessage::Disconnect(peer_id, reason))
+            .await
+    }
+
+    /// Increases the misbehavior score of a peer by a given amount.
+    /// When the score reaches `NodeConfig::ban_threshold`, the peer is disconnected
+    /// and banned for `NodeConfig::ban_duration_sec`.
+    ///
+    /// Use this to report protocol-level misbehavior that the node cannot detect by itself,
+    /// e.g. invalid or spammy transactions rejected by the mempool.
+    pub async fn penalize(&mut self, peer_id: PeerID, amount: PeerScore) {
+        self.send_internal(NodeMessage::Penalize(peer_id, amount))
+            .await
+    }
+
+    /// Sends a message to a given peer.
+    /// Messages with higher priority (lower integer) are delivered before
+    /// the lower-priority ones that were queued earlier, but not yet written to the socket.
+    /// Broadcasts are sent with `LOW_PRIORITY`.
+    pub async fn send_with_priority(&mut self, peer_id: PeerID, msg: Custom, priority: Priority) {
+        self.send_internal(NodeMessage::SendToPeer(peer_id, msg, priority))
+            .await
+    }
+
+    /// Sends a request to a given peer and waits for the response.
+    /// Requests and responses are sent with `HIGH_PRIORITY`.
+    ///
+    /// Fails with `RpcError::NotConnected` if the peer is not connected,
+    /// with `RpcError::Disconnected` if it disconnects before responding,
+    /// and with `RpcError::Timeout` if it does not respond within the timeout.
+    /// Timeouts too large to be represented as a point in time are rejected with `RpcError::Timeout`.
+    pub async fn request(
+        &mut self,
+        peer_id: PeerID,
+        msg: Custom,
+        timeout: Duration,
+    ) -> Result<Custom, RpcError> {
+        let deadline = Instant::now()
+            .checked_add(timeout)
+            .ok_or(RpcError::Timeout)?;
+        let (tx, rx) = sync::oneshot::channel::<Result<Custom, RpcError>>();
+        self.send_internal(NodeMessage::Request(peer_id, msg, deadline, tx))
+            .await;
+        match time::timeout_at(time::Instant::from_std(deadline), rx).await {
+            Ok(Ok(result)) => result,
+            // The node has shut down and dropped the request.
+            Ok(Err(_)) => Err(RpcError::Disconnected),
+            Err(_) => Err(RpcError::Timeout),
+        }
+    }
+
+    /// Sends a response to the request received in `NodeNotification::RequestReceived`.
+    pub async fn respond(&mut self, peer_id: PeerID, request_id: RequestID, msg: Custom) {
+        self.send_internal(NodeMessage::Respond(peer_id, request_id, msg))
+            .await
+    }
+
     /// Returns the PeerID of the node.
     pub fn id(&self) -> PeerID {
         self.peer_id
@@ -213,8 +312,10 @@ impl<Custom: Codable> NodeHandle<Custom> {
     }
 
     /// Broadcasts a message to all peers.
-    pub async fn broadcast(&mut self, msg: Custom) {
-        self.send_internal(NodeMessage::Broadcast(msg)).await
+    /// The policy determines what happens to the peers that do not keep up with the messages.
+    pub async fn broadcast(&mut self, msg: Custom, policy: BroadcastPolicy) {
+        self.send_internal(NodeMessage::Broadcast(msg, policy))
+            .await
     }
 
     pub async fn list_peers(&mut self) -> Vec<PeerInfo> {
@@ -252,8 +353,30 @@ where
                 self.connect_peer_or_notify(stream, expected_pid, HIGH_PRIORITY)
                     .await
             }
-            NodeMessage::RemovePeer(peer_id) => self.remove_peer(&peer_id).await,
-            NodeMessage::Broadcast(msg) => self.broadcast(msg).await,
+            NodeMessage::RemovePeer(peer_id) => self.remove_peer(&peer_id, None).await,
+            NodeMessage::Disconnect(peer_id, reason) => {
+                self.disconnect_peer(&peer_id, reason).await
+            }
+            NodeMessage::Penalize(peer_id, amount) => self.penalize(peer_id, amount).await,
+            NodeMessage::SendToPeer(peer_id, msg, priority) => {
+                if let Some(peer) = self.peers.get_mut(&peer_id) {
+                    peer.link
+                        .send_with_priority(PeerMessage::Data(msg), priority)
+                        .await;
+                }
+            }
+            NodeMessage::Broadcast(msg, policy) => self.broadcast(msg, policy).await,
+            NodeMessage::Request(peer_id, msg, deadline, reply) => {
+                self.send_request(peer_id, msg, deadline, reply).await
+            }
+            NodeMessage::Respond(peer_id, request_id, msg) => {
+                self.send_to_peer_with_priority(
+                    &peer_id,
+                    PeerMessage::Response(request_id, msg),
+                    HIGH_PRIORITY,
+                )
+                .await
+            }
             NodeMessage::CountPeers(reply) => self.count_peers(reply).await,
             NodeMessage::ListPeers(reply) => self.list_peers(reply).await,
         }
@@ -261,6 +384,9 @@ where
 
     /// Perform periodic update about yourself and your peers.
     async fn heartbeat_tick(&mut self) {
+        self.peer_scores.remove_expired_bans();
+        self.expire_requests();
+
         // Broadcast a list of your peers to everyone.
         // TODO: make this more efficient to avoid copying the list of peers all the time,
         // but instead sending a shared read-only buffer.
@@ -292,6 +418,8 @@ where
             )
             .await?;
 
+            let peer_link = self.reject_if_banned(peer_link).await?;
+
             // If the handshake did not fail, forget the semaphore permit,
             // so it's consumed until the peer disconnects. When we get about actually
             // removing the peer, then we'll add a new permit to the semaphore.
@@ -337,6 +465,8 @@ where
         )
         .await?;
 
+        let peer_link = self.reject_if_banned(peer_link).await?;
+
         self.register_peer(peer_link, addr, Direction::Outbound, min_priority)
             .await;
 
@@ -384,6 +514,7 @@ where
                 self.inbound_semaphore.add_permits(1);
             }
 
+            peer_link.disconnect(DisconnectReason::Duplicate).await;
             return;
         }
 
@@ -417,7 +548,7 @@ where
             .await
     }
 
-    async fn remove_peer(&mut self, peer_id: &PeerID) {
+    async fn remove_peer(&mut self, peer_id: &PeerID, reason: Option<DisconnectReason>) {
         // First, check if this peer has duplicates - then silently decrement the count
         // and keep it in place.
         if let Some(mut peer) = self.peers.get_mut(&peer_id) {
@@ -431,13 +562,85 @@ where
                 // if that was an inbound peer, restore the permit it consumed.
                 self.inbound_semaphore.add_permits(1);
             }
-            self.notify(NodeNotification::PeerDisconnected(*peer.link.id()))
+            self.fail_requests(peer_id);
+            self.notify(NodeNotification::PeerDisconnected(*peer.link.id(), reason))
                 .await;
         }
 
         self.connect_to_more_peers_if_needed().await;
     }
 
+    async fn penalize(&mut self, peer_id: PeerID, amount: PeerScore) {
+        let score = self.peer_scores.penalize(peer_id, amount);
+        if score < self.config.ban_threshold {
+            return;
+        }
+        self.peer_scores
+            .ban(peer_id, Duration::from_secs(self.config.ban_duration_sec));
+
+        self.notify(NodeNotification::PeerBanned(peer_id)).await;
+        self.disconnect_peer(&peer_id, DisconnectReason::Banned)
+            .await;
+    }
+
+    /// Returns the link back if the peer is not banned,
+    /// otherwise closes the connection and returns an error.
+    async fn reject_if_banned(
+        &self,
+        peer_link: PeerLink<Custom>,
+    ) -> Result<PeerLink<Custom>, io::Error> {
+        if !self.peer_scores.is_banned(peer_link.id()) {
+            return Ok(peer_link);
+        }
+        let err = io::Error::new(
+            io::ErrorKind::Other,
+            format!("Peer {} is banned.", peer_link.id()),
+        );
+        peer_link.disconnect(DisconnectReason::Banned).await;
+        Err(err)
+    }
+
+    /// Drops the peer whose queue has overflown, regardless of the duplicate connections.
+    /// Unlike `disconnect_peer`, does not wait for the room in the peer's queue to send the reason.
+    async fn disconnect_slow_peer(&mut self, peer_id: &PeerID) {
+        let reason = DisconnectReason::SlowPeer;
+        if let Some(peer) = self.peers.remove(peer_id) {
+            if peer.direction == Direction::Inbound {
+                // if that was an inbound peer, restore the permit it consumed.
+                self.inbound_semaphore.add_permits(1);
+            }
+            peer.link.disconnect_now(reason);
+            self.fail_requests(peer_id);
+            self.notify(NodeNotification::PeerDisconnected(*peer_id, Some(reason)))
+                .await;
+        }
+    }
+
+    /// Sends the reason to the peer and drops it, regardless of the duplicate connections.
+    async fn disconnect_peer(&mut self, peer_id: &PeerID, reason: DisconnectReason) {
+        if let Some(peer) = self.peers.remove(peer_id) {
+            if peer.direction == Direction::Inbound {
+                // if that was an inbound peer, restore the permit it consumed.
+                self.inbound_semaphore.add_permits(1);
+            }
+            peer.link.disconnect(reason).await;
+            self.fail_requests(peer_id);
+            self.notify(NodeNotification::PeerDisconnected(*peer_id, Some(reason)))
+                .await;
+        }
+
+        self.connect_to_more_peers_if_needed().await;
+    }
+
+    async fn disconnect_all(&mut self, reason: DisconnectReason) {
+        for (_pid, peer) in self.peers.drain() {
+            peer.link.disconnect(reason).await;
+        }
+        for (_key, request) in self.pending_requests.drain() {
+            request.resolve(Err(RpcError::Disconnected));
+        }
+    }
+
     fn count_peers_with_direction(&self, direction: Direction) -> usize {
         self.peers
             .iter()
@@ -445,9 +648,24 @@ where
             .count()
     }
 
-    async fn broadcast(&mut self, msg: Custom) {
-        for (_id, peer_link) in self.peers.iter_mut() {
-            peer_link.link.send(PeerMessage::Data(msg.clone())).await;
+    async fn broadcast(&mut self, msg: Custom, policy: BroadcastPolicy) {
+        let mut slow_peers = Vec::new();
+        for (id, peer) in self.peers.iter_mut() {
+            let msg = PeerMessage::Data(msg.clone());
+            match policy {
+                BroadcastPolicy::Block => peer.link.send(msg).await,
+                BroadcastPolicy::DropForSlowPeers => {
+                    let _ = peer.link.try_send(msg, LOW_PRIORITY);
+                }
+                BroadcastPolicy::Buffer(limit) => {
+                    if peer.link.queue_depth() >= limit || !peer.link.try_send(msg, LOW_PRIORITY) {
+                        slow_peers.push(*id);
+                    }
+                }
+            }
+        }
+        for id in slow_peers {
+            self.disconnect_slow_peer(&id).await;
         }
     }
 
@@ -465,11 +683,81 @@ where
         }
     }
 
+    async fn send_to_peer_with_priority(
+        &mut self,
+        pid: &PeerID,
+        msg: PeerMessage<Custom>,
+        priority: Priority,
+    ) {
+        if let Some(peer) = self.peers.get_mut(&pid) {
+            peer.link.send_with_priority(msg, priority).await;
+        }
+    }
+
+    async fn send_request(
+        &mut self,
+        peer_id: PeerID,
+        msg: Custom,
+        deadline: Instant,
+        reply: Reply<Result<Custom, RpcError>>,
+    ) {
+        let peer = match self.peers.get_mut(&peer_id) {
+            Some(peer) => peer,
+            None => {
+                reply.send(Err(RpcError::NotConnected)).unwrap_or(());
+                return;
+            }
+        };
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        self.pending_requests
+            .insert((peer_id, request_id), PendingRequest { reply, deadline });
+        peer.link
+            .send_with_priority(PeerMessage::Request(request_id, msg), HIGH_PRIORITY)
+            .await;
+    }
+
+    /// Resolves the pending requests to the peer with `RpcError::Disconnected`.
+    fn fail_requests(&mut self, peer_id: &PeerID) {
+        let keys: Vec<_> = self
+            .pending_requests
+            .keys()
+            .filter(|(pid, _)| pid == peer_id)
+            .cloned()
+            .collect();
+        for key in keys {
+            if let Some(request) = self.pending_requests.remove(&key) {
+                request.resolve(Err(RpcError::Disconnected));
+            }
+        }
+    }
+
+    /// Resolves the requests that were not responded in time with `RpcError::Timeout`.
+    /// The requester normally stops waiting on its own, so this mostly frees the memory.
+    fn expire_requests(&mut self) {
+        let now = Instant::now();
+        let keys: Vec<_> = self
+            .pending_requests
+            .iter()
+            .filter(|(_, request)| request.deadline <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            if let Some(request) = self.pending_requests.remove(&key) {
+                request.resolve(Err(RpcError::Timeout));
+            }
+        }
+    }
+
     async fn handle_peer_notification(&mut self, notif: PeerNotification<Custom>) {
         let (id, peermsg) = match notif {
             PeerNotification::Received(id, peermsg) => (id, peermsg),
-            PeerNotification::Disconnected(id) => {
-                self.remove_peer(&id).await;
+            PeerNotification::Disconnected { peer, reason } => {
+                self.remove_peer(&peer, reason).await;
+                return;
+            }
+            PeerNotification::MalformedMessage(id) => {
+                self.penalize(id, MALFORMED_MESSAGE_PENALTY).await;
                 return;
             }
         };
@@ -482,10 +770,23 @@ where
                     peer.listening_addr = Some(addr);
                 }
             }
+            PeerMessage::Goodbye(_) => {
+                // Never happens: the peer link reports it as a disconnect.
+            }
             PeerMessage::Data(msg) => {
                 self.notify(NodeNotification::MessageReceived(id, msg))
                     .await
             }
+            PeerMessage::Request(request_id, msg) => {
+                self.notify(NodeNotification::RequestReceived(id, request_id, msg))
+                    .await
+            }
+            PeerMessage::Response(request_id, msg) => {
+                // Responses to unknown or expired requests are ignored.
+                if let Some(request) = self.pending_requests.remove(&(id, request_id)) {
+                    request.resolve(Ok(msg));
+                }
+            }
             PeerMessage::Peers(mut list) => {
                 list.truncate(self.peer_list_limit());
                 self.peers.get_mut(&id).map(|peer| {
@@ -515,8 +816,10 @@ where
                     .peer_addrs
                     .iter()
                     .filter(|peer_addr| {
-                        // ignore all addresses to which we are already connected.
-                        self.peers.get(&peer_addr.id).is_none() && peer_addr.id != self_pid
+                        // ignore all addresses to which we are already connected, and the banned ones.
+                        self.peers.get(&peer_addr.id).is_none()
+                            && peer_addr.id != self_pid
+                            && !self.peer_scores.is_banned(&peer_addr.id)
                     })
                     .map(|peer_addr| {
                         let priority = self
@@ -637,6 +940,8 @@ where
                 public: peerstate.listening_addr.is_some(),
                 direction: peerstate.direction,
                 priority: self.peer_priorities.get(pid).unwrap_or(LOW_PRIORITY),
+                score: self.peer_scores.get(pid),
+                queue_depth: peerstate.link.queue_depth(),
             })
             .collect::<Vec<_>>()
     }
@@ -655,7 +960,7 @@ impl fmt::Display for PeerInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{} {} {}   priority: {}   public: {}",
+            "{} {} {}   priority: {}   score: {}   public: {}   queued: {}",
             match self.direction {
                 Direction::Inbound => " [in]",
                 Direction::Outbound => "[out]",
@@ -663,7 +968,283 @@ impl fmt::Display for PeerInfo {
             self.address,
             self.id,
             self.priority,
-            self.public
+            self.score,
+            self.public,
+            self.queue_depth
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use readerwriter::{Decodable, Encodable, ReadError, Reader, WriteError, Writer};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Message(Vec<u8>);
+
+    impl Encodable for Message {
+        fn encode(&self, dst: &mut impl Writer) -> Result<(), WriteError> {
+            dst.write(b"data", &self.0)
+        }
+    }
+
+    impl Decodable for Message {
+        fn decode(buf: &mut impl Reader) -> Result<Self, ReadError> {
+            Ok(Message(buf.read_bytes(buf.remaining_bytes())?))
+        }
+    }
+
+    fn message(data: &str) -> Message {
+        Message(data.as_bytes().to_vec())
+    }
+
+    type Notifications = sync::mpsc::Receiver<NodeNotification<Message>>;
+
+    async fn spawn_node() -> (NodeHandle<Message>, Notifications) {
+        let privkey = cybershake::PrivateKey::from(Scalar::random(&mut thread_rng()));
+        let config = NodeConfig {
+            listen_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+            inbound_limit: 10,
+            outbound_limit: 10,
+            heartbeat_interval_sec: 3600,
+            ban_threshold: 100,
+            ban_duration_sec: 60,
+        };
+        Node::spawn(privkey, config)
+            .await
+            .expect("Should bind normally.")
+    }
+
+    /// Connects alice to bob and waits until both of them add each other.
+    async fn connect(
+        alice: &mut NodeHandle<Message>,
+        alice_notifs: &mut Notifications,
+        bob: &NodeHandle<Message>,
+        bob_notifs: &mut Notifications,
+    ) {
+        alice
+            .connect_to_peer(bob.socket_address(), Some(bob.id()))
+            .await
+            .expect("Should connect normally.");
+        assert_eq!(next_peer_added(alice_notifs).await, bob.id());
+        assert_eq!(next_peer_added(bob_notifs).await, alice.id());
+    }
+
+    async fn next_peer_added(notifs: &mut Notifications) -> PeerID {
+        loop {
+            if let NodeNotification::PeerAdded(pid) = notifs.recv().await.unwrap() {
+                return pid;
+            }
+        }
+    }
+
+    async fn next_request(notifs: &mut Notifications) -> (PeerID, RequestID, Message) {
+        loop {
+            if let NodeNotification::RequestReceived(pid, id, msg) = notifs.recv().await.unwrap() {
+                return (pid, id, msg);
+            }
+        }
+    }
+
+    async fn queue_depth(node: &mut NodeHandle<Message>, peer_id: PeerID) -> usize {
+        node.list_peers()
+            .await
+            .into_iter()
+            .find(|info| info.id == peer_id)
+            .map(|info| info.queue_depth)
+            .unwrap_or(0)
+    }
+
+    /// Sends large messages to the peer until at least `min_depth` of them are stuck in the queue.
+    /// This happens when the peer's node stops reading from the socket
+    /// because nobody consumes its notifications.
+    async fn fill_queue(node: &mut NodeHandle<Message>, peer_id: PeerID, min_depth: usize) {
+        for _ in 0..10_000 {
+            node.send_with_priority(peer_id, Message(vec![0u8; 64 * 1024]), LOW_PRIORITY)
+                .await;
+            if queue_depth(node, peer_id).await >= min_depth {
+                // Make sure the messages are not just waiting for the peer task to write them.
+                time::delay_for(Duration::from_millis(100)).await;
+                if queue_depth(node, peer_id).await >= min_depth {
+                    return;
+                }
+            }
+        }
+        panic!("The peer must stop reading the messages");
+    }
+
+    /// Sends a request in the background, so the test can respond to it.
+    fn spawn_request(
+        node: &NodeHandle<Message>,
+        peer_id: PeerID,
+        msg: Message,
+        timeout: Duration,
+    ) -> task::JoinHandle<Result<Message, RpcError>> {
+        let mut node = node.clone();
+        task::spawn_local(async move { node.request(peer_id, msg, timeout).await })
+    }
+
+    #[tokio::test]
+    async fn rpc_responses_are_matched_to_requests() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (mut alice, mut alice_notifs) = spawn_node().await;
+                let (mut bob, mut bob_notifs) = spawn_node().await;
+                connect(&mut alice, &mut alice_notifs, &bob, &mut bob_notifs).await;
+
+                let timeout = Duration::from_secs(10);
+                let first = spawn_request(&alice, bob.id(), message("first"), timeout);
+                let (pid, first_id, msg) = next_request(&mut bob_notifs).await;
+                assert_eq!(pid, alice.id());
+                assert_eq!(msg, message("first"));
+
+                let second = spawn_request(&alice, bob.id(), message("second"), timeout);
+                let (_, second_id, msg) = next_request(&mut bob_notifs).await;
+                assert_eq!(msg, message("second"));
+
+                // Respond in the reverse order.
+                bob.respond(pid, second_id, message("second response"))
+                    .await;
+                bob.respond(pid, first_id, message("first response")).await;
+                assert_eq!(second.await.unwrap(), Ok(message("second response")));
+                assert_eq!(first.await.unwrap(), Ok(message("first response")));
+
+                // Responses to unknown requests are ignored.
+                bob.respond(pid, first_id, message("unexpected")).await;
+                let third = spawn_request(&alice, bob.id(), message("third"), timeout);
+                let (_, third_id, _) = next_request(&mut bob_notifs).await;
+                bob.respond(pid, third_id, message("third response")).await;
+                assert_eq!(third.await.unwrap(), Ok(message("third response")));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn rpc_request_times_out() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (mut alice, mut alice_notifs) = spawn_node().await;
+                let (bob, mut bob_notifs) = spawn_node().await;
+                connect(&mut alice, &mut alice_notifs, &bob, &mut bob_notifs).await;
+
+                let result = alice
+                    .request(bob.id(), message("ping"), Duration::from_millis(100))
+                    .await;
+                assert_eq!(result, Err(RpcError::Timeout));
+
+                // The timeout that overflows the clock is rejected without sending the request.
+                let result = alice
+                    .request(
+                        bob.id(),
+                        message("ping"),
+                        Duration::from_secs(u64::max_value()),
+                    )
+                    .await;
+                assert_eq!(result, Err(RpcError::Timeout));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn rpc_request_fails_when_peer_disconnects() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (mut alice, mut alice_notifs) = spawn_node().await;
+                let (mut bob, mut bob_notifs) = spawn_node().await;
+                connect(&mut alice, &mut alice_notifs, &bob, &mut bob_notifs).await;
+
+                let request =
+                    spawn_request(&alice, bob.id(), message("ping"), Duration::from_secs(10));
+                let (pid, _, _) = next_request(&mut bob_notifs).await;
+                bob.remove_peer(pid).await;
+                assert_eq!(request.await.unwrap(), Err(RpcError::Disconnected));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn rpc_request_fails_when_peer_is_not_connected() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (mut alice, _alice_notifs) = spawn_node().await;
+                let (bob, _bob_notifs) = spawn_node().await;
+
+                let result = alice
+                    .request(bob.id(), message("ping"), Duration::from_secs(10))
+                    .await;
+                assert_eq!(result, Err(RpcError::NotConnected));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn high_priority_messages_overtake_queued_ones() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (mut alice, mut alice_notifs) = spawn_node().await;
+                let (bob, mut bob_notifs) = spawn_node().await;
+                connect(&mut alice, &mut alice_notifs, &bob, &mut bob_notifs).await;
+
+                fill_queue(&mut alice, bob.id(), 5).await;
+                alice
+                    .send_with_priority(bob.id(), message("low"), LOW_PRIORITY)
+                    .await;
+                alice
+                    .send_with_priority(bob.id(), message("high"), HIGH_PRIORITY)
+                    .await;
+
+                let mut received = Vec::new();
+                while received.len() < 2 {
+                    if let NodeNotification::MessageReceived(_, msg) =
+                        bob_notifs.recv().await.unwrap()
+                    {
+                        if msg == message("low") || msg == message("high") {
+                            received.push(msg);
+                        }
+                    }
+                }
+                assert_eq!(received, vec![message("high"), message("low")]);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn broadcast_disconnects_slow_peers() {
+        task::LocalSet::new()
+            .run_until(async {
+                let (mut alice, mut alice_notifs) = spawn_node().await;
+                let (bob, mut bob_notifs) = spawn_node().await;
+                let (carol, mut carol_notifs) = spawn_node().await;
+                connect(&mut alice, &mut alice_notifs, &bob, &mut bob_notifs).await;
+                connect(&mut alice, &mut alice_notifs, &carol, &mut carol_notifs).await;
+
+                // Bob does not keep up, while Carol's queue is empty.
+                fill_queue(&mut alice, bob.id(), 5).await;
+                alice
+                    .broadcast(message("news"), BroadcastPolicy::Buffer(5))
+                    .await;
+
+                match alice_notifs.recv().await.unwrap() {
+                    NodeNotification::PeerDisconnected(pid, reason) => {
+                        assert_eq!(pid, bob.id());
+                        assert_eq!(reason, Some(DisconnectReason::SlowPeer));
+                    }
+                    other => panic!("Unexpected notification: {:?}", other),
+                }
+                assert_eq!(alice.count_peers().await, 1);
+
+                loop {
+                    if let NodeNotification::MessageReceived(pid, msg) =
+                        carol_notifs.recv().await.unwrap()
+                    {
+                        assert_eq!(pid, alice.id());
+                        assert_eq!(msg, message("news"));
+                        break;
+                    }
+                }
+            })
+            .await
+    }
+}
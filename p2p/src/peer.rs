@@ -2,9 +2,14 @@
 //! - Use PeerLink::spawn() to establish a fully authenticated connection over a given socket stream.
 //! - Use PeerID to identify the peer.
 use core::fmt;
+use core::pin::Pin;
+use core::task::Poll;
+use futures::future::{self, FutureExt};
 use futures::stream::StreamExt;
 use std::hash::{Hash, Hasher};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use tokio::io;
 use tokio::prelude::*;
@@ -15,10 +20,15 @@ use curve25519_dalek::ristretto::CompressedRistretto;
 use rand_core::{CryptoRng, RngCore};
 
 use crate::cybershake;
-use futures::SinkExt;
+use crate::priority::{Priority, PriorityQueue, LOW_PRIORITY};
+use crate::rpc::RequestID;
+use futures::{Sink, SinkExt};
 use readerwriter::Codable;
 use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
 
+/// Maximum number of outgoing messages buffered for the peer.
+const MAX_QUEUED_MESSAGES: usize = 100;
+
 /// Identifier of the peer.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PeerID(pub cybershake::PublicKey);
@@ -38,12 +48,34 @@ pub enum PeerMessage<T: Codable> {
     Peers(Vec<PeerAddr>),
     // An underlying message.
     Data(T),
+    // A final message before closing the connection.
+    Goodbye(DisconnectReason),
+    // A request with the ID to be returned in the response.
+    Request(RequestID, T),
+    // A response to the request with a given ID.
+    Response(RequestID, T),
+}
+
+/// Reason for closing the connection that is sent to the peer in the final message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The node is shutting down.
+    Shutdown,
+    /// The peer has sent an invalid message.
+    ProtocolViolation,
+    /// The connection duplicates an existing connection to the same peer.
+    Duplicate,
+    /// The peer is banned for misbehavior and should not reconnect for a while.
+    Banned,
+    /// The peer does not read the messages fast enough and its outgoing queue has overflown.
+    SlowPeer,
 }
 
 /// Interface for communication with the peer.
 pub struct PeerLink<Custom: Codable> {
     peer_id: PeerID,
-    channel: sync::mpsc::Sender<PeerMessage<Custom>>,
+    channel: sync::mpsc::Sender<(Priority, PeerMessage<Custom>)>,
+    queued: Arc<AtomicUsize>, // number of messages sent to the peer, but not yet written to the socket
 }
 
 /// Notifications that we receive from the peer.
@@ -51,8 +83,13 @@ pub struct PeerLink<Custom: Codable> {
 pub enum PeerNotification<Custom: Codable> {
     /// Received a message from a peer
     Received(PeerID, PeerMessage<Custom>),
-    /// Peer got disconnected. This message is not sent if the peer was stopped by the host.
-    Disconnected(PeerID),
+    /// Peer got disconnected. The reason is present if the peer has sent it before closing the connection.
+    Disconnected {
+        peer: PeerID,
+        reason: Option<DisconnectReason>,
+    },
+    /// Peer sent a message that could not be decoded. The peer is disconnected right after that.
+    MalformedMessage(PeerID),
 }
 
 impl<Custom: Codable + 'static> PeerLink<Custom> {
@@ -61,13 +98,54 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
         &self.peer_id
     }
 
-    /// Sends a message to the peer.
+    /// Sends a message to the peer with the lowest priority.
     pub async fn send(&mut self, msg: PeerMessage<Custom>) -> () {
+        self.send_with_priority(msg, LOW_PRIORITY).await
+    }
+
+    /// Sends a message to the peer with a given priority.
+    /// Messages that are not yet written to the socket are delivered in the priority order,
+    /// and messages with equal priority are delivered in the order they were sent.
+    pub async fn send_with_priority(&mut self, msg: PeerMessage<Custom>, priority: Priority) -> () {
         // We intentionally ignore the error because it's only returned if the recipient has disconnected,
         // but even Ok is of no guarantee that the message will be delivered, so we simply ignore the error entirely.
         // Specifically, in this implementation, Node's task does not stop until all senders disappear,
         // so we will never have an error condition here.
-        self.channel.send(msg).await.unwrap_or(())
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.channel.send((priority, msg)).await.unwrap_or(())
+    }
+
+    /// Sends a message to the peer with a given priority without waiting for the room in the queue.
+    /// Returns `false` if the message was dropped because the peer's queue is full.
+    pub fn try_send(&mut self, msg: PeerMessage<Custom>, priority: Priority) -> bool {
+        match self.channel.try_send((priority, msg)) {
+            Ok(()) => {
+                self.queued.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Number of messages sent to the peer, but not yet written to the socket.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Sends a final message with a given reason and closes the connection
+    /// after all the previously sent messages are delivered.
+    pub async fn disconnect(mut self, reason: DisconnectReason) {
+        // The lowest possible priority guarantees that this message is written last.
+        self.send_with_priority(PeerMessage::Goodbye(reason), Priority::max_value())
+            .await
+        // Dropping the link stops the peer task when all queued messages are written.
+    }
+
+    /// Closes the connection without waiting for the room in the queue.
+    /// The final message with a given reason is sent only if the queue is not full.
+    pub fn disconnect_now(mut self, reason: DisconnectReason) {
+        let _ = self.try_send(PeerMessage::Goodbye(reason), Priority::max_value());
+        // Dropping the link stops the peer task when all queued messages are written.
     }
 
     /// Spawns a peer task that will send notifications to a provided channel.
@@ -97,7 +175,7 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
             cybershake::cybershake(host_identity, r, w, rng).await?;
 
         let mut outgoing = FramedWrite::new(outgoing, encoder);
-        let incoming = FramedRead::new(incoming, decoder);
+        let mut incoming = FramedRead::new(incoming, decoder);
 
         let id = PeerID(id_pubkey);
         let retid = id.clone();
@@ -111,47 +189,136 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
             }
         }
 
-        let (cmd_sender, cmd_receiver) = sync::mpsc::channel::<PeerMessage<Custom>>(100);
+        let (cmd_sender, mut cmd_receiver) =
+            sync::mpsc::channel::<(Priority, PeerMessage<Custom>)>(MAX_QUEUED_MESSAGES);
+        let queued = Arc::new(AtomicUsize::new(0));
+        let written = queued.clone();
 
         enum PeerEvent<Custom: Codable> {
-            Send(PeerMessage<Custom>),
+            Send(Priority, PeerMessage<Custom>),
             Receive(Result<PeerMessage<Custom>, io::Error>),
+            // Outgoing sink is ready to accept the next message (true) or has been flushed (false).
+            Writable(Result<bool, io::Error>),
             Stopped,
         }
 
-        // This configures a merged stream of commands from the host and messages from the peer.
-        let mut stream = futures::stream::select(
-            cmd_receiver
-                .map(PeerEvent::Send)
-                // when the owner drops the PeerLink, we'll get the Stopped event.
-                .chain(futures::stream::once(async { PeerEvent::Stopped })),
-            incoming.map(PeerEvent::Receive),
-        )
-        .boxed_local();
-
         task::spawn_local(async move {
-            while let Some(event) = stream.next().await {
+            // Messages waiting for the outgoing sink to become ready.
+            // Commands are moved here as soon as they arrive, so that the higher-priority messages
+            // can overtake the lower-priority ones that are not yet written.
+            let mut queue = PriorityQueue::new();
+            let mut needs_flush = false;
+
+            loop {
+                let event = {
+                    // Stop taking commands when the queue is full to keep the backpressure on the host.
+                    let next_cmd = if queue.len() < MAX_QUEUED_MESSAGES {
+                        cmd_receiver.next().left_future()
+                    } else {
+                        future::pending().right_future()
+                    };
+                    let has_queued = !queue.is_empty();
+                    let outgoing_ready = future::poll_fn(|cx| {
+                        if has_queued {
+                            Sink::<PeerMessage<Custom>>::poll_ready(Pin::new(&mut outgoing), cx)
+                                .map_ok(|_| true)
+                        } else if needs_flush {
+                            Sink::<PeerMessage<Custom>>::poll_flush(Pin::new(&mut outgoing), cx)
+                                .map_ok(|_| false)
+                        } else {
+                            Poll::Pending
+                        }
+                    });
+                    // Commands go first so that they are all queued before we pick the next message to write.
+                    futures::select_biased! {
+                        cmd = next_cmd.fuse() => match cmd {
+                            Some((priority, msg)) => PeerEvent::Send(priority, msg),
+                            // when the owner drops the PeerLink, we'll get the Stopped event.
+                            None => PeerEvent::Stopped,
+                        },
+                        msg = incoming.next().fuse() => match msg {
+                            Some(msg) => PeerEvent::Receive(msg),
+                            None => PeerEvent::Receive(Err(io::ErrorKind::UnexpectedEof.into())),
+                        },
+                        ready = outgoing_ready.fuse() => PeerEvent::Writable(ready),
+                    }
+                };
+
                 // First, handle successful events (think of this as Result::async_map)
-                let result: Result<(), Option<_>> = (async {
+                // Errors carry the reason for disconnect, if the peer has sent one.
+                let result: Result<(), Option<DisconnectReason>> = (async {
                     match event {
-                        PeerEvent::Send(msg) => outgoing.send(msg).await.map_err(Some),
+                        PeerEvent::Send(priority, msg) => {
+                            queue.push(msg, priority);
+                            Ok(())
+                        }
+                        PeerEvent::Writable(Ok(true)) => {
+                            needs_flush = true;
+                            match queue.pop() {
+                                Some(msg) => {
+                                    written.fetch_sub(1, Ordering::Relaxed);
+                                    Pin::new(&mut outgoing).start_send(msg).map_err(|_| None)
+                                }
+                                None => Ok(()),
+                            }
+                        }
+                        PeerEvent::Writable(Ok(false)) => {
+                            needs_flush = false;
+                            Ok(())
+                        }
+                        PeerEvent::Writable(Err(_)) => Err(None),
                         PeerEvent::Receive(msg) => {
-                            let msg = msg.map_err(Some)?;
+                            let msg = match msg {
+                                Ok(msg) => msg,
+                                Err(e) => {
+                                    if e.kind() == io::ErrorKind::InvalidData {
+                                        let _ = outgoing
+                                            .send(PeerMessage::Goodbye(
+                                                DisconnectReason::ProtocolViolation,
+                                            ))
+                                            .await;
+                                        let _ = notifications_channel
+                                            .send(
+                                                PeerNotification::MalformedMessage(id.clone())
+                                                    .into(),
+                                            )
+                                            .await;
+                                    }
+                                    return Err(None);
+                                }
+                            };
+                            if let PeerMessage::Goodbye(reason) = msg {
+                                return Err(Some(reason));
+                            }
 
                             notifications_channel
                                 .send(PeerNotification::Received(id.clone(), msg).into())
                                 .await
                                 .map_err(|_| None) // stop the actor if the recipient no longer interested in notifications.
                         }
-                        PeerEvent::Stopped => Err(None),
+                        PeerEvent::Stopped => {
+                            // Deliver the messages that were sent before the link was dropped.
+                            while let Some(msg) = queue.pop() {
+                                written.fetch_sub(1, Ordering::Relaxed);
+                                outgoing.feed(msg).await.map_err(|_| None)?;
+                            }
+                            outgoing.flush().await.map_err(|_| None)?;
+                            Err(None)
+                        }
                     }
                 })
                 .await;
 
                 // Second, handle the errors that occured before or after event processing.
-                if let Err(_maybe_err) = result {
+                if let Err(reason) = result {
                     let _ = notifications_channel
-                        .send(PeerNotification::Disconnected(id.clone()).into())
+                        .send(
+                            PeerNotification::Disconnected {
+                                peer: id.clone(),
+                                reason,
+                            }
+                            .into(),
+                        )
                         .await; // ignore failure since we are on the way out anyway
                     break;
                 }
@@ -161,6 +328,7 @@ impl<Custom: Codable + 'static> PeerLink<Custom> {
         Ok(Self {
             peer_id: retid,
             channel: cmd_sender,
+            queued,
         })
     }
 }
@@ -186,6 +354,31 @@ impl PeerID {
     }
 }
 
+impl DisconnectReason {
+    /// Converts the reason into a byte for encoding.
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            DisconnectReason::Shutdown => 0,
+            DisconnectReason::ProtocolViolation => 1,
+            DisconnectReason::Duplicate => 2,
+            DisconnectReason::Banned => 3,
+            DisconnectReason::SlowPeer => 4,
+        }
+    }
+
+    /// Converts the byte into a reason. Returns None for unknown codes.
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(DisconnectReason::Shutdown),
+            1 => Some(DisconnectReason::ProtocolViolation),
+            2 => Some(DisconnectReason::Duplicate),
+            3 => Some(DisconnectReason::Banned),
+            4 => Some(DisconnectReason::SlowPeer),
+            _ => None,
+        }
+    }
+}
+
 impl From<cybershake::PublicKey> for PeerID {
     fn from(pk: cybershake::PublicKey) -> Self {
         PeerID(pk)
@@ -1,7 +1,7 @@
 //! Prioritization utilities.
 
-use std::cmp::Eq;
-use std::collections::HashMap;
+use std::cmp::{Eq, Ordering};
+use std::collections::{BinaryHeap, HashMap};
 use std::default::Default;
 use std::hash::Hash;
 use std::mem;
@@ -101,3 +101,95 @@ impl<K: Hash + Eq> PriorityTable<K> {
         self.items.extend(list);
     }
 }
+
+/// Queue that yields the items with the highest priority first,
+/// and items with equal priority in the order of insertion.
+pub struct PriorityQueue<T> {
+    heap: BinaryHeap<QueueEntry<T>>,
+    sequence: u64,
+}
+
+struct QueueEntry<T> {
+    priority: Priority,
+    sequence: u64,
+    item: T,
+}
+
+impl<T> PriorityQueue<T> {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Adds an item with a given priority.
+    pub fn push(&mut self, item: T, priority: Priority) {
+        self.heap.push(QueueEntry {
+            priority,
+            sequence: self.sequence,
+            item,
+        });
+        self.sequence += 1;
+    }
+
+    /// Removes the item with the highest priority.
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|entry| entry.item)
+    }
+
+    /// Number of items in the queue.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Returns true if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+impl<T> Ord for QueueEntry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap pops the greatest entry first, so we reverse the order:
+        // lower integer means higher priority, and the earlier entries go first.
+        (other.priority, other.sequence).cmp(&(self.priority, self.sequence))
+    }
+}
+
+impl<T> PartialOrd for QueueEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for QueueEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.sequence == other.sequence
+    }
+}
+
+impl<T> Eq for QueueEntry<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_queue_order() {
+        let mut queue = PriorityQueue::new();
+        queue.push("bulk 1", LOW_PRIORITY);
+        queue.push("bulk 2", LOW_PRIORITY);
+        queue.push("urgent", HIGH_PRIORITY);
+        queue.push("normal", 100);
+        assert_eq!(queue.len(), 4);
+
+        assert_eq!(queue.pop(), Some("urgent"));
+        assert_eq!(queue.pop(), Some("normal"));
+        assert_eq!(queue.pop(), Some("bulk 1"));
+        assert_eq!(queue.pop(), Some("bulk 2"));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+//! Request/response RPC between the peers.
+//!
+//! Requests and responses are sent over the same encrypted connection as the other messages,
+//! and are matched by the request ID assigned by the requesting node:
+//! - Use `NodeHandle::request` to send a request and wait for the response.
+//! - Handle `NodeNotification::RequestReceived` and reply with `NodeHandle::respond`.
+//!
+//! Outstanding requests are resolved with an error when the peer does not reply in time
+//! or gets disconnected. Responses to unknown or expired requests are ignored.
+use core::fmt;
+use std::time::Instant;
+
+use tokio::sync;
+
+/// Identifier of the request, unique among the outstanding requests of the node.
+pub type RequestID = u64;
+
+/// Reason for a request to fail without a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcError {
+    /// The peer is not connected.
+    NotConnected,
+    /// The peer got disconnected before sending the response.
+    Disconnected,
+    /// The peer did not reply within the timeout.
+    Timeout,
+}
+
+/// Request waiting for the response from the peer.
+pub(crate) struct PendingRequest<T> {
+    pub(crate) reply: sync::oneshot::Sender<Result<T, RpcError>>,
+    pub(crate) deadline: Instant,
+}
+
+impl<T> PendingRequest<T> {
+    /// Resolves the request with the response or an error.
+    pub(crate) fn resolve(self, result: Result<T, RpcError>) {
+        // The requester may have stopped waiting, which is fine.
+        let _ = self.reply.send(result);
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::NotConnected => write!(f, "peer is not connected"),
+            RpcError::Disconnected => write!(f, "peer disconnected before responding"),
+            RpcError::Timeout => write!(f, "peer did not respond in time"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
@@ -0,0 +1,89 @@
+//! Peer misbehavior scoring and bans.
+
+use std::cmp::Eq;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Misbehavior score of the peer. Higher integer - worse peer.
+pub type PeerScore = u64;
+
+/// Penalty for a message that could not be decoded.
+pub const MALFORMED_MESSAGE_PENALTY: PeerScore = 20;
+
+/// Table of peer scores and active bans.
+/// Scores are kept only for peers that were penalized at least once.
+#[derive(Debug)]
+pub struct ScoreTable<K: Hash + Eq> {
+    scores: HashMap<K, PeerScore>,
+    bans: HashMap<K, Instant>,
+}
+
+impl<K: Hash + Eq> ScoreTable<K> {
+    /// Creates an empty score table.
+    pub fn new() -> Self {
+        Self {
+            scores: HashMap::new(),
+            bans: HashMap::new(),
+        }
+    }
+
+    /// Adds a penalty to the item's score and returns the updated score.
+    pub fn penalize(&mut self, item: K, amount: PeerScore) -> PeerScore {
+        let score = self.scores.entry(item).or_insert(0);
+        *score = score.saturating_add(amount);
+        *score
+    }
+
+    /// Returns the current score of the item. Unknown items have zero score.
+    pub fn get(&self, item: &K) -> PeerScore {
+        self.scores.get(item).copied().unwrap_or(0)
+    }
+
+    /// Bans the item for a given duration and resets its score,
+    /// so it starts from scratch when the ban expires.
+    pub fn ban(&mut self, item: K, duration: Duration) {
+        self.scores.remove(&item);
+        self.bans.insert(item, Instant::now() + duration);
+    }
+
+    /// Returns true if the item is currently banned.
+    pub fn is_banned(&self, item: &K) -> bool {
+        self.bans
+            .get(item)
+            .map(|until| *until > Instant::now())
+            .unwrap_or(false)
+    }
+
+    /// Forgets all the bans that have expired.
+    pub fn remove_expired_bans(&mut self) {
+        let now = Instant::now();
+        self.bans.retain(|_, until| *until > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn penalties_and_bans() {
+        let mut table = ScoreTable::new();
+        assert_eq!(table.get(&1u8), 0);
+        assert_eq!(table.penalize(1u8, 10), 10);
+        assert_eq!(
+            table.penalize(1u8, PeerScore::max_value()),
+            PeerScore::max_value()
+        );
+        assert!(!table.is_banned(&1u8));
+
+        table.ban(1u8, Duration::from_secs(3600));
+        assert!(table.is_banned(&1u8));
+        assert_eq!(table.get(&1u8), 0);
+
+        table.ban(2u8, Duration::from_secs(0));
+        assert!(!table.is_banned(&2u8));
+        table.remove_expired_bans();
+        assert_eq!(table.bans.len(), 1);
+    }
+}
@@ -115,12 +115,7 @@ fn test() {
                 // timeout is checked as a range proof
                 // tx.mintime > self.timeout =>
                 // tx.mintime - self.timeout > 0
-                p.dup(2) // copy the timeout from the stack
-                    .commit()
-                    .neg()
-                    .mintime()
-                    .add()
-                    .range()
+                p.assert_mintime_after(2) // copy the timeout from the stack and check it
                     // then, evaluate the distribution program
                     .roll(1); // we won't need to keep the program, so move it to the top
 
@@ -0,0 +1,254 @@
+//! Async counterparts of the Reader and Writer traits.
+//! AsyncReader implementation for tokio::io::AsyncRead.
+//! AsyncWriter implementation for tokio::io::AsyncWrite.
+//!
+//! Note: tokio's `AsyncReadExt` and `AsyncWriteExt` have methods with the same names
+//! (e.g. `read_u64`, which is big-endian there), so avoid importing both traits in the same scope.
+
+use crate::reader::VarintDecoder;
+use crate::writer::encode_varint;
+use crate::{ReadError, WriteError};
+use async_trait::async_trait;
+use core::future::Future;
+use core::pin::Pin;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Maximum number of bytes or items preallocated before the data actually arrives.
+/// Lengths are often read from untrusted sources, so we grow the buffers gradually.
+const PREALLOCATION_LIMIT: usize = 4096;
+
+/// Boxed future borrowing the reader, returned by the `AsyncReader` methods.
+pub type ReadFuture<'r, T> = Pin<Box<dyn Future<Output = T> + Send + 'r>>;
+
+/// An interface for reading binary data asynchronously.
+#[async_trait]
+pub trait AsyncReader: Send {
+    /// Copies bytes into a slice, waiting for the data to arrive.
+    /// If the stream ends before the slice is filled, returns ReadError::InsufficientBytes.
+    async fn read(&mut self, dst: &mut [u8]) -> Result<(), ReadError>;
+
+    /// Reads a single byte.
+    #[inline]
+    async fn read_u8(&mut self) -> Result<u8, ReadError> {
+        let mut buf = [0u8; 1];
+        self.read(&mut buf).await?;
+        Ok(buf[0])
+    }
+
+    /// Reads a 4-byte LE32 integer.
+    #[inline]
+    async fn read_u32(&mut self) -> Result<u32, ReadError> {
+        let mut buf = [0u8; 4];
+        self.read(&mut buf).await?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Reads an 8-byte LE64 integer.
+    #[inline]
+    async fn read_u64(&mut self) -> Result<u64, ReadError> {
+        let mut buf = [0u8; 8];
+        self.read(&mut buf).await?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    /// Reads an unsigned LEB128-encoded integer.
+    /// Fails with ReadError::InvalidFormat if the encoding is not minimal
+    /// or the integer does not fit in 64 bits.
+    #[inline]
+    async fn read_varint(&mut self) -> Result<u64, ReadError> {
+        let mut varint = VarintDecoder::new();
+        loop {
+            if let Some(x) = varint.push(self.read_u8().await?)? {
+                return Ok(x);
+            }
+        }
+    }
+
+    /// Reads a 32-byte string.
+    #[inline]
+    async fn read_u8x32(&mut self) -> Result<[u8; 32], ReadError> {
+        let mut buf = [0u8; 32];
+        self.read(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Reads a 64-byte string.
+    #[inline]
+    async fn read_u8x64(&mut self) -> Result<[u8; 64], ReadError> {
+        let mut buf = [0u8; 64];
+        self.read(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Reads a vector of bytes with the required length.
+    /// Unlike `Reader::read_bytes`, the remaining length is not known upfront,
+    /// so the vector grows as the data arrives.
+    #[inline]
+    async fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, ReadError> {
+        let mut vec = Vec::new();
+        while vec.len() < len {
+            let offset = vec.len();
+            vec.resize(offset + (len - offset).min(PREALLOCATION_LIMIT), 0u8);
+            self.read(&mut vec[offset..]).await?;
+        }
+        Ok(vec)
+    }
+
+    /// Reads a vector of items with the required count.
+    /// The closure reads a single item and can be an `AsyncReader` method: `r.read_vec(n, |r| r.read_u8x32())`.
+    #[inline]
+    async fn read_vec<T, E, F>(&mut self, len: usize, closure: F) -> Result<Vec<T>, E>
+    where
+        T: Send,
+        E: From<ReadError> + Send,
+        F: for<'r> Fn(&'r mut Self) -> ReadFuture<'r, Result<T, E>> + Send + Sync,
+    {
+        let mut vec = Vec::with_capacity(len.min(PREALLOCATION_LIMIT));
+        for _ in 0..len {
+            vec.push(closure(self).await?);
+        }
+        Ok(vec)
+    }
+}
+
+/// Interface for writing binary data asynchronously.
+#[async_trait]
+pub trait AsyncWriter: Send {
+    /// Writes bytes with the given label, waiting until all of them are accepted.
+    async fn write(&mut self, label: &'static [u8], src: &[u8]) -> Result<(), WriteError>;
+
+    /// Writes a single byte.
+    #[inline]
+    async fn write_u8(&mut self, label: &'static [u8], x: u8) -> Result<(), WriteError> {
+        self.write(label, &[x]).await
+    }
+
+    /// Writes a LE32-encoded integer.
+    #[inline]
+    async fn write_u32(&mut self, label: &'static [u8], x: u32) -> Result<(), WriteError> {
+        self.write(label, &x.to_le_bytes()).await
+    }
+
+    /// Writes a LE64-encoded integer.
+    #[inline]
+    async fn write_u64(&mut self, label: &'static [u8], x: u64) -> Result<(), WriteError> {
+        self.write(label, &x.to_le_bytes()).await
+    }
+
+    /// Writes an unsigned LEB128-encoded integer (1 to 10 bytes).
+    #[inline]
+    async fn write_varint(&mut self, label: &'static [u8], x: u64) -> Result<(), WriteError> {
+        let (buf, len) = encode_varint(x);
+        self.write(label, &buf[..len]).await
+    }
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> AsyncReader for R {
+    #[inline]
+    async fn read(&mut self, dst: &mut [u8]) -> Result<(), ReadError> {
+        match AsyncReadExt::read_exact(self, dst).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(ReadError::InsufficientBytes),
+            Err(e) => Err(ReadError::Custom(Box::new(e))),
+        }
+    }
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Unpin + Send> AsyncWriter for W {
+    #[inline]
+    async fn write(&mut self, _label: &'static [u8], src: &[u8]) -> Result<(), WriteError> {
+        AsyncWriteExt::write_all(self, src)
+            .await
+            .map_err(|e| WriteError::Io(e.kind()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncReader, AsyncWriter};
+    use crate::ReadError;
+
+    async fn write_message<W: AsyncWriter>(w: &mut W, bytes: &[u8]) {
+        w.write_u8(b"u8", 0xab).await.unwrap();
+        w.write_u32(b"u32", 0xdead_beef).await.unwrap();
+        w.write_u64(b"u64", u64::max_value() - 1).await.unwrap();
+        w.write_varint(b"len", bytes.len() as u64).await.unwrap();
+        w.write(b"bytes", bytes).await.unwrap();
+        w.write(b"items", &[1u8; 32]).await.unwrap();
+        w.write(b"items", &[2u8; 32]).await.unwrap();
+    }
+
+    async fn read_message<R: AsyncReader>(
+        r: &mut R,
+    ) -> Result<(u8, u32, u64, Vec<u8>, Vec<[u8; 32]>), ReadError> {
+        let a = r.read_u8().await?;
+        let b = r.read_u32().await?;
+        let c = r.read_u64().await?;
+        let len = r.read_varint().await? as usize;
+        let bytes = r.read_bytes(len).await?;
+        let items = r.read_vec(2, |r| r.read_u8x32()).await?;
+        Ok((a, b, c, bytes, items))
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let payload = vec![0x42u8; 5000];
+        let mut buf = Vec::new();
+        write_message(&mut buf, &payload).await;
+
+        let mut reader = &buf[..];
+        let (a, b, c, bytes, items) = read_message(&mut reader).await.unwrap();
+        assert_eq!(a, 0xab);
+        assert_eq!(b, 0xdead_beef);
+        assert_eq!(c, u64::max_value() - 1);
+        assert_eq!(bytes, payload);
+        assert_eq!(items, vec![[1u8; 32], [2u8; 32]]);
+        assert!(reader.is_empty());
+
+        // The stream is exhausted.
+        match reader.read_u8().await {
+            Err(ReadError::InsufficientBytes) => {}
+            _ => panic!("Reading past the end must fail"),
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated_stream() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, b"hello").await;
+
+        for len in 0..buf.len() {
+            let mut reader = std::io::Cursor::new(&buf[..len]);
+            match read_message(&mut reader).await {
+                Err(ReadError::InsufficientBytes) => {}
+                _ => panic!("Truncated message of {} bytes must fail", len),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn over_length_prefix() {
+        // The length prefix claims much more data than the stream has:
+        // the reader fails when the stream ends, without allocating the whole length upfront.
+        let mut buf = Vec::new();
+        buf.write_varint(b"len", 1 << 40).await.unwrap();
+        buf.write(b"bytes", &[0u8; 10]).await.unwrap();
+
+        let mut reader = &buf[..];
+        let len = reader.read_varint().await.unwrap() as usize;
+        match reader.read_bytes(len).await {
+            Err(ReadError::InsufficientBytes) => {}
+            _ => panic!("Over-length prefix must fail"),
+        }
+
+        // The prefix itself does not fit in 64 bits.
+        let mut reader = &[0xffu8; 11][..];
+        match reader.read_varint().await {
+            Err(ReadError::InvalidFormat) => {}
+            _ => panic!("Overlong varint must fail"),
+        }
+    }
+}
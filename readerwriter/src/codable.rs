@@ -1,4 +1,5 @@
 use crate::{ReadError, Reader, WriteError, Writer};
+use alloc::vec::Vec;
 
 /// A trait for encoding structures using the [Writer] trait.
 ///
@@ -1,10 +1,19 @@
+//! Traits and implementations for reading and writing binary data.
+//! The crate supports `no_std` environments with `alloc` when the default `std` feature is disabled.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod codable;
 mod reader;
 mod writer;
 
 pub use codable::{Codable, Decodable, Encodable, ExactSizeEncodable};
 pub use reader::{ReadError, Reader};
-pub use writer::{WriteError, Writer};
+pub use writer::{encoded_varint_size, WriteError, Writer};
+
+#[cfg(test)]
+mod tests;
 
 #[cfg(feature = "merlin")]
 mod merlin_support;
@@ -15,3 +24,8 @@ pub use merlin_support::*;
 mod bytes_support;
 #[cfg(feature = "bytes")]
 pub use bytes_support::*;
+
+#[cfg(feature = "tokio")]
+mod async_support;
+#[cfg(feature = "tokio")]
+pub use async_support::*;
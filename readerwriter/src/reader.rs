@@ -1,5 +1,7 @@
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt::{Display, Formatter};
 
 /// Error kinds returns by the reader.
 #[derive(Debug)]
@@ -7,19 +9,22 @@ pub enum ReadError {
     InsufficientBytes,
     TrailingBytes,
     InvalidFormat,
+    #[cfg(feature = "std")]
     Custom(Box<dyn Error + Send + Sync>),
 }
 
 impl Display for ReadError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), core::fmt::Error> {
         match self {
             ReadError::InsufficientBytes => write!(f, "insufficient bytes"),
             ReadError::TrailingBytes => write!(f, "trailing bytes"),
             ReadError::InvalidFormat => write!(f, "invalid format"),
+            #[cfg(feature = "std")]
             ReadError::Custom(d) => d.fmt(f),
         }
     }
 }
+#[cfg(feature = "std")]
 impl std::error::Error for ReadError {}
 
 /// An interface for reading binary data.
@@ -85,6 +90,19 @@ pub trait Reader {
         Ok(u64::from_le_bytes(buf))
     }
 
+    /// Reads an unsigned LEB128-encoded integer.
+    /// Fails with ReadError::InvalidFormat if the encoding is not minimal
+    /// or the integer does not fit in 64 bits.
+    #[inline]
+    fn read_varint(&mut self) -> Result<u64, ReadError> {
+        let mut varint = VarintDecoder::new();
+        loop {
+            if let Some(x) = varint.push(self.read_u8()?)? {
+                return Ok(x);
+            }
+        }
+    }
+
     /// Reads a 32-byte string.
     #[inline]
     fn read_u8x32(&mut self) -> Result<[u8; 32], ReadError> {
@@ -136,6 +154,38 @@ pub trait Reader {
     }
 }
 
+/// Incremental decoder of the LEB128-encoded integers.
+pub(crate) struct VarintDecoder {
+    value: u64,
+    shift: u32,
+}
+
+impl VarintDecoder {
+    pub(crate) fn new() -> Self {
+        VarintDecoder { value: 0, shift: 0 }
+    }
+
+    /// Adds the next byte to the decoded integer.
+    /// Returns the integer if this byte was the last one.
+    #[inline]
+    pub(crate) fn push(&mut self, byte: u8) -> Result<Option<u64>, ReadError> {
+        // The 10th byte may only contain the highest bit of the integer.
+        if self.shift == 63 && byte > 1 {
+            return Err(ReadError::InvalidFormat);
+        }
+        self.value |= ((byte & 0x7f) as u64) << self.shift;
+        if byte & 0x80 != 0 {
+            self.shift += 7;
+            return Ok(None);
+        }
+        // Trailing zero byte is redundant: encoding must be minimal.
+        if byte == 0 && self.shift > 0 {
+            return Err(ReadError::InvalidFormat);
+        }
+        Ok(Some(self.value))
+    }
+}
+
 impl Reader for &[u8] {
     #[inline]
     fn read(&mut self, dst: &mut [u8]) -> Result<(), ReadError> {
@@ -0,0 +1,84 @@
+use super::*;
+
+fn varint_bytes(x: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.write_varint(b"x", x).unwrap();
+    buf
+}
+
+fn read_varint(mut bytes: &[u8]) -> Result<u64, ReadError> {
+    bytes.read_all(|r| r.read_varint())
+}
+
+#[test]
+fn varint_encoding() {
+    let cases: &[(u64, &[u8])] = &[
+        (0, &[0x00]),
+        (1, &[0x01]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (300, &[0xac, 0x02]),
+        (16383, &[0xff, 0x7f]),
+        (16384, &[0x80, 0x80, 0x01]),
+        (
+            u64::max_value(),
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+    ];
+    for (x, bytes) in cases.iter() {
+        assert_eq!(&varint_bytes(*x)[..], *bytes);
+        assert_eq!(encoded_varint_size(*x), bytes.len());
+        assert_eq!(read_varint(bytes).unwrap(), *x);
+    }
+}
+
+#[test]
+fn varint_size_boundaries() {
+    for bits in 0..64u32 {
+        let x = 1u64 << bits;
+        assert_eq!(encoded_varint_size(x), varint_bytes(x).len());
+        assert_eq!(encoded_varint_size(x - 1), varint_bytes(x - 1).len());
+    }
+}
+
+#[test]
+fn varint_invalid_encodings() {
+    // truncated
+    assert!(matches!(
+        read_varint(&[]),
+        Err(ReadError::InsufficientBytes)
+    ));
+    assert!(matches!(
+        read_varint(&[0x80]),
+        Err(ReadError::InsufficientBytes)
+    ));
+    // non-minimal
+    assert!(matches!(
+        read_varint(&[0x80, 0x00]),
+        Err(ReadError::InvalidFormat)
+    ));
+    assert!(matches!(
+        read_varint(&[0xff, 0x00]),
+        Err(ReadError::InvalidFormat)
+    ));
+    // overflows u64
+    assert!(matches!(
+        read_varint(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]),
+        Err(ReadError::InvalidFormat)
+    ));
+    assert!(matches!(
+        read_varint(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x81, 0x00]),
+        Err(ReadError::InvalidFormat)
+    ));
+}
+
+#[test]
+fn varint_does_not_write_partially() {
+    let mut buf = [0u8; 1];
+    let mut slice = &mut buf[..];
+    assert_eq!(
+        slice.write_varint(b"x", 128),
+        Err(WriteError::InsufficientCapacity)
+    );
+    assert_eq!(slice.len(), 1);
+}
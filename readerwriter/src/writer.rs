@@ -1,19 +1,25 @@
+use alloc::vec::Vec;
+use core::fmt::Formatter;
 use core::mem;
-use std::fmt::Formatter;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum WriteError {
     InsufficientCapacity,
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
 }
 
-impl std::fmt::Display for WriteError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), core::fmt::Error> {
         match self {
             WriteError::InsufficientCapacity => write!(f, "insufficient capacity"),
+            #[cfg(feature = "std")]
+            WriteError::Io(kind) => write!(f, "i/o error: {:?}", kind),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for WriteError {}
 
 /// Interface for writing binary data.
@@ -42,6 +48,38 @@ pub trait Writer {
     fn write_u64(&mut self, label: &'static [u8], x: u64) -> Result<(), WriteError> {
         self.write(label, &x.to_le_bytes())
     }
+
+    /// Writes an unsigned LEB128-encoded integer (1 to 10 bytes).
+    #[inline]
+    fn write_varint(&mut self, label: &'static [u8], x: u64) -> Result<(), WriteError> {
+        let (buf, len) = encode_varint(x);
+        self.write(label, &buf[..len])
+    }
+}
+
+/// Returns the number of bytes needed to encode the integer with `write_varint`.
+#[inline]
+pub fn encoded_varint_size(x: u64) -> usize {
+    let bits = (64 - x.leading_zeros()).max(1);
+    ((bits + 6) / 7) as usize
+}
+
+/// Encodes the integer in LEB128 format into a buffer.
+/// Returns the buffer and the number of bytes used.
+#[inline]
+pub(crate) fn encode_varint(mut x: u64) -> ([u8; 10], usize) {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (x & 0x7f) as u8;
+        x >>= 7;
+        if x == 0 {
+            buf[len] = byte;
+            return (buf, len + 1);
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
 }
 
 impl Writer for Vec<u8> {
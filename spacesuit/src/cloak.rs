@@ -6,11 +6,41 @@ use bulletproofs::r1cs::{R1CSError, RandomizableConstraintSystem};
 
 /// Enforces that the outputs are a valid rearrangement of the inputs, following the
 /// soundness and secrecy requirements in the [Cloak specification](../spec.md).
+///
+/// This is a gadget: it only adds constraints to the provided constraint system
+/// and does not create or verify the proof. The caller can add its own constraints
+/// on the same allocated values before or after calling `cloak`, and then prove
+/// or verify the entire constraint system at once.
+/// The constraint system must be randomizable because the shuffle gadgets
+/// use challenges derived from the committed values.
 pub fn cloak<CS: RandomizableConstraintSystem>(
     cs: &mut CS,
     inputs: Vec<AllocatedValue>,
     outputs: Vec<AllocatedValue>,
 ) -> Result<(), R1CSError> {
+    cloak_with_zero_outputs(cs, inputs, outputs, &[])
+}
+
+/// Same as [`cloak`], but the outputs at the indices listed in `zero_outputs`
+/// are constrained to have zero quantity instead of being range-proven,
+/// since zero is trivially in range. This saves 64 multipliers per such output,
+/// which shrinks the proofs for transactions with many zero-padded outputs.
+///
+/// The list of zero outputs is public: the prover and the verifier must use
+/// the same indices, otherwise the proof will not verify.
+/// Returns an error if any of the indices is out of bounds.
+pub fn cloak_with_zero_outputs<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    outputs: Vec<AllocatedValue>,
+    zero_outputs: &[usize],
+) -> Result<(), R1CSError> {
+    if zero_outputs.iter().any(|i| *i >= outputs.len()) {
+        return Err(R1CSError::GadgetError {
+            description: "Index of the zero output is out of bounds".to_string(),
+        });
+    }
+
     // Merge
     let (merge_in, merge_out) = merge(cs, inputs.clone())?;
 
@@ -34,13 +64,18 @@ pub fn cloak<CS: RandomizableConstraintSystem>(
 
     // Range Proof
     // Check that each of the quantities in `outputs` lies in [0, 2^64).
-    for output in outputs {
-        range_proof(
-            cs,
-            output.q.into(),
-            output.assignment.map(|v| v.q),
-            BitRange::max(),
-        )?;
+    // Zero outputs are simply constrained to be zero.
+    for (i, output) in outputs.into_iter().enumerate() {
+        if zero_outputs.contains(&i) {
+            cs.constrain(output.q.into());
+        } else {
+            range_proof(
+                cs,
+                output.q.into(),
+                output.assignment.map(|v| v.q),
+                BitRange::max(),
+            )?;
+        }
     }
 
     Ok(())
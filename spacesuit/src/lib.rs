@@ -3,6 +3,11 @@
 //! [Bulletproofs](https://crypto.stanford.edu/bulletproofs/) zero-knowledge proof system.
 //! _Cloaked transactions_ exchange values of different “asset types” (which we call flavors).
 //! See the [Cloak specification](https://github.com/interstellar/slingshot/blob/main/spacesuit/spec.md) for details.
+//!
+//! The gadgets do not fix the Pedersen generators: the values are committed
+//! with the `PedersenGens` of the constraint system they are added to.
+//! The prover and the verifier must use the same generators, and commitments
+//! made with different generators cannot be mixed in one proof.
 #![deny(missing_docs)]
 
 mod bit_range;
@@ -14,7 +19,7 @@ mod signed_integer;
 mod value;
 
 pub use crate::bit_range::BitRange;
-pub use crate::cloak::cloak;
+pub use crate::cloak::{cloak, cloak_with_zero_outputs};
 pub use crate::range_proof::range_proof;
 pub use crate::signed_integer::SignedInteger;
 pub use crate::value::{AllocatedValue, CommittedValue, Value};
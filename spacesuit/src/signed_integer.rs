@@ -2,7 +2,7 @@
 use core::ops::Neg;
 use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
-use std::ops::{Add, Mul};
+use std::ops::{Add, Mul, Sub};
 use subtle::{Choice, ConditionallySelectable};
 
 /// Represents a signed integer with absolute value in the 64-bit range.
@@ -20,12 +20,24 @@ impl SignedInteger {
         }
     }
 
+    /// Converts a signed 64-bit integer. Always in range.
+    pub fn from_i64(i: i64) -> SignedInteger {
+        SignedInteger(i as i128)
+    }
+
     /// Converts the integer to Scalar.
+    /// Negative integers are mapped to their additive inverses modulo the group order.
     pub fn to_scalar(self) -> Scalar {
         self.into()
     }
 }
 
+impl From<i64> for SignedInteger {
+    fn from(i: i64) -> SignedInteger {
+        SignedInteger::from_i64(i)
+    }
+}
+
 impl From<u64> for SignedInteger {
     fn from(u: u64) -> SignedInteger {
         SignedInteger(u as i128)
@@ -56,6 +68,14 @@ impl Add for SignedInteger {
     }
 }
 
+impl Sub for SignedInteger {
+    type Output = Option<SignedInteger>;
+
+    fn sub(self, rhs: SignedInteger) -> Option<SignedInteger> {
+        self + (-rhs)
+    }
+}
+
 impl Mul for SignedInteger {
     type Output = Option<SignedInteger>;
 
@@ -126,4 +146,46 @@ mod tests {
         let b = -SignedInteger::from(u64::max_value());
         assert_eq!(a * b, None);
     }
+
+    #[test]
+    fn sub_overflow() {
+        let a = -SignedInteger::from(u64::max_value());
+        let b = SignedInteger::from(0u64);
+        assert_eq!((a - b).unwrap(), -SignedInteger::from(u64::max_value()));
+
+        let a = -SignedInteger::from(u64::max_value());
+        let b = SignedInteger::from(1u64);
+        assert_eq!(a - b, None);
+
+        let a = SignedInteger::from(3u64);
+        let b = SignedInteger::from(5u64);
+        assert_eq!((a - b).unwrap(), SignedInteger::from_i64(-2));
+    }
+
+    #[test]
+    fn add_negation() {
+        for a in [0i64, 1, -1, i64::max_value(), i64::min_value()].iter() {
+            let a = SignedInteger::from_i64(*a);
+            assert_eq!((a + (-a)).unwrap(), SignedInteger::from(0u64));
+        }
+        let a = SignedInteger::from(u64::max_value());
+        assert_eq!((a + (-a)).unwrap(), SignedInteger::from(0u64));
+    }
+
+    #[test]
+    fn negative_to_scalar() {
+        assert_eq!(SignedInteger::from_i64(-5).to_scalar(), -Scalar::from(5u64));
+        assert_eq!(
+            SignedInteger::from_i64(i64::min_value()).to_scalar(),
+            -Scalar::from(1u64 << 63)
+        );
+        assert_eq!(
+            (-SignedInteger::from(u64::max_value())).to_scalar(),
+            -Scalar::from(u64::max_value())
+        );
+        assert_eq!(
+            SignedInteger::from_i64(-5).to_scalar() + Scalar::from(5u64),
+            Scalar::zero()
+        );
+    }
 }
@@ -1,14 +1,21 @@
 extern crate bulletproofs;
+extern crate curve25519_dalek;
 extern crate merlin;
 extern crate rand;
 extern crate spacesuit;
 
-use bulletproofs::r1cs::{Prover, R1CSError, R1CSProof, Verifier};
+use bulletproofs::r1cs::{
+    ConstraintSystem, LinearCombination, Prover, R1CSError, R1CSProof, Verifier,
+};
 use bulletproofs::{BulletproofGens, PedersenGens};
+use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use rand::{CryptoRng, Rng};
 
-use spacesuit::{cloak, CommittedValue, ProverCommittable, Value, VerifierCommittable};
+use spacesuit::{
+    cloak, cloak_with_zero_outputs, AllocatedValue, CommittedValue, ProverCommittable, Value,
+    VerifierCommittable,
+};
 
 fn spacesuit_helper(
     bp_gens: &BulletproofGens,
@@ -64,6 +71,66 @@ fn verify(
     Ok(verifier.verify(&proof, &pc_gens, &bp_gens)?)
 }
 
+// Proves and verifies cloak together with a custom constraint
+// that the first output has a publicly known quantity.
+fn composed_helper(
+    bp_gens: &BulletproofGens,
+    inputs: Vec<Value>,
+    outputs: Vec<Value>,
+    public_qty: u64,
+) -> Result<(), R1CSError> {
+    let pc_gens = PedersenGens::default();
+    let mut rng = rand::thread_rng();
+
+    let mut prover_transcript = Transcript::new(b"ComposedTest");
+    let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+    let (in_com, in_vars) = inputs.commit(&mut prover, &mut rng);
+    let (out_com, out_vars) = outputs.commit(&mut prover, &mut rng);
+    constrain_quantity(&mut prover, &out_vars[0], public_qty);
+    cloak(&mut prover, in_vars, out_vars)?;
+    let proof = prover.prove(&bp_gens)?;
+
+    let mut verifier_transcript = Transcript::new(b"ComposedTest");
+    let mut verifier = Verifier::new(&mut verifier_transcript);
+    let in_vars = in_com.commit(&mut verifier);
+    let out_vars = out_com.commit(&mut verifier);
+    constrain_quantity(&mut verifier, &out_vars[0], public_qty);
+    cloak(&mut verifier, in_vars, out_vars)?;
+    verifier.verify(&proof, &pc_gens, &bp_gens)
+}
+
+// Proves and verifies cloak where some outputs are flagged as zero,
+// returning the size of the proof.
+fn zero_outputs_helper(
+    bp_gens: &BulletproofGens,
+    inputs: Vec<Value>,
+    outputs: Vec<Value>,
+    zero_outputs: &[usize],
+) -> Result<usize, R1CSError> {
+    let pc_gens = PedersenGens::default();
+    let mut rng = rand::thread_rng();
+
+    let mut prover_transcript = Transcript::new(b"ZeroOutputsTest");
+    let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+    let (in_com, in_vars) = inputs.commit(&mut prover, &mut rng);
+    let (out_com, out_vars) = outputs.commit(&mut prover, &mut rng);
+    cloak_with_zero_outputs(&mut prover, in_vars, out_vars, zero_outputs)?;
+    let proof = prover.prove(&bp_gens)?;
+
+    let mut verifier_transcript = Transcript::new(b"ZeroOutputsTest");
+    let mut verifier = Verifier::new(&mut verifier_transcript);
+    let in_vars = in_com.commit(&mut verifier);
+    let out_vars = out_com.commit(&mut verifier);
+    cloak_with_zero_outputs(&mut verifier, in_vars, out_vars, zero_outputs)?;
+    verifier.verify(&proof, &pc_gens, &bp_gens)?;
+
+    Ok(proof.serialized_size())
+}
+
+fn constrain_quantity<CS: ConstraintSystem>(cs: &mut CS, value: &AllocatedValue, q: u64) {
+    cs.constrain(LinearCombination::from(value.q) - Scalar::from(q));
+}
+
 // Helper functions to make the tests easier to read
 fn yuan(q: u64) -> Value {
     Value {
@@ -96,6 +163,50 @@ fn spacesuit_1_1() {
     assert!(spacesuit_helper(&bp_gens, vec![yuan(1)], vec![peso(4)]).is_err());
 }
 
+// cloak is combined with other constraints in the same constraint system
+#[test]
+fn spacesuit_composed() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    assert!(composed_helper(&bp_gens, vec![yuan(3), yuan(2)], vec![yuan(5), zero()], 5).is_ok());
+    assert!(composed_helper(&bp_gens, vec![yuan(3), yuan(2)], vec![yuan(5), zero()], 4).is_err());
+    assert!(composed_helper(&bp_gens, vec![yuan(3), yuan(2)], vec![yuan(4), yuan(1)], 5).is_err());
+}
+
+// zero-padded outputs skip the range proof
+#[test]
+fn spacesuit_zero_outputs() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let inputs = vec![yuan(3), yuan(2)];
+    let outputs = vec![yuan(5), zero(), zero()];
+
+    let full_size = zero_outputs_helper(&bp_gens, inputs.clone(), outputs.clone(), &[]).unwrap();
+    let short_size =
+        zero_outputs_helper(&bp_gens, inputs.clone(), outputs.clone(), &[1, 2]).unwrap();
+    assert!(short_size < full_size);
+
+    // Non-zero outputs cannot be flagged as zero.
+    assert!(zero_outputs_helper(&bp_gens, inputs.clone(), outputs.clone(), &[0]).is_err());
+    // Out of bounds indices are rejected.
+    assert!(zero_outputs_helper(&bp_gens, inputs, outputs, &[3]).is_err());
+}
+
+// gadgets work with any Pedersen generators shared by the prover and the verifier
+#[test]
+fn spacesuit_custom_gens() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let default_gens = PedersenGens::default();
+    let pc_gens = PedersenGens {
+        B: default_gens.B * Scalar::from(3u64),
+        B_blinding: default_gens.B_blinding * Scalar::from(5u64),
+    };
+    let mut rng = rand::thread_rng();
+    let (proof, in_com, out_com) =
+        prove(&bp_gens, &pc_gens, &vec![yuan(3)], &vec![yuan(3)], &mut rng).unwrap();
+
+    assert!(verify(&bp_gens, &pc_gens, &proof, &in_com, &out_com).is_ok());
+    assert!(verify(&bp_gens, &default_gens, &proof, &in_com, &out_com).is_err());
+}
+
 // max(m, n) = 2
 #[test]
 fn spacesuit_uneven_2() {
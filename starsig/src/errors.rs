@@ -2,7 +2,7 @@ use thiserror::Error;
 /// Represents an error in key aggregation, signing, or verification.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
 pub enum StarsigError {
-    /// This error occurs when a point is not a valid compressed Ristretto point
+    /// This error occurs when a signature is malformed or fails to verify
     #[error("Signature verification failed")]
     InvalidSignature,
 
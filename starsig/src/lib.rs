@@ -14,6 +14,6 @@ mod tests;
 
 pub use self::batch::{BatchVerification, BatchVerifier, SingleVerifier};
 pub use self::errors::StarsigError;
-pub use self::key::{SigningKey, VerificationKey};
+pub use self::key::{SigningKey, VerificationKey, VerificationKeySet};
 pub use self::signature::Signature;
 pub use self::transcript::TranscriptProtocol;
@@ -127,6 +127,31 @@ impl Signature {
         )
     }
 
+    /// Signs data committed incrementally by a user-provided closure.
+    /// Internally it creates a Transcript instance labelled "Starsig.sign_streaming"
+    /// and lets the closure append arbitrary framed data to it (e.g. a large message in chunks),
+    /// so the message does not need to be materialized in memory.
+    pub fn sign_streaming(privkey: Scalar, feed: impl FnOnce(&mut Transcript)) -> Signature {
+        Self::sign(&mut Self::transcript_for_stream(feed), privkey)
+    }
+
+    /// Verifies the signature over data committed by a user-provided closure.
+    /// The closure must append exactly the same data as it did in the `sign_streaming` call
+    /// that created the signature.
+    pub fn verify_streaming(
+        &self,
+        pubkey: VerificationKey,
+        feed: impl FnOnce(&mut Transcript),
+    ) -> Result<(), StarsigError> {
+        self.verify(&mut Self::transcript_for_stream(feed), pubkey)
+    }
+
+    fn transcript_for_stream(feed: impl FnOnce(&mut Transcript)) -> Transcript {
+        let mut t = Transcript::new(b"Starsig.sign_streaming");
+        feed(&mut t);
+        t
+    }
+
     fn transcript_for_message(label: &'static [u8], message: &[u8]) -> Transcript {
         let mut t = Transcript::new(b"Starsig.sign_message");
         t.append_message(label, message);
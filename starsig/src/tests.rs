@@ -1,6 +1,9 @@
-use super::{BatchVerifier, Signature, StarsigError, VerificationKey};
+use super::{
+    BatchVerifier, Signature, StarsigError, TranscriptProtocol, VerificationKey, VerificationKeySet,
+};
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
+use subtle::ConstantTimeEq;
 
 #[test]
 fn sign_and_verify_single() {
@@ -133,3 +136,93 @@ fn sign_and_verify_batch() {
 
     assert_eq!(bad_batch.verify(), Err(StarsigError::InvalidBatch));
 }
+
+#[test]
+fn domain_versions_are_separated() {
+    let challenge = |label: &'static [u8], version: u64| {
+        let mut t = Transcript::new(b"example transcript");
+        t.append_domain_version(label, version);
+        t.append_message(b"msg", b"hello");
+        t.challenge_scalar(b"c")
+    };
+    assert_eq!(challenge(b"protocol", 1), challenge(b"protocol", 1));
+    assert_ne!(challenge(b"protocol", 1), challenge(b"protocol", 2));
+    assert_ne!(challenge(b"protocol", 1), challenge(b"other", 1));
+}
+
+#[test]
+fn errors_implement_std_error() {
+    let errors: Vec<Box<dyn std::error::Error>> = vec![
+        Box::new(StarsigError::InvalidSignature),
+        Box::new(StarsigError::InvalidBatch),
+    ];
+    let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Signature verification failed",
+            "Batch signature verification failed"
+        ]
+    );
+}
+
+#[test]
+fn batch_from_secrets() {
+    let privkeys: Vec<Scalar> = (0u64..20).map(Scalar::from).collect();
+    let pubkeys = VerificationKey::batch_from_secrets(&privkeys);
+    assert_eq!(pubkeys.len(), privkeys.len());
+    for (privkey, pubkey) in privkeys.iter().zip(pubkeys.iter()) {
+        assert_eq!(pubkey, &VerificationKey::from_secret(privkey));
+    }
+    assert!(VerificationKey::batch_from_secrets(&[]).is_empty());
+}
+
+#[test]
+fn sign_and_verify_streaming() {
+    let privkey = Scalar::from(42u64);
+    let X = VerificationKey::from_secret(&privkey);
+    let chunks: Vec<Vec<u8>> = (0u8..10).map(|i| vec![i; 1000]).collect();
+    let feed = |t: &mut Transcript| {
+        for chunk in chunks.iter() {
+            t.append_message(b"chunk", chunk);
+        }
+    };
+
+    let sig = Signature::sign_streaming(privkey, feed);
+    assert!(sig.verify_streaming(X, feed).is_ok());
+
+    // Different chunks are rejected.
+    assert!(sig
+        .verify_streaming(X, |t| t.append_message(b"chunk", &chunks.concat()))
+        .is_err());
+    // Streaming signatures are domain-separated from the message signatures.
+    assert!(sig.verify_message(b"chunk", &chunks.concat(), X).is_err());
+}
+
+#[test]
+fn verification_key_set() {
+    let keys = VerificationKey::batch_from_secrets(&[
+        Scalar::from(1u64),
+        Scalar::from(2u64),
+        Scalar::from(3u64),
+    ]);
+    let other = VerificationKey::from_secret(&Scalar::from(4u64));
+
+    assert!(bool::from(keys[0].ct_eq(&keys[0])));
+    assert!(!bool::from(keys[0].ct_eq(&keys[1])));
+
+    let mut set: VerificationKeySet = keys.iter().copied().collect();
+    assert_eq!(set.len(), 3);
+    for (i, key) in keys.iter().enumerate() {
+        assert!(set.contains(key));
+        assert_eq!(set.position(key), Some(i));
+    }
+    assert!(!set.contains(&other));
+    assert_eq!(set.position(&other), None);
+
+    assert!(!set.insert(keys[1]));
+    assert!(set.insert(other));
+    assert_eq!(set.position(&other), Some(3));
+    assert!(set.iter().eq(keys.iter().chain(Some(&other))));
+    assert!(VerificationKeySet::new().is_empty());
+}
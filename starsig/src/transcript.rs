@@ -7,6 +7,9 @@ use merlin::Transcript;
 pub trait TranscriptProtocol {
     /// Commit a domain separator for a single-message signature protocol.
     fn starsig_domain_sep(&mut self);
+    /// Commit a domain separator with the `label` of the protocol and its `version`,
+    /// so that transcripts of different versions of the protocol never collide.
+    fn append_domain_version(&mut self, label: &'static [u8], version: u64);
     /// Commit a `scalar` with the given `label`.
     fn append_scalar(&mut self, label: &'static [u8], scalar: &Scalar);
     /// Commit a `point` with the given `label`.
@@ -20,6 +23,11 @@ impl TranscriptProtocol for Transcript {
         self.append_message(b"dom-sep", b"starsig v1");
     }
 
+    fn append_domain_version(&mut self, label: &'static [u8], version: u64) {
+        self.append_message(b"dom-sep", label);
+        self.append_u64(b"version", version);
+    }
+
     fn append_scalar(&mut self, label: &'static [u8], scalar: &Scalar) {
         self.append_message(label, scalar.as_bytes());
     }
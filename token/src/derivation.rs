@@ -26,6 +26,9 @@ pub trait XpubDerivation {
     /// Derives an Address for a given sequence number.
     fn derive_token(&self, alias: &str) -> Token;
 
+    /// Computes the flavor of the token for a given asset alias without issuing it.
+    fn derive_flavor(&self, alias: &str) -> Scalar;
+
     /// Derives blinding factors for the given value and sequence number.
     /// Q: Why deterministic derivation?
     /// A: Blinding factors are high-entropy, so loss of such data is fatal.
@@ -47,6 +50,10 @@ impl XpubDerivation for Xpub {
         )
     }
 
+    fn derive_flavor(&self, alias: &str) -> Scalar {
+        self.derive_token(alias).flavor()
+    }
+
     fn value_blinding_factor(&self, alias: &str, qty: u64) -> Scalar {
         // Blinding factors are deterministically derived in order to avoid
         // having to backup secret material.
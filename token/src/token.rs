@@ -1,5 +1,5 @@
 use curve25519_dalek::scalar::Scalar;
-use zkvm::{Commitment, Contract, Predicate, Program, String, Value};
+use zkvm::{Commitment, Contract, PortableItem, Predicate, Program, String, VMError, Value};
 
 /// Represents a ZkVM Token with unique flavor and embedded
 /// metadata protected by a user-supplied Predicate.
@@ -18,7 +18,14 @@ impl Token {
         }
     }
 
-    /// Returns the Token's flavor.
+    /// Returns a Token with the same issuance predicate and new metadata.
+    pub fn with_metadata(&self, metadata: Vec<u8>) -> Self {
+        Token::new(self.issuance_predicate.clone(), metadata)
+    }
+
+    /// Returns the Token's flavor, the same one as computed by the `issue` instruction.
+    /// The flavor is a hash of both the issuance predicate and the metadata,
+    /// so any change to the metadata produces an unrelated flavor.
     pub fn flavor(&self) -> Scalar {
         Value::issue_flavor(
             &self.issuance_predicate,
@@ -59,11 +66,45 @@ impl Token {
     pub fn retire<'a>(program: &'a mut Program, prev_output: Contract) -> &'a mut Program {
         program.push(prev_output).input().signtx().retire()
     }
+
+    /// Adds instructions to a program to retire a given UTXO holding a value of this Token
+    /// and issue the same quantity of the Token with the new metadata to a given destination predicate.
+    /// Returns the new Token.
+    ///
+    /// Since the new metadata yields a new flavor (see [`flavor`](Token::flavor)),
+    /// the old and the new units are not interchangeable: the supply of the old flavor
+    /// is reduced by the retired quantity and the supply of the new one is increased by the same quantity.
+    /// The VM does not link the two quantities, so the issued quantity is taken from the witness
+    /// of the retired value: `old_value` must hold a single value of this Token with an open
+    /// quantity commitment (as in the outputs tracked by the prover), otherwise fails with `VMError::BadArguments`.
+    /// Both quantities are visible as commitments in the `retire` and `issue` log entries.
+    pub fn reissue(
+        &self,
+        program: &mut Program,
+        old_value: Contract,
+        new_metadata: Vec<u8>,
+        dest: Predicate,
+    ) -> Result<Token, VMError> {
+        let qty = match old_value.payload.as_slice() {
+            [PortableItem::Value(value)] => match value.assignment() {
+                Some((qty, flv)) if flv == self.flavor() => qty.to_u64(),
+                _ => None,
+            },
+            _ => None,
+        }
+        .ok_or(VMError::BadArguments)?;
+
+        let new_token = self.with_metadata(new_metadata);
+        program.push(old_value).input().signtx().retire();
+        new_token.issue_to(program, qty, dest);
+        Ok(new_token)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::XpubDerivation;
     use bulletproofs::{BulletproofGens, PedersenGens};
     use merlin::Transcript;
     use zkvm::{
@@ -130,6 +171,113 @@ mod tests {
         assert!(tx.verify(&bp_gens).is_ok());
     }
 
+    #[test]
+    fn flavor_matches_issue() {
+        let usd = Token::new(Predicate::with_witness(Scalar::from(1u64)), b"USD".to_vec());
+        let program = Program::build(|p| {
+            add_dummy_input(p, Scalar::from(3u64));
+            usd.issue_to(p, 10u64, Predicate::with_witness(Scalar::from(2u64)));
+        });
+        let (_, _, txlog) = build_tx(program).unwrap();
+
+        let issued_flavor = txlog
+            .iter()
+            .find_map(|entry| match entry {
+                TxEntry::Issue(_, flv) => Some(*flv),
+                _ => None,
+            })
+            .expect("Issue entry must be present");
+        assert_eq!(
+            issued_flavor,
+            Commitment::unblinded(usd.flavor()).to_point()
+        );
+
+        // Flavor computed from the xpub matches the flavor of the derived token.
+        let xprv = keytree::Xprv::random(rand::thread_rng());
+        let xpub = xprv.to_xpub();
+        assert_eq!(xpub.derive_flavor("USD"), xpub.derive_token("USD").flavor());
+        assert_ne!(xpub.derive_flavor("USD"), xpub.derive_flavor("EUR"));
+    }
+
+    #[test]
+    fn reissue() {
+        let issue_key = Scalar::from(1u64);
+        let dest_key = Scalar::from(2u64);
+        let dummy_key = Scalar::from(3u64);
+        let usd = Token::new(Predicate::with_witness(issue_key), b"USD".to_vec());
+        let dest = Predicate::with_witness(dest_key);
+
+        let issue_program = Program::build(|p| {
+            add_dummy_input(p, dummy_key);
+            usd.issue_to(p, 10u64, dest.clone());
+        });
+        let (_, _, issue_txlog) = build_tx(issue_program).unwrap();
+        let issue_output = issue_txlog.outputs().next().unwrap().clone();
+
+        let mut reissue_program = Program::new();
+        let usd2 = usd
+            .reissue(&mut reissue_program, issue_output, b"USD v2".to_vec(), dest)
+            .unwrap();
+        assert_ne!(usd.flavor(), usd2.flavor());
+        assert_eq!(usd2, usd.with_metadata(b"USD v2".to_vec()));
+
+        let (tx, _, txlog) = build_tx(reissue_program).unwrap();
+        let bp_gens = BulletproofGens::new(256, 1);
+        assert!(tx.verify(&bp_gens).is_ok());
+
+        // Old flavor is retired and the new flavor is issued.
+        let flavors: Vec<_> = txlog
+            .iter()
+            .filter_map(|entry| match entry {
+                TxEntry::Retire(_, flv) => Some(("retire", *flv)),
+                TxEntry::Issue(_, flv) => Some(("issue", *flv)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            flavors,
+            vec![
+                ("retire", Commitment::unblinded(usd.flavor()).to_point()),
+                ("issue", Commitment::unblinded(usd2.flavor()).to_point()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reissue_requires_known_qty() {
+        let usd = Token::new(Predicate::with_witness(Scalar::from(1u64)), b"USD".to_vec());
+        let eur = Token::new(Predicate::with_witness(Scalar::from(1u64)), b"EUR".to_vec());
+        let dest = Predicate::with_witness(Scalar::from(2u64));
+
+        let issue_program = Program::build(|p| {
+            add_dummy_input(p, Scalar::from(3u64));
+            usd.issue_to(p, 10u64, dest.clone());
+        });
+        let (_, _, issue_txlog) = build_tx(issue_program).unwrap();
+        let issue_output = issue_txlog.outputs().next().unwrap().clone();
+
+        // Value of another flavor cannot be reissued as this token.
+        assert_eq!(
+            eur.reissue(
+                &mut Program::new(),
+                issue_output.clone(),
+                b"EUR v2".to_vec(),
+                dest.clone()
+            ),
+            Err(VMError::BadArguments)
+        );
+
+        // Quantity hidden in a closed commitment cannot be reissued.
+        let mut closed_output = issue_output;
+        if let [PortableItem::Value(value)] = closed_output.payload.as_mut_slice() {
+            value.qty = Commitment::Closed(value.qty.to_point());
+        }
+        assert_eq!(
+            usd.reissue(&mut Program::new(), closed_output, b"USD v2".to_vec(), dest),
+            Err(VMError::BadArguments)
+        );
+    }
+
     // Helper functions
     fn build_tx(program: Program) -> Result<(Tx, TxID, TxLog), VMError> {
         let bp_gens = BulletproofGens::new(256, 1);
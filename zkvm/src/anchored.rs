@@ -0,0 +1,92 @@
+use crate::contract::{Anchor, Contract, ContractID, PortableItem};
+use crate::errors::VMError;
+use crate::predicate::Predicate;
+use crate::program::Program;
+use crate::types::Value;
+
+/// A program builder that follows the anchor of the VM,
+/// so that the IDs of the created contracts are known while the program is being built.
+///
+/// The VM sets the anchor when a contract is spent with `input`,
+/// and each contract created with `output` (as well as `contract` and `issue`)
+/// consumes the current anchor and replaces it with its own ID.
+/// Instructions added via [`program`](AnchoredProgram::program) are not tracked,
+/// so they must not create contracts.
+///
+/// Example:
+/// ```ascii
+/// let mut builder = AnchoredProgram::new();
+/// builder.add_input(prev_contract);
+/// builder.program().signtx()...cloak(1, 2);
+/// let id1 = builder.add_output(value1, pred1)?;
+/// let id2 = builder.add_output(value2, pred2)?;
+/// let program = builder.into_program();
+/// ```
+#[derive(Clone)]
+pub struct AnchoredProgram {
+    program: Program,
+    anchor: Option<Anchor>,
+}
+
+impl AnchoredProgram {
+    /// Creates an empty program without an anchor.
+    pub fn new() -> Self {
+        AnchoredProgram {
+            program: Program::new(),
+            anchor: None,
+        }
+    }
+
+    /// Returns the anchor that will be used by the next created contract.
+    pub fn anchor(&self) -> Option<Anchor> {
+        self.anchor
+    }
+
+    /// Provides access to the underlying program to add instructions that do not create contracts.
+    pub fn program(&mut self) -> &mut Program {
+        &mut self.program
+    }
+
+    /// Adds `push:contract input` instructions that spend the contract.
+    /// Returns the ID of the spent contract.
+    pub fn add_input(&mut self, contract: Contract) -> ContractID {
+        let id = contract.id();
+        self.program.push(contract).input();
+        self.anchor = Some(id.to_anchor().ratchet());
+        id
+    }
+
+    /// Adds `push:predicate output:1` instructions that lock the value
+    /// on top of the stack with the predicate.
+    /// The value must have the same commitments as the value on the stack
+    /// (e.g. the ones pushed for the `cloak` instruction).
+    /// Returns the ID of the created contract, or `VMError::AnchorMissing` if there is no anchor.
+    pub fn add_output(
+        &mut self,
+        value: Value,
+        predicate: Predicate,
+    ) -> Result<ContractID, VMError> {
+        self.add_output_with_payload(vec![PortableItem::Value(value)], predicate)
+    }
+
+    /// Adds `push:predicate output:k` instructions that lock `k` items
+    /// on top of the stack with the predicate.
+    /// The payload must be equal to the items on the stack, listed from the deepest to the topmost one.
+    /// Returns the ID of the created contract, or `VMError::AnchorMissing` if there is no anchor.
+    pub fn add_output_with_payload(
+        &mut self,
+        payload: Vec<PortableItem>,
+        predicate: Predicate,
+    ) -> Result<ContractID, VMError> {
+        let anchor = self.anchor.ok_or(VMError::AnchorMissing)?;
+        let id = ContractID::compute(&predicate, &payload, &anchor);
+        self.program.push(predicate).output(payload.len());
+        self.anchor = Some(id.to_anchor());
+        Ok(id)
+    }
+
+    /// Returns the built program.
+    pub fn into_program(self) -> Program {
+        self.program
+    }
+}
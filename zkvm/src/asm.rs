@@ -0,0 +1,488 @@
+//! Human-readable assembly for programs.
+//!
+//! Each instruction is written on its own line:
+//! * Instructions without immediate data are written as their names: `commit`, `range`, `signtx`.
+//! * Immediate integers follow the name: `roll 2`, `cloak 3 2`, `output 1`.
+//! * Pushed strings are hex-encoded: `push 0x01ab`.
+//! * Nested programs are written in braces and indented:
+//!   ```ascii
+//!   program {
+//!       push 0x01ab
+//!       signtx
+//!   }
+//!   ```
+//!   If the nested bytecode cannot be parsed, or the programs are nested deeper than `DEFAULT_MAX_DEPTH`,
+//!   it is written as a hex string: `program 0x01ab`.
+//! * Unassigned opcodes are written as `ext 0x42`.
+//!
+//! The assembler accepts the same syntax, with a few additions for writing programs by hand:
+//! * Instructions can be separated by any whitespace or `;`: `push 0x01ab; commit; range`.
+//! * Pushed strings can be quoted: `push "hello"`. Quoted strings support escape sequences `\"`, `\\`, `\n` and `\xNN`.
+//! * Integers can be written in decimal or hex: `roll 2`, `ext 0x42`.
+//! * `#` starts a comment until the end of the line.
+//!
+//! Programs in braces can be nested up to `DEFAULT_MAX_DEPTH` levels.
+
+use core::iter::Peekable;
+use core::str::CharIndices;
+use std::fmt;
+
+use crate::encoding::Encodable;
+use crate::errors::AssembleError;
+use crate::ops::{Instruction, Opcode};
+use crate::program::{Program, ProgramItem};
+use crate::types::String;
+use crate::vm::DEFAULT_MAX_DEPTH;
+
+const INDENT: &str = "    ";
+
+type StdString = std::string::String;
+
+enum Token<'a> {
+    Word(&'a str),
+    Quoted(Vec<u8>),
+    Open,
+    Close,
+}
+
+impl Program {
+    /// Renders the program as human-readable assembly, one instruction per line.
+    /// Nested programs are disassembled recursively and indented
+    /// up to `DEFAULT_MAX_DEPTH` levels, and the deeper ones are written as bytecode.
+    pub fn disassemble(&self) -> StdString {
+        let mut lines = Vec::new();
+        disassemble_into(self, &mut StdString::new(), &mut lines);
+        lines.join("\n")
+    }
+
+    /// Parses the program from the assembly text.
+    /// Nested programs are assembled into bytecode (the verifier's view),
+    /// so that disassembling and assembling a parsed program reproduces it exactly.
+    pub fn assemble(src: &str) -> Result<Program, AssembleError> {
+        let mut tokens = tokenize(src)?.into_iter();
+        assemble_block(&mut tokens, 0)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.disassemble())
+    }
+}
+
+/// Disassembles the instructions into lines prefixed with the `indent`,
+/// which is one `INDENT` per nesting level.
+fn disassemble_into(program: &[Instruction], indent: &mut StdString, lines: &mut Vec<StdString>) {
+    let too_deep = indent.len() >= DEFAULT_MAX_DEPTH * INDENT.len();
+    for instr in program.iter() {
+        match instr {
+            // Deeper programs are not parsed, which also bounds the repeated parsing
+            // of the nested bytecode.
+            Instruction::Program(item) if too_deep => lines.push(format!(
+                "{}program 0x{}",
+                indent,
+                hex::encode(item.to_bytes())
+            )),
+            Instruction::Program(ProgramItem::Program(subprog)) => {
+                disassemble_subprogram(subprog, indent, lines)
+            }
+            Instruction::Program(ProgramItem::Bytecode(bytes)) => match Program::parse(bytes) {
+                Ok(subprog) => disassemble_subprogram(&subprog, indent, lines),
+                Err(_) => lines.push(format!("{}program 0x{}", indent, hex::encode(bytes))),
+            },
+            Instruction::Push(string) => lines.push(format!(
+                "{}push 0x{}",
+                indent,
+                hex::encode(string.encode_to_vec())
+            )),
+            Instruction::Dup(k) | Instruction::Roll(k) => {
+                lines.push(format!("{}{} {}", indent, instr.name(), k))
+            }
+            Instruction::Output(k) | Instruction::Contract(k) => {
+                lines.push(format!("{}{} {}", indent, instr.name(), k))
+            }
+            Instruction::Cloak(m, n) => lines.push(format!("{}cloak {} {}", indent, m, n)),
+            Instruction::Ext(byte) => lines.push(format!("{}ext 0x{:02x}", indent, byte)),
+            _ => lines.push(format!("{}{}", indent, instr.name())),
+        }
+    }
+}
+
+fn disassemble_subprogram(subprog: &Program, indent: &mut StdString, lines: &mut Vec<StdString>) {
+    lines.push(format!("{}program {{", indent));
+    indent.push_str(INDENT);
+    disassemble_into(subprog, indent, lines);
+    indent.truncate(indent.len() - INDENT.len());
+    lines.push(format!("{}}}", indent));
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token<'_>>, AssembleError> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() || c == ';' => {}
+            '#' => {
+                while let Some((_, c)) = chars.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => tokens.push(Token::Quoted(read_quoted(&mut chars)?)),
+            _ => {
+                let mut end = i + c.len_utf8();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() || ";{}\"#".contains(c) {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(&src[i..end]));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads the quoted string after the opening quote.
+fn read_quoted(chars: &mut Peekable<CharIndices<'_>>) -> Result<Vec<u8>, AssembleError> {
+    let mut bytes = Vec::new();
+    let mut next_char = || {
+        chars
+            .next()
+            .map(|(_, c)| c)
+            .ok_or(AssembleError::InvalidQuotedString)
+    };
+    loop {
+        match next_char()? {
+            '"' => return Ok(bytes),
+            '\\' => match next_char()? {
+                c @ '"' | c @ '\\' => bytes.push(c as u8),
+                'n' => bytes.push(b'\n'),
+                'x' => {
+                    let hi = next_char()?.to_digit(16);
+                    let lo = next_char()?.to_digit(16);
+                    match (hi, lo) {
+                        (Some(hi), Some(lo)) => bytes.push((hi * 16 + lo) as u8),
+                        _ => return Err(AssembleError::InvalidQuotedString),
+                    }
+                }
+                _ => return Err(AssembleError::InvalidQuotedString),
+            },
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes()),
+        }
+    }
+}
+
+/// Assembles the instructions until the end of the block at a given nesting depth
+/// (zero for the top-level program).
+fn assemble_block<'a, I>(tokens: &mut I, depth: usize) -> Result<Program, AssembleError>
+where
+    I: Iterator<Item = Token<'a>>,
+{
+    let nested = depth > 0;
+    let mut instructions = Vec::new();
+    loop {
+        let name = match tokens.next() {
+            None if nested => return Err(AssembleError::UnclosedProgram),
+            None => break,
+            Some(Token::Close) if nested => break,
+            Some(Token::Word(name)) => name,
+            Some(token) => return Err(AssembleError::UnexpectedToken(token.to_string())),
+        };
+        let instr = match name {
+            "push" => Instruction::Push(String::Opaque(match tokens.next() {
+                Some(Token::Word(word)) => decode_hex("push", word)?,
+                Some(Token::Quoted(bytes)) => bytes,
+                Some(token) => {
+                    return Err(AssembleError::InvalidArgument("push", token.to_string()))
+                }
+                None => return Err(AssembleError::MissingArgument("push")),
+            })),
+            "program" => Instruction::Program(ProgramItem::Bytecode(match tokens.next() {
+                Some(Token::Open) if depth >= DEFAULT_MAX_DEPTH => {
+                    return Err(AssembleError::NestingTooDeep)
+                }
+                Some(Token::Open) => assemble_block(tokens, depth + 1)?.to_bytes(),
+                Some(Token::Word(word)) => decode_hex("program", word)?,
+                Some(token) => {
+                    return Err(AssembleError::InvalidArgument("program", token.to_string()))
+                }
+                None => return Err(AssembleError::MissingArgument("program")),
+            })),
+            "dup" => Instruction::Dup(read_int(tokens, "dup")?),
+            "roll" => Instruction::Roll(read_int(tokens, "roll")?),
+            "cloak" => {
+                let m = read_int(tokens, "cloak")?;
+                let n = read_int(tokens, "cloak")?;
+                Instruction::Cloak(m, n)
+            }
+            "output" => Instruction::Output(read_int(tokens, "output")?),
+            "contract" => Instruction::Contract(read_int(tokens, "contract")?),
+            "ext" => {
+                let code = read_int(tokens, "ext")?;
+                // Assigned opcodes must be written with their names.
+                if code > 0xff || Opcode::from_u8(code as u8).is_some() {
+                    return Err(AssembleError::InvalidArgument("ext", code.to_string()));
+                }
+                Instruction::Ext(code as u8)
+            }
+            name => simple_instruction(name)
+                .ok_or_else(|| AssembleError::UnknownInstruction(name.to_string()))?,
+        };
+        instructions.push(instr);
+    }
+    Ok(Program::from_vec(instructions))
+}
+
+/// Reads an integer argument that fits in LE32.
+fn read_int<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+    name: &'static str,
+) -> Result<usize, AssembleError> {
+    match tokens.next() {
+        Some(Token::Word(word)) => {
+            let int = if word.starts_with("0x") {
+                u32::from_str_radix(&word[2..], 16)
+            } else {
+                word.parse::<u32>()
+            };
+            int.map(|int| int as usize)
+                .map_err(|_| AssembleError::InvalidArgument(name, word.to_string()))
+        }
+        Some(token) => Err(AssembleError::InvalidArgument(name, token.to_string())),
+        None => Err(AssembleError::MissingArgument(name)),
+    }
+}
+
+fn decode_hex(name: &'static str, word: &str) -> Result<Vec<u8>, AssembleError> {
+    if !word.starts_with("0x") {
+        return Err(AssembleError::InvalidArgument(name, word.to_string()));
+    }
+    hex::decode(&word[2..]).map_err(|_| AssembleError::InvalidArgument(name, word.to_string()))
+}
+
+/// Instruction without immediate data with the given name.
+fn simple_instruction(name: &str) -> Option<Instruction> {
+    let instr = match name {
+        "drop" => Instruction::Drop,
+        "scalar" => Instruction::Scalar,
+        "commit" => Instruction::Commit,
+        "alloc" => Instruction::Alloc(None),
+        "mintime" => Instruction::Mintime,
+        "maxtime" => Instruction::Maxtime,
+        "expr" => Instruction::Expr,
+        "neg" => Instruction::Neg,
+        "add" => Instruction::Add,
+        "mul" => Instruction::Mul,
+        "eq" => Instruction::Eq,
+        "range" => Instruction::Range,
+        "and" => Instruction::And,
+        "or" => Instruction::Or,
+        "not" => Instruction::Not,
+        "verify" => Instruction::Verify,
+        "unblind" => Instruction::Unblind,
+        "issue" => Instruction::Issue,
+        "borrow" => Instruction::Borrow,
+        "retire" => Instruction::Retire,
+        "fee" => Instruction::Fee,
+        "input" => Instruction::Input,
+        "log" => Instruction::Log,
+        "eval" => Instruction::Eval,
+        "call" => Instruction::Call,
+        "signtx" => Instruction::Signtx,
+        "signid" => Instruction::Signid,
+        "signtag" => Instruction::Signtag,
+        "reblind" => Instruction::Reblind,
+        _ => return None,
+    };
+    Some(instr)
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => f.write_str(word),
+            Token::Quoted(bytes) => write!(f, "\"{}\"", StdString::from_utf8_lossy(bytes)),
+            Token::Open => f.write_str("{"),
+            Token::Close => f.write_str("}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_program() -> Program {
+        Program::build(|p| {
+            p.push(String::Opaque(vec![0x01, 0xab]))
+                .program(Program::build(|p| {
+                    p.push(String::Opaque(vec![]))
+                        .program(Program::new())
+                        .signtx();
+                }))
+                .roll(2)
+                .cloak(3, 2)
+                .output(1);
+        })
+    }
+
+    #[test]
+    fn disassemble_program() {
+        let program = test_program();
+        let mut bytecode = program.to_bytes();
+        bytecode.push(0xff);
+
+        let expected = "push 0x01ab
+program {
+    push 0x
+    program {
+    }
+    signtx
+}
+roll 2
+cloak 3 2
+output 1
+ext 0xff";
+        assert_eq!(Program::parse(&bytecode).unwrap().disassemble(), expected);
+        // Prover's view of the nested programs is rendered the same way as the parsed bytecode.
+        assert_eq!(format!("{}\next 0xff", program), expected);
+
+        let truncated = Program::build(|p| {
+            p.program(ProgramItem::Bytecode(vec![0x00, 0x01]));
+        });
+        assert_eq!(truncated.disassemble(), "program 0x0001");
+    }
+
+    #[test]
+    fn assemble_program() {
+        let program = Program::assemble("push 0x01ab; commit; range").unwrap();
+        let expected = Program::build(|p| {
+            p.push(String::Opaque(vec![0x01, 0xab])).commit().range();
+        });
+        assert_eq!(program, expected);
+
+        let program = Program::assemble(
+            r#"
+            # comments and quoted strings
+            push "a \"b\" \\ \x00\n"
+            program {} ext 0xff ext 254
+            "#,
+        )
+        .unwrap();
+        let expected = Program::from_vec(vec![
+            Instruction::Push(String::Opaque(b"a \"b\" \\ \x00\n".to_vec())),
+            Instruction::Program(ProgramItem::Bytecode(vec![])),
+            Instruction::Ext(0xff),
+            Instruction::Ext(0xfe),
+        ]);
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn assembly_roundtrip() {
+        let mut bytecode = test_program().to_bytes();
+        bytecode.push(0xff);
+        let program = Program::parse(&bytecode).unwrap();
+        assert_eq!(Program::assemble(&program.disassemble()).unwrap(), program);
+
+        // Prover's view is reproduced up to the bytecode.
+        let program = test_program();
+        assert_eq!(
+            Program::assemble(&program.disassemble())
+                .unwrap()
+                .to_bytes(),
+            program.to_bytes()
+        );
+    }
+
+    #[test]
+    fn assembly_errors() {
+        let err = |src: &str| Program::assemble(src).unwrap_err();
+        assert_eq!(
+            err("push 0x00; foo"),
+            AssembleError::UnknownInstruction("foo".to_string())
+        );
+        assert_eq!(err("roll"), AssembleError::MissingArgument("roll"));
+        assert_eq!(
+            err("roll x"),
+            AssembleError::InvalidArgument("roll", "x".to_string())
+        );
+        assert_eq!(
+            err("push 01ab"),
+            AssembleError::InvalidArgument("push", "01ab".to_string())
+        );
+        assert_eq!(
+            err("ext 0x00"),
+            AssembleError::InvalidArgument("ext", "0".to_string())
+        );
+        assert_eq!(err("program { drop"), AssembleError::UnclosedProgram);
+        assert_eq!(
+            err("drop }"),
+            AssembleError::UnexpectedToken("}".to_string())
+        );
+        assert_eq!(err("push \"abc"), AssembleError::InvalidQuotedString);
+        assert_eq!(err("push \"\\q\""), AssembleError::InvalidQuotedString);
+    }
+
+    #[test]
+    fn nesting_depth_limit() {
+        let nested = |depth: usize| {
+            (0..depth).fold(Program::new(), |program, _| {
+                Program::from_vec(vec![Instruction::Program(ProgramItem::Program(program))])
+            })
+        };
+        let program = nested(DEFAULT_MAX_DEPTH + 2);
+        let asm = program.disassemble();
+        // Programs within the limit are written in braces, and the deeper ones as bytecode.
+        assert_eq!(asm.matches("program {").count(), DEFAULT_MAX_DEPTH);
+        assert_eq!(asm.matches("program 0x").count(), 1);
+        assert_eq!(
+            Program::parse(&program.to_bytes()).unwrap().disassemble(),
+            asm
+        );
+        assert_eq!(
+            Program::assemble(&asm).unwrap().to_bytes(),
+            program.to_bytes()
+        );
+
+        let src = |depth: usize| format!("{}{}", "program {".repeat(depth), "}".repeat(depth));
+        assert_eq!(
+            Program::assemble(&src(DEFAULT_MAX_DEPTH))
+                .unwrap()
+                .to_bytes(),
+            nested(DEFAULT_MAX_DEPTH).to_bytes()
+        );
+        assert_eq!(
+            Program::assemble(&src(DEFAULT_MAX_DEPTH + 1)).unwrap_err(),
+            AssembleError::NestingTooDeep
+        );
+    }
+
+    #[test]
+    fn opcode_names() {
+        for code in 0..=0xffu8 {
+            let op = match Opcode::from_u8(code) {
+                Some(op) => op,
+                None => {
+                    assert_eq!(Instruction::Ext(code).opcode(), None);
+                    assert_eq!(Instruction::Ext(code).name(), "ext");
+                    continue;
+                }
+            };
+            // Every instruction is encoded with the opcode it reports.
+            if let Some(instr) = simple_instruction(op.name()) {
+                assert_eq!(instr.opcode(), Some(op));
+                assert_eq!(instr.name(), op.name());
+                assert_eq!(instr.encode_to_vec(), vec![code]);
+            }
+        }
+        assert_eq!(Instruction::Cloak(2, 3).opcode(), Some(Opcode::Cloak));
+        assert_eq!(Instruction::Cloak(2, 3).name(), "cloak");
+    }
+}
@@ -2,8 +2,9 @@
 //! Commitments, Variables, Expressions and Constraints.
 
 use bulletproofs::{r1cs, r1cs::ConstraintSystem, PedersenGens};
-use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
 use serde::{Deserialize, Serialize};
 use std::iter::FromIterator;
 use std::ops::{Add, Neg};
@@ -12,6 +13,7 @@ use subtle::{ConditionallySelectable, ConstantTimeEq};
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::scalar_witness::ScalarWitness;
+use crate::transcript::TranscriptProtocol;
 
 /// Variable represents a high-level R1CS variable specified by its
 /// Pedersen commitment. In ZkVM variables are actually indices to a list
@@ -85,6 +87,9 @@ pub enum Commitment {
 pub struct CommitmentWitness {
     value: ScalarWitness,
     blinding: Scalar,
+    /// Generators `(B, B_blinding)` if the commitment does not use the default `PedersenGens`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gens: Option<(RistrettoPoint, RistrettoPoint)>,
 }
 
 impl Constraint {
@@ -285,6 +290,7 @@ impl ExactSizeEncodable for Commitment {
 }
 impl Commitment {
     /// Converts a Commitment to a compressed point.
+    /// Open commitments are computed with the generators they were created with.
     pub fn to_point(&self) -> CompressedRistretto {
         match self {
             Commitment::Closed(x) => *x,
@@ -297,6 +303,7 @@ impl Commitment {
         Commitment::Open(Box::new(CommitmentWitness {
             blinding: Scalar::zero(),
             value: x.into(),
+            gens: None,
         }))
     }
 
@@ -305,6 +312,7 @@ impl Commitment {
         Commitment::Open(Box::new(CommitmentWitness {
             blinding: Scalar::random(&mut rand::thread_rng()),
             value: x.into(),
+            gens: None,
         }))
     }
 
@@ -313,6 +321,23 @@ impl Commitment {
         Commitment::Open(Box::new(CommitmentWitness {
             blinding,
             value: x.into(),
+            gens: None,
+        }))
+    }
+
+    /// Creates an open commitment with a specified blinding factor and custom generators.
+    /// The commitment can only be used in a transaction built with the same generators
+    /// (see `Prover::build_tx_with_gens`), and its point is not compatible
+    /// with the commitments to the same value and blinding factor under the default generators.
+    pub fn blinded_with_gens<T: Into<ScalarWitness>>(
+        x: T,
+        blinding: Scalar,
+        gens: &PedersenGens,
+    ) -> Self {
+        Commitment::Open(Box::new(CommitmentWitness {
+            blinding,
+            value: x.into(),
+            gens: Some((gens.B, gens.B_blinding)),
         }))
     }
 
@@ -333,12 +358,113 @@ impl Commitment {
             Commitment::Open(w) => Some(w.value),
         }
     }
+
+    /// Checks that the commitment opens to a given value and blinding factor,
+    /// e.g. when the recipient checks the blinding factors provided by the sender.
+    /// Open commitments compare the secrets directly, closed commitments
+    /// are recomputed from the provided secrets and compared with the stored point.
+    pub fn verify_opening<T: Into<ScalarWitness>>(&self, value: T, blinding: Scalar) -> bool {
+        self.verify_opening_with_gens(value, blinding, &PedersenGens::default())
+    }
+
+    /// Checks that the commitment opens to a given value and blinding factor
+    /// under the given generators. Open commitments must also have been created with these generators.
+    pub fn verify_opening_with_gens<T: Into<ScalarWitness>>(
+        &self,
+        value: T,
+        blinding: Scalar,
+        gens: &PedersenGens,
+    ) -> bool {
+        let value = value.into();
+        match self {
+            Commitment::Open(w) => {
+                w.uses_gens(gens)
+                    && w.value.to_scalar() == value.to_scalar()
+                    && w.blinding == blinding
+            }
+            Commitment::Closed(point) => {
+                Commitment::blinded_with_gens(value, blinding, gens).to_point() == *point
+            }
+        }
+    }
+
+    /// Returns false if the commitment is open and was created with different generators.
+    /// Closed commitments do not carry the generators, so they are assumed to match.
+    pub(crate) fn uses_gens(&self, gens: &PedersenGens) -> bool {
+        match self {
+            Commitment::Closed(_) => true,
+            Commitment::Open(w) => w.uses_gens(gens),
+        }
+    }
+
+    /// Creates a commitment to the same value with a new blinding factor,
+    /// and a 64-byte reblinding proof for the `reblind` instruction.
+    /// Returns an error if the witness is missing.
+    pub fn reblind(&self, new_blinding: Scalar) -> Result<(Commitment, Vec<u8>), VMError> {
+        let (value, blinding) = self.witness().ok_or(VMError::WitnessMissing)?;
+        let gens = match self {
+            Commitment::Open(w) => w.pedersen_gens(),
+            Commitment::Closed(_) => PedersenGens::default(),
+        };
+        let new_commitment = Commitment::blinded_with_gens(value, new_blinding, &gens);
+        let v1 = self.to_point();
+        let v2 = new_commitment.to_point();
+
+        // Schnorr proof of knowledge of `dlog(V2 - V1)` w.r.t. the blinding base point B2:
+        // s·B2 == R + e·(V2 - V1)
+        let diff = new_blinding - blinding;
+        let mut t = Transcript::new(b"ZkVM.reblind");
+        t.commit_point(b"V1", &v1);
+        t.commit_point(b"V2", &v2);
+        let mut rng = t
+            .build_rng()
+            .rekey_with_witness_bytes(b"diff", diff.as_bytes())
+            .finalize(&mut rand::thread_rng());
+        let k = Scalar::random(&mut rng);
+        let r_point = (k * gens.B_blinding).compress();
+        let e = Self::reblind_challenge(&v1, &v2, &r_point);
+        let s = k + e * diff;
+
+        let mut proof = Vec::with_capacity(64);
+        proof.extend_from_slice(r_point.as_bytes());
+        proof.extend_from_slice(s.as_bytes());
+        Ok((new_commitment, proof))
+    }
+
+    /// Computes the challenge for the reblinding proof.
+    pub(crate) fn reblind_challenge(
+        v1: &CompressedRistretto,
+        v2: &CompressedRistretto,
+        r: &CompressedRistretto,
+    ) -> Scalar {
+        let mut t = Transcript::new(b"ZkVM.reblind");
+        t.commit_point(b"V1", v1);
+        t.commit_point(b"V2", v2);
+        t.commit_point(b"R", r);
+        t.challenge_scalar(b"e")
+    }
 }
 
 impl CommitmentWitness {
     fn to_point(&self) -> CompressedRistretto {
-        let gens = PedersenGens::default();
-        gens.commit(self.value.into(), self.blinding).compress()
+        self.pedersen_gens()
+            .commit(self.value.into(), self.blinding)
+            .compress()
+    }
+
+    fn pedersen_gens(&self) -> PedersenGens {
+        match self.gens {
+            Some((b, b_blinding)) => PedersenGens {
+                B: b,
+                B_blinding: b_blinding,
+            },
+            None => PedersenGens::default(),
+        }
+    }
+
+    fn uses_gens(&self, gens: &PedersenGens) -> bool {
+        let own = self.pedersen_gens();
+        own.B == gens.B && own.B_blinding == gens.B_blinding
     }
 }
 
@@ -348,6 +474,37 @@ impl Expression {
         Expression::Constant(a.into())
     }
 
+    /// Adds up the expressions, producing the same linear combination as
+    /// the left fold with `+`, but in a single pass: all the variable terms are
+    /// collected in one vector, and the constants are folded into one term at the end.
+    /// Returns a constant expression if all the expressions are constants (zero if there are none).
+    pub fn sum<I: IntoIterator<Item = Expression>>(exprs: I) -> Self {
+        let mut terms = Vec::new();
+        let mut constant: Option<ScalarWitness> = None;
+        let mut assignment = Some(ScalarWitness::from(0u64));
+        let mut is_constant = true;
+        for expr in exprs {
+            match expr {
+                Expression::Constant(c) => {
+                    constant = Some(constant.map(|a| a + c).unwrap_or(c));
+                }
+                Expression::LinearCombination(expr_terms, expr_assignment) => {
+                    is_constant = false;
+                    terms.extend(expr_terms);
+                    assignment = assignment.and_then(|a| expr_assignment.map(|b| a + b));
+                }
+            }
+        }
+        if is_constant {
+            return Expression::Constant(constant.unwrap_or(0u64.into()));
+        }
+        if let Some(c) = constant {
+            terms.push((r1cs::Variable::One(), c.into()));
+            assignment = assignment.map(|a| a + c);
+        }
+        Expression::LinearCombination(terms, assignment)
+    }
+
     /// Multiplies two expressions by constraining them to the left/right wires
     /// of a newly allocated R1CS multiplier, and returns
     /// the output wire wrapped in Expression type.
@@ -504,6 +661,115 @@ mod tests {
     use super::*;
     use merlin::Transcript;
 
+    #[test]
+    fn commitment_opening() {
+        let blinding = Scalar::from(7u64);
+        let open = Commitment::blinded_with_factor(42u64, blinding);
+        let closed = Commitment::Closed(open.to_point());
+
+        for c in [open, closed].iter() {
+            assert!(c.verify_opening(42u64, blinding));
+            assert!(c.verify_opening(Scalar::from(42u64), blinding));
+            assert!(!c.verify_opening(43u64, blinding));
+            assert!(!c.verify_opening(42u64, Scalar::from(8u64)));
+        }
+
+        let default_gens = PedersenGens::default();
+        let gens = PedersenGens {
+            B: default_gens.B_blinding,
+            B_blinding: default_gens.B,
+        };
+        let open = Commitment::blinded_with_gens(42u64, blinding, &gens);
+        let closed = Commitment::Closed(open.to_point());
+        assert_ne!(
+            open.to_point(),
+            Commitment::blinded_with_factor(42u64, blinding).to_point()
+        );
+
+        for c in [open, closed].iter() {
+            assert!(c.verify_opening_with_gens(42u64, blinding, &gens));
+            assert!(!c.verify_opening_with_gens(43u64, blinding, &gens));
+            assert!(!c.verify_opening(42u64, blinding));
+        }
+    }
+
+    #[test]
+    fn expression_sum() {
+        let lc = |var: usize, weight: u64, value: u64| {
+            Expression::LinearCombination(
+                vec![(r1cs::Variable::Committed(var), weight.into())],
+                Some(value.into()),
+            )
+        };
+        let fold = |exprs: Vec<Expression>| {
+            let mut exprs = exprs.into_iter();
+            let first = exprs.next().unwrap();
+            exprs.fold(first, |sum, e| sum + e)
+        };
+        // Sums up the weights per variable to compare equivalent linear combinations.
+        let weights = |expr: &Expression| match expr {
+            Expression::Constant(c) => vec![(r1cs::Variable::One(), c.to_scalar())],
+            Expression::LinearCombination(terms, _) => {
+                let mut weights: Vec<(r1cs::Variable, Scalar)> = Vec::new();
+                for (var, w) in terms {
+                    match weights.iter_mut().find(|(v, _)| v == var) {
+                        Some((_, total)) => *total += w,
+                        None => weights.push((*var, *w)),
+                    }
+                }
+                weights
+            }
+        };
+        let assert_equivalent = |a: Expression, b: Expression| {
+            let (wa, wb) = (weights(&a), weights(&b));
+            assert_eq!(wa.len(), wb.len());
+            assert!(wa.iter().all(|t| wb.contains(t)));
+            assert_eq!(
+                a.eval().map(|x| x.to_scalar()),
+                b.eval().map(|x| x.to_scalar())
+            );
+        };
+
+        // constants only => const
+        let consts = vec![
+            Expression::constant(1u64),
+            Expression::constant(2u64),
+            -Expression::constant(10u64),
+        ];
+        assert_eq!(Expression::sum(consts.clone()), fold(consts));
+        assert_eq!(Expression::sum(vec![]), Expression::constant(0u64));
+
+        // lincombs are concatenated, constants are folded into one term
+        let exprs = vec![
+            Expression::constant(5u64),
+            lc(1, 11, 100),
+            Expression::constant(6u64),
+            lc(2, 22, 42),
+            -lc(1, 1, 7),
+        ];
+        assert_eq!(
+            Expression::sum(exprs.clone()),
+            Expression::LinearCombination(
+                vec![
+                    (r1cs::Variable::Committed(1), 11u64.into()),
+                    (r1cs::Variable::Committed(2), 22u64.into()),
+                    (r1cs::Variable::Committed(1), -Scalar::from(1u64)),
+                    (r1cs::Variable::One(), 11u64.into()),
+                ],
+                Some(146u64.into())
+            )
+        );
+        assert_equivalent(Expression::sum(exprs.clone()), fold(exprs));
+
+        // missing assignment makes the sum unassigned
+        let unassigned = vec![
+            lc(1, 1, 1),
+            Expression::LinearCombination(vec![(r1cs::Variable::Committed(2), 1u64.into())], None),
+        ];
+        assert_eq!(Expression::sum(unassigned.clone()).eval(), None);
+        assert_equivalent(Expression::sum(unassigned.clone()), fold(unassigned));
+    }
+
     #[test]
     fn expression_arithmetic() {
         // const + const => const
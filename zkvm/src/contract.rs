@@ -22,7 +22,7 @@ pub const PROG_TYPE: u8 = 0x01;
 pub const VALUE_TYPE: u8 = 0x02;
 
 /// A unique identifier for an anchor
-#[derive(Clone, Copy, PartialEq, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Anchor(pub [u8; 32]);
 serialize_bytes32!(Anchor);
 
@@ -60,23 +60,59 @@ pub enum PortableItem {
 impl Contract {
     /// Returns the contract's ID
     pub fn id(&self) -> ContractID {
-        let mut t = Transcript::new(b"ZkVM.contractid");
-        self.encode(&mut t)
-            .expect("Writing to Transcript never fails.");
-        ContractID(t.challenge_u8x32(b"id"))
+        ContractID::compute(&self.predicate, &self.payload, &self.anchor)
+    }
+
+    /// Returns the contract's payload items without consuming the contract.
+    pub fn payload(&self) -> &[PortableItem] {
+        &self.payload
+    }
+
+    /// Iterates over the values stored in the payload.
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.payload.iter().filter_map(|item| match item {
+            PortableItem::Value(v) => Some(v),
+            _ => None,
+        })
+    }
+
+    /// Iterates over the strings stored in the payload.
+    pub fn strings(&self) -> impl Iterator<Item = &String> {
+        self.payload.iter().filter_map(|item| match item {
+            PortableItem::String(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    /// Iterates over the programs stored in the payload.
+    pub fn programs(&self) -> impl Iterator<Item = &ProgramItem> {
+        self.payload.iter().filter_map(|item| match item {
+            PortableItem::Program(p) => Some(p),
+            _ => None,
+        })
     }
 }
 
 impl Encodable for Contract {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
-        w.write(b"anchor", &self.anchor.0)?;
-        w.write_point(b"predicate", &self.predicate.to_point())?;
-        w.write_size(b"k", self.payload.len())?;
-        for item in self.payload.iter() {
-            item.encode(w)?;
-        }
-        Ok(())
+        encode_contract(&self.predicate, &self.payload, &self.anchor, w)
+    }
+}
+
+/// Encodes the contract from its components.
+fn encode_contract(
+    predicate: &Predicate,
+    payload: &[PortableItem],
+    anchor: &Anchor,
+    w: &mut impl Writer,
+) -> Result<(), WriteError> {
+    w.write(b"anchor", &anchor.0)?;
+    w.write_point(b"predicate", &predicate.to_point())?;
+    w.write_size(b"k", payload.len())?;
+    for item in payload.iter() {
+        item.encode(w)?;
     }
+    Ok(())
 }
 
 impl ExactSizeEncodable for Contract {
@@ -144,6 +180,15 @@ impl Anchor {
 }
 
 impl ContractID {
+    /// Computes the ID of the contract with the given components
+    /// exactly as the VM does, without constructing the contract.
+    pub fn compute(predicate: &Predicate, payload: &[PortableItem], anchor: &Anchor) -> Self {
+        let mut t = Transcript::new(b"ZkVM.contractid");
+        encode_contract(predicate, payload, anchor, &mut t)
+            .expect("Writing to Transcript never fails.");
+        ContractID(t.challenge_u8x32(b"id"))
+    }
+
     /// Provides a view into the contract ID's bytes.
     pub fn as_bytes(&self) -> &[u8] {
         &self.0
@@ -459,3 +504,33 @@ impl Contract {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use curve25519_dalek::scalar::Scalar;
+
+    #[test]
+    fn payload_inspection() {
+        let value = Value {
+            qty: Commitment::unblinded(10u64),
+            flv: Commitment::unblinded(Scalar::zero()),
+        };
+        let contract = Contract {
+            predicate: Predicate::new(VerificationKey::from_secret(&Scalar::from(1u64))),
+            payload: vec![
+                PortableItem::String(String::Opaque(vec![1, 2, 3])),
+                PortableItem::Value(value.clone()),
+                PortableItem::Program(ProgramItem::Program(Program::new())),
+                PortableItem::Value(value.clone()),
+            ],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+
+        assert_eq!(contract.payload().len(), 4);
+        assert_eq!(contract.values().collect::<Vec<_>>(), vec![&value, &value]);
+        assert_eq!(contract.strings().count(), 1);
+        assert_eq!(contract.programs().count(), 1);
+    }
+}
@@ -98,40 +98,13 @@ impl Instruction {
                 }
             }
             Instruction::Program(subprog) => write!(f, "[{:?}]", subprog),
-            Instruction::Drop => write!(f, "drop"),
             Instruction::Dup(i) => write!(f, "dup:{}", i),
             Instruction::Roll(i) => write!(f, "roll:{}", i),
-            Instruction::Scalar => write!(f, "scalar"),
-            Instruction::Commit => write!(f, "commit"),
-            Instruction::Alloc(_) => write!(f, "alloc"),
-            Instruction::Mintime => write!(f, "mintime"),
-            Instruction::Maxtime => write!(f, "maxtime"),
-            Instruction::Expr => write!(f, "expr"),
-            Instruction::Neg => write!(f, "neg"),
-            Instruction::Add => write!(f, "add"),
-            Instruction::Mul => write!(f, "mul"),
-            Instruction::Eq => write!(f, "eq"),
-            Instruction::Range => write!(f, "range"),
-            Instruction::And => write!(f, "and"),
-            Instruction::Or => write!(f, "or"),
-            Instruction::Not => write!(f, "not"),
-            Instruction::Verify => write!(f, "verify"),
-            Instruction::Unblind => write!(f, "unblind"),
-            Instruction::Issue => write!(f, "issue"),
-            Instruction::Borrow => write!(f, "borrow"),
-            Instruction::Retire => write!(f, "retire"),
             Instruction::Cloak(m, n) => write!(f, "cloak:{}:{}", m, n),
-            Instruction::Fee => write!(f, "fee"),
-            Instruction::Input => write!(f, "input"),
             Instruction::Output(k) => write!(f, "output:{}", k),
             Instruction::Contract(k) => write!(f, "contract:{}", k),
-            Instruction::Log => write!(f, "log"),
-            Instruction::Eval => write!(f, "eval"),
-            Instruction::Call => write!(f, "call"),
-            Instruction::Signtx => write!(f, "signtx"),
-            Instruction::Signid => write!(f, "signid"),
-            Instruction::Signtag => write!(f, "signtag"),
             Instruction::Ext(byte) => write!(f, "ext:{:x}", byte),
+            instr => f.write_str(instr.name()),
         }?;
 
         // If we know that there are more instructions in the program,
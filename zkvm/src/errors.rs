@@ -122,6 +122,15 @@ pub enum VMError {
     #[error("Stack is not cleared by the program")]
     StackNotClean,
 
+    /// This error occurs when VM executes more steps than allowed by its budget.
+    #[error("Execution budget is exceeded")]
+    BudgetExceeded,
+
+    /// This error occurs when the programs are nested too deeply,
+    /// or the call proof's predicate tree is too deep.
+    #[error("Program nesting or predicate tree depth limit is exceeded")]
+    DepthLimitExceeded,
+
     /// This error occurs when VM's anchor remains unset.
     #[error("VM anchor is not set via `input`")]
     AnchorMissing,
@@ -173,4 +182,48 @@ pub enum VMError {
     /// This error occurs when tx attempts to add a fee beyond the limit.
     #[error("Fee is too high")]
     FeeTooHigh,
+
+    /// This error occurs when cleartext values of different flavors are combined.
+    #[error("Values have different flavors")]
+    FlavorMismatch,
+
+    /// This error occurs when the sum of cleartext quantities does not fit in 64 bits.
+    #[error("Value quantity overflow")]
+    ValueOverflow,
+
+    /// This error occurs when a commitment uses different Pedersen generators than the transaction.
+    #[error("Commitment uses different Pedersen generators than the transaction")]
+    GeneratorsMismatch,
+}
+
+/// Represents an error in parsing the program assembly.
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+pub enum AssembleError {
+    /// This error occurs when the instruction name is not recognized.
+    #[error("Unknown instruction `{0}`")]
+    UnknownInstruction(String),
+
+    /// This error occurs when the instruction is missing its immediate argument.
+    #[error("Missing argument for `{0}`")]
+    MissingArgument(&'static str),
+
+    /// This error occurs when the immediate argument is malformed or out of range.
+    #[error("Invalid argument `{1}` for `{0}`")]
+    InvalidArgument(&'static str, String),
+
+    /// This error occurs when a token appears where an instruction is expected.
+    #[error("Unexpected `{0}`")]
+    UnexpectedToken(String),
+
+    /// This error occurs when a nested program is not closed with `}`.
+    #[error("Nested program is not closed")]
+    UnclosedProgram,
+
+    /// This error occurs when a quoted string is not closed or contains an invalid escape sequence.
+    #[error("Invalid quoted string")]
+    InvalidQuotedString,
+
+    /// This error occurs when the nested programs are deeper than `DEFAULT_MAX_DEPTH`.
+    #[error("Nested programs are too deep")]
+    NestingTooDeep,
 }
@@ -3,6 +3,8 @@ use core::cmp::Ordering;
 use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
 
+use crate::errors::VMError;
+
 /// Maximum amount of fee, which allows overflow-safe size-by-fee multiplication.
 pub const MAX_FEE: u64 = 1 << 24;
 
@@ -38,7 +40,39 @@ impl FeeRate {
         }
     }
 
+    /// Creates a new fee rate from a given fee amount and size in bytes,
+    /// e.g. for the fee estimation outside of the transaction verification.
+    /// The fee is capped at `MAX_FEE`, as no transaction can pay more than that,
+    /// which keeps the comparison of fee rates overflow-safe.
+    pub fn from_fee_and_size(fee: u64, size: usize) -> Self {
+        FeeRate {
+            fee: fee.min(MAX_FEE),
+            size: size as u64,
+        }
+    }
+
+    /// Computes the combined fee rate of a package of transactions (e.g. a parent and a child paying for it),
+    /// given the fee and the size of each transaction.
+    /// Fails with `VMError::FeeTooHigh` if the total fee exceeds `MAX_FEE`.
+    pub fn package(txs: impl IntoIterator<Item = (CheckedFee, usize)>) -> Result<Self, VMError> {
+        let mut total_fee = CheckedFee::zero();
+        let mut total_size = 0;
+        for (fee, size) in txs {
+            total_fee = total_fee.combine(fee)?;
+            total_size += size;
+        }
+        Ok(FeeRate::new(total_fee, total_size))
+    }
+
     /// Combines the fee rate with another fee rate, adding up the fees and sizes.
+    /// The result is the fee rate of both transactions (or packages) taken together,
+    /// and does not depend on the order of the arguments.
+    ///
+    /// Note: when computing the effective fee rate of a transaction with its ancestors,
+    /// each ancestor must be combined exactly once. In a "diamond" graph, where two parents
+    /// spend outputs of the same grandparent, combining the parents' effective fee rates
+    /// would count the grandparent twice. The mempool combines the individual fee rates
+    /// over a deduplicated set of ancestors instead.
     pub fn combine(self, other: FeeRate) -> Self {
         FeeRate {
             fee: self.fee + other.fee,
@@ -52,8 +86,14 @@ impl FeeRate {
     }
 
     /// Normalizes feerate by dividing the fee by size rounding it down.
-    /// Yields a fee amount per 1 byte of size.
+    /// Yields a fee amount per 1 byte of size, e.g. to be compared with the minimum fee rate
+    /// or displayed to the user. Normalization preserves the order of fee rates,
+    /// but the fee rates that differ by less than 1 unit per byte may become equal.
+    /// Fee rate with zero size is returned as is.
     pub fn normalize(mut self) -> Self {
+        if self.size == 0 {
+            return self;
+        }
         self.fee /= self.size;
         self.size = 1;
         self
@@ -75,14 +115,26 @@ impl FeeRate {
 
     /// Discounts the fee and the size by a given factor.
     /// E.g. feerate 100/1200 discounted by 2 gives 50/600.
-    /// Same ratio, but lower weight when combined with other feerates.
+    /// Same ratio (up to rounding), but lower weight when combined with other feerates.
+    /// This can be used to split the fee rate of a shared ancestor among its descendants.
+    /// Discounting by 0 or 1 leaves the fee rate unchanged.
     pub fn discount(mut self, parts: usize) -> Self {
-        let parts = parts as u64;
+        let parts = (parts as u64).max(1);
         self.fee /= parts;
         self.size /= parts;
         self
     }
 
+    /// Returns the minimum fee for a transaction (or a package) of a given size
+    /// to have at least this fee rate. Zero fee rate requires zero fee.
+    pub fn fee_for_size(self, size: usize) -> u64 {
+        if self.size == 0 {
+            return 0;
+        }
+        let fee = (self.fee as u128 * size as u128 + self.size as u128 - 1) / self.size as u128;
+        fee.min(u64::max_value() as u128) as u64
+    }
+
     /// Returns the fee component of the feerate.
     pub fn fee(&self) -> u64 {
         self.fee
@@ -116,8 +168,16 @@ impl CheckedFee {
         self.inner += fee;
         Some(self)
     }
+
+    /// Adds up two fees, e.g. of the transactions in a package.
+    /// Fails with `VMError::FeeTooHigh` if the sum exceeds `MAX_FEE`.
+    pub fn combine(self, other: CheckedFee) -> Result<Self, VMError> {
+        self.add(other.inner).ok_or(VMError::FeeTooHigh)
+    }
 }
 
+/// Fee rates are compared by their ratios of fee to size, regardless of the absolute amounts:
+/// 100/1200 is equal to 50/600, and 84/1000 is greater than 100/1200.
 impl PartialEq for FeeRate {
     fn eq(&self, other: &Self) -> bool {
         self.fee * other.size == self.size * other.fee
@@ -136,3 +196,192 @@ impl PartialOrd for FeeRate {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine_fees() {
+        let fee = CheckedFee::new(MAX_FEE - 10).unwrap();
+        assert_eq!(
+            fee.combine(CheckedFee::new(10).unwrap()).unwrap().inner,
+            MAX_FEE
+        );
+        assert_eq!(
+            fee.combine(CheckedFee::new(11).unwrap()).unwrap_err(),
+            VMError::FeeTooHigh
+        );
+    }
+
+    #[test]
+    fn package_feerate() {
+        let parent = (CheckedFee::new(100).unwrap(), 1000);
+        let child = (CheckedFee::new(500).unwrap(), 200);
+        let package = FeeRate::package(vec![parent, child]).unwrap();
+        assert_eq!(package.fee(), 600);
+        assert_eq!(package.size(), 1200);
+        assert_eq!(FeeRate::package(vec![]).unwrap().fee(), 0);
+
+        let max = (CheckedFee::new(MAX_FEE).unwrap(), 100);
+        assert_eq!(
+            FeeRate::package(vec![parent, max]).unwrap_err(),
+            VMError::FeeTooHigh
+        );
+    }
+
+    fn feerate(fee: u64, size: usize) -> FeeRate {
+        FeeRate::new(CheckedFee::new(fee).unwrap(), size)
+    }
+
+    #[test]
+    fn fee_for_size() {
+        assert_eq!(FeeRate::zero().fee_for_size(1000), 0);
+        assert_eq!(feerate(100, 1200).fee_for_size(1200), 100);
+        // Rounded up, so the fee rate is never below the target.
+        assert_eq!(feerate(100, 1200).fee_for_size(1000), 84);
+        assert!(feerate(84, 1000) >= feerate(100, 1200));
+        assert!(feerate(83, 1000) < feerate(100, 1200));
+
+        // Child paying for the parent: package fee rate reaches the target.
+        let parent = feerate(10, 1000);
+        let target = feerate(5, 1);
+        let child_size = 300;
+        let child_fee = target.fee_for_size(parent.size() + child_size) - parent.fee();
+        assert_eq!(parent.combine(feerate(child_fee, child_size)), target);
+    }
+
+    #[test]
+    fn combine_is_commutative() {
+        let rates = [
+            feerate(0, 1),
+            feerate(1, 1),
+            feerate(100, 1200),
+            feerate(500, 200),
+            feerate(MAX_FEE, 3),
+            feerate(7, 1_000_000),
+        ];
+        for a in rates.iter() {
+            for b in rates.iter() {
+                let ab = a.combine(*b);
+                let ba = b.combine(*a);
+                assert_eq!(ab.fee(), ba.fee());
+                assert_eq!(ab.size(), ba.size());
+                assert_eq!(ab.fee(), a.fee() + b.fee());
+                assert_eq!(ab.size(), a.size() + b.size());
+            }
+        }
+    }
+
+    #[test]
+    fn from_fee_and_size() {
+        assert_eq!(FeeRate::from_fee_and_size(100, 1200), feerate(100, 1200));
+        assert_eq!(FeeRate::from_fee_and_size(100, 1200).fee(), 100);
+        assert_eq!(FeeRate::from_fee_and_size(100, 1200).size(), 1200);
+        assert_eq!(
+            FeeRate::from_fee_and_size(u64::max_value(), 1).fee(),
+            MAX_FEE
+        );
+    }
+
+    fn sample_rates() -> Vec<FeeRate> {
+        let mut rates = Vec::new();
+        for &fee in [0, 1, 2, 3, 7, 100, 1000, MAX_FEE - 1, MAX_FEE].iter() {
+            for &size in [1, 2, 3, 10, 999, 1000, 1200, 1_000_000].iter() {
+                rates.push(FeeRate::from_fee_and_size(fee, size));
+            }
+        }
+        rates
+    }
+
+    #[test]
+    fn feerate_ordering() {
+        let rates = sample_rates();
+        for a in rates.iter() {
+            for b in rates.iter() {
+                // Ordering follows the fee per byte.
+                let (x, y) = (
+                    a.fee() as u128 * b.size() as u128,
+                    b.fee() as u128 * a.size() as u128,
+                );
+                assert_eq!(a.cmp(b), x.cmp(&y));
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                assert_eq!(a == b, x == y);
+            }
+            // Scaling both the fee and the size does not change the fee rate.
+            let scaled = FeeRate {
+                fee: a.fee * 3,
+                size: a.size * 3,
+            };
+            assert_eq!(*a, scaled);
+            // Strictly higher fee at the same size compares greater.
+            if a.fee() < MAX_FEE {
+                assert!(FeeRate::from_fee_and_size(a.fee() + 1, a.size()) > *a);
+            }
+        }
+    }
+
+    #[test]
+    fn normalize_preserves_ordering() {
+        let rates = sample_rates();
+        for a in rates.iter() {
+            let n = a.normalize();
+            assert_eq!(n.size(), 1);
+            assert_eq!(n.fee(), a.fee() / a.size() as u64);
+            assert!(n <= *a);
+            for b in rates.iter() {
+                if a <= b {
+                    assert!(a.normalize() <= b.normalize());
+                }
+            }
+        }
+        // Fee rates within 1 unit per byte become equal.
+        assert!(feerate(5, 2) > feerate(4, 2));
+        assert_eq!(feerate(5, 2).normalize(), feerate(4, 2).normalize());
+
+        assert_eq!(FeeRate::zero().normalize().size(), 0);
+    }
+
+    #[test]
+    fn discount_feerate() {
+        let rate = feerate(100, 1200);
+        for parts in 0..2 {
+            let discounted = rate.discount(parts);
+            assert_eq!(discounted.fee(), rate.fee());
+            assert_eq!(discounted.size(), rate.size());
+        }
+        let half = rate.discount(2);
+        assert_eq!(half.fee(), 50);
+        assert_eq!(half.size(), 600);
+        assert_eq!(half, rate);
+    }
+
+    #[test]
+    fn diamond_overcounting() {
+        // Grandparent is spent by two parents, which are both spent by the child.
+        let grandparent = feerate(1000, 100);
+        let parent1 = feerate(10, 100);
+        let parent2 = feerate(10, 100);
+        let child = feerate(10, 100);
+
+        // Correct: every ancestor is counted once.
+        let effective = child.combine(parent1).combine(parent2).combine(grandparent);
+        assert_eq!(effective.fee(), 1030);
+        assert_eq!(effective.size(), 400);
+
+        // Combining effective fee rates of the parents counts the grandparent twice
+        // and overestimates the fee rate of the package.
+        let overcounted = child
+            .combine(parent1.combine(grandparent))
+            .combine(parent2.combine(grandparent));
+        assert_eq!(overcounted.fee(), 2030);
+        assert!(overcounted > effective);
+
+        // Splitting the grandparent between the two parents avoids overcounting.
+        let split = child
+            .combine(parent1.combine(grandparent.discount(2)))
+            .combine(parent2.combine(grandparent.discount(2)));
+        assert_eq!(split.fee(), effective.fee());
+        assert_eq!(split.size(), effective.size());
+    }
+}
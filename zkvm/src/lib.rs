@@ -14,6 +14,8 @@ extern crate serde;
 
 #[macro_use]
 mod serialization;
+mod anchored;
+mod asm;
 mod constraints;
 mod contract;
 mod debug;
@@ -31,19 +33,24 @@ mod types;
 mod verifier;
 mod vm;
 
+pub use self::anchored::AnchoredProgram;
 pub use self::constraints::{Commitment, CommitmentWitness, Constraint, Expression, Variable};
 pub use self::contract::{Anchor, Contract, ContractID, PortableItem};
-pub use self::errors::VMError;
+pub use self::errors::{AssembleError, VMError};
 pub use self::fees::{fee_flavor, CheckedFee, FeeRate, MAX_FEE};
 pub use self::ops::{Instruction, Opcode};
-pub use self::predicate::{Predicate, PredicateTree, PredicateWitness};
+pub use self::predicate::{CallProof, Predicate, PredicateTree, PredicateWitness};
 pub use self::program::{Program, ProgramItem};
 pub use self::prover::Prover;
 pub use self::scalar_witness::ScalarWitness;
 pub use self::transcript::TranscriptProtocol;
-pub use self::tx::{Tx, TxEntry, TxHeader, TxID, TxLog, UnsignedTx, VerifiedTx};
-pub use self::types::{ClearValue, Item, String, Value, WideValue};
-pub use self::verifier::Verifier;
+pub use self::tx::{
+    PrecomputedTx, RetiredValue, Tx, TxEntry, TxHeader, TxID, TxLog, TxStatus, TxSummary,
+    UnsignedTx, VerifiedTx,
+};
+pub use self::types::{group_by_flavor, ClearValue, Item, String, Value, WideValue};
+pub use self::verifier::{DeferredOperations, Verifier};
+pub use self::vm::{DEFAULT_BUDGET, DEFAULT_MAX_DEPTH};
 pub use merkle::{Hash, Hasher, MerkleItem, MerkleTree};
 
-pub use musig::{Multikey, Multisignature, Signature, VerificationKey};
+pub use musig::{Multikey, Multisignature, Signature, StarsigTranscriptProtocol, VerificationKey};
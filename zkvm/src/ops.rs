@@ -501,6 +501,34 @@ pub enum Instruction {
     /// 4. or last item in the `payload` (`tag`) is not a _string_.
     Signtag,
 
+    /// _var1 V2 proof_ **reblind** → _var2_
+    ///
+    /// The instruction is assigned in tx version 2.
+    /// In the earlier versions its opcode is treated as `ext`.
+    ///
+    /// 1. Pops 64-byte _string_ `proof`.
+    /// 2. Pops _point_ `V2`.
+    /// 3. Pops _variable_ `var1` with the commitment `V1`.
+    /// 4. Verifies the _reblinding proof_ showing that `V1` and `V2` commit to the same value
+    ///    with different blinding factors, _deferring all point operations_:
+    ///     ```ascii
+    ///     T = Transcript("ZkVM.reblind")
+    ///     T.append("V1", V1)
+    ///     T.append("V2", V2)
+    ///     T.append("R", R)
+    ///     e = T.challenge_scalar("e")
+    ///     s·B2  ==  R + e·(V2 - V1)
+    ///     ```
+    ///    where `R = proof[0..32]`, `s = proof[32..64]`.
+    /// 5. Pushes _variable_ `var2` with the commitment `V2`.
+    ///
+    /// Fails if:
+    /// 1. tx version is lower than 2 and the extension flag is not set,
+    /// 2. or `proof` is not a 64-byte long _string_,
+    /// 3. or `V2` is not a valid _point_,
+    /// 4. or `var1` is not a _variable_.
+    Reblind,
+
     /// Unassigned opcode.
     Ext(u8),
 }
@@ -578,10 +606,12 @@ pub enum Opcode {
     /// A code for [Instruction::Signid]
     Signid = 0x21,
     /// A code for [Instruction::Signtag]
-    Signtag = MAX_OPCODE,
+    Signtag = 0x22,
+    /// A code for [Instruction::Reblind]
+    Reblind = MAX_OPCODE,
 }
 
-const MAX_OPCODE: u8 = 0x22;
+const MAX_OPCODE: u8 = 0x23;
 
 impl Opcode {
     /// Converts the opcode to `u8`.
@@ -598,74 +628,78 @@ impl Opcode {
             unsafe { mem::transmute(code) }
         }
     }
+
+    /// Returns the assembly name of the opcode, e.g. `"signtx"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::Push => "push",
+            Opcode::Program => "program",
+            Opcode::Drop => "drop",
+            Opcode::Dup => "dup",
+            Opcode::Roll => "roll",
+            Opcode::Scalar => "scalar",
+            Opcode::Commit => "commit",
+            Opcode::Alloc => "alloc",
+            Opcode::Mintime => "mintime",
+            Opcode::Maxtime => "maxtime",
+            Opcode::Expr => "expr",
+            Opcode::Neg => "neg",
+            Opcode::Add => "add",
+            Opcode::Mul => "mul",
+            Opcode::Eq => "eq",
+            Opcode::Range => "range",
+            Opcode::And => "and",
+            Opcode::Or => "or",
+            Opcode::Not => "not",
+            Opcode::Verify => "verify",
+            Opcode::Unblind => "unblind",
+            Opcode::Issue => "issue",
+            Opcode::Borrow => "borrow",
+            Opcode::Retire => "retire",
+            Opcode::Cloak => "cloak",
+            Opcode::Fee => "fee",
+            Opcode::Input => "input",
+            Opcode::Output => "output",
+            Opcode::Contract => "contract",
+            Opcode::Log => "log",
+            Opcode::Eval => "eval",
+            Opcode::Call => "call",
+            Opcode::Signtx => "signtx",
+            Opcode::Signid => "signid",
+            Opcode::Signtag => "signtag",
+            Opcode::Reblind => "reblind",
+        }
+    }
 }
 
 impl Encodable for Instruction {
     /// Appends the bytecode representation of an Instruction
     /// to the program.
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
-        let mut write = |op: Opcode| w.write_u8(b"op", op.to_u8());
+        if let Some(op) = self.opcode() {
+            w.write_u8(b"op", op.to_u8())?;
+        }
         match self {
             Instruction::Push(data) => {
-                write(Opcode::Push)?;
                 w.write_u32(b"n", data.encoded_size() as u32)?;
                 data.encode(w)?;
             }
             Instruction::Program(subprog) => {
-                write(Opcode::Program)?;
                 w.write_u32(b"n", subprog.encoded_size() as u32)?;
                 subprog.encode(w)?;
             }
-            Instruction::Drop => write(Opcode::Drop)?,
-            Instruction::Dup(idx) => {
-                write(Opcode::Dup)?;
-                w.write_u32(b"k", *idx as u32)?;
-            }
-            Instruction::Roll(idx) => {
-                write(Opcode::Roll)?;
+            Instruction::Dup(idx) | Instruction::Roll(idx) => {
                 w.write_u32(b"k", *idx as u32)?;
             }
-            Instruction::Scalar => write(Opcode::Scalar)?,
-            Instruction::Commit => write(Opcode::Commit)?,
-            Instruction::Alloc(_) => write(Opcode::Alloc)?,
-            Instruction::Mintime => write(Opcode::Mintime)?,
-            Instruction::Maxtime => write(Opcode::Maxtime)?,
-            Instruction::Expr => write(Opcode::Expr)?,
-            Instruction::Neg => write(Opcode::Neg)?,
-            Instruction::Add => write(Opcode::Add)?,
-            Instruction::Mul => write(Opcode::Mul)?,
-            Instruction::Eq => write(Opcode::Eq)?,
-            Instruction::Range => write(Opcode::Range)?,
-            Instruction::And => write(Opcode::And)?,
-            Instruction::Or => write(Opcode::Or)?,
-            Instruction::Not => write(Opcode::Not)?,
-            Instruction::Verify => write(Opcode::Verify)?,
-            Instruction::Unblind => write(Opcode::Unblind)?,
-            Instruction::Issue => write(Opcode::Issue)?,
-            Instruction::Borrow => write(Opcode::Borrow)?,
-            Instruction::Retire => write(Opcode::Retire)?,
             Instruction::Cloak(m, n) => {
-                write(Opcode::Cloak)?;
                 w.write_u32(b"m", *m as u32)?;
                 w.write_u32(b"n", *n as u32)?;
             }
-            Instruction::Fee => write(Opcode::Fee)?,
-            Instruction::Input => write(Opcode::Input)?,
-            Instruction::Output(k) => {
-                write(Opcode::Output)?;
-                w.write_u32(b"k", *k as u32)?;
-            }
-            Instruction::Contract(k) => {
-                write(Opcode::Contract)?;
+            Instruction::Output(k) | Instruction::Contract(k) => {
                 w.write_u32(b"k", *k as u32)?;
             }
-            Instruction::Log => write(Opcode::Log)?,
-            Instruction::Eval => write(Opcode::Eval)?,
-            Instruction::Call => write(Opcode::Call)?,
-            Instruction::Signtx => write(Opcode::Signtx)?,
-            Instruction::Signid => write(Opcode::Signid)?,
-            Instruction::Signtag => write(Opcode::Signtag)?,
             Instruction::Ext(x) => w.write_u8(b"ext", *x)?,
+            _ => {}
         };
         Ok(())
     }
@@ -687,6 +721,56 @@ impl ExactSizeEncodable for Instruction {
 }
 
 impl Instruction {
+    /// Returns the opcode of the instruction, or `None` for an unassigned opcode (`Ext`).
+    pub fn opcode(&self) -> Option<Opcode> {
+        let op = match self {
+            Instruction::Push(_) => Opcode::Push,
+            Instruction::Program(_) => Opcode::Program,
+            Instruction::Drop => Opcode::Drop,
+            Instruction::Dup(_) => Opcode::Dup,
+            Instruction::Roll(_) => Opcode::Roll,
+            Instruction::Scalar => Opcode::Scalar,
+            Instruction::Commit => Opcode::Commit,
+            Instruction::Alloc(_) => Opcode::Alloc,
+            Instruction::Mintime => Opcode::Mintime,
+            Instruction::Maxtime => Opcode::Maxtime,
+            Instruction::Expr => Opcode::Expr,
+            Instruction::Neg => Opcode::Neg,
+            Instruction::Add => Opcode::Add,
+            Instruction::Mul => Opcode::Mul,
+            Instruction::Eq => Opcode::Eq,
+            Instruction::Range => Opcode::Range,
+            Instruction::And => Opcode::And,
+            Instruction::Or => Opcode::Or,
+            Instruction::Not => Opcode::Not,
+            Instruction::Verify => Opcode::Verify,
+            Instruction::Unblind => Opcode::Unblind,
+            Instruction::Issue => Opcode::Issue,
+            Instruction::Borrow => Opcode::Borrow,
+            Instruction::Retire => Opcode::Retire,
+            Instruction::Cloak(_, _) => Opcode::Cloak,
+            Instruction::Fee => Opcode::Fee,
+            Instruction::Input => Opcode::Input,
+            Instruction::Output(_) => Opcode::Output,
+            Instruction::Contract(_) => Opcode::Contract,
+            Instruction::Log => Opcode::Log,
+            Instruction::Eval => Opcode::Eval,
+            Instruction::Call => Opcode::Call,
+            Instruction::Signtx => Opcode::Signtx,
+            Instruction::Signid => Opcode::Signid,
+            Instruction::Signtag => Opcode::Signtag,
+            Instruction::Reblind => Opcode::Reblind,
+            Instruction::Ext(_) => return None,
+        };
+        Some(op)
+    }
+
+    /// Returns the assembly name of the instruction, e.g. `"signtx"`.
+    /// Unassigned opcodes are named `"ext"`.
+    pub fn name(&self) -> &'static str {
+        self.opcode().map(Opcode::name).unwrap_or("ext")
+    }
+
     /// Returns a parsed instruction from a subslice of the program string, modifying
     /// the subslice according to the bytes the instruction occupies
     /// E.g. a push instruction with 5-byte string occupies 1+4+5=10 bytes,
@@ -765,6 +849,7 @@ impl Instruction {
             Opcode::Signtx => Ok(Instruction::Signtx),
             Opcode::Signid => Ok(Instruction::Signid),
             Opcode::Signtag => Ok(Instruction::Signtag),
+            Opcode::Reblind => Ok(Instruction::Reblind),
         }
     }
 }
@@ -6,6 +6,7 @@
 
 use bulletproofs::PedersenGens;
 use core::any::Any;
+use core::convert::TryFrom;
 use core::fmt::Debug;
 use core::iter;
 use curve25519_dalek::ristretto::CompressedRistretto;
@@ -49,6 +50,10 @@ impl PredicateWitness for Scalar {
 }
 
 /// Represents a ZkVM predicate with its optional witness data.
+///
+/// Only the verification key is serialized: the witness is prover's private data
+/// and is absent in the deserialized predicate. To store a predicate tree,
+/// serialize the `PredicateTree` itself and restore the predicate with `Predicate::tree`.
 #[derive(Deserialize, Serialize)]
 pub struct Predicate {
     /// Verifier's view on the predicate in a compressed form to defer decompression cost.
@@ -70,7 +75,12 @@ impl Clone for Predicate {
 }
 
 /// Represents a ZkVM predicate tree.
+///
+/// The tree is serialized with all its programs and the blinding key.
+/// When deserialized, the outer key is recomputed from the leaves and the inner predicate,
+/// and the tree is rejected with `VMError::InvalidPredicateTree` if it does not match the stored one.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "SerializedPredicateTree")]
 pub struct PredicateTree {
     /// Vector of the programs and blinding factors, stored as Merkelized predicate leafs.
     leaves: Vec<PredicateLeaf>,
@@ -89,6 +99,16 @@ pub struct PredicateTree {
     adjustment_factor: Scalar,
 }
 
+/// Serialized form of the `PredicateTree` that is checked for consistency before use.
+#[derive(Deserialize)]
+struct SerializedPredicateTree {
+    leaves: Vec<PredicateLeaf>,
+    inner_predicate: Predicate,
+    blinding_key: [u8; 32],
+    precomputed_key: VerificationKey,
+    adjustment_factor: Scalar,
+}
+
 impl PredicateWitness for PredicateTree {
     fn verification_key(&self) -> VerificationKey {
         self.precomputed_key
@@ -105,10 +125,10 @@ impl PredicateWitness for PredicateTree {
 /// Used by `call` instruction. The program is not the part of the proof.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CallProof {
-    // Pure verification key
+    /// Pure verification key
     pub verification_key: VerificationKey,
 
-    // Merkle path.
+    /// Merkle path.
     pub path: Path,
 }
 
@@ -240,6 +260,14 @@ impl PredicateTree {
         // If the key is None, use a point with provably unknown discrete log w.r.t. primary basepoint.
         let inner_predicate = inner_predicate.unwrap_or_else(|| Predicate::unsignable());
         let leaves = Self::create_merkle_leaves(&progs, blinding_key);
+        Self::from_leaves(inner_predicate, leaves, blinding_key)
+    }
+
+    fn from_leaves(
+        inner_predicate: Predicate,
+        leaves: Vec<PredicateLeaf>,
+        blinding_key: [u8; 32],
+    ) -> Result<Self, VMError> {
         if leaves.len() > (1 << 31) {
             return Err(VMError::InvalidPredicateTree);
         }
@@ -284,14 +312,30 @@ impl PredicateTree {
         &self.inner_predicate
     }
 
+    /// Returns the number of programs (branches) committed to the tree.
+    pub fn branches_len(&self) -> usize {
+        self.leaves.len() / 2
+    }
+
+    /// Iterates over the programs (branches) committed to the tree, skipping the blinding leaves.
+    /// The position of a program in this sequence is the index accepted by `create_callproof`.
+    pub fn branches(&self) -> impl Iterator<Item = &ProgramItem> {
+        self.leaves.iter().filter_map(|leaf| match leaf {
+            PredicateLeaf::Program(prog) => Some(prog),
+            PredicateLeaf::Blinding(_) => None,
+        })
+    }
+
     /// Creates the call proof and returns that with the program at an index.
+    /// The proof contains the inner verification key and the merkle path (neighbors and their positions)
+    /// required by the `call` instruction.
     pub fn create_callproof(&self, prog_index: usize) -> Result<(CallProof, Program), VMError> {
         // The `prog_index` is used over the list of the programs,
         // but the actual tree contains also contains blinding factors,
         // so we need to adjust the index accordingly.
         // Blinding factors are located randomly to the left or right of the program leaf,
         // so we simply pick the left leaf, check it, and if it's not the program, pick the right one instead.
-        if prog_index >= self.leaves.len() / 2 {
+        if prog_index >= self.branches_len() {
             return Err(VMError::BadArguments);
         }
         let possible_leaf = &self.leaves[2 * prog_index];
@@ -344,6 +388,24 @@ impl PredicateTree {
     }
 }
 
+impl TryFrom<SerializedPredicateTree> for PredicateTree {
+    type Error = VMError;
+
+    fn try_from(serialized: SerializedPredicateTree) -> Result<Self, VMError> {
+        let tree = PredicateTree::from_leaves(
+            serialized.inner_predicate,
+            serialized.leaves,
+            serialized.blinding_key,
+        )?;
+        if tree.precomputed_key != serialized.precomputed_key
+            || tree.adjustment_factor != serialized.adjustment_factor
+        {
+            return Err(VMError::InvalidPredicateTree);
+        }
+        Ok(tree)
+    }
+}
+
 impl Into<CompressedRistretto> for Predicate {
     fn into(self) -> CompressedRistretto {
         self.to_point()
@@ -379,6 +441,7 @@ impl Decodable for CallProof {
 }
 
 impl CallProof {
+    /// Serializes the call proof into a byte array.
     pub fn to_bytes(&self) -> Vec<u8> {
         self.encode_to_vec()
     }
@@ -439,6 +502,80 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn taproot_branches() {
+        let progs: Vec<_> = (0..5)
+            .map(|i| {
+                Program::build(|p| {
+                    p.dup(i);
+                })
+            })
+            .collect();
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let tree = PredicateTree::new(None, progs.clone(), blinding_key).unwrap();
+        let tree_pred = Predicate::tree(tree.clone());
+
+        assert_eq!(tree.branches_len(), progs.len());
+        for (i, (branch, prog)) in tree.branches().zip(progs.iter()).enumerate() {
+            assert_eq!(branch, &ProgramItem::Program(prog.clone()));
+
+            // The proof satisfies the `call` relation: 0 == -P + X + h1(X, M)·G.
+            let (call_proof, call_prog) = tree.create_callproof(i).unwrap();
+            assert_eq!(&call_prog, prog);
+            assert!(tree_pred
+                .verify_taproot(&ProgramItem::Program(call_prog), &call_proof)
+                .is_ok());
+        }
+        assert_eq!(
+            tree.create_callproof(progs.len()).unwrap_err(),
+            VMError::BadArguments
+        );
+    }
+
+    #[test]
+    fn predicate_tree_serde() {
+        let progs: Vec<_> = (0..3)
+            .map(|i| {
+                Program::build(|p| {
+                    p.dup(i).drop();
+                })
+            })
+            .collect();
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let inner = Predicate::new(VerificationKey::from_secret(&Scalar::from(7u64)));
+        let tree = PredicateTree::new(Some(inner), progs.clone(), blinding_key).unwrap();
+        let tree_pred = Predicate::tree(tree.clone());
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: PredicateTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, tree);
+        assert_eq!(restored.blinding_key, blinding_key);
+        assert!(restored.branches().eq(tree.branches()));
+
+        // Restored tree produces the same predicate and valid call proofs for all branches.
+        let restored_pred = Predicate::tree(restored.clone());
+        assert_eq!(restored_pred.to_point(), tree_pred.to_point());
+        for (i, prog) in progs.iter().enumerate() {
+            let (call_proof, call_prog) = restored.create_callproof(i).unwrap();
+            assert_eq!(&call_prog, prog);
+            assert!(tree_pred
+                .verify_taproot(&ProgramItem::Program(call_prog), &call_proof)
+                .is_ok());
+        }
+
+        // Predicate is serialized without the witness.
+        let json = serde_json::to_string(&tree_pred).unwrap();
+        let opaque: Predicate = serde_json::from_str(&json).unwrap();
+        assert_eq!(opaque, tree_pred);
+        assert!(opaque.verification_key_witness::<PredicateTree>().is_none());
+
+        // Tree with the leaves that do not match the key is rejected.
+        let mut tampered = tree.clone();
+        tampered.leaves.swap(0, 1);
+        let json = serde_json::to_string(&tampered).unwrap();
+        assert!(serde_json::from_str::<PredicateTree>(&json).is_err());
+    }
+
     #[test]
     fn invalid_taproot() {
         let prog1 = Program::build(|p| {
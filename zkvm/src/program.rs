@@ -1,10 +1,11 @@
+use crate::constraints::Commitment;
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::merkle::MerkleItem;
 use crate::ops::Instruction;
 use crate::predicate::PredicateTree;
 use crate::scalar_witness::ScalarWitness;
-use crate::types::String;
+use crate::types::{ClearValue, String};
 
 use alloc::vec;
 use core::borrow::Borrow;
@@ -133,6 +134,8 @@ impl Program {
     }
 
     /// Creates a program by parsing a bytecode slice.
+    /// The entire slice must consist of complete instructions:
+    /// a truncated instruction at the end fails with `VMError::InvalidFormat`.
     pub fn parse(mut data: &[u8]) -> Result<Self, VMError> {
         data.read_all(|r| {
             let mut program = Self::new();
@@ -192,6 +195,7 @@ impl Program {
     def_op!(signtx, Signtx, "signtx");
     def_op!(signid, Signid, "signid");
     def_op!(signtag, Signtag, "signtag");
+    def_op!(reblind, Reblind, "reblind");
 
     /// Takes predicate tree and index of program in Merkle tree to verify
     /// the program's membership in that Merkle tree and call the program.
@@ -207,6 +211,122 @@ impl Program {
         Ok(self)
     }
 
+    /// Adds instructions that check that `tx.mintime` is not earlier than the timeout
+    /// located at the `k`-th position from the top of the stack (`k = 0` is the top item):
+    /// ```ascii
+    /// dup:k commit neg mintime add range
+    /// ```
+    /// The timeout must be a copyable string and stays on the stack,
+    /// followed by the expression `tx.mintime - timeout` that is proven to be non-negative.
+    pub fn assert_mintime_after(&mut self, timeout_index: usize) -> &mut Program {
+        self.dup(timeout_index)
+            .commit()
+            .neg()
+            .mintime()
+            .add()
+            .range()
+    }
+
+    /// Adds instructions that check that `tx.maxtime` is not later than the timeout
+    /// located at the `k`-th position from the top of the stack (`k = 0` is the top item):
+    /// ```ascii
+    /// dup:k commit maxtime neg add range
+    /// ```
+    /// The timeout must be a copyable string and stays on the stack,
+    /// followed by the expression `timeout - tx.maxtime` that is proven to be non-negative.
+    pub fn assert_maxtime_before(&mut self, timeout_index: usize) -> &mut Program {
+        self.dup(timeout_index)
+            .commit()
+            .maxtime()
+            .neg()
+            .add()
+            .range()
+    }
+
+    /// Adds a [`cloak:m:n`](crate::ops::Instruction::Cloak) instruction followed by the rolls
+    /// that place the resulting values in the same order as their commitments:
+    /// ```ascii
+    /// A B C → cloak:m:3 roll:1 roll:2 → A B C
+    /// ```
+    /// The raw `cloak` pushes the values in the reverse order (`A B C → C B A`).
+    /// Both produce the same constraints and the same proof,
+    /// this only adds `n-1` [`roll`](crate::ops::Instruction::Roll) instructions.
+    pub fn cloak_ordered(&mut self, m: usize, n: usize) -> &mut Program {
+        self.cloak(m, n);
+        for k in 1..n {
+            self.roll(k);
+        }
+        self
+    }
+
+    /// Adds instructions that split the value on top of the stack into values
+    /// with the given quantities (of the same flavor):
+    /// ```ascii
+    /// value → cloak:1:n → value(amounts[0]) ... value(amounts[n-1])
+    /// ```
+    /// The resulting values are placed in the same order as `amounts` (the last one on top).
+    /// Cloak adds a range proof for each of the new quantities.
+    ///
+    /// Fails without emitting any instructions if the sum of `amounts` is not equal to the quantity of `value`.
+    pub fn split_value(
+        &mut self,
+        value: ClearValue,
+        amounts: &[u64],
+    ) -> Result<&mut Program, VMError> {
+        let total = amounts
+            .iter()
+            .try_fold(0u64, |total, qty| total.checked_add(*qty));
+        if total != Some(value.qty) {
+            return Err(VMError::BadArguments);
+        }
+        // Cloak places the values in the reverse order of their commitments.
+        for qty in amounts.iter().rev() {
+            self.push(Commitment::blinded(*qty))
+                .push(Commitment::blinded(value.flv));
+        }
+        self.cloak(1, amounts.len());
+        Ok(self)
+    }
+
+    /// Adds instructions that merge the values on top of the stack into one value:
+    /// ```ascii
+    /// values... → cloak:m:1 → value
+    /// ```
+    /// Cloak adds a range proof for the merged quantity.
+    ///
+    /// Fails without emitting any instructions if the list is empty,
+    /// the values have different flavors or the total quantity overflows.
+    pub fn merge_values(&mut self, values: &[ClearValue]) -> Result<&mut Program, VMError> {
+        let flv = values.first().ok_or(VMError::BadArguments)?.flv;
+        if values.iter().any(|v| v.flv != flv) {
+            return Err(VMError::BadArguments);
+        }
+        let qty = values
+            .iter()
+            .try_fold(0u64, |total, v| total.checked_add(v.qty))
+            .ok_or(VMError::BadArguments)?;
+        self.push(Commitment::blinded(qty))
+            .push(Commitment::blinded(flv))
+            .cloak(values.len(), 1);
+        Ok(self)
+    }
+
+    /// Returns the top-level instructions of the program.
+    /// Nested programs are represented by a single `program` instruction each.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.0
+    }
+
+    /// Returns the number of top-level instructions in the program.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the program contains no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Serializes a Program into a byte array.
     pub fn to_bytes(&self) -> Vec<u8> {
         self.encode_to_vec()
@@ -298,3 +418,62 @@ impl core::iter::IntoIterator for Program {
         self.0.into_iter()
     }
 }
+
+impl<'a> core::iter::IntoIterator for &'a Program {
+    type Item = &'a Instruction;
+    type IntoIter = core::slice::Iter<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_view() {
+        assert!(Program::new().is_empty());
+        assert_eq!(Program::new().len(), 0);
+
+        let inner = Program::build(|p| {
+            p.drop().drop().drop();
+        });
+        let prog = Program::build(|p| {
+            p.push(String::U32(1)).program(inner).call();
+        });
+        assert!(!prog.is_empty());
+        assert_eq!(prog.len(), 3);
+        assert_eq!(prog.instructions().len(), 3);
+        assert_eq!((&prog).into_iter().count(), 3);
+        match prog.instructions()[1] {
+            Instruction::Program(_) => {}
+            _ => panic!("Expected a nested program"),
+        }
+
+        // The view matches the parsed program.
+        let parsed = Program::parse(&prog.to_bytes()).unwrap();
+        assert_eq!(parsed.len(), 3);
+    }
+
+    #[test]
+    fn cloak_ordered() {
+        let prog = Program::build(|p| {
+            p.cloak_ordered(2, 3);
+        });
+        assert_eq!(prog.len(), 3);
+        match prog.instructions() {
+            [Instruction::Cloak(2, 3), Instruction::Roll(1), Instruction::Roll(2)] => {}
+            _ => panic!("Expected cloak followed by the rolls"),
+        }
+
+        let prog = Program::build(|p| {
+            p.cloak_ordered(1, 1);
+        });
+        match prog.instructions() {
+            [Instruction::Cloak(1, 1)] => {}
+            _ => panic!("Expected a single cloak"),
+        }
+    }
+}
@@ -2,6 +2,7 @@ use bulletproofs::r1cs;
 use bulletproofs::r1cs::ConstraintSystem;
 use bulletproofs::{BulletproofGens, PedersenGens};
 use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use std::collections::VecDeque;
 
@@ -12,8 +13,9 @@ use crate::errors::VMError;
 use crate::ops::Instruction;
 use crate::predicate::Predicate;
 use crate::program::{Program, ProgramItem};
+use crate::transcript::TranscriptProtocol;
 use crate::tx::{TxHeader, UnsignedTx};
-use crate::vm::{Delegate, VM};
+use crate::vm::{Delegate, DEFAULT_BUDGET, VM};
 
 /// This is the entry point API for creating a transaction.
 /// Prover passes the list of instructions through the VM,
@@ -23,6 +25,7 @@ pub struct Prover<'g> {
     // TBD: use Multikey as a witness thing
     signtx_items: Vec<(Predicate, ContractID)>,
     cs: r1cs::Prover<'g, Transcript>,
+    pc_gens: &'g PedersenGens,
     batch: musig::BatchVerifier<rand::rngs::ThreadRng>,
 }
 
@@ -30,6 +33,28 @@ pub(crate) struct ProverRun {
     program: VecDeque<Instruction>,
 }
 
+/// Delegate that runs the program against a `MetricsCS`
+/// to count the constraints without computing the proof.
+struct DryRun {
+    cs: MetricsCS,
+    pc_gens: PedersenGens,
+    batch: musig::BatchVerifier<rand::rngs::ThreadRng>,
+}
+
+/// Constraint system that only counts the allocated multipliers and constraints.
+/// Randomized constraints are deferred till the end of the run, like in the real prover.
+struct MetricsCS {
+    transcript: Transcript,
+    num_commitments: usize,
+    num_multipliers: usize,
+    // Index of the multiplier whose right wire is not yet taken by `allocate`, like in `r1cs::Prover`.
+    pending_multiplier: Option<usize>,
+    phase_one_constraints: usize,
+    phase_two_constraints: usize,
+    in_phase_two: bool,
+    deferred: Vec<Box<dyn FnOnce(&mut MetricsCS) -> Result<(), r1cs::R1CSError>>>,
+}
+
 impl<'t, 'g> Delegate<r1cs::Prover<'g, Transcript>> for Prover<'g> {
     type RunType = ProverRun;
     type BatchVerifier = musig::BatchVerifier<rand::rngs::ThreadRng>;
@@ -38,10 +63,17 @@ impl<'t, 'g> Delegate<r1cs::Prover<'g, Transcript>> for Prover<'g> {
         &mut self,
         com: &Commitment,
     ) -> Result<(CompressedRistretto, r1cs::Variable), VMError> {
+        if !com.uses_gens(self.pc_gens) {
+            return Err(VMError::GeneratorsMismatch);
+        }
         let (v, v_blinding) = com.witness().ok_or(VMError::WitnessMissing)?;
         Ok(self.cs.commit(v.into(), v_blinding))
     }
 
+    fn pc_gens(&self) -> &PedersenGens {
+        self.pc_gens
+    }
+
     fn process_tx_signature(
         &mut self,
         pred: Predicate,
@@ -73,18 +105,198 @@ impl<'t, 'g> Delegate<r1cs::Prover<'g, Transcript>> for Prover<'g> {
     }
 }
 
+impl Delegate<MetricsCS> for DryRun {
+    type RunType = ProverRun;
+    type BatchVerifier = musig::BatchVerifier<rand::rngs::ThreadRng>;
+
+    fn commit_variable(
+        &mut self,
+        com: &Commitment,
+    ) -> Result<(CompressedRistretto, r1cs::Variable), VMError> {
+        Ok((com.to_point(), self.cs.commit()))
+    }
+
+    fn pc_gens(&self) -> &PedersenGens {
+        &self.pc_gens
+    }
+
+    fn process_tx_signature(
+        &mut self,
+        _pred: Predicate,
+        _contract_id: ContractID,
+    ) -> Result<(), VMError> {
+        Ok(())
+    }
+
+    fn next_instruction(
+        &mut self,
+        run: &mut Self::RunType,
+    ) -> Result<Option<Instruction>, VMError> {
+        Ok(run.program.pop_front())
+    }
+
+    fn new_run(&self, data: ProgramItem) -> Result<Self::RunType, VMError> {
+        Ok(ProverRun {
+            program: data.to_program()?.to_vec().into(),
+        })
+    }
+
+    fn cs(&mut self) -> &mut MetricsCS {
+        &mut self.cs
+    }
+
+    fn batch_verifier(&mut self) -> &mut Self::BatchVerifier {
+        &mut self.batch
+    }
+}
+
+impl MetricsCS {
+    fn new() -> Self {
+        MetricsCS {
+            transcript: Transcript::new(b"ZkVM.r1cs"),
+            num_commitments: 0,
+            num_multipliers: 0,
+            pending_multiplier: None,
+            phase_one_constraints: 0,
+            phase_two_constraints: 0,
+            in_phase_two: false,
+            deferred: Vec::new(),
+        }
+    }
+
+    fn commit(&mut self) -> r1cs::Variable {
+        let i = self.num_commitments;
+        self.num_commitments += 1;
+        r1cs::Variable::Committed(i)
+    }
+
+    fn allocate_multiplier_vars(&mut self) -> (r1cs::Variable, r1cs::Variable, r1cs::Variable) {
+        let i = self.num_multipliers;
+        self.num_multipliers += 1;
+        (
+            r1cs::Variable::MultiplierLeft(i),
+            r1cs::Variable::MultiplierRight(i),
+            r1cs::Variable::MultiplierOutput(i),
+        )
+    }
+
+    fn count_constraints(&mut self, n: usize) {
+        if self.in_phase_two {
+            self.phase_two_constraints += n;
+        } else {
+            self.phase_one_constraints += n;
+        }
+    }
+
+    /// Runs the deferred randomized constraints.
+    fn finalize(&mut self) -> Result<(), r1cs::R1CSError> {
+        self.in_phase_two = true;
+        // The prover commits to the multipliers before the randomized constraints,
+        // so the half-used multiplier is not reused in the second phase.
+        self.pending_multiplier = None;
+        for callback in core::mem::replace(&mut self.deferred, Vec::new()) {
+            callback(self)?;
+        }
+        Ok(())
+    }
+}
+
+impl r1cs::ConstraintSystem for MetricsCS {
+    fn transcript(&mut self) -> &mut Transcript {
+        &mut self.transcript
+    }
+
+    fn multiply(
+        &mut self,
+        _left: r1cs::LinearCombination,
+        _right: r1cs::LinearCombination,
+    ) -> (r1cs::Variable, r1cs::Variable, r1cs::Variable) {
+        let (l, r, o) = self.allocate_multiplier_vars();
+        // Both the left and the right wires are constrained to the given combinations.
+        self.count_constraints(2);
+        (l, r, o)
+    }
+
+    fn allocate(&mut self, _assignment: Option<Scalar>) -> Result<r1cs::Variable, r1cs::R1CSError> {
+        // Two allocated variables share one multiplier: the left and the right wire.
+        match self.pending_multiplier.take() {
+            Some(i) => Ok(r1cs::Variable::MultiplierRight(i)),
+            None => {
+                let (l, _, _) = self.allocate_multiplier_vars();
+                self.pending_multiplier = Some(self.num_multipliers - 1);
+                Ok(l)
+            }
+        }
+    }
+
+    fn allocate_multiplier(
+        &mut self,
+        _assignments: Option<(Scalar, Scalar)>,
+    ) -> Result<(r1cs::Variable, r1cs::Variable, r1cs::Variable), r1cs::R1CSError> {
+        Ok(self.allocate_multiplier_vars())
+    }
+
+    fn metrics(&self) -> r1cs::Metrics {
+        r1cs::Metrics {
+            multipliers: self.num_multipliers,
+            constraints: self.phase_one_constraints + self.phase_two_constraints,
+            phase_one_constraints: self.phase_one_constraints,
+            phase_two_constraints: self.phase_two_constraints,
+        }
+    }
+
+    fn constrain(&mut self, _lc: r1cs::LinearCombination) {
+        self.count_constraints(1);
+    }
+}
+
+impl r1cs::RandomizableConstraintSystem for MetricsCS {
+    type RandomizedCS = Self;
+
+    fn specify_randomized_constraints<F>(&mut self, callback: F) -> Result<(), r1cs::R1CSError>
+    where
+        F: 'static + FnOnce(&mut Self::RandomizedCS) -> Result<(), r1cs::R1CSError>,
+    {
+        self.deferred.push(Box::new(callback));
+        Ok(())
+    }
+}
+
+impl r1cs::RandomizedConstraintSystem for MetricsCS {
+    fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        self.transcript.challenge_scalar(label)
+    }
+}
+
 impl<'g> Prover<'g> {
     /// Builds a transaction with a given list of instructions and a `TxHeader`.
     /// Returns a transaction `Tx` along with its ID (`TxID`) and a transaction log (`TxLog`).
     /// Fails if the input program is malformed, or some witness data is missing.
+    /// Fails with `VMError::BudgetExceeded` if the program executes more than `DEFAULT_BUDGET` steps,
+    /// since the verifiers reject such transactions.
     pub fn build_tx(
         program: Program,
         header: TxHeader,
         bp_gens: &BulletproofGens,
+    ) -> Result<UnsignedTx, VMError> {
+        Self::build_tx_with_gens(program, header, bp_gens, &PedersenGens::default())
+    }
+
+    /// Builds a transaction like `build_tx`, but with custom Pedersen generators
+    /// for all the commitments and the R1CS proof.
+    /// Open commitments in the program must be created with the same generators
+    /// (see `Commitment::blinded_with_gens`), otherwise fails with `VMError::GeneratorsMismatch`.
+    ///
+    /// The transaction can only be verified by a `Verifier` configured with the same generators,
+    /// so it is not valid on a network that uses the default generators.
+    pub fn build_tx_with_gens(
+        program: Program,
+        header: TxHeader,
+        bp_gens: &BulletproofGens,
+        pc_gens: &PedersenGens,
     ) -> Result<UnsignedTx, VMError> {
         // Prepare the constraint system
-        let pc_gens = PedersenGens::default();
-        let cs = r1cs::Prover::new(&pc_gens, Transcript::new(b"ZkVM.r1cs"));
+        let cs = r1cs::Prover::new(pc_gens, Transcript::new(b"ZkVM.r1cs"));
 
         // Serialize the tx program
         let mut bytecode = Vec::new();
@@ -93,6 +305,7 @@ impl<'g> Prover<'g> {
         let mut prover = Prover {
             signtx_items: Vec::new(),
             cs: cs,
+            pc_gens,
             batch: musig::BatchVerifier::new(rand::thread_rng()),
         };
 
@@ -102,7 +315,8 @@ impl<'g> Prover<'g> {
                 program: program.to_vec().into(),
             },
             &mut prover,
-        );
+        )
+        .with_budget(DEFAULT_BUDGET);
 
         let (txid, txlog, _fee) = vm.run()?;
 
@@ -125,4 +339,95 @@ impl<'g> Prover<'g> {
             signing_instructions: prover.signtx_items,
         })
     }
+
+    /// Runs the program through the VM without creating the proof and the signatures,
+    /// and returns the number of multipliers and constraints the R1CS proof would contain.
+    /// Useful to estimate the proving time and the proof size before building the transaction.
+    /// Does not require the witness data for the commitments.
+    pub fn metrics_for(program: &Program, header: TxHeader) -> Result<r1cs::Metrics, VMError> {
+        let mut cs = Self::dry_run(program, header)?;
+        cs.finalize().map_err(VMError::R1CSError)?;
+        Ok(cs.metrics())
+    }
+
+    /// Runs the program against `MetricsCS`, leaving the randomized constraints deferred.
+    fn dry_run(program: &Program, header: TxHeader) -> Result<MetricsCS, VMError> {
+        let mut dry_run = DryRun {
+            cs: MetricsCS::new(),
+            pc_gens: PedersenGens::default(),
+            batch: musig::BatchVerifier::new(rand::thread_rng()),
+        };
+
+        let vm = VM::new(
+            header,
+            ProverRun {
+                program: program.clone().to_vec().into(),
+            },
+            &mut dry_run,
+        )
+        .with_budget(DEFAULT_BUDGET);
+        vm.run()?;
+
+        Ok(dry_run.cs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use musig::VerificationKey;
+
+    #[test]
+    fn metrics_match_prover() {
+        let flv = Scalar::from(1u64);
+        let predicate = Predicate::new(VerificationKey::from_secret(&Scalar::from(1u64)));
+        // `borrow` allocates a single variable for the negative quantity.
+        let program = Program::build(|p| {
+            p.push(Commitment::blinded(5u64))
+                .commit()
+                .push(Commitment::blinded(flv))
+                .commit()
+                .borrow()
+                .push(Commitment::blinded(0u64))
+                .push(Commitment::blinded(flv))
+                .cloak(2, 1)
+                .push(predicate)
+                .output(1);
+        });
+        let header = TxHeader {
+            version: 0u64,
+            mintime_ms: 0u64,
+            maxtime_ms: 0u64,
+        };
+
+        let pc_gens = PedersenGens::default();
+        let mut prover = Prover {
+            signtx_items: Vec::new(),
+            cs: r1cs::Prover::new(&pc_gens, Transcript::new(b"ZkVM.r1cs")),
+            pc_gens: &pc_gens,
+            batch: musig::BatchVerifier::new(rand::thread_rng()),
+        };
+        let vm = VM::new(
+            header,
+            ProverRun {
+                program: program.clone().to_vec().into(),
+            },
+            &mut prover,
+        );
+        vm.run().unwrap();
+
+        // The r1cs prover runs the randomized constraints only when making the proof,
+        // so the metrics are compared before the second phase.
+        let expected = prover.cs.metrics();
+        let actual = Prover::dry_run(&program, header).unwrap().metrics();
+        assert_eq!(actual.multipliers, expected.multipliers);
+        assert_eq!(actual.phase_one_constraints, expected.phase_one_constraints);
+
+        let metrics = Prover::metrics_for(&program, header).unwrap();
+        assert!(metrics.multipliers >= expected.multipliers);
+        assert_eq!(
+            metrics.phase_one_constraints,
+            expected.phase_one_constraints
+        );
+    }
 }
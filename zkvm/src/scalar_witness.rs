@@ -3,6 +3,7 @@
 use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
 use spacesuit::SignedInteger;
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 
 use crate::encoding::*;
 use crate::errors::VMError;
@@ -63,6 +64,34 @@ impl ScalarWitness {
     }
 }
 
+// Constant-time comparison and selection.
+// The kind of the witness (integer or scalar) is not secret,
+// so only the underlying values are handled in constant time.
+
+impl ConstantTimeEq for ScalarWitness {
+    /// Compares the witnesses as scalars, so an integer is equal to the scalar it maps to.
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.to_scalar().ct_eq(&other.to_scalar())
+    }
+}
+
+impl ConditionallySelectable for ScalarWitness {
+    /// Returns `a` if `choice == 0` and `b` if `choice == 1`.
+    /// Two integers produce an integer; any other combination produces a scalar.
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        match (a, b) {
+            (ScalarWitness::Integer(a), ScalarWitness::Integer(b)) => {
+                ScalarWitness::Integer(SignedInteger::conditional_select(a, b, choice))
+            }
+            (a, b) => ScalarWitness::Scalar(Scalar::conditional_select(
+                &a.to_scalar(),
+                &b.to_scalar(),
+                choice,
+            )),
+        }
+    }
+}
+
 // Implementing arithmetic operatons for ScalarWitness
 
 impl Neg for ScalarWitness {
@@ -247,6 +276,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ct_eq() {
+        assert!(bool::from(
+            ScalarWitness::from(5u64).ct_eq(&ScalarWitness::from(5u64))
+        ));
+        assert!(bool::from(
+            ScalarWitness::from(5u64).ct_eq(&ScalarWitness::from(Scalar::from(5u64)))
+        ));
+        assert!(bool::from(
+            (-ScalarWitness::from(5u64)).ct_eq(&ScalarWitness::from(-Scalar::from(5u64)))
+        ));
+        assert!(!bool::from(
+            ScalarWitness::from(5u64).ct_eq(&ScalarWitness::from(Scalar::from(6u64)))
+        ));
+    }
+
+    #[test]
+    fn conditional_select() {
+        let a = ScalarWitness::from(1u64);
+        let b = ScalarWitness::from(2u64);
+        assert_eq!(ScalarWitness::conditional_select(&a, &b, 0.into()), a);
+        assert_eq!(ScalarWitness::conditional_select(&a, &b, 1.into()), b);
+
+        let c = ScalarWitness::from(Scalar::from(3u64));
+        assert_eq!(
+            ScalarWitness::conditional_select(&a, &c, 0.into()),
+            ScalarWitness::from(Scalar::from(1u64))
+        );
+        assert_eq!(ScalarWitness::conditional_select(&a, &c, 1.into()), c);
+    }
+
     #[test]
     fn overflow() {
         assert_eq!(
@@ -1,18 +1,21 @@
 use bulletproofs::r1cs::R1CSProof;
-use bulletproofs::BulletproofGens;
+use bulletproofs::{BulletproofGens, PedersenGens};
 use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
-use musig::Signature;
+use musig::{BatchVerification, Signature, VerificationKey};
 use serde::{Deserialize, Serialize};
 
-use crate::contract::{Contract, ContractID};
+use crate::constraints::Commitment;
+use crate::contract::{Anchor, Contract, ContractID};
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::fees::FeeRate;
 use crate::merkle::{Hash, MerkleItem, MerkleTree};
 use crate::predicate::Predicate;
 use crate::transcript::TranscriptProtocol;
-use crate::verifier::Verifier;
+use crate::types::ClearValue;
+use crate::verifier::{DeferredOperations, Verifier};
 
 /// Transaction log, a list of all effects of a transaction called [entries](TxEntry).
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -44,6 +47,19 @@ pub enum TxEntry {
     Data(Vec<u8>),
 }
 
+/// Value destroyed by the `retire` instruction, as recorded in the [transaction log](TxLog).
+///
+/// The log contains only the commitments to the quantity and flavor,
+/// so the cleartext value is known to those who have the openings
+/// (e.g. the issuer who redeems its asset), and can be checked with `RetiredValue::open`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RetiredValue {
+    /// Commitment to the quantity.
+    pub qty: CompressedRistretto,
+    /// Commitment to the flavor.
+    pub flv: CompressedRistretto,
+}
+
 /// Header metadata for the transaction
 #[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
 pub struct TxHeader {
@@ -113,9 +129,6 @@ pub struct PrecomputedTx {
     /// Verifier to continue verification of the transaction
     pub(crate) verifier: Verifier,
 
-    /// Schnorr signature
-    pub(crate) signature: Signature,
-
     /// R1CS proof
     pub(crate) proof: R1CSProof,
 }
@@ -136,6 +149,51 @@ pub struct VerifiedTx {
     pub feerate: FeeRate,
 }
 
+/// Compact summary of a verified transaction for the explorers and UIs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TxSummary {
+    /// Transaction ID
+    pub id: TxID,
+
+    /// Total amount of fees paid in the transaction
+    pub fee: u64,
+
+    /// Timestamp before which tx is invalid (in milliseconds since the Unix epoch)
+    pub mintime_ms: u64,
+
+    /// Timestamp after which tx is invalid (in milliseconds since the Unix epoch)
+    pub maxtime_ms: u64,
+
+    /// Number of the issuance entries
+    pub issuances: usize,
+
+    /// Number of the retirement entries
+    pub retirements: usize,
+
+    /// IDs of the contracts spent by the transaction
+    pub inputs: Vec<ContractID>,
+
+    /// IDs of the contracts created by the transaction
+    pub outputs: Vec<ContractID>,
+
+    /// Status of the transaction, filled in by the caller who tracks the mempool and the blockchain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<TxStatus>,
+}
+
+/// Status of the transaction in the node.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Transaction is in the mempool, waiting to be included in a block.
+    Mempool,
+    /// Transaction is included in the block at the given height.
+    Confirmed {
+        /// Height of the block that contains the transaction.
+        height: u64,
+    },
+}
+
 impl Encodable for TxHeader {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
         w.write_u64(b"version", self.version)?;
@@ -161,7 +219,29 @@ impl Decodable for TxHeader {
 }
 
 impl UnsignedTx {
-    /// Attaches the signature to the transaction.
+    /// Returns the transcript over which the aggregated signature must be made
+    /// by the keys listed in `signing_instructions`.
+    ///
+    /// This allows signing the transaction outside of the prover, e.g. on an air-gapped device:
+    /// the external signer needs only this transcript and the signing instructions
+    /// to produce the signature with `Signature::sign_multi`, which is then attached with `sign`.
+    pub fn signing_transcript(&self) -> Transcript {
+        signtx_transcript(&self.txid)
+    }
+
+    /// Returns the keys that must sign the transaction, one per `signtx` instruction,
+    /// in the order of execution. These are exactly the keys the verifier aggregates
+    /// to check the transaction signature, so a key appears as many times as it is used.
+    pub fn required_signers(&self) -> Vec<VerificationKey> {
+        self.signing_instructions
+            .iter()
+            .map(|(predicate, _)| predicate.verification_key())
+            .collect()
+    }
+
+    /// Attaches the signature to the transaction, producing a transaction ready for verification.
+    /// The signature may be made inline or by an external signer (see `signing_transcript`);
+    /// it is checked only when the transaction is verified.
     pub fn sign(self, signature: Signature) -> Tx {
         Tx {
             header: self.header,
@@ -172,6 +252,13 @@ impl UnsignedTx {
     }
 }
 
+/// Creates the transcript for the aggregated signature of the transaction with a given ID.
+pub(crate) fn signtx_transcript(txid: &TxID) -> Transcript {
+    let mut t = Transcript::new(b"ZkVM.signtx");
+    t.append_message(b"txid", &txid.0);
+    t
+}
+
 impl Encodable for Tx {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
         self.header.encode(w)?;
@@ -218,6 +305,7 @@ impl Decodable for Tx {
 
 impl Tx {
     /// Computes the TxID and TxLog without verifying the transaction.
+    /// Fails with `VMError::BudgetExceeded` if the program executes more than `DEFAULT_BUDGET` steps.
     pub fn precompute(&self) -> Result<PrecomputedTx, VMError> {
         Verifier::precompute(self)
     }
@@ -228,6 +316,12 @@ impl Tx {
         self.precompute()?.verify(bp_gens)
     }
 
+    /// Returns the size of the encoded transaction in bytes,
+    /// which is also the size component of its `FeeRate`.
+    pub fn size(&self) -> usize {
+        self.encoded_size()
+    }
+
     /// Serializes the tx into a byte array.
     pub fn to_bytes(&self) -> Vec<u8> {
         self.encode_to_vec()
@@ -235,11 +329,14 @@ impl Tx {
 
     /// Deserializes the tx from a byte slice.
     ///
-    /// Returns an error if the byte slice cannot be parsed into a `Tx`.
+    /// Returns `VMError::TrailingBytes` if the slice contains bytes after the encoded `Tx`,
+    /// so the same transaction cannot be received in several encodings,
+    /// and `VMError::InvalidFormat` if the byte slice cannot be parsed into a `Tx`.
     pub fn from_bytes(mut slice: &[u8]) -> Result<Tx, VMError> {
-        slice
-            .read_all(|r| Self::decode(r))
-            .map_err(|_| VMError::InvalidFormat)
+        slice.read_all(|r| Self::decode(r)).map_err(|e| match e {
+            ReadError::TrailingBytes => VMError::TrailingBytes,
+            _ => VMError::InvalidFormat,
+        })
     }
 }
 
@@ -251,14 +348,40 @@ impl PrecomputedTx {
         Verifier::verify_tx(self, bp_gens)
     }
 
+    /// Point operations (signatures and other Ristretto255 operations)
+    /// that are checked by [`verify`](Self::verify) after the R1CS proof.
+    pub fn deferred_operations(&self) -> &DeferredOperations {
+        self.verifier.deferred_operations()
+    }
+
+    /// Verifies the R1CS proof and appends the deferred point operations to the caller's batch
+    /// instead of checking them immediately.
+    /// The returned `VerifiedTx` is valid only if the batch verification succeeds.
+    pub fn verify_deferred(
+        self,
+        bp_gens: &BulletproofGens,
+        batch: &mut impl BatchVerification,
+    ) -> Result<VerifiedTx, VMError> {
+        Verifier::verify_tx_deferred(self, bp_gens, batch)
+    }
+
     /// Verifies a batch of transactions, typically coming from a Block.
+    /// Point operations of all transactions are checked in a single batch.
     pub fn verify_batch(
         txs: impl IntoIterator<Item = Self>,
         bp_gens: &BulletproofGens,
     ) -> Result<Vec<VerifiedTx>, VMError> {
         // TODO: implement and adopt a batch verification API for R1CS proofs.
 
-        txs.into_iter().map(|tx| tx.verify(bp_gens)).collect()
+        let mut batch = musig::BatchVerifier::new(rand::thread_rng());
+        let vtxs = txs
+            .into_iter()
+            .map(|tx| tx.verify_deferred(bp_gens, &mut batch))
+            .collect::<Result<Vec<_>, _>>()?;
+        batch
+            .verify()
+            .map_err(|_| VMError::BatchSignatureVerificationFailed)?;
+        Ok(vtxs)
     }
 }
 
@@ -283,10 +406,7 @@ impl TxEntry {
 impl TxLog {
     /// Total amount of fees paid in the transaction
     pub fn fee(&self) -> u64 {
-        self.0
-            .iter()
-            .map(|e| if let TxEntry::Fee(f) = e { *f } else { 0 })
-            .sum()
+        self.fees().sum()
     }
 
     /// Adds an entry to the txlog.
@@ -302,6 +422,16 @@ impl TxLog {
         })
     }
 
+    /// Returns the ratcheted ID of the first spent contract, which uniquely identifies
+    /// the transaction among the transactions spending the same set of contracts.
+    /// Note: this is not the VM's anchor, which is updated by every input and output.
+    /// Returns `None` if the tx has no inputs.
+    pub fn anchor(&self) -> Option<Anchor> {
+        self.inputs()
+            .next()
+            .map(|contract_id| contract_id.to_anchor().ratchet())
+    }
+
     /// Iterator over the output entries
     pub fn outputs(&self) -> impl Iterator<Item = &Contract> {
         self.0.iter().filter_map(|entry| match entry {
@@ -310,6 +440,43 @@ impl TxLog {
         })
     }
 
+    /// Iterator over the issuance entries as pairs of (quantity, flavor) commitments
+    pub fn issuances(&self) -> impl Iterator<Item = (&CompressedRistretto, &CompressedRistretto)> {
+        self.0.iter().filter_map(|entry| match entry {
+            TxEntry::Issue(qty, flv) => Some((qty, flv)),
+            _ => None,
+        })
+    }
+
+    /// Iterator over the retirement entries as pairs of (quantity, flavor) commitments
+    pub fn retirements(
+        &self,
+    ) -> impl Iterator<Item = (&CompressedRistretto, &CompressedRistretto)> {
+        self.0.iter().filter_map(|entry| match entry {
+            TxEntry::Retire(qty, flv) => Some((qty, flv)),
+            _ => None,
+        })
+    }
+
+    /// Iterator over the values retired in the transaction.
+    /// To account for the supply of an asset, open the retired values
+    /// with `RetiredValue::open` and sum them up with `group_by_flavor`,
+    /// together with the issued values (see `issuances`).
+    pub fn retired_values(&self) -> impl Iterator<Item = RetiredValue> + '_ {
+        self.retirements().map(|(qty, flv)| RetiredValue {
+            qty: *qty,
+            flv: *flv,
+        })
+    }
+
+    /// Iterator over the fee entries
+    pub fn fees(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.iter().filter_map(|entry| match entry {
+            TxEntry::Fee(fee) => Some(*fee),
+            _ => None,
+        })
+    }
+
     /// Iterator over all data entries
     pub fn data_entries<'a>(&'a self) -> impl Iterator<Item = &'a [u8]> {
         self.0.iter().filter_map(|entry| match entry {
@@ -319,6 +486,57 @@ impl TxLog {
     }
 }
 
+impl VerifiedTx {
+    /// Returns the summary of the transaction with the `status` left empty.
+    /// The counts of inputs and outputs are the lengths of the `inputs` and `outputs` lists.
+    pub fn summary(&self) -> TxSummary {
+        TxSummary {
+            id: self.id,
+            fee: self.log.fee(),
+            mintime_ms: self.header.mintime_ms,
+            maxtime_ms: self.header.maxtime_ms,
+            issuances: self.log.issuances().count(),
+            retirements: self.log.retirements().count(),
+            inputs: self.log.inputs().cloned().collect(),
+            outputs: self.log.outputs().map(|c| c.id()).collect(),
+            status: None,
+        }
+    }
+}
+
+impl RetiredValue {
+    /// Returns the cleartext value if the commitments open to it with the given blinding factors.
+    /// Values retired without blinding open with zero blinding factors.
+    pub fn open(
+        &self,
+        value: ClearValue,
+        qty_blinding: Scalar,
+        flv_blinding: Scalar,
+    ) -> Option<ClearValue> {
+        self.open_with_gens(value, qty_blinding, flv_blinding, &PedersenGens::default())
+    }
+
+    /// Same as `open`, but for the transactions verified with custom Pedersen generators
+    /// (see `Verifier::with_gens`).
+    pub fn open_with_gens(
+        &self,
+        value: ClearValue,
+        qty_blinding: Scalar,
+        flv_blinding: Scalar,
+        gens: &PedersenGens,
+    ) -> Option<ClearValue> {
+        let qty = Commitment::Closed(self.qty);
+        let flv = Commitment::Closed(self.flv);
+        if qty.verify_opening_with_gens(value.qty, qty_blinding, gens)
+            && flv.verify_opening_with_gens(value.flv, flv_blinding, gens)
+        {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
 impl From<Vec<TxEntry>> for TxLog {
     fn from(v: Vec<TxEntry>) -> TxLog {
         TxLog(v)
@@ -424,6 +642,34 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn typed_iterators() {
+        let mut entries = txlog_helper();
+        entries.push(TxEntry::Fee(10));
+        entries.push(TxEntry::Retire(
+            CompressedRistretto::from_slice(&[2u8; 32]),
+            CompressedRistretto::from_slice(&[3u8; 32]),
+        ));
+        entries.push(TxEntry::Fee(5));
+        let txlog = TxLog::from(entries);
+
+        let issuances = txlog.issuances().collect::<Vec<_>>();
+        assert_eq!(issuances.len(), 1);
+        assert_eq!(issuances[0].0.as_bytes(), &[0u8; 32]);
+        assert_eq!(issuances[0].1.as_bytes(), &[1u8; 32]);
+
+        let retirements = txlog.retirements().collect::<Vec<_>>();
+        assert_eq!(retirements.len(), 1);
+        assert_eq!(retirements[0].0.as_bytes(), &[2u8; 32]);
+        assert_eq!(retirements[0].1.as_bytes(), &[3u8; 32]);
+
+        assert_eq!(txlog.fees().collect::<Vec<_>>(), vec![10, 5]);
+        assert_eq!(txlog.fee(), 15);
+        assert_eq!(txlog.data_entries().count(), 3);
+        assert_eq!(txlog.inputs().count(), 0);
+        assert_eq!(txlog.outputs().count(), 0);
+    }
+
     #[test]
     fn valid_txid_proof() {
         let hasher = Hasher::new(b"ZkVM.txid");
@@ -1,10 +1,12 @@
 //! Core ZkVM stack types: data, variables, values, contracts etc.
 
+use bulletproofs::r1cs;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use musig::VerificationKey;
 use serde::{Deserialize, Serialize};
 use spacesuit::{self, SignedInteger};
+use std::collections::HashMap;
 
 use crate::constraints::{Commitment, Constraint, Expression, Variable};
 use crate::contract::{Contract, PortableItem};
@@ -120,6 +122,9 @@ pub struct ClearValue {
 }
 
 /// A wide value type (for negative values created by `borrow`).
+///
+/// Wide values are not portable: they cannot be stored in contracts
+/// and must be consumed by `cloak` (or a custom balancing gadget) within the same transaction.
 #[derive(Debug)]
 pub struct WideValue(pub(crate) spacesuit::AllocatedValue);
 
@@ -353,6 +358,99 @@ impl Value {
     }
 }
 
+impl WideValue {
+    /// Allocates a wide value in the constraint system.
+    /// The prover provides the (qty, flavor) assignment, and the verifier passes `None`.
+    ///
+    /// The quantity is NOT range-checked: the caller must constrain it
+    /// (e.g. with `spacesuit::range_proof`, as `borrow` does), or consume it
+    /// only in a gadget that range-checks the resulting values, such as `cloak`.
+    pub fn allocate<CS: r1cs::ConstraintSystem>(
+        cs: &mut CS,
+        assignment: Option<(SignedInteger, Scalar)>,
+    ) -> Result<Self, VMError> {
+        let (q, f, _) = cs
+            .allocate_multiplier(assignment.map(|(q, f)| (q.to_scalar(), f)))
+            .map_err(|e| VMError::R1CSError(e))?;
+        Ok(WideValue(spacesuit::AllocatedValue {
+            q,
+            f,
+            assignment: assignment.map(|(q, f)| spacesuit::Value { q, f }),
+        }))
+    }
+
+    /// Returns a (qty,flavor) assignment to a wide value, or None if it is unassigned.
+    pub fn assignment(&self) -> Option<(SignedInteger, Scalar)> {
+        self.0.assignment.map(|v| (v.q, v.f))
+    }
+
+    /// Combines two wide values of the same flavor into one, adding up their quantities.
+    /// The flavors are constrained to be equal.
+    /// Fails with `VMError::InconsistentWitness` if the assigned flavors differ
+    /// or the sum of the assigned quantities is out of range.
+    pub fn combine<CS: r1cs::ConstraintSystem>(
+        self,
+        other: WideValue,
+        cs: &mut CS,
+    ) -> Result<WideValue, VMError> {
+        let assignment = match (self.0.assignment, other.0.assignment) {
+            (Some(a), Some(b)) => {
+                if a.f != b.f {
+                    return Err(VMError::InconsistentWitness);
+                }
+                let q = (a.q + b.q).ok_or(VMError::InconsistentWitness)?;
+                Some(spacesuit::Value { q, f: a.f })
+            }
+            (_, _) => None,
+        };
+        let q = cs
+            .allocate(assignment.map(|v| v.q.to_scalar()))
+            .map_err(|e| VMError::R1CSError(e))?;
+
+        cs.constrain(q - self.0.q - other.0.q);
+        cs.constrain(self.0.f - other.0.f);
+
+        Ok(WideValue(spacesuit::AllocatedValue {
+            q,
+            f: self.0.f,
+            assignment,
+        }))
+    }
+
+    /// Constrains the wide values to add up to a zero quantity of a given flavor:
+    /// each value must have that flavor, and the sum of their quantities must be zero.
+    /// This is the conservation check that `cloak` performs for the values of each flavor.
+    /// Fails with `VMError::InconsistentWitness` if the assignments do not satisfy the constraints.
+    pub fn constrain_zero_sum<CS: r1cs::ConstraintSystem>(
+        values: impl IntoIterator<Item = WideValue>,
+        flv: Scalar,
+        cs: &mut CS,
+    ) -> Result<(), VMError> {
+        let mut sum = r1cs::LinearCombination::default();
+        let mut assigned_sum = Some(SignedInteger::from(0u64));
+        for value in values {
+            match value.0.assignment {
+                Some(v) if v.f != flv => return Err(VMError::InconsistentWitness),
+                Some(v) => {
+                    assigned_sum = assigned_sum
+                        .map(|s| (s + v.q).ok_or(VMError::InconsistentWitness))
+                        .transpose()?;
+                }
+                None => assigned_sum = None,
+            }
+            cs.constrain(value.0.f - flv);
+            sum = sum + value.0.q;
+        }
+        if let Some(s) = assigned_sum {
+            if s != SignedInteger::from(0u64) {
+                return Err(VMError::InconsistentWitness);
+            }
+        }
+        cs.constrain(sum);
+        Ok(())
+    }
+}
+
 impl Encodable for Value {
     fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
         w.write_point(b"qty", &self.qty.to_point())?;
@@ -367,6 +465,20 @@ impl ExactSizeEncodable for Value {
 }
 
 impl ClearValue {
+    /// Adds two values of the same flavor.
+    /// Fails with `VMError::FlavorMismatch` if the flavors are different,
+    /// and with `VMError::ValueOverflow` if the total quantity does not fit in 64 bits.
+    pub fn checked_add(&self, other: &ClearValue) -> Result<ClearValue, VMError> {
+        if self.flv != other.flv {
+            return Err(VMError::FlavorMismatch);
+        }
+        let qty = self
+            .qty
+            .checked_add(other.qty)
+            .ok_or(VMError::ValueOverflow)?;
+        Ok(ClearValue { qty, flv: self.flv })
+    }
+
     /// Selects a subset of coins to be equal or greater than the given value.
     /// Returns the list of selected values and an amount of _change_ quantity.
     pub fn select_coins<I, T>(&self, coins: I) -> Option<(Vec<T>, ClearValue)>
@@ -401,6 +513,16 @@ impl ClearValue {
     }
 }
 
+/// Sums up the quantities of the values per flavor.
+/// Totals that do not fit in 64 bits are capped at `u64::MAX`.
+pub fn group_by_flavor(values: &[ClearValue]) -> HashMap<Scalar, u64> {
+    values.iter().fold(HashMap::new(), |mut totals, value| {
+        let total = totals.entry(value.flv).or_insert(0u64);
+        *total = total.saturating_add(value.qty);
+        totals
+    })
+}
+
 // Upcasting all witness data types to String
 
 impl<T> From<T> for String
@@ -500,3 +622,61 @@ impl From<CopyableItem> for Item {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bulletproofs::{BulletproofGens, PedersenGens};
+
+    /// Cloaks a wide value allocated without a range check into a single output
+    /// that carries the same witness, while the prover claims `claimed_qty`
+    /// as the output quantity in the range proof.
+    fn cloak_unchecked<CS: r1cs::RandomizableConstraintSystem>(
+        cs: &mut CS,
+        assignment: Option<(SignedInteger, Scalar)>,
+        claimed_qty: Option<SignedInteger>,
+    ) -> Result<(), VMError> {
+        let input = WideValue::allocate(cs, assignment)?;
+        let output = WideValue::allocate(cs, assignment)?.0;
+        let output = spacesuit::AllocatedValue {
+            assignment: output
+                .assignment
+                .and_then(|v| claimed_qty.map(|q| spacesuit::Value { q, f: v.f })),
+            ..output
+        };
+        spacesuit::cloak(cs, vec![input.0], vec![output]).map_err(|e| VMError::R1CSError(e))
+    }
+
+    fn prove_and_verify(qty: SignedInteger, claimed_qty: SignedInteger) -> Result<(), VMError> {
+        let flv = Scalar::from(1u64);
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(128, 1);
+
+        let mut prover = r1cs::Prover::new(&pc_gens, Transcript::new(b"UncheckedWideValue"));
+        cloak_unchecked(&mut prover, Some((qty, flv)), Some(claimed_qty))?;
+        let proof = prover.prove(&bp_gens).map_err(|e| VMError::R1CSError(e))?;
+
+        let mut verifier = r1cs::Verifier::new(Transcript::new(b"UncheckedWideValue"));
+        cloak_unchecked(&mut verifier, None, None)?;
+        verifier
+            .verify(&proof, &pc_gens, &bp_gens)
+            .map_err(|_| VMError::InvalidR1CSProof)
+    }
+
+    #[test]
+    fn unchecked_wide_value_is_range_checked_by_cloak() {
+        // An in-range quantity passes through cloak.
+        assert!(prove_and_verify(5u64.into(), 5u64.into()).is_ok());
+
+        // A negative quantity is a field element far above 2^64,
+        // so the prover cannot produce bits for the output range proof...
+        assert!(prove_and_verify((-5i64).into(), (-5i64).into()).is_err());
+
+        // ...and claiming an in-range quantity instead fails verification.
+        let forged = SignedInteger::from(u64::max_value() - 4);
+        assert_eq!(
+            prove_and_verify((-5i64).into(), forged),
+            Err(VMError::InvalidR1CSProof)
+        );
+    }
+}
@@ -1,20 +1,22 @@
 use bulletproofs::r1cs;
 use bulletproofs::r1cs::ConstraintSystem;
 use bulletproofs::{BulletproofGens, PedersenGens};
-use curve25519_dalek::ristretto::CompressedRistretto;
+use core::borrow::Borrow;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
-use musig::{Multisignature, VerificationKey};
+use musig::{BatchVerification, Multisignature, VerificationKey};
 
 use crate::constraints::Commitment;
 use crate::contract::ContractID;
-use crate::encoding::{ExactSizeEncodable, Reader};
+use crate::encoding::Reader;
 use crate::errors::VMError;
 use crate::fees::FeeRate;
 use crate::ops::Instruction;
 use crate::predicate::Predicate;
 use crate::program::ProgramItem;
-use crate::tx::{PrecomputedTx, Tx, VerifiedTx};
-use crate::vm::{Delegate, VM};
+use crate::tx::{signtx_transcript, PrecomputedTx, Tx, TxHeader, TxLog, VerifiedTx};
+use crate::vm::{Delegate, DEFAULT_BUDGET, DEFAULT_MAX_DEPTH, VM};
 
 /// This is the entry point API for verifying a transaction.
 /// Verifier passes the `Tx` object through the VM,
@@ -24,7 +26,29 @@ use crate::vm::{Delegate, VM};
 pub struct Verifier {
     signtx_items: Vec<(VerificationKey, ContractID)>,
     cs: r1cs::Verifier<Transcript>,
-    batch: musig::BatchVerifier<rand::rngs::ThreadRng>,
+    deferred: DeferredOperations,
+    budget: u64,
+    max_program_depth: usize,
+    max_predicate_depth: usize,
+    pc_gens: PedersenGens,
+}
+
+/// Point operations that the verifier defers until the end of the transaction verification:
+/// signature checks and other Ristretto255 operations performed by the VM.
+/// Each operation is a statement that a linear combination of points is the identity.
+/// The operations can be appended to any `BatchVerification` instance,
+/// so they can be checked in one batch together with other transactions or protocols.
+#[derive(Clone, Default)]
+pub struct DeferredOperations {
+    ops: Vec<DeferredOperation>,
+}
+
+/// Statement `basepoint_scalar·B + sum{dynamic_scalars[i]·dynamic_points[i]} == 0`.
+#[derive(Clone)]
+struct DeferredOperation {
+    basepoint_scalar: Scalar,
+    dynamic_scalars: Vec<Scalar>,
+    dynamic_points: Vec<Option<RistrettoPoint>>,
 }
 
 /// Verifier's implementation of the running state of the program.
@@ -35,7 +59,7 @@ pub struct VerifierRun {
 
 impl Delegate<r1cs::Verifier<Transcript>> for Verifier {
     type RunType = VerifierRun;
-    type BatchVerifier = musig::BatchVerifier<rand::rngs::ThreadRng>;
+    type BatchVerifier = DeferredOperations;
 
     fn commit_variable(
         &mut self,
@@ -46,6 +70,10 @@ impl Delegate<r1cs::Verifier<Transcript>> for Verifier {
         Ok((point, var))
     }
 
+    fn pc_gens(&self) -> &PedersenGens {
+        &self.pc_gens
+    }
+
     fn process_tx_signature(
         &mut self,
         pred: Predicate,
@@ -78,7 +106,7 @@ impl Delegate<r1cs::Verifier<Transcript>> for Verifier {
     }
 
     fn batch_verifier(&mut self) -> &mut Self::BatchVerifier {
-        &mut self.batch
+        &mut self.deferred
     }
 }
 
@@ -90,47 +118,145 @@ impl Verifier {
     /// only holds a &mut of the transcript that can only be parked in the lexical scope,
     /// but not in the struct. And we need CS instance both for building tx and for verifying.
     pub(crate) fn precompute(tx: &Tx) -> Result<PrecomputedTx, VMError> {
-        let cs = r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs"));
+        Verifier::new().precompute_tx(tx)
+    }
 
-        let mut verifier = Verifier {
-            signtx_items: Vec::new(),
-            cs: cs,
-            batch: musig::BatchVerifier::new(rand::thread_rng()),
-        };
+    /// Creates a verifier that limits the execution of the transaction program
+    /// to `budget` steps: each executed instruction and each nested program
+    /// (expanded by `eval`, `call` and other instructions) costs one step.
+    /// The step count depends only on the transaction, so all nodes agree
+    /// on whether the transaction fits the budget.
+    ///
+    /// `Tx::precompute` and `Tx::verify` use `DEFAULT_BUDGET`; use this with `precompute_tx`
+    /// to apply a different budget.
+    pub fn with_budget(budget: u64) -> Self {
+        Verifier {
+            budget,
+            ..Verifier::new()
+        }
+    }
+
+    /// Configures the verifier to check the commitments and the R1CS proof
+    /// against custom Pedersen generators, e.g. `Verifier::with_budget(budget).with_gens(pc_gens)`.
+    /// Transactions built with the default generators do not verify with custom ones, and vice versa:
+    /// all nodes of a network must use the same generators.
+    pub fn with_gens(self, pc_gens: PedersenGens) -> Self {
+        Verifier { pc_gens, ..self }
+    }
+
+    /// Limits the nesting depth of the programs executed by `eval`, `call` and other instructions,
+    /// and the depth of the predicate trees in the call proofs.
+    /// Both limits default to `DEFAULT_MAX_DEPTH`, which is enough for any practical contract,
+    /// while bounding the memory spent on adversarial transactions.
+    pub fn with_max_depth(self, max_program_depth: usize, max_predicate_depth: usize) -> Self {
+        Verifier {
+            max_program_depth,
+            max_predicate_depth,
+            ..self
+        }
+    }
+
+    /// Computes the TxID and TxLog like `Tx::precompute`, but within the verifier's budget.
+    /// Fails with `VMError::BudgetExceeded` if the program executes more steps than allowed,
+    /// and with `VMError::DepthLimitExceeded` if it exceeds the depth limits.
+    pub fn precompute_tx(self, tx: &Tx) -> Result<PrecomputedTx, VMError> {
+        let mut verifier = self;
+        let budget = verifier.budget;
+        let (max_program_depth, max_predicate_depth) =
+            (verifier.max_program_depth, verifier.max_predicate_depth);
 
         let vm = VM::new(
             tx.header,
             VerifierRun::new(tx.program.clone()),
             &mut verifier,
-        );
+        )
+        .with_budget(budget)
+        .with_max_depth(max_program_depth, max_predicate_depth);
 
         let (id, log, fee) = vm.run()?;
 
+        // Defer the check of the signature over txid
+        if verifier.signtx_items.len() != 0 {
+            let mut signtx_transcript = signtx_transcript(&id);
+            tx.signature.verify_multi_batched(
+                &mut signtx_transcript,
+                core::mem::replace(&mut verifier.signtx_items, Vec::new()),
+                &mut verifier.deferred,
+            );
+        }
+
         Ok(PrecomputedTx {
             header: tx.header,
             id,
             log,
-            feerate: FeeRate::new(fee, tx.encoded_size()),
-            signature: tx.signature.clone(),
+            feerate: FeeRate::new(fee, tx.size()),
             proof: tx.proof.clone(),
             verifier,
         })
     }
 
+    /// Checks structural validity of a transaction given only its header and program,
+    /// and returns the resulting `TxLog`.
+    /// The input and output commitments are carried by the program itself.
+    ///
+    /// This executes the VM logic and collects the constraints, but does not check
+    /// the R1CS proof, the transaction signature or other deferred point operations.
+    /// A successful result does not mean the transaction is valid:
+    /// use `Verifier::verify_tx` for the complete verification.
+    pub fn verify_structure(header: TxHeader, program: &[u8]) -> Result<TxLog, VMError> {
+        let mut verifier = Verifier::new();
+        let vm = VM::new(header, VerifierRun::new(program.to_vec()), &mut verifier)
+            .with_budget(DEFAULT_BUDGET);
+        let (_id, log, _fee) = vm.run()?;
+        Ok(log)
+    }
+
+    fn new() -> Self {
+        Verifier {
+            signtx_items: Vec::new(),
+            cs: r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs")),
+            deferred: DeferredOperations::new(),
+            budget: DEFAULT_BUDGET,
+            max_program_depth: DEFAULT_MAX_DEPTH,
+            max_predicate_depth: DEFAULT_MAX_DEPTH,
+            pc_gens: PedersenGens::default(),
+        }
+    }
+
+    /// Point operations deferred during the VM execution.
+    pub(crate) fn deferred_operations(&self) -> &DeferredOperations {
+        &self.deferred
+    }
+
     /// Verifies the `Tx` object by executing the VM and returns the `VerifiedTx`.
     /// Returns an error if the program is malformed or any of the proofs are not valid.
     pub fn verify_tx(
         verifiable_tx: PrecomputedTx,
         bp_gens: &BulletproofGens,
     ) -> Result<VerifiedTx, VMError> {
-        let pc_gens = PedersenGens::default();
+        let mut batch = musig::BatchVerifier::new(rand::thread_rng());
+        let vtx = Self::verify_tx_deferred(verifiable_tx, bp_gens, &mut batch)?;
+
+        // Verify all deferred crypto operations.
+        batch
+            .verify()
+            .map_err(|_| VMError::BatchSignatureVerificationFailed)?;
+
+        Ok(vtx)
+    }
 
+    /// Verifies the R1CS proof of the `Tx` and appends the deferred point operations to the batch.
+    /// The returned `VerifiedTx` is valid only if the batch verification succeeds.
+    pub fn verify_tx_deferred(
+        verifiable_tx: PrecomputedTx,
+        bp_gens: &BulletproofGens,
+        batch: &mut impl BatchVerification,
+    ) -> Result<VerifiedTx, VMError> {
         let PrecomputedTx {
             header,
             id,
             log,
             feerate,
-            signature,
             proof,
             mut verifier,
         } = verifiable_tx;
@@ -141,26 +267,10 @@ impl Verifier {
         // Verify the R1CS proof
         verifier
             .cs
-            .verify(&proof, &pc_gens, bp_gens)
+            .verify(&proof, &verifier.pc_gens, bp_gens)
             .map_err(|_| VMError::InvalidR1CSProof)?;
 
-        // Verify the signatures over txid
-        let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
-        signtx_transcript.append_message(b"txid", &id);
-
-        if verifier.signtx_items.len() != 0 {
-            signature.verify_multi_batched(
-                &mut signtx_transcript,
-                verifier.signtx_items,
-                &mut verifier.batch,
-            );
-        }
-
-        // Verify all deferred crypto operations.
-        verifier
-            .batch
-            .verify()
-            .map_err(|_| VMError::BatchSignatureVerificationFailed)?;
+        verifier.deferred.append_to(batch);
 
         Ok(VerifiedTx {
             header,
@@ -171,6 +281,49 @@ impl Verifier {
     }
 }
 
+impl DeferredOperations {
+    /// Creates an empty list of operations.
+    pub fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    /// Returns the number of deferred operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns true if there are no deferred operations.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Appends all the operations to the batch.
+    pub fn append_to(&self, batch: &mut impl BatchVerification) {
+        for op in self.ops.iter() {
+            batch.append(
+                op.basepoint_scalar,
+                op.dynamic_scalars.iter().copied(),
+                op.dynamic_points.iter().copied(),
+            );
+        }
+    }
+}
+
+impl BatchVerification for DeferredOperations {
+    fn append<I, J>(&mut self, basepoint_scalar: I::Item, dynamic_scalars: I, dynamic_points: J)
+    where
+        I: IntoIterator<Item = Scalar>,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator<Item = Option<RistrettoPoint>>,
+    {
+        self.ops.push(DeferredOperation {
+            basepoint_scalar,
+            dynamic_scalars: dynamic_scalars.into_iter().collect(),
+            dynamic_points: dynamic_points.into_iter().collect(),
+        });
+    }
+}
+
 impl VerifierRun {
     fn new(program: Vec<u8>) -> Self {
         VerifierRun { program, offset: 0 }
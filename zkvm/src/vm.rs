@@ -1,4 +1,4 @@
-use bulletproofs::r1cs;
+use bulletproofs::{r1cs, PedersenGens};
 use core::iter;
 use core::iter::FromIterator;
 use curve25519_dalek::ristretto::CompressedRistretto;
@@ -14,7 +14,7 @@ use crate::contract::{Anchor, Contract, ContractID, PortableItem};
 use crate::encoding::*;
 use crate::errors::VMError;
 use crate::fees::{fee_flavor, CheckedFee};
-use crate::ops::Instruction;
+use crate::ops::{Instruction, Opcode};
 use crate::predicate::{CallProof, Predicate};
 use crate::program::ProgramItem;
 use crate::scalar_witness::ScalarWitness;
@@ -22,7 +22,19 @@ use crate::tx::{TxEntry, TxHeader, TxID, TxLog};
 use crate::types::*;
 
 /// Current tx version determines which extension opcodes are treated as noops (see VM.extension flag).
-pub const CURRENT_VERSION: u64 = 1;
+pub const CURRENT_VERSION: u64 = 2;
+
+/// Tx version that assigns the `reblind` instruction.
+/// In the earlier versions its opcode is an extension opcode.
+const REBLIND_VERSION: u64 = 2;
+
+/// Default limit on the program nesting depth and the predicate tree depth
+/// (see `Verifier::with_max_depth`).
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Default limit on the number of steps executed by the verifier
+/// (see `Verifier::with_budget`).
+pub const DEFAULT_BUDGET: u64 = 1 << 16;
 
 pub(crate) struct VM<'d, CS, D>
 where
@@ -32,6 +44,9 @@ where
     mintime_ms: u64,
     maxtime_ms: u64,
 
+    // tx version that determines the set of assigned opcodes
+    version: u64,
+
     // is true when tx version is in the future and
     // we allow treating unassigned opcodes as no-ops.
     extension: bool,
@@ -50,6 +65,15 @@ where
 
     // collect the total fee to check for overflow
     total_fee: CheckedFee,
+
+    // number of steps left: each instruction and each nested program costs one step
+    budget: u64,
+
+    // maximum number of paused programs in the run stack
+    max_program_depth: usize,
+
+    // maximum number of neighbors in the merkle path of the call proof
+    max_predicate_depth: usize,
 }
 
 pub(crate) trait Delegate<CS: r1cs::RandomizableConstraintSystem> {
@@ -65,6 +89,9 @@ pub(crate) trait Delegate<CS: r1cs::RandomizableConstraintSystem> {
         com: &Commitment,
     ) -> Result<(CompressedRistretto, r1cs::Variable), VMError>;
 
+    /// Returns the Pedersen generators used by all the commitments in the transaction.
+    fn pc_gens(&self) -> &PedersenGens;
+
     /// Adds a key represented by Predicate to either verify or
     /// sign a transaction
     fn process_tx_signature(
@@ -99,6 +126,7 @@ where
         VM {
             mintime_ms: header.mintime_ms,
             maxtime_ms: header.maxtime_ms,
+            version: header.version,
             extension: header.version > CURRENT_VERSION,
             last_anchor: None,
             delegate,
@@ -107,9 +135,29 @@ where
             run_stack: Vec::new(),
             txlog: vec![TxEntry::Header(header)].into(),
             total_fee: CheckedFee::zero(),
+            budget: u64::max_value(),
+            max_program_depth: DEFAULT_MAX_DEPTH,
+            max_predicate_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
+    /// Limits the number of steps the VM may execute:
+    /// each instruction and each nested program (`eval`, `call`, etc.) costs one step.
+    /// The VM fails with `VMError::BudgetExceeded` once the budget is spent.
+    pub fn with_budget(mut self, budget: u64) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Limits the nesting depth of the programs (`eval`, `call`, etc.)
+    /// and the depth of the predicate trees used by `call`.
+    /// The VM fails with `VMError::DepthLimitExceeded` when either limit is exceeded.
+    pub fn with_max_depth(mut self, max_program_depth: usize, max_predicate_depth: usize) -> Self {
+        self.max_program_depth = max_program_depth;
+        self.max_predicate_depth = max_predicate_depth;
+        self
+    }
+
     /// Runs through the entire program and nested programs until completion.
     pub fn run(mut self) -> Result<(TxID, TxLog, CheckedFee), VMError> {
         loop {
@@ -145,6 +193,7 @@ where
     /// Returns a flag indicating whether to continue the execution
     fn step(&mut self) -> Result<bool, VMError> {
         if let Some(instr) = self.delegate.next_instruction(&mut self.current_run)? {
+            self.consume_budget()?;
             // Attempt to read the next instruction and advance the program state
             match instr {
                 Instruction::Push(data) => self.pushdata(data),
@@ -182,6 +231,7 @@ where
                 Instruction::Signtx => self.signtx()?,
                 Instruction::Signid => self.signid()?,
                 Instruction::Signtag => self.signtag()?,
+                Instruction::Reblind => self.reblind()?,
                 Instruction::Ext(opcode) => self.ext(opcode)?,
             }
             return Ok(true);
@@ -301,10 +351,12 @@ where
         let v_scalar = self.pop_item()?.to_string()?.to_scalar()?.to_scalar();
         let v_point = self.pop_item()?.to_string()?.to_commitment()?.to_point();
 
+        // 0 == -v·B + V
+        let b_point = self.delegate.pc_gens().B;
         self.delegate.batch_verifier().append(
-            -v_scalar,
-            iter::once(Scalar::one()),
-            iter::once(v_point.decompress()),
+            Scalar::zero(),
+            iter::once(-v_scalar).chain(iter::once(Scalar::one())),
+            iter::once(Some(b_point)).chain(iter::once(v_point.decompress())),
         );
 
         // Push commitment item
@@ -589,6 +641,10 @@ where
         let call_proof = (&call_proof_bytes[..]).read_all(|r| CallProof::decode(r))?;
         let contract = self.pop_item()?.to_contract()?;
 
+        if call_proof.path.neighbors.len() > self.max_predicate_depth {
+            return Err(VMError::DepthLimitExceeded);
+        }
+
         // 0 == -P + X + h1(X, M)*B
         contract.predicate.verify_taproot_batched(
             &program_item,
@@ -672,6 +728,46 @@ where
         Ok(())
     }
 
+    fn reblind(&mut self) -> Result<(), VMError> {
+        if self.version < REBLIND_VERSION {
+            return self.ext(Opcode::Reblind.to_u8());
+        }
+
+        // Proof
+        let proof = self.pop_item()?.to_string()?.to_bytes();
+        let (r_point, s) = (&proof[..]).read_all(|r| {
+            let r_point = r.read_point()?;
+            let s = r.read_scalar()?;
+            Ok::<_, VMError>((r_point, s))
+        })?;
+
+        // New commitment
+        let v2 = self.pop_item()?.to_string()?.to_commitment()?;
+
+        // Old variable
+        let var1 = self.pop_item()?.to_variable()?;
+
+        // 0 == -s·B2 + R + e·V2 - e·V1
+        let v1_point = var1.commitment.to_point();
+        let v2_point = v2.to_point();
+        let e = Commitment::reblind_challenge(&v1_point, &v2_point, &r_point);
+        let b_blinding = self.delegate.pc_gens().B_blinding;
+        self.delegate.batch_verifier().append(
+            Scalar::zero(),
+            iter::once(-s)
+                .chain(iter::once(Scalar::one()))
+                .chain(iter::once(e))
+                .chain(iter::once(-e)),
+            iter::once(Some(b_blinding))
+                .chain(iter::once(r_point.decompress()))
+                .chain(iter::once(v2_point.decompress()))
+                .chain(iter::once(v1_point.decompress())),
+        );
+
+        self.push_item(Variable { commitment: v2 });
+        Ok(())
+    }
+
     fn ext(&mut self, _: u8) -> Result<(), VMError> {
         if self.extension {
             // if extensions are allowed by tx version,
@@ -729,12 +825,21 @@ where
     }
 
     fn continue_with_program(&mut self, prog: ProgramItem) -> Result<(), VMError> {
+        self.consume_budget()?;
+        if self.run_stack.len() >= self.max_program_depth {
+            return Err(VMError::DepthLimitExceeded);
+        }
         let new_run = self.delegate.new_run(prog)?;
         let paused_run = mem::replace(&mut self.current_run, new_run);
         self.run_stack.push(paused_run);
         Ok(())
     }
 
+    fn consume_budget(&mut self) -> Result<(), VMError> {
+        self.budget = self.budget.checked_sub(1).ok_or(VMError::BudgetExceeded)?;
+        Ok(())
+    }
+
     fn add_range_proof(&mut self, expr: Expression) -> Result<(), VMError> {
         match expr {
             Expression::Constant(x) => {
@@ -1,16 +1,18 @@
-use bulletproofs::BulletproofGens;
+use bulletproofs::{r1cs, BulletproofGens, PedersenGens};
 use curve25519_dalek::scalar::Scalar;
 use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_COMPRESSED, ristretto::CompressedRistretto, traits::Identity,
 };
 use merlin::Transcript;
-use musig::{Multisignature, Signature};
+use musig::{BatchVerifier, Multisignature, Signature};
 use rand::Rng;
 
 use zkvm::{
-    Anchor, Commitment, Contract, PortableItem, Predicate, PredicateTree, Program, Prover, String,
-    TxHeader, TxID, TxLog, VMError, Value,
+    group_by_flavor, Anchor, AnchoredProgram, ClearValue, Commitment, Contract, ContractID,
+    PortableItem, Predicate, PredicateTree, Program, Prover, RetiredValue, String, Tx, TxEntry,
+    TxHeader, TxID, TxLog, VMError, Value, Verifier, WideValue, DEFAULT_BUDGET, DEFAULT_MAX_DEPTH,
 };
+use zkvm::{merkle, Hash, Hasher, MerkleTree};
 
 // TODO(vniu): move builder convenience functions into separate crate,
 // and refactor tests and Token
@@ -105,44 +107,7 @@ fn make_output(qty: u64, flv: Scalar, predicate: Predicate) -> Contract {
 }
 
 fn build_and_verify(program: Program) -> Result<(TxID, TxLog), VMError> {
-    let (txlog, tx) = {
-        // Build tx
-        let bp_gens = BulletproofGens::new(256, 1);
-        let header = TxHeader {
-            version: 0u64,
-            mintime_ms: 0u64,
-            maxtime_ms: 0u64,
-        };
-        let utx = Prover::build_tx(program, header, &bp_gens)?;
-
-        let sig = if utx.signing_instructions.len() == 0 {
-            Signature {
-                R: CompressedRistretto::identity(),
-                s: Scalar::zero(),
-            }
-        } else {
-            // find all the secret scalars for the pubkeys used in the VM
-            let privkeys: Vec<Scalar> = utx
-                .signing_instructions
-                .iter()
-                .map(|(predicate, _msg)| predicate_privkey(predicate))
-                .collect();
-
-            let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
-            signtx_transcript.append_message(b"txid", &utx.txid.0);
-            Signature::sign_multi(
-                privkeys,
-                utx.signing_instructions
-                    .iter()
-                    .map(|(p, m)| (p.verification_key(), m))
-                    .collect(),
-                &mut signtx_transcript,
-            )
-            .unwrap()
-        };
-
-        (utx.txlog.clone(), utx.sign(sig))
-    };
+    let (txlog, tx) = build_tx(program)?;
 
     // Verify tx
     let bp_gens = BulletproofGens::new(256, 1);
@@ -151,6 +116,55 @@ fn build_and_verify(program: Program) -> Result<(TxID, TxLog), VMError> {
     Ok((vtx.id, txlog))
 }
 
+fn build_tx(program: Program) -> Result<(TxLog, Tx), VMError> {
+    build_tx_with_gens(program, &PedersenGens::default())
+}
+
+fn build_tx_with_gens(program: Program, pc_gens: &PedersenGens) -> Result<(TxLog, Tx), VMError> {
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    build_tx_with_header(program, header, pc_gens)
+}
+
+fn build_tx_with_header(
+    program: Program,
+    header: TxHeader,
+    pc_gens: &PedersenGens,
+) -> Result<(TxLog, Tx), VMError> {
+    let bp_gens = BulletproofGens::new(256, 1);
+    let utx = Prover::build_tx_with_gens(program, header, &bp_gens, pc_gens)?;
+
+    let sig = if utx.signing_instructions.len() == 0 {
+        Signature {
+            R: CompressedRistretto::identity(),
+            s: Scalar::zero(),
+        }
+    } else {
+        // find all the secret scalars for the pubkeys used in the VM
+        let privkeys: Vec<Scalar> = utx
+            .signing_instructions
+            .iter()
+            .map(|(predicate, _msg)| predicate_privkey(predicate))
+            .collect();
+
+        let mut signtx_transcript = utx.signing_transcript();
+        Signature::sign_multi(
+            privkeys,
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &mut signtx_transcript,
+        )
+        .unwrap()
+    };
+
+    Ok((utx.txlog.clone(), utx.sign(sig)))
+}
+
 fn spend_1_1_contract(
     input: u64,
     output: u64,
@@ -165,6 +179,291 @@ fn spend_1_1_contract(
     })
 }
 
+#[test]
+fn anchored_outputs() {
+    let flavor = Scalar::from(1u64);
+    let input = make_output(10u64, flavor, generate_predicate(1));
+    let outputs: Vec<(Value, Predicate)> = [1u64, 2, 3, 4]
+        .iter()
+        .map(|qty| {
+            let value = Value {
+                qty: Commitment::blinded(*qty),
+                flv: Commitment::blinded(flavor),
+            };
+            (value, generate_predicate(10 + qty))
+        })
+        .collect();
+
+    let mut builder = AnchoredProgram::new();
+    assert_eq!(
+        builder
+            .add_output(outputs[0].0.clone(), outputs[0].1.clone())
+            .unwrap_err(),
+        VMError::AnchorMissing
+    );
+    let input_id = builder.add_input(input);
+    builder.program().signtx();
+    for (value, _) in outputs.iter() {
+        builder
+            .program()
+            .push(value.qty.clone())
+            .push(value.flv.clone());
+    }
+    builder.program().cloak(1, outputs.len());
+    let output_ids: Vec<ContractID> = outputs
+        .into_iter()
+        .map(|(value, pred)| builder.add_output(value, pred).unwrap())
+        .collect();
+
+    let (_, txlog) = build_and_verify(builder.into_program()).unwrap();
+    assert_eq!(txlog.inputs().collect::<Vec<_>>(), vec![&input_id]);
+    assert_eq!(
+        txlog
+            .outputs()
+            .map(|contract| contract.id())
+            .collect::<Vec<_>>(),
+        output_ids
+    );
+}
+
+#[test]
+fn deferred_verification() {
+    let flavor = Scalar::from(1u64);
+    let (_, tx1) = build_tx(spend_1_1_contract(
+        10u64,
+        10u64,
+        flavor,
+        generate_predicate(1),
+        generate_predicate(2),
+    ))
+    .unwrap();
+    let (_, tx2) = build_tx(spend_1_1_contract(
+        5u64,
+        5u64,
+        flavor,
+        generate_predicate(3),
+        generate_predicate(4),
+    ))
+    .unwrap();
+    let bp_gens = BulletproofGens::new(256, 1);
+
+    // Point operations of both transactions are checked in the caller's batch.
+    let mut batch = BatchVerifier::new(rand::thread_rng());
+    for tx in vec![tx1.clone(), tx2] {
+        let ptx = tx.precompute().unwrap();
+        assert!(!ptx.deferred_operations().is_empty());
+        ptx.verify_deferred(&bp_gens, &mut batch).unwrap();
+    }
+    assert!(batch.verify().is_ok());
+
+    // Invalid signature is detected only by the batch.
+    let mut bad_tx = tx1;
+    bad_tx.signature.s += Scalar::one();
+    let mut batch = BatchVerifier::new(rand::thread_rng());
+    bad_tx
+        .precompute()
+        .unwrap()
+        .verify_deferred(&bp_gens, &mut batch)
+        .unwrap();
+    assert!(batch.verify().is_err());
+    assert_eq!(
+        bad_tx.verify(&bp_gens).unwrap_err(),
+        VMError::BatchSignatureVerificationFailed
+    );
+}
+
+#[test]
+fn structural_verification() {
+    let (txlog, tx) = build_tx(spend_1_1_contract(
+        10u64,
+        10u64,
+        Scalar::from(1u64),
+        generate_predicate(1),
+        generate_predicate(2),
+    ))
+    .unwrap();
+
+    let log = Verifier::verify_structure(tx.header, &tx.program).unwrap();
+    assert!(TxID::from_log(&log) == TxID::from_log(&txlog));
+
+    // Malformed programs are rejected without the proof.
+    let prog = Program::build(|p| {
+        p.mintime().dup(0);
+    });
+    assert_eq!(
+        Verifier::verify_structure(tx.header, &prog.to_bytes()).unwrap_err(),
+        VMError::TypeNotCopyable
+    );
+}
+
+#[test]
+fn reblind() {
+    let flavor = Scalar::from(1u64);
+    let reblind_program = |v1: Commitment, v2: Commitment, proof: Vec<u8>, qty: u64| {
+        let mut program = spend_1_1_contract(
+            10u64,
+            10u64,
+            flavor,
+            generate_predicate(1),
+            generate_predicate(2),
+        );
+        program
+            .push(v1)
+            .commit()
+            .push(v2)
+            .push(String::Opaque(proof))
+            .reblind()
+            .expr()
+            .push(String::Scalar(Box::new(qty.into())))
+            .scalar()
+            .eq()
+            .verify();
+        program
+    };
+
+    // The instruction is assigned in tx version 2.
+    let build_and_verify_v2 = |program: Program| {
+        let header = TxHeader {
+            version: 2u64,
+            mintime_ms: 0u64,
+            maxtime_ms: 0u64,
+        };
+        let (_, tx) = build_tx_with_header(program, header, &PedersenGens::default())?;
+        tx.verify(&BulletproofGens::new(256, 1)).map(|_| ())
+    };
+
+    let v1 = Commitment::blinded(5u64);
+    let (v2, proof) = v1.reblind(Scalar::from(42u64)).unwrap();
+    assert_eq!(v2.assignment(), v1.assignment());
+    assert_ne!(v2.to_point(), v1.to_point());
+    assert!(build_and_verify_v2(reblind_program(v1.clone(), v2.clone(), proof.clone(), 5)).is_ok());
+
+    // In the earlier versions the opcode is an extension opcode.
+    assert_eq!(
+        build_and_verify(reblind_program(v1.clone(), v2, proof, 5)).unwrap_err(),
+        VMError::ExtensionsNotAllowed
+    );
+
+    // Proof for a commitment to another value is rejected.
+    let (v3, proof) = Commitment::blinded(6u64)
+        .reblind(Scalar::from(42u64))
+        .unwrap();
+    assert_eq!(
+        build_and_verify_v2(reblind_program(v1, v3, proof, 6)).unwrap_err(),
+        VMError::BatchSignatureVerificationFailed
+    );
+}
+
+/// Combines all wide values except the last one, and checks that the result cancels out the last one.
+fn wide_value_gadget<CS: r1cs::ConstraintSystem>(
+    cs: &mut CS,
+    assignments: Vec<Option<(i64, Scalar)>>,
+    flv: Scalar,
+) -> Result<(), VMError> {
+    let mut values = assignments
+        .into_iter()
+        .map(|a| WideValue::allocate(cs, a.map(|(q, f)| (q.into(), f))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let last = values.pop().unwrap();
+    let first = values.remove(0);
+    let combined = values
+        .into_iter()
+        .try_fold(first, |sum, v| sum.combine(v, cs))?;
+    WideValue::constrain_zero_sum(vec![combined, last], flv, cs)
+}
+
+#[test]
+fn wide_value_conservation() {
+    let flv = Scalar::from(1u64);
+    let other_flv = Scalar::from(2u64);
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(64, 1);
+
+    let mut prover = r1cs::Prover::new(&pc_gens, Transcript::new(b"WideValueTest"));
+    // 10 + 5 - 15 = 0
+    wide_value_gadget(
+        &mut prover,
+        vec![Some((10, flv)), Some((5, flv)), Some((-15, flv))],
+        flv,
+    )
+    .unwrap();
+    let proof = prover.prove(&bp_gens).unwrap();
+
+    let mut verifier = r1cs::Verifier::new(Transcript::new(b"WideValueTest"));
+    wide_value_gadget(&mut verifier, vec![None, None, None], flv).unwrap();
+    assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
+
+    // Prover detects the unbalanced and mixed-flavor values early.
+    let mut prover = r1cs::Prover::new(&pc_gens, Transcript::new(b"WideValueTest"));
+    assert_eq!(
+        wide_value_gadget(
+            &mut prover,
+            vec![Some((10, flv)), Some((5, flv)), Some((-14, flv))],
+            flv,
+        ),
+        Err(VMError::InconsistentWitness)
+    );
+    let mut prover = r1cs::Prover::new(&pc_gens, Transcript::new(b"WideValueTest"));
+    assert_eq!(
+        wide_value_gadget(
+            &mut prover,
+            vec![Some((10, flv)), Some((5, other_flv)), Some((-15, flv))],
+            flv,
+        ),
+        Err(VMError::InconsistentWitness)
+    );
+}
+
+#[test]
+fn split_and_merge_values() {
+    let flv = Scalar::from(1u64);
+    let value = |qty| ClearValue { qty, flv };
+
+    // 10 → 3 + 7
+    let prog = Program::build(|p| {
+        p.input_helper(10, flv, generate_predicate(1))
+            .split_value(value(10), &[3, 7])
+            .unwrap()
+            .push(generate_predicate(2)) // 7 is on top
+            .output(1)
+            .push(generate_predicate(3))
+            .output(1);
+    });
+    let (_, txlog) = build_and_verify(prog).unwrap();
+    assert_eq!(txlog.outputs().count(), 2);
+
+    // 3 + 7 → 10
+    let prog = Program::build(|p| {
+        p.input_helper(3, flv, generate_predicate(1))
+            .input_helper(7, flv, generate_predicate(2))
+            .merge_values(&[value(3), value(7)])
+            .unwrap()
+            .output_helper(generate_predicate(3));
+    });
+    let (_, txlog) = build_and_verify(prog).unwrap();
+    assert_eq!(txlog.outputs().count(), 1);
+
+    // Invalid arguments do not emit any instructions.
+    let mut prog = Program::new();
+    assert_eq!(
+        prog.split_value(value(10), &[3, 6]).unwrap_err(),
+        VMError::BadArguments
+    );
+    assert_eq!(
+        prog.merge_values(&[
+            value(3),
+            ClearValue {
+                qty: 7,
+                flv: Scalar::from(2u64)
+            }
+        ])
+        .unwrap_err(),
+        VMError::BadArguments
+    );
+    assert_eq!(prog.merge_values(&[]).unwrap_err(), VMError::BadArguments);
+    assert_eq!(prog.to_bytes().len(), 0);
+}
+
 #[test]
 fn spend_1_1() {
     // Generate predicates and flavor
@@ -507,7 +806,22 @@ fn taproot_program_path() {
             .program(call_prog.clone())
             .call();
     });
-    build_and_verify(prog).unwrap();
+    build_and_verify(prog.clone()).unwrap();
+
+    // The call proof's path is deeper than allowed.
+    let (_, tx) = build_tx(prog).unwrap();
+    let depth = call_proof.path.neighbors.len();
+    assert!(Verifier::with_budget(u64::max_value())
+        .with_max_depth(DEFAULT_MAX_DEPTH, depth)
+        .precompute_tx(&tx)
+        .is_ok());
+    assert_eq!(
+        Verifier::with_budget(u64::max_value())
+            .with_max_depth(DEFAULT_MAX_DEPTH, depth - 1)
+            .precompute_tx(&tx)
+            .err(),
+        Some(VMError::DepthLimitExceeded)
+    );
 
     let wrong_prog = Program::build(|p| {
         p.push(secret_scalar + Scalar::one())
@@ -593,6 +907,307 @@ fn eval_test() {
     build_and_verify(prog).expect("should succeed");
 }
 
+#[test]
+fn execution_budget() {
+    let pred = generate_predicate(1);
+    let prog = Program::build(|p| {
+        p.push(String::from(Scalar::from(20u64)));
+        p.scalar();
+        p.program(Program::build(|p2| {
+            p2.push(String::from(Scalar::from(1u64)));
+            p2.scalar();
+            p2.program(Program::build(|p3| {
+                p3.add();
+            }));
+            p2.eval();
+        }));
+        p.eval();
+        p.push(String::from(Scalar::from(21u64)));
+        p.scalar();
+        p.eq();
+        p.verify();
+        p.input_helper(0, Scalar::zero(), pred.clone());
+        p.output_helper(pred);
+    });
+    // Top-level instructions, plus 4 + 1 nested instructions and 2 nested programs.
+    let steps = prog.len() as u64 + 7;
+    let (_, tx) = build_tx(prog).unwrap();
+
+    assert!(Verifier::with_budget(steps).precompute_tx(&tx).is_ok());
+    assert_eq!(
+        Verifier::with_budget(steps - 1).precompute_tx(&tx).err(),
+        Some(VMError::BudgetExceeded)
+    );
+    assert!(tx.precompute().is_ok());
+
+    // The program has 2 nested programs.
+    let verifier = || Verifier::with_budget(u64::max_value());
+    assert!(verifier()
+        .with_max_depth(2, DEFAULT_MAX_DEPTH)
+        .precompute_tx(&tx)
+        .is_ok());
+    assert_eq!(
+        verifier()
+            .with_max_depth(1, DEFAULT_MAX_DEPTH)
+            .precompute_tx(&tx)
+            .err(),
+        Some(VMError::DepthLimitExceeded)
+    );
+}
+
+#[test]
+fn program_depth_limit() {
+    let pred = generate_predicate(1);
+    let nested = |depth: usize| {
+        let mut prog = Program::new();
+        for _ in 0..depth {
+            prog = Program::build(|p| {
+                p.program(prog.clone()).eval();
+            });
+        }
+        Program::build(|p| {
+            p.program(prog).eval();
+            p.input_helper(0, Scalar::zero(), pred.clone());
+            p.output_helper(pred.clone());
+        })
+    };
+
+    assert!(build_and_verify(nested(DEFAULT_MAX_DEPTH - 1)).is_ok());
+    assert_eq!(
+        build_and_verify(nested(DEFAULT_MAX_DEPTH)).err(),
+        Some(VMError::DepthLimitExceeded)
+    );
+}
+
+#[test]
+fn tx_from_bytes_exact() {
+    let (_, flv) = make_flavor();
+    let prog = spend_1_1_contract(10, 10, flv, generate_predicate(1), generate_predicate(2));
+    let (_, tx) = build_tx(prog).unwrap();
+    let bytes = tx.to_bytes();
+
+    assert_eq!(Tx::from_bytes(&bytes).unwrap().to_bytes(), bytes);
+
+    let mut extended = bytes.clone();
+    extended.push(0);
+    assert_eq!(
+        Tx::from_bytes(&extended).err(),
+        Some(VMError::TrailingBytes)
+    );
+
+    let truncated = &bytes[..bytes.len() - 1];
+    assert_eq!(
+        Tx::from_bytes(truncated).err(),
+        Some(VMError::InvalidFormat)
+    );
+
+    // Programs consist of instructions only, so a truncated instruction is malformed.
+    let program = tx.program.clone();
+    assert!(Program::parse(&program).is_ok());
+    assert_eq!(
+        Program::parse(&program[..program.len() - 1]).err(),
+        Some(VMError::InvalidFormat)
+    );
+}
+
+#[test]
+fn custom_pedersen_gens() {
+    let default_gens = PedersenGens::default();
+    let pc_gens = PedersenGens {
+        B: default_gens.B * Scalar::from(3u64),
+        B_blinding: default_gens.B_blinding * Scalar::from(5u64),
+    };
+    let bp_gens = BulletproofGens::new(256, 1);
+    let (_, flv) = make_flavor();
+    let input_pred = generate_predicate(1);
+    let output_pred = generate_predicate(2);
+
+    let program = |gens: &PedersenGens| {
+        let input = Contract {
+            predicate: input_pred.clone(),
+            payload: vec![PortableItem::Value(Value {
+                qty: Commitment::blinded_with_gens(10u64, Scalar::from(1u64), gens),
+                flv: Commitment::blinded_with_gens(flv, Scalar::from(2u64), gens),
+            })],
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+        Program::build(|p| {
+            p.push(input)
+                .input()
+                .signtx()
+                .push(Commitment::blinded_with_gens(
+                    10u64,
+                    Scalar::from(3u64),
+                    gens,
+                ))
+                .push(Commitment::blinded_with_gens(flv, Scalar::from(4u64), gens))
+                .cloak(1, 1)
+                .output_helper(output_pred.clone());
+        })
+    };
+
+    // Commitments must use the generators of the transaction.
+    assert_eq!(
+        build_tx(program(&pc_gens)).err(),
+        Some(VMError::GeneratorsMismatch)
+    );
+
+    let (txlog, tx) = build_tx_with_gens(program(&pc_gens), &pc_gens).unwrap();
+
+    // The transaction does not verify with the default generators...
+    assert!(tx.verify(&bp_gens).is_err());
+
+    // ...but verifies with the generators it was built with.
+    let ptx = Verifier::with_budget(u64::max_value())
+        .with_gens(pc_gens)
+        .precompute_tx(&tx)
+        .unwrap();
+    let vtx = Verifier::verify_tx(ptx, &bp_gens).unwrap();
+    let output_ids = |log: &TxLog| log.outputs().map(|c| c.id()).collect::<Vec<_>>();
+    assert_eq!(output_ids(&vtx.log), output_ids(&txlog));
+
+    // The same program with the default generators produces different commitments.
+    let (default_txlog, _) = build_tx(program(&default_gens)).unwrap();
+    assert_ne!(output_ids(&default_txlog), output_ids(&txlog));
+}
+
+#[test]
+fn retired_values() {
+    let flv = Scalar::from(1u64);
+    let qty_blinding = Scalar::from(7u64);
+    let prog = Program::build(|p| {
+        p.input_helper(10, flv, generate_predicate(1))
+            .push(Commitment::unblinded(4u64))
+            .push(Commitment::unblinded(flv))
+            .push(Commitment::blinded_with_factor(6u64, qty_blinding))
+            .push(Commitment::unblinded(flv))
+            .cloak(1, 2)
+            .retire()
+            .retire();
+    });
+    let (_, txlog) = build_and_verify(prog).unwrap();
+    let retired: Vec<RetiredValue> = txlog.retired_values().collect();
+    assert_eq!(retired.len(), 2);
+
+    // Retired values are opened by the caller who knows the openings.
+    let openings = [
+        (ClearValue { qty: 4, flv }, Scalar::zero()),
+        (ClearValue { qty: 6, flv }, qty_blinding),
+    ];
+    let opened: Vec<ClearValue> = retired
+        .iter()
+        .filter_map(|r| {
+            openings
+                .iter()
+                .find_map(|(value, blinding)| r.open(*value, *blinding, Scalar::zero()))
+        })
+        .collect();
+    assert_eq!(opened.len(), 2);
+    assert_eq!(group_by_flavor(&opened)[&flv], 10);
+
+    // Wrong openings are rejected.
+    assert!(retired.iter().all(|r| r
+        .open(ClearValue { qty: 5, flv }, Scalar::zero(), Scalar::zero())
+        .is_none()));
+    assert!(retired.iter().all(|r| r
+        .open(ClearValue { qty: 6, flv }, Scalar::zero(), Scalar::zero())
+        .is_none()));
+
+    // Values retired with custom generators open only with the same generators.
+    let default_gens = PedersenGens::default();
+    let pc_gens = PedersenGens {
+        B: default_gens.B * Scalar::from(3u64),
+        B_blinding: default_gens.B_blinding * Scalar::from(5u64),
+    };
+    let value = ClearValue { qty: 6, flv };
+    let retired = RetiredValue {
+        qty: Commitment::blinded_with_gens(6u64, qty_blinding, &pc_gens).to_point(),
+        flv: Commitment::blinded_with_gens(flv, Scalar::zero(), &pc_gens).to_point(),
+    };
+    assert_eq!(
+        retired.open_with_gens(value, qty_blinding, Scalar::zero(), &pc_gens),
+        Some(value)
+    );
+    assert_eq!(retired.open(value, qty_blinding, Scalar::zero()), None);
+}
+
+#[test]
+fn prover_applies_default_budget() {
+    let pred = generate_predicate(1);
+    let prog = Program::build(|p| {
+        for _ in 0..DEFAULT_BUDGET / 2 {
+            p.push(String::from(Scalar::zero()));
+            p.drop();
+        }
+        p.input_helper(0, Scalar::zero(), pred.clone());
+        p.output_helper(pred);
+    });
+    // Wallets cannot build the transactions that the verifiers would reject.
+    assert_eq!(build_tx(prog).err(), Some(VMError::BudgetExceeded));
+}
+
+#[test]
+fn timeout_helpers() {
+    let pred = generate_predicate(1);
+    let prog = Program::build(|p| {
+        p.push(String::from(Scalar::zero())) // stack: timeout
+            .assert_mintime_after(0) // stack: timeout, mintime-timeout
+            .assert_maxtime_before(1) // stack: timeout, mintime-timeout, timeout-maxtime
+            .drop()
+            .drop()
+            .drop();
+
+        // to make program finish we need to spend a dummy input
+        p.input_helper(0, Scalar::zero(), pred.clone());
+        p.output_helper(pred);
+    });
+
+    // Helpers produce the same bytecode as the hand-written instructions.
+    let handwritten = Program::build(|p| {
+        p.dup(2).commit().neg().mintime().add().range();
+        p.dup(2).commit().maxtime().neg().add().range();
+    });
+    let generated = Program::build(|p| {
+        p.assert_mintime_after(2).assert_maxtime_before(2);
+    });
+    assert_eq!(generated.to_bytes(), handwritten.to_bytes());
+
+    build_and_verify(prog).expect("should succeed");
+}
+
+#[test]
+fn contract_id_matches_vm() {
+    let pred = generate_predicate(1);
+    let contract = make_output(0, Scalar::zero(), pred.clone());
+    let expected_id = ContractID::compute(&contract.predicate, &contract.payload, &contract.anchor);
+
+    let prog = Program::build(|p| {
+        p.push(contract.clone()).input().signtx();
+        p.output_helper(pred);
+    });
+    let (_, txlog) = build_and_verify(prog).unwrap();
+
+    let mut inputs = 0;
+    let mut outputs = 0;
+    for entry in txlog.iter() {
+        match entry {
+            TxEntry::Input(id) => {
+                assert_eq!(id, &expected_id);
+                inputs += 1;
+            }
+            TxEntry::Output(c) => {
+                assert_eq!(
+                    ContractID::compute(&c.predicate, &c.payload, &c.anchor),
+                    c.id()
+                );
+                outputs += 1;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!((inputs, outputs), (1, 1));
+}
+
 #[test]
 fn borrow_output() {
     //inputs 10 units, borrows 5 units, outputs two (5 units)
@@ -613,3 +1228,269 @@ fn borrow_output() {
     });
     build_and_verify(borrow_prog).unwrap();
 }
+
+#[test]
+fn merkle_tree_of_ids() {
+    let txids: Vec<TxID> = (0u8..5).map(|i| TxID(Hash([i; 32]))).collect();
+    let hasher = Hasher::new(b"app.txids");
+    let root = MerkleTree::root(b"app.txids", txids.iter());
+
+    for (i, txid) in txids.iter().enumerate() {
+        let path = merkle::Path::new(&txids, i, &hasher).expect("Index is within the list");
+        assert!(path.verify_root(&root, txid, &hasher));
+    }
+    let path = merkle::Path::new(&txids, 1, &hasher).unwrap();
+    assert!(!path.verify_root(&root, &txids[2], &hasher));
+    assert!(!path.verify_root(&root, &TxID(Hash([9; 32])), &hasher));
+
+    let contract_ids: Vec<ContractID> = (0u8..3).map(|i| ContractID([i; 32])).collect();
+    let hasher = Hasher::new(b"app.contracts");
+    let root = MerkleTree::root(b"app.contracts", contract_ids.iter());
+    let path = merkle::Path::new(&contract_ids, 2, &hasher).unwrap();
+    assert!(path.verify_root(&root, &contract_ids[2], &hasher));
+    assert!(!path.verify_root(&root, &contract_ids[0], &hasher));
+}
+
+#[test]
+fn clear_value_arithmetic() {
+    let usd = Scalar::from(1u64);
+    let eur = Scalar::from(2u64);
+    let value = |qty, flv| ClearValue { qty, flv };
+
+    assert_eq!(
+        value(10, usd).checked_add(&value(5, usd)),
+        Ok(value(15, usd))
+    );
+    assert_eq!(
+        value(10, usd).checked_add(&value(5, eur)),
+        Err(VMError::FlavorMismatch)
+    );
+    assert_eq!(
+        value(u64::max_value(), usd).checked_add(&value(1, usd)),
+        Err(VMError::ValueOverflow)
+    );
+
+    let totals = group_by_flavor(&[value(10, usd), value(7, eur), value(5, usd)]);
+    assert_eq!(totals.len(), 2);
+    assert_eq!(totals[&usd], 15);
+    assert_eq!(totals[&eur], 7);
+    assert!(group_by_flavor(&[]).is_empty());
+}
+
+#[test]
+fn dry_run_metrics() {
+    let flv = Scalar::from(1u64);
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let program = spend_1_1_contract(10, 10, flv, generate_predicate(1), generate_predicate(2));
+    let metrics = Prover::metrics_for(&program, header).unwrap();
+    assert!(metrics.multipliers > 0);
+    assert_eq!(
+        metrics.constraints,
+        metrics.phase_one_constraints + metrics.phase_two_constraints
+    );
+
+    // More values require more multipliers.
+    let bigger_program = spend_2_2_contract(
+        6,
+        4,
+        3,
+        7,
+        flv,
+        generate_predicate(1),
+        generate_predicate(2),
+        generate_predicate(3),
+        generate_predicate(4),
+    );
+    let bigger_metrics = Prover::metrics_for(&bigger_program, header).unwrap();
+    assert!(bigger_metrics.multipliers > metrics.multipliers);
+
+    // The same program without the witness data allocates the same constraints.
+    let closed = |c: Commitment| Commitment::Closed(c.to_point());
+    let input = Contract {
+        predicate: generate_predicate(1),
+        payload: vec![PortableItem::Value(Value {
+            qty: closed(Commitment::blinded(10u64)),
+            flv: closed(Commitment::blinded(flv)),
+        })],
+        anchor: Anchor::from_raw_bytes([0u8; 32]),
+    };
+    let witnessless_program = Program::build(|p| {
+        p.push(input)
+            .input()
+            .signtx()
+            .push(closed(Commitment::blinded(10u64)))
+            .push(closed(Commitment::blinded(flv)))
+            .cloak(1, 1)
+            .output_helper(generate_predicate(2));
+    });
+    let witnessless_metrics = Prover::metrics_for(&witnessless_program, header).unwrap();
+    assert_eq!(witnessless_metrics.multipliers, metrics.multipliers);
+    assert_eq!(witnessless_metrics.constraints, metrics.constraints);
+    assert_eq!(
+        build_tx(witnessless_program).unwrap_err(),
+        VMError::WitnessMissing
+    );
+}
+
+#[test]
+fn required_signers() {
+    let pred1 = generate_predicate(1);
+    let pred2 = generate_predicate(2);
+    let flavor = Scalar::from(1u64);
+
+    // both inputs are locked by the same key
+    let program = spend_2_2_contract(
+        6u64,
+        4u64,
+        9u64,
+        1u64,
+        flavor,
+        pred1.clone(),
+        pred1.clone(),
+        pred2.clone(),
+        pred2.clone(),
+    );
+    let bp_gens = BulletproofGens::new(256, 1);
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let utx = Prover::build_tx(program, header, &bp_gens).unwrap();
+    let signers = utx.required_signers();
+    assert_eq!(
+        signers,
+        vec![pred1.verification_key(), pred1.verification_key()]
+    );
+
+    // signature made with the listed keys is accepted by the verifier
+    let mut signtx_transcript = utx.signing_transcript();
+    let sig = Signature::sign_multi(
+        vec![predicate_privkey(&pred1); signers.len()],
+        signers
+            .into_iter()
+            .zip(utx.signing_instructions.iter().map(|(_, m)| m))
+            .collect(),
+        &mut signtx_transcript,
+    )
+    .unwrap();
+    assert!(utx.sign(sig).verify(&bp_gens).is_ok());
+}
+
+#[test]
+fn external_signer() {
+    let pred1 = generate_predicate(1);
+    let pred2 = generate_predicate(2);
+    let flavor = Scalar::from(1u64);
+    let program = spend_1_1_contract(5u64, 5u64, flavor, pred1.clone(), pred2);
+    let bp_gens = BulletproofGens::new(256, 1);
+    let header = TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    };
+    let utx = Prover::build_tx(program, header, &bp_gens).unwrap();
+
+    // signer receives the unsigned tx without the witness data and returns the signature
+    let json = serde_json::to_string(&utx).unwrap();
+    let sig = {
+        let utx: zkvm::UnsignedTx = serde_json::from_str(&json).unwrap();
+        Signature::sign_multi(
+            vec![predicate_privkey(&pred1)],
+            utx.signing_instructions
+                .iter()
+                .map(|(p, m)| (p.verification_key(), m))
+                .collect(),
+            &mut utx.signing_transcript(),
+        )
+        .unwrap()
+    };
+    let vtx = utx.clone().sign(sig).verify(&bp_gens).unwrap();
+    assert!(vtx.id == utx.txid);
+
+    // signature over a different transcript is rejected
+    let wrong_sig = Signature::sign_multi(
+        vec![predicate_privkey(&pred1)],
+        utx.signing_instructions
+            .iter()
+            .map(|(p, m)| (p.verification_key(), m))
+            .collect(),
+        &mut Transcript::new(b"ZkVM.signtx"),
+    )
+    .unwrap();
+    assert!(utx.sign(wrong_sig).verify(&bp_gens).is_err());
+}
+
+#[test]
+fn cloak_ordered() {
+    let flavor = Scalar::from(1u64);
+    let output_qtys = |ordered: bool| {
+        let program = Program::build(|p| {
+            p.input_helper(10u64, flavor, generate_predicate(1))
+                .push(Commitment::blinded(9u64))
+                .push(Commitment::blinded(flavor))
+                .push(Commitment::blinded(1u64))
+                .push(Commitment::blinded(flavor));
+            if ordered {
+                p.cloak_ordered(1, 2);
+            } else {
+                p.cloak(1, 2);
+            }
+            // outputs are created from the top of the stack
+            p.output_helper(generate_predicate(2))
+                .output_helper(generate_predicate(3));
+        });
+        let (_txid, txlog) = build_and_verify(program).unwrap();
+        txlog
+            .outputs()
+            .map(|c| {
+                c.payload[0]
+                    .as_value()
+                    .unwrap()
+                    .assignment()
+                    .unwrap()
+                    .0
+                    .to_u64()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // ordered cloak leaves the last value on top, like the commitments were
+    assert_eq!(output_qtys(true), vec![1u64, 9u64]);
+    assert_eq!(output_qtys(false), vec![9u64, 1u64]);
+}
+
+#[test]
+fn verified_tx_summary() {
+    let flv = Scalar::from(1u64);
+    let prev_output = make_output(10, flv, generate_predicate(1));
+    let prog = Program::build(|p| {
+        p.push(prev_output.clone())
+            .input()
+            .signtx()
+            .cloak_helper(1, vec![(4u64, flv), (6u64, flv)])
+            .retire()
+            .output_helper(generate_predicate(2));
+    });
+    let (txlog, tx) = build_tx(prog).unwrap();
+    let vtx = tx.verify(&BulletproofGens::new(256, 1)).unwrap();
+
+    let summary = vtx.summary();
+    assert_eq!(summary.id, vtx.id);
+    assert_eq!(summary.fee, 0);
+    assert_eq!(summary.maxtime_ms, tx.header.maxtime_ms);
+    assert_eq!(summary.issuances, 0);
+    assert_eq!(summary.retirements, 1);
+    assert_eq!(summary.inputs, vec![prev_output.id()]);
+    assert_eq!(
+        summary.outputs,
+        txlog.outputs().map(|c| c.id()).collect::<Vec<_>>()
+    );
+    assert_eq!(summary.outputs.len(), 1);
+    assert_eq!(summary.status, None);
+}